@@ -32,6 +32,88 @@
 //! };
 //! # }
 //! ```
+//! ## Publishing a batch of events
+//! ```no_run
+//! extern crate exar;
+//! extern crate exar_client;
+//!
+//! # fn main() {
+//! use exar::*;
+//! use exar_client::*;
+//!
+//! let addr = "127.0.0.1:38580";
+//! let mut client = Client::connect(addr, "test", Some("username"), Some("password")).unwrap();
+//!
+//! let events = vec![Event::new("payload", vec!["tag1"]), Event::new("payload", vec!["tag2"])];
+//!
+//! match client.publish_batch(events) {
+//!     Ok((first_event_id, last_event_id)) => println!("Published events with IDs: {}-{}", first_event_id, last_event_id),
+//!     Err(err) => panic!("Unable to publish events: {}", err)
+//! };
+//! # }
+//! ```
+//! ## Publishing events expecting the last event published
+//! ```no_run
+//! extern crate exar;
+//! extern crate exar_client;
+//!
+//! # fn main() {
+//! use exar::*;
+//! use exar_client::*;
+//!
+//! let addr = "127.0.0.1:38580";
+//! let mut client = Client::connect(addr, "test", Some("username"), Some("password")).unwrap();
+//!
+//! let event = Event::new("payload", vec!["tag1", "tag2"]);
+//!
+//! match client.publish_expecting(event, 41) {
+//!     Ok(event_id) => println!("Published event with ID: {}", event_id),
+//!     Err(DatabaseError::WrongExpectedVersion { actual, .. }) => println!("Another event has been published: {}", actual),
+//!     Err(err) => panic!("Unable to publish event: {}", err)
+//! };
+//! # }
+//! ```
+//! ## Publishing events idempotently
+//! ```no_run
+//! extern crate exar;
+//! extern crate exar_client;
+//!
+//! # fn main() {
+//! use exar::*;
+//! use exar_client::*;
+//!
+//! let addr = "127.0.0.1:38580";
+//! let mut client = Client::connect(addr, "test", Some("username"), Some("password")).unwrap();
+//!
+//! let event = Event::new("payload", vec!["tag1", "tag2"]).with_client_id("8c9e5a1f-3c2b-4d6e-9f7a-1b2c3d4e5f60");
+//!
+//! // publishing the event again after a failure returns the id of the original event
+//! let event_id = client.publish(event.clone()).or_else(|_| client.publish(event)).unwrap();
+//! println!("Published event with ID: {}", event_id);
+//! # }
+//! ```
+//! ## Publishing events into a stream expecting its version
+//! ```no_run
+//! extern crate exar;
+//! extern crate exar_client;
+//!
+//! # fn main() {
+//! use exar::*;
+//! use exar_client::*;
+//!
+//! let addr = "127.0.0.1:38580";
+//! let mut client = Client::connect(addr, "test", Some("username"), Some("password")).unwrap();
+//!
+//! let version = client.stream_version("order-123").unwrap();
+//! let event = Event::new("payload", vec!["order"]).with_stream("order-123");
+//!
+//! match client.publish_expecting_stream_version(event, version) {
+//!     Ok(event_id) => println!("Published event with ID: {}", event_id),
+//!     Err(DatabaseError::WrongExpectedVersion { actual, .. }) => println!("The stream has been updated: {}", actual),
+//!     Err(err) => panic!("Unable to publish event: {}", err)
+//! };
+//! # }
+//! ```
 //! ## Querying events
 //! ```no_run
 //! extern crate exar;
@@ -49,6 +131,79 @@
 //! for event in event_stream {
 //!     println!("Received event: {}", event);
 //! }
+//!
+//! let latest_events_stream = client.subscribe(Query::last(20)).unwrap();
+//! for event in latest_events_stream {
+//!     println!("Received event: {}", event);
+//! }
+//!
+//! // the live event stream ends promptly once unsubscribed
+//! let live_event_stream = client.subscribe(Query::live()).unwrap();
+//! for event in live_event_stream {
+//!     if event.tags.contains(&"shutdown".to_owned()) {
+//!         client.unsubscribe().unwrap();
+//!     }
+//! }
+//! # }
+//! ```
+//! ## Durable subscriptions
+//! ```no_run
+//! extern crate exar;
+//! extern crate exar_client;
+//!
+//! # fn main() {
+//! use exar::*;
+//! use exar_client::*;
+//!
+//! let addr = "127.0.0.1:38580";
+//! let mut client = Client::connect(addr, "test", Some("username"), Some("password")).unwrap();
+//!
+//! // resumes right after the last event acknowledged by the consumer
+//! let event_stream = client.subscribe_durable("billing", Query::live().by_tag("order-placed")).unwrap();
+//! for event in event_stream {
+//!     println!("Received event: {}", event);
+//!     client.acknowledge("billing", event.id).unwrap();
+//! }
+//! # }
+//! ```
+//! ## Consumer groups
+//! ```no_run
+//! extern crate exar;
+//! extern crate exar_client;
+//!
+//! # fn main() {
+//! use exar::*;
+//! use exar_client::*;
+//!
+//! let addr = "127.0.0.1:38580";
+//! let mut client = Client::connect(addr, "test", Some("username"), Some("password")).unwrap();
+//!
+//! // each event is delivered to a single member of the group
+//! let event_stream = client.join_group("billing", Query::live().by_tag("order-placed")).unwrap();
+//! for event in event_stream {
+//!     println!("Received event: {}", event);
+//!     client.ack(event.id).unwrap();
+//! }
+//! # }
+//! ```
+//! ## Aggregating events
+//! ```no_run
+//! extern crate exar;
+//! extern crate exar_client;
+//!
+//! # fn main() {
+//! use exar::*;
+//! use exar_client::*;
+//!
+//! let addr = "127.0.0.1:38580";
+//! let mut client = Client::connect(addr, "test", Some("username"), Some("password")).unwrap();
+//!
+//! // counting the events per tag and hour, updated every 5 seconds
+//! let aggregation = Aggregation::new().group_by_tag().bucket_size(3600000).update_interval(5000);
+//! let aggregate_stream = client.aggregate(Query::live().by_tag("order-placed"), aggregation).unwrap();
+//! for aggregate in aggregate_stream {
+//!     println!("Received aggregate: {}", aggregate);
+//! }
 //! # }
 //! ```
 
@@ -66,14 +221,19 @@ use exar_net::*;
 
 use std::io::ErrorKind;
 use std::net::{ToSocketAddrs, TcpStream};
-use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, sync_channel};
 use std::thread;
 
 /// # Exar DB's client
 pub struct Client {
-    stream: TcpMessageStream<TcpStream>
+    stream: TcpMessageStream<TcpStream>,
+    event_stream_buffer_size: usize
 }
 
+/// The default number of events buffered by the client for each event stream.
+pub const DEFAULT_EVENT_STREAM_BUFFER_SIZE: usize = 1000;
+
 impl Client {
     /// Connects to the given address and collection, optionally using the credentials provided,
     /// it returns a `Client` or a `DatabaseError` if a failure occurs.
@@ -87,7 +247,10 @@ impl Client {
                 let connection_message = TcpMessage::Connect(collection_name.to_owned(), username, password);
                 try!(stream.send_message(connection_message));
                 match stream.recv_message() {
-                    Ok(TcpMessage::Connected) => Ok(Client { stream: stream }),
+                    Ok(TcpMessage::Connected) => Ok(Client {
+                        stream: stream,
+                        event_stream_buffer_size: DEFAULT_EVENT_STREAM_BUFFER_SIZE
+                    }),
                     Ok(TcpMessage::Error(error)) => Err(error),
                     Ok(_) => Err(DatabaseError::ConnectionError),
                     Err(err) => Err(err)
@@ -97,8 +260,20 @@ impl Client {
         }
     }
 
+    /// Returns the client with the given number of events buffered for each event stream.
+    ///
+    /// Once the buffer of an event stream is full the client stops reading from the connection,
+    /// so that the server pauses the subscription until the events buffered are consumed.
+    pub fn with_event_stream_buffer_size(mut self, event_stream_buffer_size: usize) -> Client {
+        self.event_stream_buffer_size = event_stream_buffer_size;
+        self
+    }
+
     /// Publishes an event and returns the `id` for the event created
     /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// If the event carries a client `id`, it can be safely published again after a failure,
+    /// the `id` of the original event is returned if it has already been published.
     pub fn publish(&mut self, event: Event) -> Result<u64, DatabaseError> {
         try!(self.stream.send_message(TcpMessage::Publish(event)));
         match self.stream.recv_message() {
@@ -109,47 +284,253 @@ impl Client {
         }
     }
 
+    /// Publishes a batch of events atomically and returns the `id`s of the first and last events created
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn publish_batch(&mut self, events: Vec<Event>) -> Result<(u64, u64), DatabaseError> {
+        try!(self.stream.send_message(TcpMessage::PublishBatch(events)));
+        match self.stream.recv_message() {
+            Ok(TcpMessage::PublishedBatch(first_event_id, last_event_id)) => Ok((first_event_id, last_event_id)),
+            Ok(TcpMessage::Error(error)) => Err(error),
+            Ok(_) => Err(DatabaseError::IoError(ErrorKind::InvalidData, "unexpected TCP message".to_owned())),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Publishes an event only if the `id` of the last event published is the expected one,
+    /// and returns the `id` for the event created or a `DatabaseError` if a failure occurs.
+    ///
+    /// The client `id` of the event is not sent, `publish_batch_expecting` can be used to publish an event carrying one.
+    pub fn publish_expecting(&mut self, event: Event, expected_last_id: u64) -> Result<u64, DatabaseError> {
+        try!(self.stream.send_message(TcpMessage::PublishExpecting(event, expected_last_id)));
+        match self.stream.recv_message() {
+            Ok(TcpMessage::Published(event_id)) => Ok(event_id),
+            Ok(TcpMessage::Error(error)) => Err(error),
+            Ok(_) => Err(DatabaseError::IoError(ErrorKind::InvalidData, "unexpected TCP message".to_owned())),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Publishes a batch of events atomically only if the `id` of the last event published is the expected one,
+    /// and returns the `id`s of the first and last events created or a `DatabaseError` if a failure occurs.
+    pub fn publish_batch_expecting(&mut self, events: Vec<Event>, expected_last_id: u64) -> Result<(u64, u64), DatabaseError> {
+        try!(self.stream.send_message(TcpMessage::PublishBatchExpecting(events, expected_last_id)));
+        match self.stream.recv_message() {
+            Ok(TcpMessage::PublishedBatch(first_event_id, last_event_id)) => Ok((first_event_id, last_event_id)),
+            Ok(TcpMessage::Error(error)) => Err(error),
+            Ok(_) => Err(DatabaseError::IoError(ErrorKind::InvalidData, "unexpected TCP message".to_owned())),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Returns the version of the last event published into the given stream, or 0 if the stream does not exist,
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn stream_version(&mut self, stream_id: &str) -> Result<u64, DatabaseError> {
+        try!(self.stream.send_message(TcpMessage::GetStreamVersion(stream_id.to_owned())));
+        match self.stream.recv_message() {
+            Ok(TcpMessage::StreamVersion(_, version)) => Ok(version),
+            Ok(TcpMessage::Error(error)) => Err(error),
+            Ok(_) => Err(DatabaseError::IoError(ErrorKind::InvalidData, "unexpected TCP message".to_owned())),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Publishes an event into its stream only if the current version of the stream is the expected one,
+    /// and returns the `id` for the event created or a `DatabaseError` if a failure occurs.
+    pub fn publish_expecting_stream_version(&mut self, event: Event, expected_version: u64) -> Result<u64, DatabaseError> {
+        try!(self.stream.send_message(TcpMessage::PublishExpectingStreamVersion(event, expected_version)));
+        match self.stream.recv_message() {
+            Ok(TcpMessage::Published(event_id)) => Ok(event_id),
+            Ok(TcpMessage::Error(error)) => Err(error),
+            Ok(_) => Err(DatabaseError::IoError(ErrorKind::InvalidData, "unexpected TCP message".to_owned())),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Publishes a batch of events into their stream atomically only if the current version of the stream
+    /// is the expected one, and returns the `id`s of the first and last events created or a `DatabaseError` if a failure occurs.
+    pub fn publish_batch_expecting_stream_version(&mut self, events: Vec<Event>, expected_version: u64) -> Result<(u64, u64), DatabaseError> {
+        try!(self.stream.send_message(TcpMessage::PublishBatchExpectingStreamVersion(events, expected_version)));
+        match self.stream.recv_message() {
+            Ok(TcpMessage::PublishedBatch(first_event_id, last_event_id)) => Ok((first_event_id, last_event_id)),
+            Ok(TcpMessage::Error(error)) => Err(error),
+            Ok(_) => Err(DatabaseError::IoError(ErrorKind::InvalidData, "unexpected TCP message".to_owned())),
+            Err(err) => Err(err)
+        }
+    }
+
     /// Subscribes using the given query and returns an event stream
     /// or a `DatabaseError` if a failure occurs.
     pub fn subscribe(&mut self, query: Query) -> Result<EventStream, DatabaseError> {
-        let subscribe_message = TcpMessage::Subscribe(query.live_stream, query.offset, query.limit, query.tag);
-        self.stream.send_message(subscribe_message).and_then(|_| {
-            self.stream.recv_message().and_then(|message| {
-                match message {
-                    TcpMessage::Subscribed => {
-                        let (sender, receiver) = channel();
-                        self.stream.try_clone().and_then(|cloned_stream| {
-                            thread::spawn(move || {
-                                for message in cloned_stream.messages() {
-                                    match message {
-                                        Ok(TcpMessage::Event(event)) => match sender.send(EventStreamMessage::Event(event)) {
-                                            Ok(_) => continue,
-                                            Err(err) => error!("Unable to send event to the event stream: {}", err)
-                                        },
-                                        Ok(TcpMessage::EndOfEventStream) => {
-                                            let _ = sender.send(EventStreamMessage::End);
-                                        },
-                                        Ok(TcpMessage::Error(error)) => error!("Received error from TCP stream: {}", error),
-                                        Ok(message) => error!("Unexpected TCP message: {}", message),
-                                        Err(err) => error!("Unable to read TCP message from stream: {}", err)
-                                    };
-                                    break
-                                }
-                            });
-                            Ok(EventStream::new(receiver))
-                        })
-                    },
-                    TcpMessage::Error(err) => Err(err),
-                    _ => Err(DatabaseError::SubscriptionError)
+        self.stream.send_message(TcpMessage::Query(query)).and_then(|_| self.recv_event_stream())
+    }
+
+    /// Cancels the event stream or live aggregate stream being received
+    /// or returns a `DatabaseError` if the cancellation cannot be sent.
+    ///
+    /// It does not wait for a response: the stream being received ends once the server has removed its subscription,
+    /// and the consumer group joined, if any, is left.
+    pub fn unsubscribe(&mut self) -> Result<(), DatabaseError> {
+        self.stream.send_message(TcpMessage::Unsubscribe)
+    }
+
+    /// Subscribes on behalf of the given durable consumer using the given query and returns an event stream
+    /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// The event stream resumes right after the last event acknowledged by the consumer.
+    pub fn subscribe_durable(&mut self, consumer_name: &str, query: Query) -> Result<EventStream, DatabaseError> {
+        let message = TcpMessage::SubscribeDurable(consumer_name.to_owned(), query);
+        self.stream.send_message(message).and_then(|_| self.recv_event_stream())
+    }
+
+    /// Acknowledges the processing of the events up to the given `id` on behalf of the given durable consumer
+    /// or returns a `DatabaseError` if the acknowledgement cannot be sent.
+    ///
+    /// It can be used while receiving an event stream, since it does not wait for a response:
+    /// a failure on the server ends the event stream being received, or is returned by the following request.
+    pub fn acknowledge(&mut self, consumer_name: &str, event_id: u64) -> Result<(), DatabaseError> {
+        self.stream.send_message(TcpMessage::Acknowledge(consumer_name.to_owned(), event_id))
+    }
+
+    /// Returns the checkpoints of the durable consumers of the collection
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn checkpoints(&mut self) -> Result<Checkpoints, DatabaseError> {
+        self.stream.send_message(TcpMessage::ListCheckpoints).and_then(|_| self.recv_checkpoints())
+    }
+
+    /// Resets the checkpoint of the given durable consumer to the given event `id` and returns the updated checkpoints
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn reset_checkpoint(&mut self, consumer_name: &str, event_id: u64) -> Result<Checkpoints, DatabaseError> {
+        let message = TcpMessage::ResetCheckpoint(consumer_name.to_owned(), event_id);
+        self.stream.send_message(message).and_then(|_| self.recv_checkpoints())
+    }
+
+    /// Deletes the checkpoint of the given durable consumer and returns the updated checkpoints
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn delete_checkpoint(&mut self, consumer_name: &str) -> Result<Checkpoints, DatabaseError> {
+        let message = TcpMessage::DeleteCheckpoint(consumer_name.to_owned());
+        self.stream.send_message(message).and_then(|_| self.recv_checkpoints())
+    }
+
+    /// Joins the consumer group with the given name, which is started using the given query if not running,
+    /// and returns an event stream or a `DatabaseError` if a failure occurs.
+    ///
+    /// Each event of the event stream is delivered to this member only and must be acknowledged using `ack`,
+    /// otherwise it is delivered again once the acknowledgement timeout expires.
+    pub fn join_group(&mut self, group_name: &str, query: Query) -> Result<EventStream, DatabaseError> {
+        let message = TcpMessage::JoinGroup(group_name.to_owned(), query);
+        self.stream.send_message(message).and_then(|_| self.recv_event_stream())
+    }
+
+    /// Acknowledges the processing of the event with the given `id` delivered by the consumer group joined
+    /// or returns a `DatabaseError` if the acknowledgement cannot be sent.
+    ///
+    /// As for `acknowledge`, it does not wait for a response.
+    pub fn ack(&mut self, event_id: u64) -> Result<(), DatabaseError> {
+        self.stream.send_message(TcpMessage::Ack(event_id))
+    }
+
+    /// Negatively acknowledges the event with the given `id` delivered by the consumer group joined,
+    /// so that it is delivered again, or returns a `DatabaseError` if the negative acknowledgement cannot be sent.
+    ///
+    /// As for `acknowledge`, it does not wait for a response.
+    pub fn nack(&mut self, event_id: u64) -> Result<(), DatabaseError> {
+        self.stream.send_message(TcpMessage::Nack(event_id))
+    }
+
+    /// Returns the description of the consumer group with the given name, including its members and lag,
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn describe_group(&mut self, group_name: &str) -> Result<ConsumerGroupInfo, DatabaseError> {
+        try!(self.stream.send_message(TcpMessage::DescribeGroup(group_name.to_owned())));
+        match self.stream.recv_message() {
+            Ok(TcpMessage::ConsumerGroup(info)) => Ok(info),
+            Ok(TcpMessage::Error(error)) => Err(error),
+            Ok(_) => Err(DatabaseError::IoError(ErrorKind::InvalidData, "unexpected TCP message".to_owned())),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Aggregates the events targeted by the given query according to the given aggregation
+    /// and returns an aggregate stream or a `DatabaseError` if a failure occurs.
+    ///
+    /// The aggregate stream of a live query returns the updated aggregates sent periodically by the server.
+    pub fn aggregate(&mut self, query: Query, aggregation: Aggregation) -> Result<AggregateStream, DatabaseError> {
+        let live_stream = query.live_stream;
+        try!(self.stream.send_message(TcpMessage::Aggregate(aggregation, query)));
+        match self.stream.recv_message() {
+            Ok(TcpMessage::Aggregated(aggregate)) => {
+                let (sender, receiver) = channel();
+                let _ = sender.send(aggregate);
+                if live_stream {
+                    let cloned_stream = try!(self.stream.try_clone());
+                    thread::spawn(move || {
+                        for message in cloned_stream.messages() {
+                            match message {
+                                Ok(TcpMessage::Aggregated(aggregate)) => match sender.send(aggregate) {
+                                    Ok(_) => continue,
+                                    Err(err) => error!("Unable to send aggregate to the aggregate stream: {}", err)
+                                },
+                                Ok(TcpMessage::EndOfEventStream) => (),
+                                Ok(TcpMessage::Error(error)) => error!("Received error from TCP stream: {}", error),
+                                Ok(message) => error!("Unexpected TCP message: {}", message),
+                                Err(err) => error!("Unable to read TCP message from stream: {}", err)
+                            };
+                            break
+                        }
+                    });
                 }
-            })
-        })
+                Ok(AggregateStream::new(receiver))
+            },
+            Ok(TcpMessage::Error(error)) => Err(error),
+            Ok(_) => Err(DatabaseError::IoError(ErrorKind::InvalidData, "unexpected TCP message".to_owned())),
+            Err(err) => Err(err)
+        }
     }
 
     /// Closes the connection.
     pub fn close(self) {
         drop(self)
     }
+
+    fn recv_event_stream(&mut self) -> Result<EventStream, DatabaseError> {
+        self.stream.recv_message().and_then(|message| {
+            match message {
+                TcpMessage::Subscribed => {
+                    let (sender, receiver) = sync_channel(self.event_stream_buffer_size);
+                    self.stream.try_clone().and_then(|cloned_stream| {
+                        thread::spawn(move || {
+                            for message in cloned_stream.messages() {
+                                match message {
+                                    Ok(TcpMessage::Event(event)) => match sender.send(EventStreamMessage::Event(Arc::new(event))) {
+                                        Ok(_) => continue,
+                                        Err(err) => error!("Unable to send event to the event stream: {}", err)
+                                    },
+                                    Ok(TcpMessage::EndOfEventStream) => {
+                                        let _ = sender.send(EventStreamMessage::End);
+                                    },
+                                    Ok(TcpMessage::Error(error)) => error!("Received error from TCP stream: {}", error),
+                                    Ok(message) => error!("Unexpected TCP message: {}", message),
+                                    Err(err) => error!("Unable to read TCP message from stream: {}", err)
+                                };
+                                break
+                            }
+                        });
+                        Ok(EventStream::new(receiver))
+                    })
+                },
+                TcpMessage::Error(err) => Err(err),
+                _ => Err(DatabaseError::SubscriptionError)
+            }
+        })
+    }
+
+    fn recv_checkpoints(&mut self) -> Result<Checkpoints, DatabaseError> {
+        match self.stream.recv_message() {
+            Ok(TcpMessage::Checkpoints(checkpoints)) => Ok(checkpoints),
+            Ok(TcpMessage::Error(error)) => Err(error),
+            Ok(_) => Err(DatabaseError::IoError(ErrorKind::InvalidData, "unexpected TCP message".to_owned())),
+            Err(err) => Err(err)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -159,6 +540,7 @@ mod tests {
     use exar_testkit::*;
     use super::*;
 
+    use std::collections::BTreeMap;
     use std::net::{TcpListener, ToSocketAddrs};
     use std::thread;
     use std::time::Duration;
@@ -266,6 +648,117 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_publish_idempotent() {
+        with_addr(&mut |addr| {
+
+            let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890).with_client_id("client1");
+
+            stub_server(addr.clone(), vec![
+                StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
+                StreamAction::Write(TcpMessage::Connected),
+                StreamAction::Read(TcpMessage::Publish(event.clone())),
+                StreamAction::Write(TcpMessage::Published(1)),
+                StreamAction::Read(TcpMessage::Publish(event.clone())),
+                StreamAction::Write(TcpMessage::Published(1))
+            ]);
+
+            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect");
+            assert_eq!(client.publish(event.clone()), Ok(1));
+            assert_eq!(client.publish(event.clone()), Ok(1));
+        });
+    }
+
+    #[test]
+    fn test_publish_batch() {
+        with_addr(&mut |addr| {
+
+            let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+
+            stub_server(addr.clone(), vec![
+                StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
+                StreamAction::Write(TcpMessage::Connected),
+                StreamAction::Read(TcpMessage::PublishBatch(vec![event.clone(), event.clone()])),
+                StreamAction::Write(TcpMessage::PublishedBatch(1, 2))
+            ]);
+
+            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect");
+            assert_eq!(client.publish_batch(vec![event.clone(), event.clone()]), Ok((1, 2)));
+        });
+    }
+
+    #[test]
+    fn test_publish_batch_failure() {
+        with_addr(&mut |addr| {
+
+            let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+            let validation_error = ValidationError::new("validation error");
+
+            stub_server(addr.clone(), vec![
+                StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
+                StreamAction::Write(TcpMessage::Connected),
+                StreamAction::Read(TcpMessage::PublishBatch(vec![event.clone()])),
+                StreamAction::Write(TcpMessage::Error(DatabaseError::ValidationError(validation_error.clone())))
+            ]);
+
+            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect");
+            assert_eq!(client.publish_batch(vec![event.clone()]), Err(DatabaseError::ValidationError(validation_error)));
+        });
+    }
+
+    #[test]
+    fn test_publish_expecting() {
+        with_addr(&mut |addr| {
+
+            let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+            let wrong_expected_version = DatabaseError::WrongExpectedVersion { expected: 1, actual: 2 };
+
+            stub_server(addr.clone(), vec![
+                StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
+                StreamAction::Write(TcpMessage::Connected),
+                StreamAction::Read(TcpMessage::PublishExpecting(event.clone(), 0)),
+                StreamAction::Write(TcpMessage::Published(1)),
+                StreamAction::Read(TcpMessage::PublishBatchExpecting(vec![event.clone(), event.clone()], 1)),
+                StreamAction::Write(TcpMessage::PublishedBatch(2, 3)),
+                StreamAction::Read(TcpMessage::PublishExpecting(event.clone(), 1)),
+                StreamAction::Write(TcpMessage::Error(wrong_expected_version.clone()))
+            ]);
+
+            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect");
+            assert_eq!(client.publish_expecting(event.clone(), 0), Ok(1));
+            assert_eq!(client.publish_batch_expecting(vec![event.clone(), event.clone()], 1), Ok((2, 3)));
+            assert_eq!(client.publish_expecting(event.clone(), 1), Err(wrong_expected_version));
+        });
+    }
+
+    #[test]
+    fn test_streams() {
+        with_addr(&mut |addr| {
+
+            let event = Event::new("data", vec!["order"]).with_timestamp(1234567890).with_stream("order-1");
+            let wrong_expected_version = DatabaseError::WrongExpectedVersion { expected: 1, actual: 3 };
+
+            stub_server(addr.clone(), vec![
+                StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
+                StreamAction::Write(TcpMessage::Connected),
+                StreamAction::Read(TcpMessage::PublishExpectingStreamVersion(event.clone(), 0)),
+                StreamAction::Write(TcpMessage::Published(1)),
+                StreamAction::Read(TcpMessage::PublishBatchExpectingStreamVersion(vec![event.clone(), event.clone()], 1)),
+                StreamAction::Write(TcpMessage::PublishedBatch(2, 3)),
+                StreamAction::Read(TcpMessage::PublishExpectingStreamVersion(event.clone(), 1)),
+                StreamAction::Write(TcpMessage::Error(wrong_expected_version.clone())),
+                StreamAction::Read(TcpMessage::GetStreamVersion("order-1".to_owned())),
+                StreamAction::Write(TcpMessage::StreamVersion("order-1".to_owned(), 3))
+            ]);
+
+            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect");
+            assert_eq!(client.publish_expecting_stream_version(event.clone(), 0), Ok(1));
+            assert_eq!(client.publish_batch_expecting_stream_version(vec![event.clone(), event.clone()], 1), Ok((2, 3)));
+            assert_eq!(client.publish_expecting_stream_version(event.clone(), 1), Err(wrong_expected_version));
+            assert_eq!(client.stream_version("order-1"), Ok(3));
+        });
+    }
+
     #[test]
     fn test_subscribe() {
         with_addr(&mut |addr| {
@@ -275,14 +768,15 @@ mod tests {
             stub_server(addr.clone(), vec![
                 StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
                 StreamAction::Write(TcpMessage::Connected),
-                StreamAction::Read(TcpMessage::Subscribe(true, 0, None, None)),
+                StreamAction::Read(TcpMessage::Query(Query::live())),
                 StreamAction::Write(TcpMessage::Subscribed),
                 StreamAction::Write(TcpMessage::Event(event.clone().with_id(1))),
                 StreamAction::Write(TcpMessage::Event(event.clone().with_id(2))),
                 StreamAction::Write(TcpMessage::EndOfEventStream)
             ]);
 
-            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect");
+            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect")
+                                   .with_event_stream_buffer_size(1);
             let mut event_stream = client.subscribe(Query::live()).expect("Unable to subscribe");
             assert_eq!(event_stream.next(), Some(event.clone().with_id(1)));
             assert_eq!(event_stream.next(), Some(event.clone().with_id(2)));
@@ -290,6 +784,30 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_unsubscribe() {
+        with_addr(&mut |addr| {
+
+            let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+
+            stub_server(addr.clone(), vec![
+                StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
+                StreamAction::Write(TcpMessage::Connected),
+                StreamAction::Read(TcpMessage::Query(Query::live())),
+                StreamAction::Write(TcpMessage::Subscribed),
+                StreamAction::Write(TcpMessage::Event(event.clone().with_id(1))),
+                StreamAction::Read(TcpMessage::Unsubscribe),
+                StreamAction::Write(TcpMessage::EndOfEventStream)
+            ]);
+
+            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect");
+            let mut event_stream = client.subscribe(Query::live()).expect("Unable to subscribe");
+            assert_eq!(event_stream.next(), Some(event.with_id(1)));
+            assert_eq!(client.unsubscribe(), Ok(()));
+            assert_eq!(event_stream.next(), None);
+        });
+    }
+
     #[test]
     fn test_subscribe_failure() {
         with_addr(&mut |addr| {
@@ -297,7 +815,7 @@ mod tests {
             stub_server(addr.clone(), vec![
                 StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
                 StreamAction::Write(TcpMessage::Connected),
-                StreamAction::Read(TcpMessage::Subscribe(true, 0, None, None)),
+                StreamAction::Read(TcpMessage::Query(Query::live())),
                 StreamAction::Write(TcpMessage::Error(DatabaseError::SubscriptionError))
             ]);
 
@@ -305,4 +823,127 @@ mod tests {
             assert_eq!(client.subscribe(Query::live()).err(), Some(DatabaseError::SubscriptionError));
         });
     }
+
+    #[test]
+    fn test_durable_subscriptions() {
+        with_addr(&mut |addr| {
+
+            let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+            let mut checkpoints = Checkpoints::new();
+            assert!(checkpoints.acknowledge("consumer", 1).is_ok());
+
+            stub_server(addr.clone(), vec![
+                StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
+                StreamAction::Write(TcpMessage::Connected),
+                StreamAction::Read(TcpMessage::SubscribeDurable("consumer".to_owned(), Query::live())),
+                StreamAction::Write(TcpMessage::Subscribed),
+                StreamAction::Write(TcpMessage::Event(event.clone().with_id(1))),
+                StreamAction::Read(TcpMessage::Acknowledge("consumer".to_owned(), 1)),
+                StreamAction::Write(TcpMessage::EndOfEventStream),
+                StreamAction::Read(TcpMessage::ListCheckpoints),
+                StreamAction::Write(TcpMessage::Checkpoints(checkpoints.clone())),
+                StreamAction::Read(TcpMessage::ResetCheckpoint("consumer".to_owned(), 5)),
+                StreamAction::Write(TcpMessage::Error(DatabaseError::ValidationError(ValidationError::new("invalid checkpoint")))),
+                StreamAction::Read(TcpMessage::DeleteCheckpoint("consumer".to_owned())),
+                StreamAction::Write(TcpMessage::Checkpoints(Checkpoints::new()))
+            ]);
+
+            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect");
+            let mut event_stream = client.subscribe_durable("consumer", Query::live()).expect("Unable to subscribe");
+            assert_eq!(event_stream.next(), Some(event.clone().with_id(1)));
+            assert_eq!(client.acknowledge("consumer", 1), Ok(()));
+            assert_eq!(event_stream.next(), None);
+
+            assert_eq!(client.checkpoints(), Ok(checkpoints));
+            assert_eq!(client.reset_checkpoint("consumer", 5),
+                       Err(DatabaseError::ValidationError(ValidationError::new("invalid checkpoint"))));
+            assert_eq!(client.delete_checkpoint("consumer"), Ok(Checkpoints::new()));
+        });
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        with_addr(&mut |addr| {
+
+            let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+            let info = ConsumerGroupInfo {
+                name: "group".to_owned(),
+                members: BTreeMap::new(),
+                position: 2,
+                lag: 0,
+                nr_of_dead_letters: 0
+            };
+
+            stub_server(addr.clone(), vec![
+                StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
+                StreamAction::Write(TcpMessage::Connected),
+                StreamAction::Read(TcpMessage::JoinGroup("group".to_owned(), Query::live())),
+                StreamAction::Write(TcpMessage::Subscribed),
+                StreamAction::Write(TcpMessage::Event(event.clone().with_id(1))),
+                StreamAction::Write(TcpMessage::Event(event.clone().with_id(2))),
+                StreamAction::Read(TcpMessage::Ack(1)),
+                StreamAction::Read(TcpMessage::Nack(2)),
+                StreamAction::Write(TcpMessage::EndOfEventStream),
+                StreamAction::Read(TcpMessage::DescribeGroup("group".to_owned())),
+                StreamAction::Write(TcpMessage::ConsumerGroup(info.clone())),
+                StreamAction::Read(TcpMessage::DescribeGroup("other".to_owned())),
+                StreamAction::Write(TcpMessage::Error(DatabaseError::ValidationError(ValidationError::new("consumer group not found"))))
+            ]);
+
+            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect");
+            let mut event_stream = client.join_group("group", Query::live()).expect("Unable to join group");
+            assert_eq!(event_stream.next(), Some(event.clone().with_id(1)));
+            assert_eq!(event_stream.next(), Some(event.clone().with_id(2)));
+            assert_eq!(client.ack(1), Ok(()));
+            assert_eq!(client.nack(2), Ok(()));
+            assert_eq!(event_stream.next(), None);
+
+            assert_eq!(client.describe_group("group"), Ok(info));
+            assert_eq!(client.describe_group("other"),
+                       Err(DatabaseError::ValidationError(ValidationError::new("consumer group not found"))));
+        });
+    }
+
+    #[test]
+    fn test_aggregate() {
+        with_addr(&mut |addr| {
+
+            let mut aggregate = Aggregate::new();
+            aggregate.add(&Event::new("data", vec!["tag1"]).with_id(1).with_timestamp(1000), &Aggregation::new());
+
+            stub_server(addr.clone(), vec![
+                StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
+                StreamAction::Write(TcpMessage::Connected),
+                StreamAction::Read(TcpMessage::Aggregate(Aggregation::new(), Query::current())),
+                StreamAction::Write(TcpMessage::Aggregated(aggregate.clone())),
+                StreamAction::Read(TcpMessage::Aggregate(Aggregation::new(), Query::live())),
+                StreamAction::Write(TcpMessage::Aggregated(Aggregate::new())),
+                StreamAction::Write(TcpMessage::Aggregated(aggregate.clone())),
+                StreamAction::Write(TcpMessage::EndOfEventStream)
+            ]);
+
+            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect");
+            let aggregates: Vec<_> = client.aggregate(Query::current(), Aggregation::new()).expect("Unable to aggregate").collect();
+            assert_eq!(aggregates, vec![aggregate.clone()]);
+
+            let aggregates: Vec<_> = client.aggregate(Query::live(), Aggregation::new()).expect("Unable to aggregate").collect();
+            assert_eq!(aggregates, vec![Aggregate::new(), aggregate]);
+        });
+    }
+
+    #[test]
+    fn test_aggregate_failure() {
+        with_addr(&mut |addr| {
+
+            stub_server(addr.clone(), vec![
+                StreamAction::Read(TcpMessage::Connect("collection".to_owned(), None, None)),
+                StreamAction::Write(TcpMessage::Connected),
+                StreamAction::Read(TcpMessage::Aggregate(Aggregation::new(), Query::live())),
+                StreamAction::Write(TcpMessage::Error(DatabaseError::SubscriptionError))
+            ]);
+
+            let mut client = Client::connect(addr, "collection", None, None).expect("Unable to connect");
+            assert_eq!(client.aggregate(Query::live(), Aggregation::new()).err(), Some(DatabaseError::SubscriptionError));
+        });
+    }
 }
@@ -0,0 +1,448 @@
+use super::*;
+
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as DisplayResult};
+use std::sync::mpsc::{sync_channel, Receiver, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Exar DB's aggregation.
+///
+/// It describes how the events targeted by a query are grouped when they are aggregated,
+/// by tag and/or by timestamp bucket, and how often a live aggregate is updated.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// // counting the events per tag and hour
+/// let aggregation = Aggregation::new().group_by_tag().bucket_size(3600000);
+///
+/// let mut aggregate = Aggregate::new();
+/// aggregate.add(&Event::new("data", vec!["order-placed"]).with_id(1).with_timestamp(1000), &aggregation);
+/// aggregate.add(&Event::new("data", vec!["order-placed"]).with_id(2).with_timestamp(2000), &aggregation);
+///
+/// assert_eq!(aggregate.count, 2);
+/// assert_eq!(aggregate.groups.get(&AggregateGroup::new(Some("order-placed"), Some(0))), Some(&2));
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Aggregation {
+    /// Indicates wether the events are grouped by tag, an event with multiple tags is counted in each of them.
+    pub group_by_tag: bool,
+    /// Indicates the size (in ms) of the timestamp buckets the events are grouped by, if specified.
+    pub bucket_size_in_ms: Option<u64>,
+    /// Indicates the interval (in ms) between the updates of a live aggregate, an interval of 0 is treated as 1.
+    pub update_interval_in_ms: u64
+}
+
+impl Aggregation {
+    /// Returns a new `Aggregation` counting the events without grouping them.
+    pub fn new() -> Aggregation {
+        Aggregation {
+            group_by_tag: false,
+            bucket_size_in_ms: None,
+            update_interval_in_ms: 1000
+        }
+    }
+
+    /// Mutates and returns the aggregation so that it groups the events by tag.
+    pub fn group_by_tag(mut self) -> Aggregation {
+        self.group_by_tag = true;
+        self
+    }
+
+    /// Mutates and returns the aggregation so that it groups the events by timestamp buckets of the given size (in ms).
+    pub fn bucket_size(mut self, bucket_size_in_ms: u64) -> Aggregation {
+        self.bucket_size_in_ms = if bucket_size_in_ms > 0 { Some(bucket_size_in_ms) } else { None };
+        self
+    }
+
+    /// Mutates and returns the aggregation by updating the interval (in ms) between the updates of a live aggregate,
+    /// the interval is at least 1 ms.
+    pub fn update_interval(mut self, update_interval_in_ms: u64) -> Aggregation {
+        self.update_interval_in_ms = max(update_interval_in_ms, 1);
+        self
+    }
+
+    /// Returns the groups the given `Event` belongs to.
+    pub fn groups(&self, event: &Event) -> Vec<AggregateGroup> {
+        let bucket = self.bucket_size_in_ms.map(|bucket_size| event.timestamp - event.timestamp % bucket_size);
+        match (self.group_by_tag, bucket) {
+            (true, bucket) => event.tags.iter().map(|tag| AggregateGroup { tag: Some(tag.to_owned()), bucket: bucket }).collect(),
+            (false, Some(bucket)) => vec![AggregateGroup { tag: None, bucket: Some(bucket) }],
+            (false, None) => vec![]
+        }
+    }
+}
+
+impl Default for Aggregation {
+    fn default() -> Aggregation {
+        Aggregation::new()
+    }
+}
+
+/// Exar DB's aggregate group.
+///
+/// It identifies the events with a given tag and/or within a timestamp bucket, starting at the given timestamp.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AggregateGroup {
+    /// The tag of the events in the group, if they are grouped by tag.
+    pub tag: Option<String>,
+    /// The first timestamp (in ms) of the bucket of the events in the group, if they are grouped by timestamp bucket.
+    pub bucket: Option<u64>
+}
+
+impl AggregateGroup {
+    /// Returns a new `AggregateGroup` for the given tag and timestamp bucket.
+    pub fn new(tag: Option<&str>, bucket: Option<u64>) -> AggregateGroup {
+        AggregateGroup {
+            tag: tag.map(|tag| tag.to_owned()),
+            bucket: bucket
+        }
+    }
+}
+
+/// Exar DB's aggregate.
+///
+/// It contains the number of events matching a query, their range of `id`s and timestamps,
+/// and the number of events in each group of an `Aggregation`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Aggregate {
+    /// The number of aggregated events.
+    pub count: u64,
+    /// The minimum `id` of the aggregated events, if any.
+    pub min_id: Option<u64>,
+    /// The maximum `id` of the aggregated events, if any.
+    pub max_id: Option<u64>,
+    /// The minimum timestamp (in ms) of the aggregated events, if any.
+    pub min_timestamp: Option<u64>,
+    /// The maximum timestamp (in ms) of the aggregated events, if any.
+    pub max_timestamp: Option<u64>,
+    /// The number of aggregated events in each group.
+    pub groups: BTreeMap<AggregateGroup, u64>
+}
+
+impl Aggregate {
+    /// Returns a new empty `Aggregate`.
+    pub fn new() -> Aggregate {
+        Aggregate::default()
+    }
+
+    /// Adds the given `Event` to the aggregate, grouping it according to the given aggregation.
+    pub fn add(&mut self, event: &Event, aggregation: &Aggregation) {
+        self.count += 1;
+        self.min_id = Some(self.min_id.map_or(event.id, |id| min(id, event.id)));
+        self.max_id = Some(self.max_id.map_or(event.id, |id| max(id, event.id)));
+        self.min_timestamp = Some(self.min_timestamp.map_or(event.timestamp, |timestamp| min(timestamp, event.timestamp)));
+        self.max_timestamp = Some(self.max_timestamp.map_or(event.timestamp, |timestamp| max(timestamp, event.timestamp)));
+        for group in aggregation.groups(event) {
+            *self.groups.entry(group).or_insert(0) += 1;
+        }
+    }
+
+    // each group is encoded as `count:bucket:tag`, the tag is last since it may contain colons
+    fn encoded_groups(&self) -> Vec<String> {
+        self.groups.iter().map(|(group, count)| {
+            let bucket = group.bucket.map_or(String::new(), |bucket| bucket.to_string());
+            format!("{}:{}:{}", count, bucket, group.tag.as_ref().map_or("", |tag| &tag[..]))
+        }).collect()
+    }
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut Formatter) -> DisplayResult {
+        write!(f, "Aggregate({}, {}, {}, {}, {}, [{}])", self.count, self.min_id.unwrap_or(0), self.max_id.unwrap_or(0),
+               self.min_timestamp.unwrap_or(0), self.max_timestamp.unwrap_or(0), self.encoded_groups().join(", "))
+    }
+}
+
+impl ToTabSeparatedString for Aggregate {
+    fn to_tab_separated_string(&self) -> String {
+        tab_separated!(self.count, self.min_id.unwrap_or(0), self.max_id.unwrap_or(0),
+                       self.min_timestamp.unwrap_or(0), self.max_timestamp.unwrap_or(0), self.encoded_groups().join(" "))
+    }
+}
+
+impl FromTabSeparatedStr for Aggregate {
+    fn from_tab_separated_str(s: &str) -> Result<Aggregate, ParseError> {
+        let mut parser = TabSeparatedParser::new(6, s);
+        let count = try!(parser.parse_next());
+        let min_id = try!(parser.parse_next());
+        let max_id = try!(parser.parse_next());
+        let min_timestamp = try!(parser.parse_next());
+        let max_timestamp = try!(parser.parse_next());
+        let encoded_groups: String = parser.parse_next().unwrap_or_default();
+        let mut groups = BTreeMap::new();
+        for encoded_group in encoded_groups.split(' ').filter(|encoded_group| !encoded_group.is_empty()) {
+            let parts: Vec<_> = encoded_group.splitn(3, ':').collect();
+            if parts.len() != 3 {
+                return Err(ParseError::ParseError(format!("invalid aggregate group: {}", encoded_group)))
+            }
+            let count = try!(parts[0].parse().map_err(|err| ParseError::ParseError(format!("{}", err))));
+            let bucket = match parts[1] {
+                "" => None,
+                bucket => Some(try!(bucket.parse().map_err(|err| ParseError::ParseError(format!("{}", err)))))
+            };
+            let tag = if parts[2].is_empty() { None } else { Some(parts[2]) };
+            groups.insert(AggregateGroup::new(tag, bucket), count);
+        }
+        let non_zero = |value: u64| if value > 0 { Some(value) } else { None };
+        Ok(Aggregate {
+            count: count,
+            min_id: non_zero(min_id),
+            max_id: non_zero(max_id),
+            min_timestamp: non_zero(min_timestamp),
+            max_timestamp: non_zero(max_timestamp),
+            groups: groups
+        })
+    }
+}
+
+/// Exar DB's aggregate stream.
+///
+/// It returns the aggregate of the events of a current query once they have all been aggregated,
+/// or the updated aggregates of a live query.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+/// use std::sync::Arc;
+/// use std::sync::mpsc::channel;
+///
+/// let (sender, receiver) = channel();
+/// let event_stream = EventStream::new(receiver);
+/// sender.send(EventStreamMessage::Event(Arc::new(Event::new("data", vec!["tag1"]).with_id(1)))).unwrap();
+/// sender.send(EventStreamMessage::End).unwrap();
+///
+/// let mut aggregate_stream = AggregateStream::aggregate(event_stream, Aggregation::new(), false);
+/// assert_eq!(aggregate_stream.next().map(|aggregate| aggregate.count), Some(1));
+/// assert_eq!(aggregate_stream.next(), None);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AggregateStream {
+    aggregate_stream_receiver: Receiver<Aggregate>,
+    cancellation_handle: CancellationHandle
+}
+
+impl AggregateStream {
+    /// Returns a new `AggregateStream` from the given `Receiver<Aggregate>`.
+    pub fn new(receiver: Receiver<Aggregate>) -> AggregateStream {
+        AggregateStream {
+            aggregate_stream_receiver: receiver,
+            cancellation_handle: CancellationHandle::new()
+        }
+    }
+
+    /// Returns a new `AggregateStream` aggregating the events of the given event stream in a separate thread.
+    ///
+    /// Only the final aggregate is returned, unless the aggregate is live, in which case it is also returned
+    /// periodically (according to the aggregation update interval) until the event stream ends.
+    /// An update is skipped while the previous one has not been received yet, so that a slow receiver
+    /// never accumulates outdated aggregates.
+    pub fn aggregate(event_stream: EventStream, aggregation: Aggregation, live: bool) -> AggregateStream {
+        let cancellation_handle = event_stream.cancellation_handle();
+        let (sender, receiver) = sync_channel(1);
+        thread::spawn(move || {
+            let update_interval = Duration::from_millis(max(aggregation.update_interval_in_ms, 1));
+            let mut last_update = Instant::now();
+            let mut aggregate = Aggregate::new();
+            loop {
+                let received_event = if live {
+                    let timeout = update_interval.checked_sub(last_update.elapsed()).unwrap_or(Duration::from_millis(0));
+                    event_stream.recv_timeout(timeout)
+                } else {
+                    event_stream.recv()
+                };
+                match received_event {
+                    Ok(event) => aggregate.add(&event, &aggregation),
+                    Err(EventStreamError::Empty) => (),
+                    Err(EventStreamError::Closed) => {
+                        let _ = sender.send(aggregate);
+                        return
+                    }
+                }
+                if live && last_update.elapsed() >= update_interval {
+                    if let Err(TrySendError::Disconnected(_)) = sender.try_send(aggregate.clone()) {
+                        return
+                    }
+                    last_update = Instant::now();
+                }
+            }
+        });
+        AggregateStream {
+            aggregate_stream_receiver: receiver,
+            cancellation_handle: cancellation_handle
+        }
+    }
+
+    /// Returns a clone of the `CancellationHandle` of the event stream being aggregated.
+    pub fn cancellation_handle(&self) -> CancellationHandle {
+        self.cancellation_handle.clone()
+    }
+
+    /// Cancels the event stream being aggregated, so that the aggregate stream ends with the final aggregate.
+    pub fn cancel(&self) {
+        self.cancellation_handle.cancel()
+    }
+
+    /// Attempts to wait for an aggregate on this aggregate stream,
+    /// returning an `EventStreamError` if the corresponding channel has hung up.
+    pub fn recv(&self) -> Result<Aggregate, EventStreamError> {
+        self.aggregate_stream_receiver.recv().map_err(|_| EventStreamError::Closed)
+    }
+}
+
+impl Iterator for AggregateStream {
+    type Item = Aggregate;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    use std::sync::Arc;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::Duration;
+
+    fn event(id: u64, timestamp: u64, tags: Vec<&str>) -> Event {
+        Event::new("data", tags).with_id(id).with_timestamp(timestamp)
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let events = vec![
+            event(3, 3600000, vec!["order-placed"]),
+            event(4, 1000, vec!["order-placed", "eu"]),
+            event(5, 7200500, vec!["refund"])
+        ];
+
+        let mut aggregate = Aggregate::new();
+        for event in &events {
+            aggregate.add(event, &Aggregation::new());
+        }
+        assert_eq!(aggregate.count, 3);
+        assert_eq!((aggregate.min_id, aggregate.max_id), (Some(3), Some(5)));
+        assert_eq!((aggregate.min_timestamp, aggregate.max_timestamp), (Some(1000), Some(7200500)));
+        assert!(aggregate.groups.is_empty());
+
+        let aggregation = Aggregation::new().group_by_tag();
+        let mut aggregate = Aggregate::new();
+        for event in &events {
+            aggregate.add(event, &aggregation);
+        }
+        assert_eq!(aggregate.groups.into_iter().collect::<Vec<_>>(), vec![
+            (AggregateGroup::new(Some("eu"), None), 1),
+            (AggregateGroup::new(Some("order-placed"), None), 2),
+            (AggregateGroup::new(Some("refund"), None), 1)
+        ]);
+
+        let aggregation = Aggregation::new().bucket_size(3600000);
+        let mut aggregate = Aggregate::new();
+        for event in &events {
+            aggregate.add(event, &aggregation);
+        }
+        assert_eq!(aggregate.groups.into_iter().collect::<Vec<_>>(), vec![
+            (AggregateGroup::new(None, Some(0)), 1),
+            (AggregateGroup::new(None, Some(3600000)), 1),
+            (AggregateGroup::new(None, Some(7200000)), 1)
+        ]);
+
+        let aggregation = Aggregation::new().group_by_tag().bucket_size(3600000);
+        let mut aggregate = Aggregate::new();
+        for event in &events {
+            aggregate.add(event, &aggregation);
+        }
+        assert_eq!(aggregate.groups.into_iter().collect::<Vec<_>>(), vec![
+            (AggregateGroup::new(Some("eu"), Some(0)), 1),
+            (AggregateGroup::new(Some("order-placed"), Some(0)), 1),
+            (AggregateGroup::new(Some("order-placed"), Some(3600000)), 1),
+            (AggregateGroup::new(Some("refund"), Some(7200000)), 1)
+        ]);
+    }
+
+    #[test]
+    fn test_aggregate_tab_separated_encoding() {
+        let aggregation = Aggregation::new().group_by_tag().bucket_size(1000);
+        let mut aggregate = Aggregate::new();
+        aggregate.add(&event(1, 1500, vec!["tag:1", "tag2"]), &aggregation);
+        aggregate.add(&event(2, 2500, vec!["tag2"]), &aggregation);
+
+        assert_encoded_eq!(aggregate, "2\t1\t2\t1500\t2500\t1:1000:tag2 1:2000:tag2 1:1000:tag:1");
+        assert_decoded_eq!("2\t1\t2\t1500\t2500\t1:1000:tag2 1:2000:tag2 1:1000:tag:1", aggregate);
+
+        let mut aggregate = Aggregate::new();
+        assert_encoded_eq!(aggregate, "0\t0\t0\t0\t0\t");
+        assert_decoded_eq!("0\t0\t0\t0\t0", aggregate.clone());
+
+        aggregate.add(&event(1, 1500, vec!["tag1"]), &Aggregation::new().bucket_size(1000));
+        assert_encoded_eq!(aggregate, "1\t1\t1\t1500\t1500\t1:1000:");
+        assert_decoded_eq!("1\t1\t1\t1500\t1500\t1:1000:", aggregate);
+
+        assert!(Aggregate::from_tab_separated_str("1\t1\t1\t1500\t1500\t1:1000").is_err());
+        assert!(Aggregate::from_tab_separated_str("1\t1\t1\t1500\t1500\ta:1000:tag1").is_err());
+    }
+
+    #[test]
+    fn test_aggregate_stream() {
+        let (sender, receiver) = channel();
+        let aggregate_stream = AggregateStream::aggregate(EventStream::new(receiver), Aggregation::new().group_by_tag(), false);
+
+        assert!(sender.send(EventStreamMessage::Event(Arc::new(event(1, 1000, vec!["tag1"])))).is_ok());
+        assert!(sender.send(EventStreamMessage::Event(Arc::new(event(2, 2000, vec!["tag2"])))).is_ok());
+        assert!(sender.send(EventStreamMessage::End).is_ok());
+
+        let aggregates: Vec<_> = aggregate_stream.collect();
+        assert_eq!(aggregates.len(), 1);
+        assert_eq!(aggregates[0].count, 2);
+        assert_eq!(aggregates[0].groups.len(), 2);
+    }
+
+    #[test]
+    fn test_live_aggregate_stream() {
+        let (sender, receiver) = channel();
+        let aggregation = Aggregation::new().update_interval(10);
+        let mut aggregate_stream = AggregateStream::aggregate(EventStream::new(receiver), aggregation, true);
+
+        assert_eq!(aggregate_stream.next().map(|aggregate| aggregate.count), Some(0));
+
+        assert!(sender.send(EventStreamMessage::Event(Arc::new(event(1, 1000, vec!["tag1"])))).is_ok());
+        thread::sleep(Duration::from_millis(50));
+
+        let aggregate = aggregate_stream.find(|aggregate| aggregate.count == 1);
+        assert_eq!(aggregate.and_then(|aggregate| aggregate.max_id), Some(1));
+
+        drop(sender);
+
+        assert_eq!(aggregate_stream.last().map(|aggregate| aggregate.count), Some(1));
+    }
+
+    #[test]
+    fn test_live_aggregate_stream_with_slow_receiver() {
+        let (sender, receiver) = channel();
+        let aggregation = Aggregation::new().update_interval(0);
+        assert_eq!(aggregation.update_interval_in_ms, 1);
+
+        let aggregate_stream = AggregateStream::aggregate(EventStream::new(receiver), aggregation, true);
+
+        assert!(sender.send(EventStreamMessage::Event(Arc::new(event(1, 1000, vec!["tag1"])))).is_ok());
+        thread::sleep(Duration::from_millis(50));
+        drop(sender);
+
+        let aggregates: Vec<_> = aggregate_stream.collect();
+        assert_eq!(aggregates.len(), 2);
+        assert_eq!(aggregates[1].count, 1);
+    }
+}
@@ -0,0 +1,154 @@
+use super::*;
+
+use std::collections::BTreeMap;
+
+/// Exar DB's durable subscription checkpoints.
+///
+/// It maps the name of each durable consumer to the `id` of the last event it has acknowledged,
+/// a durable subscription resumes right after the checkpoint of its consumer.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let mut checkpoints = Checkpoints::new();
+///
+/// assert_eq!(checkpoints.acknowledge("consumer", 10), Ok(true));
+/// assert_eq!(checkpoints.acknowledge("consumer", 5), Ok(false));
+/// assert_eq!(checkpoints.get("consumer"), Some(10));
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Checkpoints {
+    checkpoints: BTreeMap<String, u64>
+}
+
+impl Checkpoints {
+    /// Returns new empty `Checkpoints`.
+    pub fn new() -> Checkpoints {
+        Checkpoints {
+            checkpoints: BTreeMap::new()
+        }
+    }
+
+    /// Returns the `id` of the last event acknowledged by the given consumer, if any.
+    pub fn get(&self, consumer_name: &str) -> Option<u64> {
+        self.checkpoints.get(consumer_name).cloned()
+    }
+
+    /// Moves the checkpoint of the given consumer forward to the given event `id` and returns whether it has moved,
+    /// or a `ValidationError` if the consumer name is invalid.
+    ///
+    /// Acknowledging an event preceding the current checkpoint leaves it unchanged.
+    pub fn acknowledge(&mut self, consumer_name: &str, event_id: u64) -> Result<bool, ValidationError> {
+        validate_consumer_name(consumer_name).and_then(|_| {
+            if self.get(consumer_name).map_or(true, |checkpoint| event_id > checkpoint) {
+                self.checkpoints.insert(consumer_name.to_owned(), event_id);
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        })
+    }
+
+    /// Sets the checkpoint of the given consumer to the given event `id`, even if it precedes the current one,
+    /// or returns a `ValidationError` if the consumer name is invalid.
+    pub fn reset(&mut self, consumer_name: &str, event_id: u64) -> Result<(), ValidationError> {
+        validate_consumer_name(consumer_name).and_then(|_| {
+            self.checkpoints.insert(consumer_name.to_owned(), event_id);
+            Ok(())
+        })
+    }
+
+    /// Removes the checkpoint of the given consumer and returns whether it existed.
+    pub fn remove(&mut self, consumer_name: &str) -> bool {
+        self.checkpoints.remove(consumer_name).is_some()
+    }
+
+    /// Returns a reference to the inner map of checkpoints.
+    pub fn get_ref(&self) -> &BTreeMap<String, u64> {
+        &self.checkpoints
+    }
+}
+
+impl ToTabSeparatedString for Checkpoints {
+    fn to_tab_separated_string(&self) -> String {
+        let encoded_checkpoints: Vec<_> = self.checkpoints.iter().map(|(consumer_name, event_id)| {
+            format!("{}:{}", event_id, consumer_name)
+        }).collect();
+        encoded_checkpoints.join(" ")
+    }
+}
+
+impl FromTabSeparatedStr for Checkpoints {
+    fn from_tab_separated_str(s: &str) -> Result<Checkpoints, ParseError> {
+        let mut checkpoints = Checkpoints::new();
+        for encoded_checkpoint in s.split(' ').filter(|encoded_checkpoint| !encoded_checkpoint.is_empty()) {
+            let parts: Vec<_> = encoded_checkpoint.splitn(2, ':').collect();
+            if parts.len() != 2 {
+                return Err(ParseError::ParseError(format!("invalid checkpoint: {}", encoded_checkpoint)))
+            }
+            let event_id = try!(parts[0].parse().map_err(|err| ParseError::ParseError(format!("{}", err))));
+            try!(checkpoints.reset(parts[1], event_id).map_err(|err| ParseError::ParseError(format!("{}", err))));
+        }
+        Ok(checkpoints)
+    }
+}
+
+/// Validates the name of a durable consumer, which must not be empty nor contain whitespace,
+/// or returns a `ValidationError` if validation fails.
+pub fn validate_consumer_name(consumer_name: &str) -> Result<(), ValidationError> {
+    if consumer_name.is_empty() {
+        Err(ValidationError::new("consumer name must not be empty"))
+    } else if consumer_name.chars().any(|c| c.is_whitespace()) {
+        Err(ValidationError::new("consumer name must not contain whitespace"))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn test_checkpoints() {
+        let mut checkpoints = Checkpoints::new();
+
+        assert_eq!(checkpoints.get("consumer1"), None);
+        assert_eq!(checkpoints.acknowledge("consumer1", 10), Ok(true));
+        assert_eq!(checkpoints.acknowledge("consumer1", 10), Ok(false));
+        assert_eq!(checkpoints.acknowledge("consumer1", 5), Ok(false));
+        assert_eq!(checkpoints.get("consumer1"), Some(10));
+
+        assert_eq!(checkpoints.acknowledge("consumer2", 3), Ok(true));
+        assert_eq!(checkpoints.reset("consumer1", 2), Ok(()));
+        assert_eq!(checkpoints.get("consumer1"), Some(2));
+
+        assert!(checkpoints.remove("consumer2"));
+        assert!(!checkpoints.remove("consumer2"));
+        assert_eq!(checkpoints.get_ref().keys().collect::<Vec<_>>(), vec!["consumer1"]);
+
+        assert_eq!(checkpoints.acknowledge("", 1), Err(ValidationError::new("consumer name must not be empty")));
+        assert_eq!(checkpoints.reset("con sumer", 1), Err(ValidationError::new("consumer name must not contain whitespace")));
+    }
+
+    #[test]
+    fn test_tab_separated_encoding() {
+        let mut checkpoints = Checkpoints::new();
+        assert_eq!(checkpoints.to_tab_separated_string(), "");
+        assert_eq!(Checkpoints::from_tab_separated_str(""), Ok(checkpoints.clone()));
+
+        assert!(checkpoints.reset("consumer1", 10).is_ok());
+        assert!(checkpoints.reset("consumer:2", 3).is_ok());
+        assert_eq!(checkpoints.to_tab_separated_string(), "10:consumer1 3:consumer:2");
+        assert_eq!(Checkpoints::from_tab_separated_str("10:consumer1 3:consumer:2"), Ok(checkpoints));
+
+        assert!(Checkpoints::from_tab_separated_str("consumer1").is_err());
+        assert!(Checkpoints::from_tab_separated_str("ten:consumer1").is_err());
+        assert!(Checkpoints::from_tab_separated_str("10:").is_err());
+    }
+}
@@ -1,15 +1,30 @@
 use super::*;
 
-use indexed_line_reader::LinesIndex;
 use rand;
 use rand::Rng;
-use std::sync::mpsc::channel;
+use std::cmp::{max, min, Reverse};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::sync::mpsc::{channel, sync_channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use time;
 
-/// Exar DB's collection of events, containing the reference to the log and index files.
+/// Exar DB's collection of events, containing the reference to the log segments, their index files
+/// and the log tag, stream and timestamp indexes.
 ///
 /// It is responsible of creating and managing the log scanner threads and the single-threaded logger.
 /// It allows publishing and subscribing to the underling events log.
 ///
+/// The events carrying a client `id` are deduplicated within the configured window, so that publishing
+/// an event again with the same client `id` returns the `id` of the original event instead.
+///
+/// Scanners are started when a subscription is made while all of them are busy, up to the configured maximum,
+/// and the scanners idle for longer than the idle timeout are stopped by a background thread,
+/// down to the configured minimum.
+///
 /// # Examples
 /// ```no_run
 /// extern crate exar;
@@ -24,30 +39,108 @@ use std::sync::mpsc::channel;
 /// ```
 #[derive(Debug)]
 pub struct Collection {
-    index: LinesIndex,
+    index: LogIndex,
+    segment_first_id: u64,
+    tag_index: Arc<RwLock<TagIndex>>,
+    tag_index_writer: BufWriter<File>,
+    stream_index: Arc<RwLock<StreamIndex>>,
+    stream_index_writer: BufWriter<File>,
+    timestamp_index: Arc<RwLock<TimestampIndex>>,
+    tail_cache: Arc<RwLock<TailCache>>,
+    deduplication_window: DeduplicationWindow,
+    checkpoints: Checkpoints,
+    consumer_groups: BTreeMap<String, ConsumerGroup>,
+    consumer_groups_config: ConsumerGroupsConfig,
     log: Log,
-    scanners: Vec<Scanner>,
-    tail_scanners: Vec<Scanner>,
+    scanner_pool: Arc<Mutex<ScannerPool>>,
+    scanner_senders: Arc<Mutex<Vec<Sender<ScannerAction>>>>,
+    scanners_config: ScannersConfig,
+    idle_scanners_stop_sender: Option<Sender<()>>,
     routing_strategy: RoutingStrategy,
-    logger: Logger
+    event_stream_buffer_size: usize,
+    logger: Logger,
+    retention_task: Option<RetentionTask>,
+    compaction_task: Option<CompactionTask>
 }
 
 impl Collection {
     /// Creates a new instance of a collection with the given name and configuration
     /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// The collection log is recovered from a potential unclean shutdown before being used.
     pub fn new(collection_name: &str, config: &CollectionConfig) -> Result<Collection, DatabaseError> {
-        let log = Log::new(&config.logs_path, collection_name, config.index_granularity);
-        log.restore_index().and_then(|index| {
-            Logger::new(log.clone()).and_then(|logger| {
-                let (scanners, tail_scanners) = try!(Collection::run_scanners(&log, &index, &config));
-                Ok(Collection {
+        let log = Log::new(&config.logs_path, collection_name, config.index_granularity)
+                      .with_segment_size(config.segment_size.clone());
+        let recovery_report = match log.recover() {
+            Ok(recovery_report) => recovery_report,
+            Err(err) => {
+                error!("Unable to recover collection '{}': {}", collection_name, err);
+                return Err(err)
+            }
+        };
+        if recovery_report.is_repaired() {
+            warn!("Recovered collection '{}': {}", collection_name, recovery_report);
+        }
+        log.restore_indexes().and_then(|indexes| {
+            Logger::with_durability(log.clone(), config.durability.clone()).and_then(|logger| {
+                let index = match indexes.get(&logger.segment_first_id()) {
+                    Some(index) => index.clone(),
+                    None => LogIndex::new(config.index_granularity)
+                };
+                let tag_index = Arc::new(RwLock::new(try!(log.restore_tag_index(logger.last_event_id()))));
+                let tag_index_writer = try!(log.open_tag_index_writer());
+                let stream_index = Arc::new(RwLock::new(try!(log.restore_stream_index(logger.last_event_id()))));
+                let stream_index_writer = try!(log.open_stream_index_writer());
+                let timestamp_index = Arc::new(RwLock::new(try!(log.restore_timestamp_index(logger.last_event_id()))));
+                let tail_cache = Arc::new(RwLock::new(TailCache::new(config.tail_cache_size, logger.last_event_id())));
+                let deduplication_window = try!(log.restore_deduplication_window(logger.last_event_id(), &config.deduplication,
+                                                                                 get_current_timestamp_in_ms()));
+                let checkpoints = try!(log.restore_checkpoints());
+                let scanner_senders = Arc::new(Mutex::new(vec![]));
+                let retention_task = if config.retention.is_enabled() {
+                    Some(RetentionTask::with_tag_index(log.clone(), config.retention.clone(), logger.last_event_id(), tag_index.clone()))
+                } else {
+                    None
+                };
+                let compaction_task = match config.compaction.key_tag_prefix {
+                    Some(ref key_tag_prefix) => {
+                        let compactor = Compactor::with_shared_scanner_senders(log.clone(), key_tag_prefix, scanner_senders.clone())
+                                                  .with_tag_index(tag_index.clone());
+                        Some(CompactionTask::new(compactor, config.compaction.interval()))
+                    },
+                    None => None
+                };
+                let mut collection = Collection {
                     index: index,
+                    segment_first_id: logger.segment_first_id(),
+                    tag_index: tag_index,
+                    tag_index_writer: tag_index_writer,
+                    stream_index: stream_index,
+                    stream_index_writer: stream_index_writer,
+                    timestamp_index: timestamp_index,
+                    tail_cache: tail_cache,
+                    deduplication_window: deduplication_window,
+                    checkpoints: checkpoints,
+                    consumer_groups: BTreeMap::new(),
+                    consumer_groups_config: config.consumer_groups.clone(),
                     log: log,
-                    scanners: scanners,
-                    tail_scanners: tail_scanners,
+                    scanner_pool: Arc::new(Mutex::new(ScannerPool::default())),
+                    scanner_senders: scanner_senders,
+                    scanners_config: config.scanners.clone(),
+                    idle_scanners_stop_sender: None,
                     routing_strategy: config.routing_strategy.clone(),
-                    logger: logger
-                })
+                    event_stream_buffer_size: config.event_stream_buffer_size,
+                    logger: logger,
+                    retention_task: retention_task,
+                    compaction_task: compaction_task
+                };
+                for _ in 0..min(config.scanners.min_nr_of_scanners, config.scanners.max_nr_of_scanners) {
+                    try!(collection.start_scanner());
+                }
+                if config.scanners.max_nr_of_scanners > config.scanners.min_nr_of_scanners {
+                    collection.idle_scanners_stop_sender = Some(collection.stop_idle_scanners_periodically());
+                }
+                Ok(collection)
             })
         })
     }
@@ -55,82 +148,535 @@ impl Collection {
     /// Publishes an event into the collection and returns the `id` for the event created
     /// or a `DatabaseError` if a failure occurs.
     pub fn publish(&mut self, event: Event) -> Result<u64, DatabaseError> {
-        self.logger.log(event).and_then(|event_id| {
-            if (event_id + 1) % (self.log.get_index_granularity()) == 0 {
-                self.index.insert(event_id + 1, self.logger.bytes_written());
-                try!(self.log.persist_index(&self.index));
-                for scanner in &self.scanners {
-                    try!(scanner.add_line_index(event_id + 1, self.logger.bytes_written()))
-                }
-            }
-            Ok(event_id)
+        self.publish_batch(vec![event]).map(|(event_id, _)| event_id)
+    }
+
+    /// Publishes a batch of events into the collection atomically and returns the `id`s
+    /// of the first and last events created, or a `DatabaseError` if a failure occurs.
+    ///
+    /// Either all the events are published with contiguous `id`s, or none of them is,
+    /// and the scanners are woken up once they are so that live subscriptions receive them straight away.
+    ///
+    /// The events belonging to a stream are given the versions following the current version of their stream.
+    ///
+    /// If the events carrying a client `id` have already been published, the `id`s of the original events
+    /// are returned instead, a batch must not mix events already published with new ones.
+    pub fn publish_batch(&mut self, events: Vec<Event>) -> Result<(u64, u64), DatabaseError> {
+        if let Some(event_ids) = try!(self.find_published_batch(&events)) {
+            return Ok(event_ids)
+        }
+        let events = self.assign_stream_versions(events);
+        let tags = events.iter().map(|event| event.tags.clone()).collect();
+        let streams = events.iter().map(|event| (event.stream_id.clone(), event.stream_version)).collect();
+        let timestamps = events.iter().map(|event| event.timestamp).collect();
+        self.logger.log_batch(events.clone()).and_then(|(first_event_id, last_event_id)| {
+            self.update_index(last_event_id).and_then(|_| {
+                self.update_tag_index(first_event_id, tags).and_then(|_| {
+                    self.update_stream_index(first_event_id, streams).and_then(|_| {
+                        self.update_timestamp_index(first_event_id, timestamps).and_then(|_| {
+                            self.update_deduplication_window(first_event_id, &events);
+                            self.update_tail_cache(first_event_id, events);
+                            self.wake_scanners().and_then(|_| Ok((first_event_id, last_event_id)))
+                        })
+                    })
+                })
+            })
         })
     }
 
+    /// Publishes an event into the collection only if the `id` of the last event published is the expected one,
+    /// and returns the `id` for the event created or a `DatabaseError` if a failure occurs.
+    pub fn publish_expecting(&mut self, event: Event, expected_last_id: u64) -> Result<u64, DatabaseError> {
+        self.publish_batch_expecting(vec![event], expected_last_id).map(|(event_id, _)| event_id)
+    }
+
+    /// Publishes a batch of events into the collection atomically only if the `id` of the last event published
+    /// is the expected one, and returns the `id`s of the first and last events created, or a `DatabaseError` if a failure occurs.
+    ///
+    /// It fails with a `DatabaseError::WrongExpectedVersion` if any other event has been published in the meantime,
+    /// unless the events have already been published, in which case the `id`s of the original events are returned.
+    pub fn publish_batch_expecting(&mut self, events: Vec<Event>, expected_last_id: u64) -> Result<(u64, u64), DatabaseError> {
+        if let Some(event_ids) = try!(self.find_published_batch(&events)) {
+            return Ok(event_ids)
+        }
+        let last_event_id = self.logger.last_event_id();
+        if last_event_id != expected_last_id {
+            return Err(DatabaseError::WrongExpectedVersion { expected: expected_last_id, actual: last_event_id })
+        }
+        self.publish_batch(events)
+    }
+
+    /// Returns the version of the last event published into the given stream, or 0 if the stream does not exist.
+    pub fn stream_version(&self, stream_id: &str) -> u64 {
+        self.stream_index.read().unwrap_or_else(|poisoned| poisoned.into_inner()).version(stream_id)
+    }
+
+    /// Publishes an event into its stream only if the current version of the stream is the expected one,
+    /// and returns the `id` for the event created or a `DatabaseError` if a failure occurs.
+    pub fn publish_expecting_stream_version(&mut self, event: Event, expected_version: u64) -> Result<u64, DatabaseError> {
+        self.publish_batch_expecting_stream_version(vec![event], expected_version).map(|(event_id, _)| event_id)
+    }
+
+    /// Publishes a batch of events into their stream atomically only if the current version of the stream
+    /// is the expected one, and returns the `id`s of the first and last events created, or a `DatabaseError` if a failure occurs.
+    ///
+    /// All the events must belong to the same stream, and it fails with a `DatabaseError::WrongExpectedVersion`
+    /// if any other event has been published into the stream in the meantime, unless the events have already been published,
+    /// in which case the `id`s of the original events are returned.
+    pub fn publish_batch_expecting_stream_version(&mut self, events: Vec<Event>, expected_version: u64) -> Result<(u64, u64), DatabaseError> {
+        if let Some(event_ids) = try!(self.find_published_batch(&events)) {
+            return Ok(event_ids)
+        }
+        let stream_id = match events.first().and_then(|event| event.stream_id.clone()) {
+            Some(stream_id) => stream_id,
+            None => return Err(DatabaseError::ValidationError(ValidationError::new("events must belong to a stream")))
+        };
+        if events.iter().any(|event| event.stream_id.as_ref() != Some(&stream_id)) {
+            return Err(DatabaseError::ValidationError(ValidationError::new("events must belong to the same stream")))
+        }
+        let version = self.stream_version(&stream_id);
+        if version != expected_version {
+            return Err(DatabaseError::WrongExpectedVersion { expected: expected_version, actual: version })
+        }
+        self.publish_batch(events)
+    }
+
     /// Subscribes to the collection of events using the given query and returns an event stream
     /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// A query without offset starts from the earliest event retained, while a query with an offset
+    /// preceding the earliest event retained fails with a `DatabaseError::RetentionError`.
+    ///
+    /// The event stream buffers up to the configured number of events, the subscription is paused while it is full,
+    /// and it can be cancelled to remove the subscription from its scanner.
     pub fn subscribe(&mut self, query: Query) -> Result<EventStream, DatabaseError> {
-        let (sender, receiver) = channel();
-        self.apply_routing_strategy(Subscription::new(sender, query)).and_then(|updated_strategy| {
+        if query.offset > 0 {
+            if let Some(segment) = try!(self.log.segments()).first() {
+                if query.offset + 1 < segment.first_id() {
+                    return Err(DatabaseError::RetentionError(segment.first_id()))
+                }
+            }
+        }
+        let (sender, receiver) = sync_channel(self.event_stream_buffer_size);
+        let subscription = Subscription::new(sender, query);
+        let cancellation_handle = subscription.cancellation_handle();
+        try!(self.scale_scanners());
+        self.apply_routing_strategy(subscription).and_then(|updated_strategy| {
             self.routing_strategy = updated_strategy;
-            Ok(EventStream::new(receiver))
+            Ok(EventStream::new(receiver).with_cancellation_handle(cancellation_handle))
+        })
+    }
+
+    /// Aggregates the events targeted by the given query according to the given aggregation
+    /// and returns an aggregate stream or a `DatabaseError` if a failure occurs.
+    pub fn aggregate(&mut self, query: Query, aggregation: Aggregation) -> Result<AggregateStream, DatabaseError> {
+        let live_stream = query.live_stream;
+        self.subscribe(query).and_then(|event_stream| {
+            Ok(AggregateStream::aggregate(event_stream, aggregation, live_stream))
         })
     }
 
-    /// Drops the collection, kills the scanner threads and remove the log and index files.
+    /// Subscribes to the collection of events on behalf of the given durable consumer using the given query
+    /// and returns an event stream or a `DatabaseError` if a failure occurs.
+    ///
+    /// The event stream resumes right after the last event acknowledged by the consumer,
+    /// unless the query offset is further ahead. Durable subscriptions cannot be reversed.
+    pub fn subscribe_durable(&mut self, consumer_name: &str, query: Query) -> Result<EventStream, DatabaseError> {
+        try!(validate_consumer_name(consumer_name).map_err(DatabaseError::ValidationError));
+        if query.reverse {
+            return Err(DatabaseError::ValidationError(ValidationError::new("durable subscriptions cannot be reversed")))
+        }
+        let query = match self.checkpoints.get(consumer_name) {
+            Some(checkpoint) if checkpoint > query.offset => query.offset(checkpoint),
+            _ => query
+        };
+        self.subscribe(query)
+    }
+
+    /// Acknowledges the processing of the events up to the given `id` on behalf of the given durable consumer,
+    /// persisting its checkpoint, or returns a `DatabaseError` if a failure occurs.
+    ///
+    /// Acknowledging an event preceding the current checkpoint of the consumer has no effect.
+    pub fn acknowledge(&mut self, consumer_name: &str, event_id: u64) -> Result<(), DatabaseError> {
+        try!(self.validate_checkpoint(event_id));
+        match self.checkpoints.acknowledge(consumer_name, event_id) {
+            Ok(true) => self.log.persist_checkpoints(&self.checkpoints),
+            Ok(false) => Ok(()),
+            Err(err) => Err(DatabaseError::ValidationError(err))
+        }
+    }
+
+    /// Returns the checkpoints of the durable consumers of the collection.
+    pub fn checkpoints(&self) -> &Checkpoints {
+        &self.checkpoints
+    }
+
+    /// Resets the checkpoint of the given durable consumer to the given event `id`, persisting it,
+    /// or returns a `DatabaseError` if a failure occurs.
+    pub fn reset_checkpoint(&mut self, consumer_name: &str, event_id: u64) -> Result<(), DatabaseError> {
+        try!(self.validate_checkpoint(event_id));
+        try!(self.checkpoints.reset(consumer_name, event_id).map_err(DatabaseError::ValidationError));
+        self.log.persist_checkpoints(&self.checkpoints)
+    }
+
+    /// Deletes the checkpoint of the given durable consumer, if any,
+    /// or returns a `DatabaseError` if a failure occurs.
+    pub fn delete_checkpoint(&mut self, consumer_name: &str) -> Result<(), DatabaseError> {
+        if self.checkpoints.remove(consumer_name) {
+            self.log.persist_checkpoints(&self.checkpoints)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Joins the consumer group with the given name and returns the new member, along with the event stream
+    /// of the events handed to it, or a `DatabaseError` if a failure occurs.
+    ///
+    /// If the consumer group is not running, it is created using the given query and dead-letter connection,
+    /// otherwise they are ignored. The query of a consumer group cannot be reversed.
+    pub fn join_consumer_group(&mut self, group_name: &str, query: Query,
+                               dead_letter_connection: Connection) -> Result<(ConsumerGroupMember, EventStream), DatabaseError> {
+        try!(validate_consumer_name(group_name).map_err(DatabaseError::ValidationError));
+        if let Some(consumer_group) = self.consumer_groups.get(group_name) {
+            if consumer_group.is_running() {
+                return Ok(consumer_group.join())
+            }
+        }
+        if query.reverse {
+            return Err(DatabaseError::ValidationError(ValidationError::new("consumer groups cannot be reversed")))
+        }
+        self.subscribe(query).and_then(|event_stream| {
+            let consumer_group = ConsumerGroup::new(group_name, event_stream, dead_letter_connection,
+                                                    self.consumer_groups_config.clone(), self.event_stream_buffer_size);
+            self.consumer_groups.insert(group_name.to_owned(), consumer_group.clone());
+            Ok(consumer_group.join())
+        })
+    }
+
+    /// Returns the description of the consumer group with the given name, including its members and lag,
+    /// or a `DatabaseError` if the consumer group does not exist.
+    pub fn consumer_group_info(&self, group_name: &str) -> Result<ConsumerGroupInfo, DatabaseError> {
+        match self.consumer_groups.get(group_name) {
+            Some(consumer_group) => Ok(consumer_group.info(self.logger.last_event_id())),
+            None => Err(DatabaseError::ValidationError(ValidationError::new("consumer group not found")))
+        }
+    }
+
+    /// Drops the collection, kills the scanner, retention and compaction threads
+    /// and remove the log segments and their index files.
     pub fn drop(&mut self) -> Result<(), DatabaseError> {
-        self.scanners.truncate(0);
-        self.tail_scanners.truncate(0);
+        self.retention_task = None;
+        self.compaction_task = None;
+        self.consumer_groups.clear();
+        self.idle_scanners_stop_sender = None;
+        self.lock_scanner_pool().clear();
+        self.scanner_senders.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clear();
         self.log.remove()
     }
 
-    fn run_scanners(log: &Log, index: &LinesIndex, config: &CollectionConfig) -> Result<(Vec<Scanner>, Vec<Scanner>), DatabaseError> {
-        let mut scanners = vec![];
-        let mut tail_scanners = vec![];
-        for _ in 0..config.scanners.nr_of_scanners {
-            let line_reader = try!(log.open_line_reader_with_index(index.clone()));
-            let mut scanner = Scanner::new(line_reader, config.scanners_sleep_duration());
+    fn update_index(&mut self, last_event_id: u64) -> Result<(), DatabaseError> {
+        if let Some(ref retention_task) = self.retention_task {
+            retention_task.set_last_event_id(last_event_id);
+        }
+        if self.logger.segment_first_id() != self.segment_first_id {
+            self.segment_first_id = self.logger.segment_first_id();
+            self.index = LogIndex::new(self.index.granularity());
+        }
+        if self.index.should_index(last_event_id) {
+            self.index.insert(last_event_id, self.logger.bytes_written());
+            try!(self.log.segment(self.segment_first_id).persist_index(&self.index));
+            for scanner in &self.lock_scanner_pool().scanners {
+                try!(scanner.add_index_entry(self.segment_first_id, last_event_id, self.logger.bytes_written()))
+            }
+        }
+        Ok(())
+    }
 
-            let line_reader = try!(log.open_line_reader_with_index(index.clone()));
-            let tail_scanner = Scanner::new(line_reader, config.scanners_sleep_duration());
-            try!(scanner.set_tail_scanner_sender(tail_scanner.clone_action_sender()));
+    // returns the ids of the original events if the batch has already been published
+    fn find_published_batch(&mut self, events: &[Event]) -> Result<Option<(u64, u64)>, DatabaseError> {
+        if events.iter().all(|event| event.client_id.is_none()) {
+            return Ok(None)
+        }
+        self.deduplication_window.remove_expired(self.logger.last_event_id(), get_current_timestamp_in_ms());
+        let mut client_ids = HashSet::new();
+        let mut event_ids = vec![];
+        for client_id in events.iter().filter_map(|event| event.client_id.as_ref()) {
+            if !client_ids.insert(client_id) {
+                return Err(DatabaseError::ValidationError(ValidationError::new("batch must not contain duplicate client ids")))
+            }
+            if let Some(event_id) = self.deduplication_window.get(client_id) {
+                event_ids.push(event_id);
+            }
+        }
+        if event_ids.is_empty() {
+            Ok(None)
+        } else if event_ids.len() == events.len() {
+            Ok(Some((event_ids[0], event_ids[event_ids.len() - 1])))
+        } else {
+            Err(DatabaseError::ValidationError(ValidationError::new("batch must not mix published and unpublished events")))
+        }
+    }
 
-            scanners.push(scanner);
-            tail_scanners.push(tail_scanner);
+    fn update_deduplication_window(&mut self, first_event_id: u64, events: &[Event]) {
+        let timestamp = get_current_timestamp_in_ms();
+        for (event_id, event) in (first_event_id..).zip(events) {
+            if let Some(ref client_id) = event.client_id {
+                self.deduplication_window.insert(event_id, client_id, timestamp);
+            }
         }
-        Ok((scanners, tail_scanners))
     }
 
-    fn apply_routing_strategy(&mut self, subscription: Subscription) -> Result<RoutingStrategy, DatabaseError> {
-        match self.routing_strategy {
-            RoutingStrategy::Random => match rand::thread_rng().choose(&self.scanners) {
-                Some(random_scanner) => {
-                    random_scanner.handle_subscription(subscription).and_then(|_| {
-                        Ok(RoutingStrategy::Random)
-                    })
+    fn assign_stream_versions(&self, events: Vec<Event>) -> Vec<Event> {
+        let stream_index = self.stream_index.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut versions = HashMap::new();
+        events.into_iter().map(|event| {
+            let event = if event.timestamp == 0 { event.with_current_timestamp() } else { event };
+            let version = match event.stream_id {
+                Some(ref stream_id) => {
+                    let version = versions.entry(stream_id.clone()).or_insert_with(|| stream_index.version(stream_id));
+                    *version += 1;
+                    *version
                 },
-                None => Err(DatabaseError::SubscriptionError)
-            },
-            RoutingStrategy::RoundRobin(ref last_index) => {
-                let new_index = if last_index + 1 < self.scanners.len() { last_index + 1 } else { 0 };
-                match self.scanners.get(new_index) {
-                    Some(scanner) => scanner.handle_subscription(subscription).and_then(|_| {
-                        Ok(RoutingStrategy::RoundRobin(new_index))
-                    }),
-                    None => Err(DatabaseError::SubscriptionError)
+                None => 0
+            };
+            event.with_stream_version(version)
+        }).collect()
+    }
+
+    fn update_tail_cache(&mut self, first_event_id: u64, events: Vec<Event>) {
+        let mut tail_cache = self.tail_cache.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        // completed segments may be compacted or expired, so only the events of the last segment are cached
+        tail_cache.discard_before(self.segment_first_id);
+        for (event_id, event) in (first_event_id..).zip(events) {
+            tail_cache.push(event.with_id(event_id));
+        }
+    }
+
+    fn wake_scanners(&self) -> Result<(), DatabaseError> {
+        let scanner_pool = self.lock_scanner_pool();
+        for scanner in scanner_pool.scanners.iter().chain(&scanner_pool.tail_scanners) {
+            try!(scanner.wake())
+        }
+        Ok(())
+    }
+
+    fn update_tag_index(&mut self, first_event_id: u64, events_tags: Vec<Vec<String>>) -> Result<(), DatabaseError> {
+        let mut records = vec![];
+        {
+            let mut tag_index = self.tag_index.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+            for (event_id, tags) in (first_event_id..).zip(events_tags) {
+                let entry = TagIndexEntry::new(event_id, tags);
+                records.extend_from_slice(&entry.to_log_record());
+                tag_index.insert(entry.id, &entry.tags);
+            }
+        }
+        match self.tag_index_writer.write_all(&records).and_then(|_| self.tag_index_writer.flush()) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    fn update_stream_index(&mut self, first_event_id: u64, streams: Vec<(Option<String>, u64)>) -> Result<(), DatabaseError> {
+        let mut records = vec![];
+        {
+            let mut stream_index = self.stream_index.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+            for (event_id, (stream_id, version)) in (first_event_id..).zip(streams) {
+                let entry = StreamIndexEntry::new(event_id, stream_id, version);
+                // only the events belonging to a stream are persisted, the others are read again from the log when restoring
+                if entry.stream_id.is_some() {
+                    records.extend_from_slice(&entry.to_log_record());
                 }
+                stream_index.insert(entry.id, entry.stream_id.as_ref().map(|stream_id| &stream_id[..]), entry.version);
             }
         }
+        match self.stream_index_writer.write_all(&records).and_then(|_| self.stream_index_writer.flush()) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    fn update_timestamp_index(&mut self, first_event_id: u64, timestamps: Vec<u64>) -> Result<(), DatabaseError> {
+        let mut timestamp_index = self.timestamp_index.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut is_block_completed = false;
+        for (event_id, timestamp) in (first_event_id..).zip(timestamps) {
+            is_block_completed |= timestamp_index.insert(event_id, timestamp);
+        }
+        if is_block_completed {
+            self.log.persist_timestamp_index(&timestamp_index)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn validate_checkpoint(&self, event_id: u64) -> Result<(), DatabaseError> {
+        if event_id > self.logger.last_event_id() {
+            Err(DatabaseError::ValidationError(ValidationError::new("checkpoint must not follow the last event published")))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn start_scanner(&mut self) -> Result<(), DatabaseError> {
+        // the scanner pool is locked before the scanner senders, like when stopping the idle scanners
+        let mut scanner_pool = self.lock_scanner_pool();
+        let scanner_senders = self.scanner_senders.clone();
+        // the log indexes are restored while holding the scanner senders, so that no segment is being compacted meanwhile
+        let mut scanner_senders = scanner_senders.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let indexes = try!(self.log.restore_indexes());
+        let log_reader = try!(self.log.open_log_reader_with_indexes(indexes.clone()));
+        let mut scanner = Scanner::with_indexes(log_reader, self.tag_index.clone(), self.stream_index.clone(),
+                                                self.timestamp_index.clone(), self.tail_cache.clone(),
+                                                self.scanners_config.sleep_duration());
+
+        let log_reader = try!(self.log.open_log_reader_with_indexes(indexes));
+        let tail_scanner = Scanner::with_indexes(log_reader, self.tag_index.clone(), self.stream_index.clone(),
+                                                 self.timestamp_index.clone(), self.tail_cache.clone(),
+                                                 self.scanners_config.sleep_duration());
+        try!(scanner.set_tail_scanner_sender(tail_scanner.clone_action_sender()));
+
+        scanner_senders.push(scanner.clone_action_sender());
+        scanner_senders.push(tail_scanner.clone_action_sender());
+        scanner_pool.scanners.push(scanner);
+        scanner_pool.tail_scanners.push(tail_scanner);
+        scanner_pool.idle_since.push(None);
+        Ok(())
+    }
+
+    // a scanner is started when all the running ones are busy with subscriptions catching up with the log
+    fn scale_scanners(&mut self) -> Result<(), DatabaseError> {
+        let max_nr_of_scanners = max(self.scanners_config.max_nr_of_scanners, 1) as usize;
+        let should_start_scanner = {
+            let scanner_pool = self.lock_scanner_pool();
+            scanner_pool.scanners.len() < max_nr_of_scanners &&
+            scanner_pool.scanners.iter().all(|scanner| scanner.load().nr_of_subscriptions > 0)
+        };
+        if should_start_scanner {
+            try!(self.start_scanner());
+        }
+        Ok(())
+    }
+
+    // the idle scanners are looked for at twice the rate of the idle timeout, until the returned sender is dropped
+    fn stop_idle_scanners_periodically(&self) -> Sender<()> {
+        let (stop_sender, stop_receiver) = channel::<()>();
+        let scanner_pool = self.scanner_pool.clone();
+        let scanner_senders = self.scanner_senders.clone();
+        let config = self.scanners_config.clone();
+        let check_interval = max(config.idle_timeout() / 2, Duration::from_millis(1));
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(check_interval) {
+                let mut scanner_pool = scanner_pool.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                scanner_pool.stop_idle_scanners(&config, &scanner_senders, Instant::now());
+            }
+        });
+        stop_sender
+    }
+
+    fn lock_scanner_pool<'a>(&'a self) -> MutexGuard<'a, ScannerPool> {
+        self.scanner_pool.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn apply_routing_strategy(&mut self, subscription: Subscription) -> Result<RoutingStrategy, DatabaseError> {
+        let scanner_pool = self.scanner_pool.clone();
+        let mut scanner_pool = scanner_pool.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let nr_of_scanners = scanner_pool.scanners.len();
+        let (index, updated_strategy) = match self.routing_strategy {
+            RoutingStrategy::Random => {
+                let index = if nr_of_scanners > 0 { Some(rand::thread_rng().gen_range(0, nr_of_scanners)) } else { None };
+                (index, RoutingStrategy::Random)
+            },
+            RoutingStrategy::RoundRobin(last_index) => {
+                let new_index = if last_index + 1 < nr_of_scanners { last_index + 1 } else { 0 };
+                (Some(new_index), RoutingStrategy::RoundRobin(new_index))
+            },
+            RoutingStrategy::LeastLoaded => {
+                let loads: Vec<_> = scanner_pool.scanners.iter().map(|scanner| scanner.load()).collect();
+                (least_loaded_scanner_index(&loads), RoutingStrategy::LeastLoaded)
+            },
+            RoutingStrategy::IntervalAffinity => {
+                let loads: Vec<_> = scanner_pool.scanners.iter().map(|scanner| scanner.load()).collect();
+                (interval_affinity_scanner_index(&loads, &subscription.query.interval()), RoutingStrategy::IntervalAffinity)
+            }
+        };
+        match index {
+            Some(index) if index < nr_of_scanners => {
+                try!(scanner_pool.scanners[index].handle_subscription(subscription));
+                // the scanner is no longer idle, even if its load does not reflect the subscription yet
+                scanner_pool.idle_since[index] = None;
+                Ok(updated_strategy)
+            },
+            _ => Err(DatabaseError::SubscriptionError)
+        }
     }
 }
 
+// the running scanners, shared with the thread stopping the idle ones
+#[derive(Debug, Default)]
+struct ScannerPool {
+    scanners: Vec<Scanner>,
+    tail_scanners: Vec<Scanner>,
+    idle_since: Vec<Option<Instant>>
+}
+
+impl ScannerPool {
+    fn stop_idle_scanners(&mut self, config: &ScannersConfig, scanner_senders: &Mutex<Vec<Sender<ScannerAction>>>, now: Instant) {
+        for (index, idle_since) in self.idle_since.iter_mut().enumerate() {
+            let is_idle = self.scanners[index].load().nr_of_subscriptions == 0 &&
+                          self.tail_scanners[index].load().nr_of_subscriptions == 0;
+            *idle_since = if is_idle { idle_since.or(Some(now)) } else { None };
+        }
+        let min_nr_of_scanners = config.min_nr_of_scanners as usize;
+        let idle_timeout = config.idle_timeout();
+        let nr_of_scanners = self.scanners.len();
+        for index in (0..nr_of_scanners).rev() {
+            if self.scanners.len() <= min_nr_of_scanners {
+                break
+            }
+            if self.idle_since[index].map_or(false, |idle_since| now.duration_since(idle_since) >= idle_timeout) {
+                self.scanners.remove(index);
+                self.tail_scanners.remove(index);
+                self.idle_since.remove(index);
+            }
+        }
+        if self.scanners.len() < nr_of_scanners {
+            let mut scanner_senders = scanner_senders.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            *scanner_senders = self.scanners.iter().chain(&self.tail_scanners).map(|scanner| scanner.clone_action_sender()).collect();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.scanners.truncate(0);
+        self.tail_scanners.truncate(0);
+        self.idle_since.truncate(0);
+    }
+}
+
+fn get_current_timestamp_in_ms() -> u64 {
+    let timespec = time::get_time();
+    timespec.sec as u64 * 1000 + timespec.nsec as u64 / 1000 / 1000
+}
+
+fn least_loaded_scanner_index(loads: &[ScannerLoad]) -> Option<usize> {
+    loads.iter().enumerate().min_by_key(|&(_, load)| load.nr_of_subscriptions).map(|(index, _)| index)
+}
+
+// scanners with the same overlap are picked by load, so that subscriptions overlapping none of them are spread evenly
+fn interval_affinity_scanner_index(loads: &[ScannerLoad], interval: &Interval<u64>) -> Option<usize> {
+    loads.iter().enumerate().min_by_key(|&(_, load)| {
+        (Reverse(load.overlap(interval)), load.nr_of_subscriptions)
+    }).map(|(index, _)| index)
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::*;
     use exar_testkit::*;
 
-    use indexed_line_reader::LinesIndex;
-    use std::sync::mpsc::channel;
+    use std::fs::{metadata, OpenOptions};
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::sync_channel;
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn test_constructor() {
@@ -138,10 +684,10 @@ mod tests {
         let config = CollectionConfig::default();
         let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
 
-        assert_eq!(collection.index, LinesIndex::new(100000));
+        assert_eq!(collection.index, LogIndex::new(100000));
         assert_eq!(collection.log, Log::new("", collection_name, 100000));
-        assert_eq!(collection.scanners.len(), 2);
-        assert_eq!(collection.tail_scanners.len(), 2);
+        assert_eq!(collection.scanner_pool.lock().unwrap().scanners.len(), 0);
+        assert_eq!(collection.scanner_pool.lock().unwrap().tail_scanners.len(), 0);
         assert_eq!(collection.routing_strategy, RoutingStrategy::default());
 
         assert!(collection.drop().is_ok());
@@ -170,6 +716,346 @@ mod tests {
         assert!(collection.drop().is_ok());
     }
 
+    #[test]
+    fn test_publish_expecting() {
+        let ref collection_name = random_collection_name();
+        let config = CollectionConfig::default();
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        assert_eq!(collection.publish_expecting(Event::new("data", vec!["tag1"]), 0), Ok(1));
+        assert_eq!(collection.publish_expecting(Event::new("data", vec!["tag1"]), 0),
+                   Err(DatabaseError::WrongExpectedVersion { expected: 0, actual: 1 }));
+
+        let test_events = vec![Event::new("data", vec!["tag1"]); 2];
+        assert_eq!(collection.publish_batch_expecting(test_events.clone(), 1), Ok((2, 3)));
+        assert_eq!(collection.publish_batch_expecting(test_events.clone(), 2),
+                   Err(DatabaseError::WrongExpectedVersion { expected: 2, actual: 3 }));
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current()).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_deduplication() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.deduplication.window_size = 5;
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        let event = |client_id| Event::new("data", vec!["tag1"]).with_client_id(client_id);
+        assert_eq!(collection.publish(event("client1")), Ok(1));
+        assert_eq!(collection.publish(event("client1")), Ok(1));
+        assert_eq!(collection.publish_batch(vec![event("client2"), event("client3")]), Ok((2, 3)));
+        assert_eq!(collection.publish_batch(vec![event("client2"), event("client3")]), Ok((2, 3)));
+        assert_eq!(collection.publish_expecting(event("client1"), 0), Ok(1));
+        assert_eq!(collection.publish_batch(vec![event("client3"), event("client4")]),
+                   Err(DatabaseError::ValidationError(ValidationError::new("batch must not mix published and unpublished events"))));
+        assert_eq!(collection.publish_batch(vec![event("client4"), event("client4")]),
+                   Err(DatabaseError::ValidationError(ValidationError::new("batch must not contain duplicate client ids"))));
+
+        drop(collection);
+
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+        assert_eq!(collection.publish(event("client3")), Ok(3));
+        assert_eq!(collection.publish_batch(vec![Event::new("data", vec!["tag1"]); 3]), Ok((4, 6)));
+        assert_eq!(collection.publish(event("client1")), Ok(7));
+        assert_eq!(collection.publish(event("client3")), Ok(3));
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current()).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), (1..8).collect::<Vec<_>>());
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_cancel_subscription() {
+        let ref collection_name = random_collection_name();
+        let config = CollectionConfig::default();
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        assert_eq!(collection.publish(Event::new("data", vec!["tag1"])), Ok(1));
+
+        let mut event_stream = collection.subscribe(Query::live()).expect("Unable to subscribe");
+        assert_eq!(event_stream.next().map(|event| event.id), Some(1));
+
+        event_stream.cancel();
+        assert_eq!(event_stream.next(), None);
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_publish_batch_and_subscribe() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.index_granularity = 10;
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        let test_events = vec![Event::new("data", vec!["tag1"]); 7];
+        for i in 0..10 {
+            assert_eq!(collection.publish_batch(test_events.clone()), Ok((i * 7 + 1, i * 7 + 7)));
+        }
+        assert!(collection.publish_batch(vec![Event::new("data", vec!["tag1"]), Event::new("data", vec![])]).is_err());
+
+        let restored_index = collection.log.segment(1).restore_index().expect("Unable to restore persisted index");
+        assert_eq!(restored_index.get_ref().keys().cloned().collect::<Vec<_>>(), vec![14, 28, 42, 56, 70]);
+        assert_eq!(restored_index, collection.index);
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().offset(20)).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), (21..71).collect::<Vec<_>>());
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let ref collection_name = random_collection_name();
+        let config = CollectionConfig::default();
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        for timestamp in vec![1000, 2000, 3600000] {
+            assert!(collection.publish(Event::new("data", vec!["tag1"]).with_timestamp(timestamp)).is_ok());
+        }
+
+        let aggregation = Aggregation::new().bucket_size(3600000);
+        let aggregates: Vec<_> = collection.aggregate(Query::current(), aggregation).unwrap().collect();
+        assert_eq!(aggregates.len(), 1);
+        assert_eq!(aggregates[0].count, 3);
+        assert_eq!(aggregates[0].groups.get(&AggregateGroup::new(None, Some(0))), Some(&2));
+        assert_eq!(aggregates[0].groups.get(&AggregateGroup::new(None, Some(3600000))), Some(&1));
+
+        let aggregation = Aggregation::new().update_interval(10);
+        let mut aggregate_stream = collection.aggregate(Query::live().by_tag("tag1"), aggregation).unwrap();
+        assert!(aggregate_stream.any(|aggregate| aggregate.count == 3));
+
+        assert!(collection.publish(Event::new("data", vec!["tag1"]).with_timestamp(4000)).is_ok());
+        assert!(aggregate_stream.any(|aggregate| aggregate.count == 4 && aggregate.max_id == Some(4)));
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_durable_subscriptions() {
+        let ref collection_name = random_collection_name();
+        let config = CollectionConfig::default();
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        for _ in 0..5 {
+            assert!(collection.publish(Event::new("data", vec!["tag1"])).is_ok());
+        }
+
+        let event_ids = |event_stream: EventStream| event_stream.map(|e| e.id).collect::<Vec<_>>();
+
+        let event_stream = collection.subscribe_durable("consumer", Query::current()).expect("Unable to subscribe");
+        assert_eq!(event_ids(event_stream), vec![1, 2, 3, 4, 5]);
+
+        assert_eq!(collection.acknowledge("consumer", 3), Ok(()));
+        assert_eq!(collection.acknowledge("consumer", 2), Ok(()));
+        assert_eq!(collection.checkpoints().get("consumer"), Some(3));
+
+        let event_stream = collection.subscribe_durable("consumer", Query::current()).expect("Unable to subscribe");
+        assert_eq!(event_ids(event_stream), vec![4, 5]);
+        let event_stream = collection.subscribe_durable("consumer", Query::current().offset(4)).expect("Unable to subscribe");
+        assert_eq!(event_ids(event_stream), vec![5]);
+
+        let invalid_checkpoint = ValidationError::new("checkpoint must not follow the last event published");
+        assert_eq!(collection.acknowledge("consumer", 6), Err(DatabaseError::ValidationError(invalid_checkpoint.clone())));
+        assert_eq!(collection.reset_checkpoint("consumer", 6), Err(DatabaseError::ValidationError(invalid_checkpoint)));
+        assert!(collection.subscribe_durable("consumer", Query::last(1)).is_err());
+        assert!(collection.subscribe_durable("", Query::current()).is_err());
+
+        drop(collection);
+
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+        assert_eq!(collection.checkpoints().get("consumer"), Some(3));
+
+        assert_eq!(collection.reset_checkpoint("consumer", 1), Ok(()));
+        let event_stream = collection.subscribe_durable("consumer", Query::current()).expect("Unable to subscribe");
+        assert_eq!(event_ids(event_stream), vec![2, 3, 4, 5]);
+
+        assert_eq!(collection.delete_checkpoint("consumer"), Ok(()));
+        assert_eq!(collection.delete_checkpoint("consumer"), Ok(()));
+        assert_eq!(collection.checkpoints(), &Checkpoints::new());
+        assert_eq!(Collection::new(collection_name, &config).map(|c| c.checkpoints().clone()), Ok(Checkpoints::new()));
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        let ref collection_name = random_collection_name();
+        let ref dead_letter_collection_name = dead_letter_collection_name(collection_name, "group");
+        let config = CollectionConfig::default();
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+        let dead_letter_collection = Collection::new(dead_letter_collection_name, &config).expect("Unable to create collection");
+        let dead_letter_collection = Arc::new(Mutex::new(dead_letter_collection));
+        let dead_letter_connection = Connection::new(dead_letter_collection.clone());
+
+        for _ in 0..3 {
+            assert!(collection.publish(Event::new("data", vec!["tag1"])).is_ok());
+        }
+
+        let (member1, event_stream1) = collection.join_consumer_group("group", Query::live(), dead_letter_connection.clone())
+                                                 .expect("Unable to join consumer group");
+        let (member2, event_stream2) = collection.join_consumer_group("group", Query::current(), dead_letter_connection.clone())
+                                                 .expect("Unable to join consumer group");
+
+        let received_ids: Vec<_> = (0..3).map(|_| {
+            match event_stream1.recv_timeout(Duration::from_millis(50)) {
+                Ok(event) => { assert!(member1.ack(event.id)); event.id },
+                Err(_) => {
+                    let event = event_stream2.recv().expect("Unable to receive event");
+                    assert!(member2.ack(event.id));
+                    event.id
+                }
+            }
+        }).collect();
+        assert_eq!(received_ids.len(), 3);
+
+        let info = collection.consumer_group_info("group").expect("Unable to get consumer group info");
+        assert_eq!(info.members.keys().cloned().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!((info.position, info.lag), (3, 0));
+
+        assert!(collection.publish(Event::new("data", vec!["tag1"])).is_ok());
+        assert_eq!(collection.consumer_group_info("group").map(|info| info.lag), Ok(1));
+
+        assert!(collection.join_consumer_group("", Query::live(), dead_letter_connection.clone()).is_err());
+        assert!(collection.join_consumer_group("reversed", Query::last(1), dead_letter_connection.clone()).is_err());
+        assert!(collection.consumer_group_info("unknown").is_err());
+
+        assert!(collection.drop().is_ok());
+        assert!((*dead_letter_collection.lock().unwrap()).drop().is_ok());
+    }
+
+    #[test]
+    fn test_recovery_on_open() {
+        let ref collection_name = random_collection_name();
+        let config = CollectionConfig::default();
+        let test_event = Event::new("data", vec!["tag1", "tag2"]);
+        let record_size = test_event.to_log_record().len() as u64;
+
+        for remaining_bytes in 0..record_size {
+            {
+                let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+                assert_eq!(collection.publish(test_event.clone()), Ok(1));
+                assert_eq!(collection.publish(test_event.clone()), Ok(2));
+            }
+
+            let file = OpenOptions::new().write(true).open(format!("{}.1.log", collection_name)).expect("Unable to open log file");
+            assert!(file.set_len(record_size + remaining_bytes).is_ok());
+
+            let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+            assert_eq!(collection.publish(test_event.clone()), Ok(2));
+
+            let retrieved_events: Vec<_> = collection.subscribe(Query::current()).unwrap().take(2).collect();
+            assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 2]);
+
+            assert!(collection.drop().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_publish_into_rolling_segments() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.index_granularity = 10;
+        config.segment_size = SegmentSize::MaxEvents(25);
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        let test_event = Event::new("data", vec!["tag1"]);
+        for i in 0..60 {
+            assert_eq!(collection.publish(test_event.clone()), Ok(i+1));
+        }
+
+        let indexes = collection.log.restore_indexes().expect("Unable to restore persisted indexes");
+        assert_eq!(indexes.keys().cloned().collect::<Vec<_>>(), vec![1, 26, 51]);
+        assert_eq!(indexes.get(&26).map(|index| index.get_ref().keys().cloned().collect::<Vec<_>>()), Some(vec![26, 36, 46]));
+        assert_eq!(indexes.get(&51).map(|index| index.get_ref()), Some(collection.index.get_ref()));
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().offset(30).limit(25)).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), (31..56).collect::<Vec<_>>());
+
+        drop(collection);
+
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+        assert_eq!(collection.segment_first_id, 51);
+        assert_eq!(collection.publish(test_event.clone()), Ok(61));
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_retention() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.segment_size = SegmentSize::MaxEvents(10);
+        config.retention.max_events = Some(10);
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        assert!(collection.retention_task.is_some());
+
+        let test_event = Event::new("data", vec!["tag1"]);
+        for i in 0..35 {
+            assert_eq!(collection.publish(test_event.clone()), Ok(i+1));
+        }
+
+        {
+            let retention_task = collection.retention_task.as_ref().expect("Unable to get retention task");
+            assert_eq!(retention_task.apply().map(|segments| segments.len()), Ok(2));
+        }
+
+        assert_eq!(collection.log.segments().map(|segments| segments.iter().map(|s| s.first_id()).collect()), Ok(vec![21, 31]));
+        assert_eq!(collection.tag_index.read().unwrap().ids_after("tag1", 0), &(21..36).collect::<Vec<_>>()[..]);
+
+        assert_eq!(collection.subscribe(Query::current().offset(10)).err(), Some(DatabaseError::RetentionError(21)));
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().offset(20)).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), (21..36).collect::<Vec<_>>());
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current()).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), (21..36).collect::<Vec<_>>());
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_compaction() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.index_granularity = 5;
+        config.segment_size = SegmentSize::MaxEvents(10);
+        config.compaction.key_tag_prefix = Some("key:".to_owned());
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        assert!(collection.compaction_task.is_some());
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().offset(15)).unwrap().collect();
+        assert_eq!(retrieved_events, vec![]);
+
+        for i in 0..25 {
+            let ref key = format!("key:{}", i % 2);
+            let tags = if i % 10 == 0 { vec!["tag1"] } else { vec!["tag1", key] };
+            assert_eq!(collection.publish(Event::new("data", tags)), Ok(i+1));
+        }
+
+        {
+            let compaction_task = collection.compaction_task.as_ref().expect("Unable to get compaction task");
+            assert_eq!(compaction_task.compact(), Ok(18));
+        }
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current()).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 11, 21, 22, 23, 24, 25]);
+        assert_eq!(collection.tag_index.read().unwrap().ids_after("tag1", 0), &[1, 11, 21, 22, 23, 24, 25]);
+        assert_eq!(collection.tag_index.read().unwrap().ids_after("key:1", 0), &[22, 24]);
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().offset(5).limit(3)).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![11, 21, 22]);
+
+        assert!(collection.drop().is_ok());
+    }
+
     #[test]
     fn test_index_updates_on_publish() {
         let ref collection_name = random_collection_name();
@@ -182,35 +1068,295 @@ mod tests {
             assert_eq!(collection.publish(test_event.clone()), Ok(i+1));
         }
 
-        let restored_index = collection.log.restore_index().expect("Unable to restore persisted index");
-        assert_eq!(restored_index.line_count(), 100);
+        let restored_index = collection.log.segment(1).restore_index().expect("Unable to restore persisted index");
+        assert_eq!(restored_index.last_id(), 100);
+        assert_eq!(restored_index.get_ref().len(), 10);
+        assert_eq!(restored_index, collection.index);
 
         assert!(collection.drop().is_ok());
     }
 
     #[test]
-    fn test_drop() {
+    fn test_tag_index_updates_on_publish() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.index_granularity = 10;
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        for i in 0..100 {
+            let tags = if i % 25 == 0 { vec!["tag1", "rare"] } else { vec!["tag1"] };
+            assert_eq!(collection.publish(Event::new("data", tags)), Ok(i+1));
+        }
+        assert_eq!(collection.publish_batch(vec![Event::new("data", vec!["rare"]); 2]), Ok((101, 102)));
+
+        let rare_ids = vec![1, 26, 51, 76, 101, 102];
+        assert_eq!(collection.tag_index.read().expect("Unable to read tag index").ids_after("rare", 0), &rare_ids[..]);
+        assert_eq!(collection.log.restore_tag_index(102).map(|tag_index| tag_index.ids_after("rare", 0).to_vec()), Ok(rare_ids));
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().offset(1).limit(2).by_tag("rare")).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![26, 51]);
+
+        drop(collection);
+
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+        assert_eq!(collection.publish(Event::new("data", vec!["rare"])), Ok(103));
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().offset(60).by_tag("rare")).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![76, 101, 102, 103]);
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_streams() {
         let ref collection_name = random_collection_name();
         let config = CollectionConfig::default();
         let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
 
-        assert_eq!(collection.scanners.len(), 2);
-        assert_eq!(collection.tail_scanners.len(), 2);
+        let order = |stream_id| Event::new("data", vec!["order"]).with_stream(stream_id);
+        assert_eq!(collection.publish(order("order-1")), Ok(1));
+        assert_eq!(collection.publish(Event::new("data", vec!["tag1"])), Ok(2));
+        assert_eq!(collection.publish_batch(vec![order("order-2"), order("order-1"), order("order-1")]), Ok((3, 5)));
+        assert_eq!(collection.stream_version("order-1"), 3);
+        assert_eq!(collection.stream_version("order-2"), 1);
+        assert_eq!(collection.stream_version("order-3"), 0);
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().by_stream("order-1")).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| (e.id, e.stream_version)).collect::<Vec<_>>(), vec![(1, 1), (4, 2), (5, 3)]);
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().by_stream("order-1").from_stream_version(2)).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![4, 5]);
+
+        assert_eq!(collection.publish_expecting_stream_version(order("order-2"), 1), Ok(6));
+        assert_eq!(collection.publish_expecting_stream_version(order("order-2"), 1),
+                   Err(DatabaseError::WrongExpectedVersion { expected: 1, actual: 2 }));
+        assert_eq!(collection.publish_batch_expecting_stream_version(vec![order("order-3"); 2], 0), Ok((7, 8)));
+        assert_eq!(collection.publish_batch_expecting_stream_version(vec![order("order-3"), order("order-1")], 2),
+                   Err(DatabaseError::ValidationError(ValidationError::new("events must belong to the same stream"))));
+        assert_eq!(collection.publish_expecting_stream_version(Event::new("data", vec!["tag1"]), 0),
+                   Err(DatabaseError::ValidationError(ValidationError::new("events must belong to a stream"))));
+
+        // only the events belonging to a stream are persisted in the stream index file
+        let stream_entries = vec![(1, "order-1", 1), (3, "order-2", 1), (4, "order-1", 2), (5, "order-1", 3),
+                                  (6, "order-2", 2), (7, "order-3", 1), (8, "order-3", 2)];
+        let stream_index_size = stream_entries.into_iter().map(|(id, stream_id, version)| {
+            StreamIndexEntry::new(id, Some(stream_id.to_owned()), version).to_log_record().len() as u64
+        }).sum();
+        assert_eq!(metadata(collection.log.get_stream_index_path()).map(|metadata| metadata.len()).ok(), Some(stream_index_size));
+
+        drop(collection);
+
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+        assert_eq!(collection.stream_version("order-1"), 3);
+        assert_eq!(collection.publish(order("order-1")), Ok(9));
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().by_stream("order-1").from_stream_version(3)).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| (e.id, e.stream_version)).collect::<Vec<_>>(), vec![(5, 3), (9, 4)]);
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_timestamp_index_updates_on_publish() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.index_granularity = 10;
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        for i in 1..96 {
+            assert_eq!(collection.publish(Event::new("data", vec!["tag1"]).with_timestamp(i * 1000)), Ok(i));
+        }
+        assert!(collection.publish(Event::new("data", vec!["tag1"])).is_ok());
+
+        let restored_timestamp_index = collection.log.restore_timestamp_index(96).expect("Unable to restore timestamp index");
+        assert_eq!(restored_timestamp_index.completed_blocks().len(), 9);
+        assert_eq!(collection.timestamp_index.read().expect("Unable to read timestamp index").clone(), restored_timestamp_index);
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().from_timestamp(25000).to_timestamp(30000)).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), (25..31).collect::<Vec<_>>());
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().offset(90).to_timestamp(95000)).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), (91..96).collect::<Vec<_>>());
+
+        let retrieved_events: Vec<_> = collection.subscribe(Query::current().from_timestamp(96000)).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![96]);
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_tail_cache_updates_on_publish() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.segment_size = SegmentSize::MaxEvents(25);
+        config.tail_cache_size = 10;
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        for i in 1..31 {
+            assert_eq!(collection.publish(Event::new("data", vec!["tag1"])), Ok(i));
+        }
+
+        {
+            let tail_cache = collection.tail_cache.read().expect("Unable to read tail cache");
+            assert_eq!(tail_cache.last_id(), 30);
+            assert_eq!(tail_cache.first_id(), 26);
+            assert_eq!(tail_cache.events_after(27).iter().map(|e| e.id).collect::<Vec<_>>(), vec![28, 29, 30]);
+        }
+
+        let live_events: Vec<_> = collection.subscribe(Query::live().offset(28).limit(4)).unwrap().take(2).collect();
+        assert_eq!(live_events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![29, 30]);
+
+        let event_stream = collection.subscribe(Query::live().offset(20).limit(12)).unwrap();
+        for _ in 0..2 {
+            assert!(collection.publish(Event::new("data", vec!["tag1"])).is_ok());
+        }
+        assert_eq!(event_stream.map(|e| e.id).collect::<Vec<_>>(), (21..33).collect::<Vec<_>>());
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_drop() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.scanners.min_nr_of_scanners = 2;
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        assert_eq!(collection.scanner_pool.lock().unwrap().scanners.len(), 2);
+        assert_eq!(collection.scanner_pool.lock().unwrap().tail_scanners.len(), 2);
+
+        assert!(collection.drop().is_ok());
+
+        assert_eq!(collection.scanner_pool.lock().unwrap().scanners.len(), 0);
+        assert_eq!(collection.scanner_pool.lock().unwrap().tail_scanners.len(), 0);
+    }
+
+    #[test]
+    fn test_elastic_scanners() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.scanners.min_nr_of_scanners = 0;
+        config.scanners.max_nr_of_scanners = 2;
+        config.scanners.idle_timeout_in_ms = 60000;
+        config.event_stream_buffer_size = 1;
+        let mut collection = Collection::new(collection_name, &config).expect("Unable to create collection");
+
+        assert_eq!(collection.scanner_pool.lock().unwrap().scanners.len(), 0);
+
+        // subscriptions pause catching up with the log, keeping the scanners busy
+        assert!(collection.publish_batch(vec![Event::new("data", vec!["tag1"]); 10]).is_ok());
+
+        let mut event_streams = vec![collection.subscribe(Query::current()).expect("Unable to subscribe")];
+        assert_eq!(collection.scanner_pool.lock().unwrap().scanners.len(), 1);
+        assert_eq!(collection.scanner_pool.lock().unwrap().tail_scanners.len(), 1);
+        assert_eq!(collection.scanner_senders.lock().unwrap().len(), 2);
+
+        event_streams.push(collection.subscribe(Query::current()).expect("Unable to subscribe"));
+        assert_eq!(collection.scanner_pool.lock().unwrap().scanners.len(), 2);
+
+        event_streams.push(collection.subscribe(Query::current()).expect("Unable to subscribe"));
+        assert_eq!(collection.scanner_pool.lock().unwrap().scanners.len(), 2);
+
+        for event_stream in event_streams.iter_mut() {
+            event_stream.cancel();
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while collection.scanner_pool.lock().unwrap().scanners.iter().any(|scanner| scanner.load().nr_of_subscriptions > 0) {
+            assert!(Instant::now() < deadline, "Scanners did not remove the cancelled subscriptions");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // the idle scanners are only stopped once they have been idle for the idle timeout
+        let idle_timeout = config.scanners.idle_timeout();
+        let now = Instant::now();
+        {
+            let mut scanner_pool = collection.scanner_pool.lock().unwrap();
+            scanner_pool.stop_idle_scanners(&config.scanners, &collection.scanner_senders, now);
+            assert_eq!(scanner_pool.scanners.len(), 2);
+            scanner_pool.stop_idle_scanners(&config.scanners, &collection.scanner_senders, now + idle_timeout / 2);
+            assert_eq!(scanner_pool.scanners.len(), 2);
+            scanner_pool.stop_idle_scanners(&config.scanners, &collection.scanner_senders, now + idle_timeout);
+        }
+
+        assert_eq!(collection.scanner_pool.lock().unwrap().scanners.len(), 0);
+        assert_eq!(collection.scanner_pool.lock().unwrap().tail_scanners.len(), 0);
+        assert_eq!(collection.scanner_senders.lock().unwrap().len(), 0);
 
         assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_apply_least_loaded_routing_strategy() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.scanners.min_nr_of_scanners = 2;
+        let mut collection = Collection::new(collection_name, &config)
+                                        .expect("Unable to create collection");
+
+        let (sender, _) = sync_channel(1000);
+        let subscription = Subscription::new(sender, Query::current());
 
-        assert_eq!(collection.scanners.len(), 0);
-        assert_eq!(collection.tail_scanners.len(), 0);
+        collection.routing_strategy = RoutingStrategy::LeastLoaded;
+
+        let updated_strategy = collection.apply_routing_strategy(subscription)
+                                         .expect("Unable to apply routing strategy");
+
+        assert_eq!(updated_strategy, RoutingStrategy::LeastLoaded);
+
+        let load = |nr_of_subscriptions, intervals| ScannerLoad {
+            nr_of_subscriptions: nr_of_subscriptions,
+            intervals: intervals
+        };
+        assert_eq!(super::least_loaded_scanner_index(&[]), None);
+        assert_eq!(super::least_loaded_scanner_index(&[load(2, vec![]), load(1, vec![]), load(1, vec![])]), Some(1));
+
+        assert!(collection.drop().is_ok());
+    }
+
+    #[test]
+    fn test_apply_interval_affinity_routing_strategy() {
+        let ref collection_name = random_collection_name();
+        let mut config = CollectionConfig::default();
+        config.scanners.min_nr_of_scanners = 2;
+        let mut collection = Collection::new(collection_name, &config)
+                                        .expect("Unable to create collection");
+
+        let (sender, _) = sync_channel(1000);
+        let subscription = Subscription::new(sender, Query::current());
+
+        collection.routing_strategy = RoutingStrategy::IntervalAffinity;
+
+        let updated_strategy = collection.apply_routing_strategy(subscription)
+                                         .expect("Unable to apply routing strategy");
+
+        assert_eq!(updated_strategy, RoutingStrategy::IntervalAffinity);
+
+        let load = |nr_of_subscriptions, intervals| ScannerLoad {
+            nr_of_subscriptions: nr_of_subscriptions,
+            intervals: intervals
+        };
+        let loads = vec![
+            load(1, vec![Interval::new(0, 100)]),
+            load(3, vec![Interval::new(50, 200), Interval::new(300, 400)]),
+            load(0, vec![])
+        ];
+        assert_eq!(super::interval_affinity_scanner_index(&[], &Interval::new(0, 10)), None);
+        assert_eq!(super::interval_affinity_scanner_index(&loads, &Interval::new(0, 60)), Some(0));
+        assert_eq!(super::interval_affinity_scanner_index(&loads, &Interval::new(80, 150)), Some(1));
+        assert_eq!(super::interval_affinity_scanner_index(&loads, &Interval::new(250, 280)), Some(2));
+
+        assert!(collection.drop().is_ok());
     }
 
     #[test]
     fn test_apply_round_robin_routing_strategy() {
         let ref collection_name = random_collection_name();
-        let config = CollectionConfig::default();
+        let mut config = CollectionConfig::default();
+        config.scanners.min_nr_of_scanners = 2;
         let mut collection = Collection::new(collection_name, &config)
                                         .expect("Unable to create collection");
 
-        let (sender, _) = channel();
+        let (sender, _) = sync_channel(1000);
         let subscription = Subscription::new(sender, Query::current());
 
         collection.routing_strategy = RoutingStrategy::RoundRobin(0);
@@ -0,0 +1,345 @@
+use super::*;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Exar DB's log compactor.
+///
+/// It rewrites the completed segments of a log keeping only the last event for each key,
+/// where the key of an event is its first tag starting with the given key tag prefix.
+/// Events without a key are always retained, and retained events keep their original `id`s and timestamps,
+/// so reading a compacted log still returns events in `id` order, with gaps where events were removed.
+///
+/// The given scanners are asked to discard their index for a segment before it is replaced,
+/// and they receive the new index for the segment once it has been replaced.
+/// The action senders can be shared with the owner of the scanners, which is expected to lock them while
+/// starting a new scanner, so that no scanner is started while a segment is being replaced.
+///
+/// The removed events are also removed from the postings of the shared `TagIndex` set using `with_tag_index`, if any.
+///
+/// # Examples
+/// ```no_run
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let log = Log::new("/path/to/logs", "test", 100);
+/// let compactor = Compactor::new(log, "key:", vec![]);
+///
+/// let removed_events = compactor.compact().unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Compactor {
+    log: Log,
+    key_tag_prefix: String,
+    scanner_senders: Arc<Mutex<Vec<Sender<ScannerAction>>>>,
+    tag_index: Option<Arc<RwLock<TagIndex>>>
+}
+
+impl Compactor {
+    /// Creates a new compactor for the given `Log` and key tag prefix,
+    /// notifying the scanners with the given action senders about the replaced segments.
+    pub fn new(log: Log, key_tag_prefix: &str, scanner_senders: Vec<Sender<ScannerAction>>) -> Compactor {
+        Compactor::with_shared_scanner_senders(log, key_tag_prefix, Arc::new(Mutex::new(scanner_senders)))
+    }
+
+    /// Creates a new compactor for the given `Log` and key tag prefix,
+    /// notifying the scanners with the given shared action senders about the replaced segments.
+    pub fn with_shared_scanner_senders(log: Log, key_tag_prefix: &str, scanner_senders: Arc<Mutex<Vec<Sender<ScannerAction>>>>) -> Compactor {
+        Compactor {
+            log: log,
+            key_tag_prefix: key_tag_prefix.to_owned(),
+            scanner_senders: scanner_senders,
+            tag_index: None
+        }
+    }
+
+    /// Returns a modified version of the compactor removing the compacted events from the given shared `TagIndex`.
+    pub fn with_tag_index(mut self, tag_index: Arc<RwLock<TagIndex>>) -> Self {
+        self.tag_index = Some(tag_index);
+        self
+    }
+
+    /// Returns the key of the given event, if any.
+    pub fn event_key<'a>(&self, event: &'a Event) -> Option<&'a str> {
+        event.tags.iter().find(|tag| tag.starts_with(&self.key_tag_prefix)).map(|tag| &tag[..])
+    }
+
+    /// Compacts the completed log segments and returns the number of events removed
+    /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// The last segment is never compacted, since it is still being written.
+    pub fn compact(&self) -> Result<u64, DatabaseError> {
+        let segments = try!(self.log.segments());
+        if segments.len() < 2 {
+            return Ok(0)
+        }
+        let last_ids = try!(self.last_ids_by_key());
+        let mut removed_events = 0;
+        for segment in &segments[..segments.len() - 1] {
+            let compacted_events = RefCell::new(vec![]);
+            let segment_removed_events = try!(segment.write_compacted(|event| {
+                let retain = match self.event_key(event) {
+                    Some(key) => last_ids.get(key).map_or(true, |&last_id| last_id <= event.id),
+                    None => true
+                };
+                if !retain && self.tag_index.is_some() {
+                    compacted_events.borrow_mut().push((event.id, event.tags.clone()));
+                }
+                retain
+            }));
+            if segment_removed_events > 0 {
+                let scanner_senders = self.scanner_senders.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                discard_scanners_index(&scanner_senders, segment.first_id());
+                if !segment.exists() {
+                    try!(segment.remove_compacted());
+                    continue
+                }
+                let index = try!(segment.replace_with_compacted());
+                for sender in scanner_senders.iter() {
+                    let _ = sender.send(ScannerAction::ReplaceIndex(segment.first_id(), index.clone()));
+                }
+                if let Some(ref tag_index) = self.tag_index {
+                    let mut tag_index = tag_index.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+                    for (id, tags) in compacted_events.into_inner() {
+                        tag_index.remove(id, &tags);
+                    }
+                }
+                removed_events += segment_removed_events;
+            }
+        }
+        Ok(removed_events)
+    }
+
+    fn last_ids_by_key(&self) -> Result<HashMap<String, u64>, DatabaseError> {
+        let mut last_ids = HashMap::new();
+        for event in try!(self.log.open_log_reader()) {
+            let event = try!(event);
+            if let Some(key) = self.event_key(&event) {
+                last_ids.insert(key.to_owned(), event.id);
+            }
+        }
+        Ok(last_ids)
+    }
+}
+
+fn discard_scanners_index(scanner_senders: &[Sender<ScannerAction>], segment_first_id: u64) {
+    let mut ack_receivers = vec![];
+    for sender in scanner_senders {
+        let (ack_sender, ack_receiver) = channel();
+        if sender.send(ScannerAction::DiscardIndex(segment_first_id, ack_sender)).is_ok() {
+            ack_receivers.push(ack_receiver);
+        }
+    }
+    // the segment can only be replaced once no scanner is going to seek within it using its previous index
+    for ack_receiver in ack_receivers {
+        let _ = ack_receiver.recv();
+    }
+}
+
+/// Exar DB's log compaction task.
+///
+/// It runs a background thread periodically compacting a log using the given `Compactor`,
+/// the thread stops when the task is dropped, once the compaction in progress (if any) is completed.
+///
+/// # Examples
+/// ```no_run
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+/// use std::time::Duration;
+///
+/// let log = Log::new("/path/to/logs", "test", 100);
+/// let compactor = Compactor::new(log, "key:", vec![]);
+///
+/// let compaction_task = CompactionTask::new(compactor, Duration::from_secs(600));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CompactionTask {
+    compactor: Arc<Mutex<Compactor>>,
+    stop_sender: Sender<()>,
+    handle: Option<JoinHandle<()>>
+}
+
+impl CompactionTask {
+    /// Creates a new compaction task running the given `Compactor` at the given interval,
+    /// and starts its background thread.
+    pub fn new(compactor: Compactor, interval: Duration) -> CompactionTask {
+        let compactor = Arc::new(Mutex::new(compactor));
+        let (stop_sender, stop_receiver) = channel::<()>();
+        let thread_compactor = compactor.clone();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(interval) {
+                let compactor = thread_compactor.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                match compactor.compact() {
+                    Ok(removed_events) if removed_events > 0 => {
+                        info!("Removed {} events from log '{}' by compaction", removed_events, compactor.log.get_name());
+                    },
+                    Ok(_) => (),
+                    Err(err) => error!("Unable to compact log '{}': {}", compactor.log.get_name(), err)
+                }
+            }
+        });
+        CompactionTask {
+            compactor: compactor,
+            stop_sender: stop_sender,
+            handle: Some(handle)
+        }
+    }
+
+    /// Compacts the log once, without waiting for the background thread,
+    /// and returns the number of events removed or a `DatabaseError` if a failure occurs.
+    pub fn compact(&self) -> Result<u64, DatabaseError> {
+        self.compactor.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).compact()
+    }
+}
+
+impl Drop for CompactionTask {
+    fn drop(&mut self) {
+        let _ = self.stop_sender.send(());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use exar_testkit::*;
+
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::Duration;
+
+    fn create_log_with_keyed_events(nr_of_events: u64) -> Log {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 2).with_segment_size(SegmentSize::MaxEvents(10));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for id in 1..(nr_of_events + 1) {
+            let ref key = format!("key:{}", id % 3);
+            let tags = if id % 5 == 0 { vec!["tag1"] } else { vec!["tag1", key] };
+            assert!(logger.log(Event::new("data", tags).with_timestamp(id * 1000)).is_ok());
+        }
+        log
+    }
+
+    fn read_ids(log: &Log) -> Vec<u64> {
+        log.open_log_reader().expect("Unable to open reader").map(|e| e.expect("Unable to read event").id).collect()
+    }
+
+    #[test]
+    fn test_event_key() {
+        let compactor = Compactor::new(Log::new("", "test", 10), "key:", vec![]);
+        assert_eq!(compactor.event_key(&Event::new("data", vec!["tag1", "key:1", "key:2"])), Some("key:1"));
+        assert_eq!(compactor.event_key(&Event::new("data", vec!["tag1"])), None);
+    }
+
+    #[test]
+    fn test_compaction() {
+        let log = create_log_with_keyed_events(25);
+        let compactor = Compactor::new(log.clone(), "key:", vec![]);
+
+        assert_eq!(compactor.compact(), Ok(16));
+        assert_eq!(read_ids(&log), vec![5, 10, 15, 20, 21, 22, 23, 24, 25]);
+
+        let events: Vec<_> = log.open_log_reader().expect("Unable to open reader").map(|e| e.expect("Unable to read event")).collect();
+        assert!(events.iter().all(|e| e.timestamp == e.id * 1000));
+
+        assert_eq!(compactor.compact(), Ok(0));
+        assert_eq!(log.segments().map(|segments| segments.len()), Ok(3));
+
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        assert_eq!(logger.log(Event::new("data", vec!["tag1"])), Ok(26));
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_compaction_of_whole_segments() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 2).with_segment_size(SegmentSize::MaxEvents(10));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for _ in 0..25 {
+            assert!(logger.log(Event::new("data", vec!["key:1"])).is_ok());
+        }
+        let compactor = Compactor::new(log.clone(), "key:", vec![]);
+
+        assert_eq!(compactor.compact(), Ok(20));
+        assert_eq!(read_ids(&log), (21..26).collect::<Vec<_>>());
+
+        let mut reader = log.open_log_reader().expect("Unable to open reader");
+        assert!(reader.seek(3).is_ok());
+        assert_eq!(reader.next().map(|e| e.map(|e| e.id)), Some(Ok(21)));
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_scanners_notification() {
+        let log = create_log_with_keyed_events(25);
+        let (sender, receiver) = channel();
+        let compactor = Compactor::new(log.clone(), "key:", vec![sender]);
+
+        let handle = thread::spawn(move || compactor.compact());
+
+        for segment_first_id in vec![1, 11] {
+            match receiver.recv() {
+                Ok(ScannerAction::DiscardIndex(id, ack_sender)) => {
+                    assert_eq!(id, segment_first_id);
+                    assert!(ack_sender.send(()).is_ok());
+                },
+                _ => panic!("Expected to receive a DiscardIndex message")
+            }
+            match receiver.recv() {
+                Ok(ScannerAction::ReplaceIndex(id, index)) => {
+                    assert_eq!(id, segment_first_id);
+                    assert_eq!(index, log.segment(id).restore_index().expect("Unable to restore index"));
+                },
+                _ => panic!("Expected to receive a ReplaceIndex message")
+            }
+        }
+
+        assert_eq!(handle.join().expect("Unable to join compaction thread"), Ok(16));
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_compaction_task() {
+        let log = create_log_with_keyed_events(25);
+        let compactor = Compactor::new(log.clone(), "key:", vec![]);
+
+        let compaction_task = CompactionTask::new(compactor, Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(100));
+        drop(compaction_task);
+
+        assert_eq!(read_ids(&log), vec![5, 10, 15, 20, 21, 22, 23, 24, 25]);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_compaction_task_compact() {
+        let log = create_log_with_keyed_events(25);
+        let compactor = Compactor::new(log.clone(), "key:", vec![]);
+
+        let compaction_task = CompactionTask::new(compactor, Duration::from_secs(600));
+
+        assert_eq!(compaction_task.compact(), Ok(16));
+        assert_eq!(read_ids(&log), vec![5, 10, 15, 20, 21, 22, 23, 24, 25]);
+        assert_eq!(compaction_task.compact(), Ok(0));
+
+        drop(compaction_task);
+
+        assert!(log.remove().is_ok());
+    }
+}
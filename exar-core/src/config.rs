@@ -17,10 +17,20 @@ use std::time::Duration;
 ///     logs_path: "/path/to/logs".to_owned(),
 ///     index_granularity: 100000,
 ///     routing_strategy: RoutingStrategy::default(),
+///     durability: Durability::default(),
+///     segment_size: SegmentSize::default(),
+///     event_stream_buffer_size: 1000,
+///     tail_cache_size: 1000,
 ///     scanners: ScannersConfig {
-///         nr_of_scanners: 2,
+///         min_nr_of_scanners: 0,
+///         max_nr_of_scanners: 2,
+///         idle_timeout_in_ms: 60000,
 ///         sleep_time_in_ms: 10
 ///     },
+///     retention: RetentionConfig::default(),
+///     compaction: CompactionConfig::default(),
+///     consumer_groups: ConsumerGroupsConfig::default(),
+///     deduplication: DeduplicationConfig::default(),
 ///     collections: BTreeMap::new()
 /// };
 /// # }
@@ -35,8 +45,24 @@ pub struct DatabaseConfig  {
     pub index_granularity: u64,
     /// Subscriptions' routing strategy.
     pub routing_strategy: RoutingStrategy,
+    /// Log files' durability policy.
+    pub durability: Durability,
+    /// Log segments' size limit.
+    pub segment_size: SegmentSize,
+    /// Maximum number of events buffered by each event stream before its subscription is paused.
+    pub event_stream_buffer_size: usize,
+    /// Maximum number of recently published events kept in memory to serve subscriptions near the tail of the log.
+    pub tail_cache_size: usize,
     /// Log scanners' configuration.
     pub scanners: ScannersConfig,
+    /// Log retention configuration.
+    pub retention: RetentionConfig,
+    /// Log compaction configuration.
+    pub compaction: CompactionConfig,
+    /// Consumer groups' configuration.
+    pub consumer_groups: ConsumerGroupsConfig,
+    /// Published events' deduplication configuration.
+    pub deduplication: DeduplicationConfig,
     /// Holds collection-specific configuration overrides.
     pub collections: BTreeMap<String, PartialCollectionConfig>
 }
@@ -47,7 +73,15 @@ impl Default for DatabaseConfig {
             logs_path: "".to_owned(),
             index_granularity: 100000,
             routing_strategy: RoutingStrategy::default(),
+            durability: Durability::default(),
+            segment_size: SegmentSize::default(),
+            event_stream_buffer_size: 1000,
+            tail_cache_size: 1000,
             scanners: ScannersConfig::default(),
+            retention: RetentionConfig::default(),
+            compaction: CompactionConfig::default(),
+            consumer_groups: ConsumerGroupsConfig::default(),
+            deduplication: DeduplicationConfig::default(),
             collections: BTreeMap::new()
         }
     }
@@ -65,22 +99,63 @@ impl DatabaseConfig {
                     index_granularity: config.index_granularity.unwrap_or_else(|| self.index_granularity),
                     scanners: match config.scanners {
                         Some(scanners_config) => ScannersConfig {
-                            nr_of_scanners: scanners_config.nr_of_scanners.unwrap_or(self.scanners.nr_of_scanners),
+                            min_nr_of_scanners: scanners_config.min_nr_of_scanners.unwrap_or(self.scanners.min_nr_of_scanners),
+                            max_nr_of_scanners: scanners_config.max_nr_of_scanners.unwrap_or(self.scanners.max_nr_of_scanners),
+                            idle_timeout_in_ms: scanners_config.idle_timeout_in_ms.unwrap_or(self.scanners.idle_timeout_in_ms),
                             sleep_time_in_ms: scanners_config.sleep_time_in_ms.unwrap_or(self.scanners.sleep_time_in_ms)
                         },
-                        None => ScannersConfig {
-                            nr_of_scanners: self.scanners.nr_of_scanners,
-                            sleep_time_in_ms: self.scanners.sleep_time_in_ms
-                        }
+                        None => self.scanners.clone()
                     },
-                    routing_strategy: config.routing_strategy.unwrap_or_else(|| self.routing_strategy.clone())
+                    retention: match config.retention {
+                        Some(retention_config) => RetentionConfig {
+                            max_age_in_ms: retention_config.max_age_in_ms.or(self.retention.max_age_in_ms),
+                            max_bytes: retention_config.max_bytes.or(self.retention.max_bytes),
+                            max_events: retention_config.max_events.or(self.retention.max_events),
+                            check_interval_in_ms: retention_config.check_interval_in_ms.unwrap_or(self.retention.check_interval_in_ms)
+                        },
+                        None => self.retention.clone()
+                    },
+                    compaction: match config.compaction {
+                        Some(compaction_config) => CompactionConfig {
+                            key_tag_prefix: compaction_config.key_tag_prefix.or_else(|| self.compaction.key_tag_prefix.clone()),
+                            interval_in_ms: compaction_config.interval_in_ms.unwrap_or(self.compaction.interval_in_ms)
+                        },
+                        None => self.compaction.clone()
+                    },
+                    consumer_groups: match config.consumer_groups {
+                        Some(consumer_groups_config) => ConsumerGroupsConfig {
+                            ack_timeout_in_ms: consumer_groups_config.ack_timeout_in_ms.unwrap_or(self.consumer_groups.ack_timeout_in_ms),
+                            max_deliveries: consumer_groups_config.max_deliveries.unwrap_or(self.consumer_groups.max_deliveries)
+                        },
+                        None => self.consumer_groups.clone()
+                    },
+                    deduplication: match config.deduplication {
+                        Some(deduplication_config) => DeduplicationConfig {
+                            window_size: deduplication_config.window_size.unwrap_or(self.deduplication.window_size),
+                            max_age_in_ms: deduplication_config.max_age_in_ms.unwrap_or(self.deduplication.max_age_in_ms)
+                        },
+                        None => self.deduplication.clone()
+                    },
+                    routing_strategy: config.routing_strategy.unwrap_or_else(|| self.routing_strategy.clone()),
+                    durability: config.durability.unwrap_or_else(|| self.durability.clone()),
+                    segment_size: config.segment_size.unwrap_or_else(|| self.segment_size.clone()),
+                    event_stream_buffer_size: config.event_stream_buffer_size.unwrap_or(self.event_stream_buffer_size),
+                    tail_cache_size: config.tail_cache_size.unwrap_or(self.tail_cache_size)
                 }
             },
             None => CollectionConfig {
                 logs_path: self.logs_path.clone(),
                 index_granularity: self.index_granularity,
                 scanners: self.scanners.clone(),
-                routing_strategy: self.routing_strategy.clone()
+                retention: self.retention.clone(),
+                compaction: self.compaction.clone(),
+                consumer_groups: self.consumer_groups.clone(),
+                deduplication: self.deduplication.clone(),
+                routing_strategy: self.routing_strategy.clone(),
+                durability: self.durability.clone(),
+                segment_size: self.segment_size.clone(),
+                event_stream_buffer_size: self.event_stream_buffer_size,
+                tail_cache_size: self.tail_cache_size
             }
         }
     }
@@ -92,6 +167,9 @@ impl DatabaseConfig {
 
 /// Exar DB's scanners configuration.
 ///
+/// Each scanner spawns 2 threads, the scanners of a log are started as subscriptions are waiting for them
+/// and stopped once they have been idle for the idle timeout, keeping their number within the given bounds.
+///
 /// # Examples
 /// ```
 /// extern crate exar;
@@ -100,7 +178,9 @@ impl DatabaseConfig {
 /// use exar::*;
 ///
 /// let config = ScannersConfig {
-///     nr_of_scanners: 2,
+///     min_nr_of_scanners: 0,
+///     max_nr_of_scanners: 2,
+///     idle_timeout_in_ms: 60000,
 ///     sleep_time_in_ms: 10
 /// };
 /// # }
@@ -109,21 +189,40 @@ impl DatabaseConfig {
 #[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ScannersConfig {
-    /// Number of scanners for each log file (spawns 2 threads for each scanner).
-    pub nr_of_scanners: u8,
-    /// Amount of time the scanner threads should sleep between each scan.
+    /// Minimum number of scanners for each log file, which are never stopped.
+    pub min_nr_of_scanners: u8,
+    /// Maximum number of scanners for each log file.
+    pub max_nr_of_scanners: u8,
+    /// Amount of time a scanner without subscriptions is kept running before being stopped.
+    pub idle_timeout_in_ms: u64,
+    /// Amount of time the scanner threads should wait before resuming subscriptions paused by backpressure,
+    /// scanner threads are otherwise woken up as soon as new events are published.
     pub sleep_time_in_ms: u64
 }
 
 impl Default for ScannersConfig {
     fn default() -> ScannersConfig {
         ScannersConfig {
-            nr_of_scanners: 2,
+            min_nr_of_scanners: 0,
+            max_nr_of_scanners: 2,
+            idle_timeout_in_ms: 60000,
             sleep_time_in_ms: 10
         }
     }
 }
 
+impl ScannersConfig {
+    /// Returns the scanners idle timeout as an instance of `Duration`.
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.idle_timeout_in_ms)
+    }
+
+    /// Returns the scanners sleep as an instance of `Duration`.
+    pub fn sleep_duration(&self) -> Duration {
+        Duration::from_millis(self.sleep_time_in_ms)
+    }
+}
+
 /// Exar DB's partial scanners configuration.
 /// Holds overrides for the main database configuration.
 ///
@@ -135,7 +234,9 @@ impl Default for ScannersConfig {
 /// use exar::*;
 ///
 /// let config = PartialScannersConfig {
-///     nr_of_scanners: Some(2),
+///     min_nr_of_scanners: Some(1),
+///     max_nr_of_scanners: Some(4),
+///     idle_timeout_in_ms: None,
 ///     sleep_time_in_ms: Some(10)
 /// };
 /// # }
@@ -144,12 +245,331 @@ impl Default for ScannersConfig {
 #[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PartialScannersConfig {
-    /// Number of scanners for each log file (spawns 2 threads for each scanner).
-    pub nr_of_scanners: Option<u8>,
-    /// Amount of time the scanner threads should sleep between each scan.
+    /// Minimum number of scanners for each log file, which are never stopped.
+    pub min_nr_of_scanners: Option<u8>,
+    /// Maximum number of scanners for each log file.
+    pub max_nr_of_scanners: Option<u8>,
+    /// Amount of time a scanner without subscriptions is kept running before being stopped.
+    pub idle_timeout_in_ms: Option<u64>,
+    /// Amount of time the scanner threads should wait before resuming subscriptions paused by backpressure,
+    /// scanner threads are otherwise woken up as soon as new events are published.
     pub sleep_time_in_ms: Option<u64>
 }
 
+/// Exar DB's log retention configuration.
+///
+/// The oldest log segments are removed once all their events are expired according to any of the limits,
+/// the segment currently being written is never removed and event ids are not affected by the removal.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let config = RetentionConfig {
+///     max_age_in_ms: Some(604800000),
+///     max_bytes: Some(1073741824),
+///     max_events: Some(1000000),
+///     check_interval_in_ms: 60000
+/// };
+/// # }
+/// ```
+#[cfg_attr(feature = "rustc-serialization", derive(RustcEncodable, RustcDecodable))]
+#[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetentionConfig {
+    /// Maximum age of the retained events, based on their timestamp.
+    pub max_age_in_ms: Option<u64>,
+    /// Maximum number of bytes retained, older segments are removed once the newer ones exceed it.
+    pub max_bytes: Option<u64>,
+    /// Maximum number of events retained, older segments are removed once the newer ones exceed it.
+    pub max_events: Option<u64>,
+    /// Amount of time between each retention check.
+    pub check_interval_in_ms: u64
+}
+
+impl Default for RetentionConfig {
+    fn default() -> RetentionConfig {
+        RetentionConfig {
+            max_age_in_ms: None,
+            max_bytes: None,
+            max_events: None,
+            check_interval_in_ms: 60000
+        }
+    }
+}
+
+impl RetentionConfig {
+    /// Returns wether any retention limit is set.
+    pub fn is_enabled(&self) -> bool {
+        self.max_age_in_ms.is_some() || self.max_bytes.is_some() || self.max_events.is_some()
+    }
+
+    /// Returns the retention check interval as an instance of `Duration`.
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_millis(self.check_interval_in_ms)
+    }
+}
+
+/// Exar DB's partial log retention configuration.
+/// Holds overrides for the main database configuration.
+///
+/// A limit left unset falls back to the one of the main database configuration,
+/// so overrides can only change or add limits, not remove them.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let config = PartialRetentionConfig {
+///     max_age_in_ms: Some(604800000),
+///     max_bytes: None,
+///     max_events: None,
+///     check_interval_in_ms: Some(60000)
+/// };
+/// # }
+/// ```
+#[cfg_attr(feature = "rustc-serialization", derive(RustcEncodable, RustcDecodable))]
+#[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialRetentionConfig {
+    /// Maximum age of the retained events, based on their timestamp.
+    pub max_age_in_ms: Option<u64>,
+    /// Maximum number of bytes retained, older segments are removed once the newer ones exceed it.
+    pub max_bytes: Option<u64>,
+    /// Maximum number of events retained, older segments are removed once the newer ones exceed it.
+    pub max_events: Option<u64>,
+    /// Amount of time between each retention check.
+    pub check_interval_in_ms: Option<u64>
+}
+
+/// Exar DB's log compaction configuration.
+///
+/// Compaction is enabled by setting the prefix of the tag identifying the key (or entity) of an event,
+/// only the last event for each key is then retained in the completed log segments.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let config = CompactionConfig {
+///     key_tag_prefix: Some("key:".to_owned()),
+///     interval_in_ms: 600000
+/// };
+/// # }
+/// ```
+#[cfg_attr(feature = "rustc-serialization", derive(RustcEncodable, RustcDecodable))]
+#[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompactionConfig {
+    /// Prefix of the tag identifying the key of an event.
+    pub key_tag_prefix: Option<String>,
+    /// Amount of time between each log compaction.
+    pub interval_in_ms: u64
+}
+
+impl Default for CompactionConfig {
+    fn default() -> CompactionConfig {
+        CompactionConfig {
+            key_tag_prefix: None,
+            interval_in_ms: 600000
+        }
+    }
+}
+
+impl CompactionConfig {
+    /// Returns wether log compaction is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.key_tag_prefix.is_some()
+    }
+
+    /// Returns the log compaction interval as an instance of `Duration`.
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_in_ms)
+    }
+}
+
+/// Exar DB's partial log compaction configuration.
+/// Holds overrides for the main database configuration.
+///
+/// A key tag prefix left unset falls back to the one of the main database configuration,
+/// so overrides can only change or enable compaction, not disable it.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let config = PartialCompactionConfig {
+///     key_tag_prefix: Some("key:".to_owned()),
+///     interval_in_ms: Some(600000)
+/// };
+/// # }
+/// ```
+#[cfg_attr(feature = "rustc-serialization", derive(RustcEncodable, RustcDecodable))]
+#[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialCompactionConfig {
+    /// Prefix of the tag identifying the key of an event.
+    pub key_tag_prefix: Option<String>,
+    /// Amount of time between each log compaction.
+    pub interval_in_ms: Option<u64>
+}
+
+/// Exar DB's consumer groups configuration.
+///
+/// An event handed to a member of a consumer group must be acknowledged within the acknowledgement timeout,
+/// otherwise it is delivered again, and it is parked in the dead-letter collection of the group
+/// once it has failed to be processed after the maximum number of deliveries.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let config = ConsumerGroupsConfig {
+///     ack_timeout_in_ms: 30000,
+///     max_deliveries: 5
+/// };
+/// # }
+/// ```
+#[cfg_attr(feature = "rustc-serialization", derive(RustcEncodable, RustcDecodable))]
+#[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConsumerGroupsConfig {
+    /// Amount of time a member has to acknowledge an event before it is delivered again.
+    pub ack_timeout_in_ms: u64,
+    /// Maximum number of deliveries of an event before it is parked in the dead-letter collection.
+    pub max_deliveries: u64
+}
+
+impl Default for ConsumerGroupsConfig {
+    fn default() -> ConsumerGroupsConfig {
+        ConsumerGroupsConfig {
+            ack_timeout_in_ms: 30000,
+            max_deliveries: 5
+        }
+    }
+}
+
+impl ConsumerGroupsConfig {
+    /// Returns the acknowledgement timeout as an instance of `Duration`.
+    pub fn ack_timeout(&self) -> Duration {
+        Duration::from_millis(self.ack_timeout_in_ms)
+    }
+}
+
+/// Exar DB's partial consumer groups configuration.
+/// Holds overrides for the main database configuration.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let config = PartialConsumerGroupsConfig {
+///     ack_timeout_in_ms: Some(30000),
+///     max_deliveries: None
+/// };
+/// # }
+/// ```
+#[cfg_attr(feature = "rustc-serialization", derive(RustcEncodable, RustcDecodable))]
+#[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialConsumerGroupsConfig {
+    /// Amount of time a member has to acknowledge an event before it is delivered again.
+    pub ack_timeout_in_ms: Option<u64>,
+    /// Maximum number of deliveries of an event before it is parked in the dead-letter collection.
+    pub max_deliveries: Option<u64>
+}
+
+/// Exar DB's published events' deduplication configuration.
+///
+/// The client `id`s of the events published are remembered within a window of the last events published,
+/// publishing an event again with the same client `id` returns the `id` of the original event instead,
+/// as long as the original event is still within the window and its client `id` has not been remembered for longer than the maximum age.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let config = DeduplicationConfig {
+///     window_size: 10000,
+///     max_age_in_ms: 3600000
+/// };
+/// # }
+/// ```
+#[cfg_attr(feature = "rustc-serialization", derive(RustcEncodable, RustcDecodable))]
+#[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeduplicationConfig {
+    /// Number of the last events published whose client `id`s are remembered, if set to 0 events are not deduplicated.
+    pub window_size: u64,
+    /// Maximum amount of time the client `id`s are remembered for, since their events have been published
+    /// (or since the collection has been opened, for the events published earlier).
+    pub max_age_in_ms: u64
+}
+
+impl Default for DeduplicationConfig {
+    fn default() -> DeduplicationConfig {
+        DeduplicationConfig {
+            window_size: 10000,
+            max_age_in_ms: 3600000
+        }
+    }
+}
+
+impl DeduplicationConfig {
+    /// Returns wether the events published are deduplicated.
+    pub fn is_enabled(&self) -> bool {
+        self.window_size > 0
+    }
+}
+
+/// Exar DB's partial published events' deduplication configuration.
+/// Holds overrides for the main database configuration.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let config = PartialDeduplicationConfig {
+///     window_size: Some(100000),
+///     max_age_in_ms: None
+/// };
+/// # }
+/// ```
+#[cfg_attr(feature = "rustc-serialization", derive(RustcEncodable, RustcDecodable))]
+#[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialDeduplicationConfig {
+    /// Number of the last events published whose client `id`s are remembered, if set to 0 events are not deduplicated.
+    pub window_size: Option<u64>,
+    /// Maximum amount of time the client `id`s are remembered for, since their events have been published
+    /// (or since the collection has been opened, for the events published earlier).
+    pub max_age_in_ms: Option<u64>
+}
+
 /// Exar DB's collection configuration.
 ///
 /// # Examples
@@ -163,9 +583,33 @@ pub struct PartialScannersConfig {
 ///     logs_path: "/path/to/logs".to_owned(),
 ///     index_granularity: 100000,
 ///     routing_strategy: RoutingStrategy::default(),
+///     durability: Durability::default(),
+///     segment_size: SegmentSize::default(),
+///     event_stream_buffer_size: 1000,
+///     tail_cache_size: 1000,
 ///     scanners: ScannersConfig {
-///         nr_of_scanners: 2,
+///         min_nr_of_scanners: 0,
+///         max_nr_of_scanners: 2,
+///         idle_timeout_in_ms: 60000,
 ///         sleep_time_in_ms: 10
+///     },
+///     retention: RetentionConfig {
+///         max_age_in_ms: Some(604800000),
+///         max_bytes: None,
+///         max_events: None,
+///         check_interval_in_ms: 60000
+///     },
+///     compaction: CompactionConfig {
+///         key_tag_prefix: Some("key:".to_owned()),
+///         interval_in_ms: 600000
+///     },
+///     consumer_groups: ConsumerGroupsConfig {
+///         ack_timeout_in_ms: 30000,
+///         max_deliveries: 5
+///     },
+///     deduplication: DeduplicationConfig {
+///         window_size: 10000,
+///         max_age_in_ms: 3600000
 ///     }
 /// };
 /// # }
@@ -180,8 +624,24 @@ pub struct CollectionConfig {
     pub index_granularity: u64,
     /// Subscriptions' routing strategy.
     pub routing_strategy: RoutingStrategy,
+    /// Log files' durability policy.
+    pub durability: Durability,
+    /// Log segments' size limit.
+    pub segment_size: SegmentSize,
+    /// Maximum number of events buffered by each event stream before its subscription is paused.
+    pub event_stream_buffer_size: usize,
+    /// Maximum number of recently published events kept in memory to serve subscriptions near the tail of the log.
+    pub tail_cache_size: usize,
     /// Log scanners' configuration.
-    pub scanners: ScannersConfig
+    pub scanners: ScannersConfig,
+    /// Log retention configuration.
+    pub retention: RetentionConfig,
+    /// Log compaction configuration.
+    pub compaction: CompactionConfig,
+    /// Consumer groups' configuration.
+    pub consumer_groups: ConsumerGroupsConfig,
+    /// Published events' deduplication configuration.
+    pub deduplication: DeduplicationConfig
 }
 
 impl Default for CollectionConfig {
@@ -191,7 +651,15 @@ impl Default for CollectionConfig {
             logs_path: db_defaults.logs_path,
             index_granularity: db_defaults.index_granularity,
             scanners: db_defaults.scanners,
-            routing_strategy: db_defaults.routing_strategy
+            retention: db_defaults.retention,
+            compaction: db_defaults.compaction,
+            consumer_groups: db_defaults.consumer_groups,
+            deduplication: db_defaults.deduplication,
+            routing_strategy: db_defaults.routing_strategy,
+            durability: db_defaults.durability,
+            segment_size: db_defaults.segment_size,
+            event_stream_buffer_size: db_defaults.event_stream_buffer_size,
+            tail_cache_size: db_defaults.tail_cache_size
         }
     }
 }
@@ -217,9 +685,33 @@ impl CollectionConfig {
 ///     logs_path: Some("/path/to/logs".to_owned()),
 ///     index_granularity: Some(100000),
 ///     routing_strategy: Some(RoutingStrategy::default()),
+///     durability: Some(Durability::EveryEvent),
+///     segment_size: Some(SegmentSize::MaxEvents(1000000)),
+///     event_stream_buffer_size: Some(100),
+///     tail_cache_size: Some(100),
 ///     scanners: Some(PartialScannersConfig {
-///         nr_of_scanners: Some(2),
+///         min_nr_of_scanners: Some(1),
+///         max_nr_of_scanners: Some(4),
+///         idle_timeout_in_ms: None,
 ///         sleep_time_in_ms: Some(10)
+///     }),
+///     retention: Some(PartialRetentionConfig {
+///         max_age_in_ms: None,
+///         max_bytes: Some(1073741824),
+///         max_events: None,
+///         check_interval_in_ms: None
+///     }),
+///     compaction: Some(PartialCompactionConfig {
+///         key_tag_prefix: Some("key:".to_owned()),
+///         interval_in_ms: None
+///     }),
+///     consumer_groups: Some(PartialConsumerGroupsConfig {
+///         ack_timeout_in_ms: Some(60000),
+///         max_deliveries: None
+///     }),
+///     deduplication: Some(PartialDeduplicationConfig {
+///         window_size: Some(100000),
+///         max_age_in_ms: None
 ///     })
 /// };
 /// # }
@@ -234,14 +726,35 @@ pub struct PartialCollectionConfig {
     pub index_granularity: Option<u64>,
     /// Subscriptions' routing strategy.
     pub routing_strategy: Option<RoutingStrategy>,
+    /// Log files' durability policy.
+    pub durability: Option<Durability>,
+    /// Log segments' size limit.
+    pub segment_size: Option<SegmentSize>,
+    /// Maximum number of events buffered by each event stream before its subscription is paused.
+    pub event_stream_buffer_size: Option<usize>,
+    /// Maximum number of recently published events kept in memory to serve subscriptions near the tail of the log.
+    pub tail_cache_size: Option<usize>,
     /// Log scanners' configuration.
-    pub scanners: Option<PartialScannersConfig>
+    pub scanners: Option<PartialScannersConfig>,
+    /// Log retention configuration,
+    /// a retention limit set in the main database configuration cannot be disabled for a collection.
+    pub retention: Option<PartialRetentionConfig>,
+    /// Log compaction configuration,
+    /// compaction enabled in the main database configuration cannot be disabled for a collection.
+    pub compaction: Option<PartialCompactionConfig>,
+    /// Consumer groups' configuration.
+    pub consumer_groups: Option<PartialConsumerGroupsConfig>,
+    /// Published events' deduplication configuration.
+    pub deduplication: Option<PartialDeduplicationConfig>
 }
 
 #[cfg(test)]
 mod tests {
     use super::super::*;
 
+    #[cfg(feature = "serde-serialization")]
+    extern crate serde_json;
+
     #[test]
     fn test_collection_config() {
         let mut db_config = DatabaseConfig::default();
@@ -252,15 +765,47 @@ mod tests {
         assert_eq!(collection_config.index_granularity, db_config.index_granularity);
         assert_eq!(collection_config.scanners, db_config.scanners);
         assert_eq!(collection_config.routing_strategy, db_config.routing_strategy);
+        assert_eq!(collection_config.durability, db_config.durability);
+        assert_eq!(collection_config.segment_size, db_config.segment_size);
+        assert_eq!(collection_config.event_stream_buffer_size, db_config.event_stream_buffer_size);
+        assert_eq!(collection_config.tail_cache_size, db_config.tail_cache_size);
+        assert_eq!(collection_config.retention, db_config.retention);
+        assert_eq!(collection_config.compaction, db_config.compaction);
+        assert_eq!(collection_config.consumer_groups, db_config.consumer_groups);
+        assert_eq!(collection_config.deduplication, db_config.deduplication);
 
         db_config.collections.insert("test".to_owned(), PartialCollectionConfig {
             logs_path: Some("test".to_owned()),
             index_granularity: Some(1000),
             scanners: Some(PartialScannersConfig {
-                nr_of_scanners: Some(3),
+                min_nr_of_scanners: Some(1),
+                max_nr_of_scanners: Some(3),
+                idle_timeout_in_ms: None,
                 sleep_time_in_ms: Some(5)
             }),
-            routing_strategy: Some(RoutingStrategy::Random)
+            routing_strategy: Some(RoutingStrategy::Random),
+            durability: Some(Durability::IntervalMs(50)),
+            segment_size: Some(SegmentSize::MaxBytes(1024)),
+            event_stream_buffer_size: Some(100),
+            tail_cache_size: Some(10),
+            retention: Some(PartialRetentionConfig {
+                max_age_in_ms: Some(1000),
+                max_bytes: None,
+                max_events: Some(100),
+                check_interval_in_ms: None
+            }),
+            compaction: Some(PartialCompactionConfig {
+                key_tag_prefix: Some("key:".to_owned()),
+                interval_in_ms: None
+            }),
+            consumer_groups: Some(PartialConsumerGroupsConfig {
+                ack_timeout_in_ms: Some(60000),
+                max_deliveries: None
+            }),
+            deduplication: Some(PartialDeduplicationConfig {
+                window_size: Some(100),
+                max_age_in_ms: None
+            })
         });
 
         let collection_config = db_config.collection_config("test");
@@ -268,9 +813,47 @@ mod tests {
         assert_eq!(collection_config.logs_path, "test".to_owned());
         assert_eq!(collection_config.index_granularity, 1000);
         assert_eq!(collection_config.scanners, ScannersConfig {
-            nr_of_scanners: 3,
+            min_nr_of_scanners: 1,
+            max_nr_of_scanners: 3,
+            idle_timeout_in_ms: 60000,
             sleep_time_in_ms: 5
         });
         assert_eq!(collection_config.routing_strategy, RoutingStrategy::Random);
+        assert_eq!(collection_config.durability, Durability::IntervalMs(50));
+        assert_eq!(collection_config.segment_size, SegmentSize::MaxBytes(1024));
+        assert_eq!(collection_config.event_stream_buffer_size, 100);
+        assert_eq!(collection_config.tail_cache_size, 10);
+        assert_eq!(collection_config.retention, RetentionConfig {
+            max_age_in_ms: Some(1000),
+            max_bytes: None,
+            max_events: Some(100),
+            check_interval_in_ms: 60000
+        });
+        assert!(collection_config.retention.is_enabled());
+        assert!(!db_config.retention.is_enabled());
+        assert_eq!(collection_config.compaction, CompactionConfig {
+            key_tag_prefix: Some("key:".to_owned()),
+            interval_in_ms: 600000
+        });
+        assert!(collection_config.compaction.is_enabled());
+        assert!(!db_config.compaction.is_enabled());
+        assert_eq!(collection_config.consumer_groups, ConsumerGroupsConfig {
+            ack_timeout_in_ms: 60000,
+            max_deliveries: 5
+        });
+        assert_eq!(collection_config.deduplication, DeduplicationConfig {
+            window_size: 100,
+            max_age_in_ms: 3600000
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "serde-serialization")]
+    fn test_invalid_collection_config() {
+        let config = serde_json::from_str::<PartialCollectionConfig>(r#"{"segment_size": "MaxEvents(1000)"}"#).unwrap();
+        assert_eq!(config.segment_size, Some(SegmentSize::MaxEvents(1000)));
+
+        assert!(serde_json::from_str::<PartialCollectionConfig>(r#"{"segment_size": "MaxEvent(1000)"}"#).is_err());
+        assert!(serde_json::from_str::<PartialCollectionConfig>(r#"{"durability": "Always"}"#).is_err());
     }
 }
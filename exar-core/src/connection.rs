@@ -38,12 +38,96 @@ impl Connection {
         self.collection.lock().unwrap().publish(event)
     }
 
+    /// Publishes a batch of events into the underlying collection atomically and returns the `id`s
+    /// of the first and last events created, or a `DatabaseError` if a failure occurs.
+    pub fn publish_batch(&self, events: Vec<Event>) -> Result<(u64, u64), DatabaseError> {
+        self.collection.lock().unwrap().publish_batch(events)
+    }
+
+    /// Publishes an event into the underlying collection only if the `id` of the last event published is the expected one,
+    /// and returns the `id` for the event created or a `DatabaseError` if a failure occurs.
+    pub fn publish_expecting(&self, event: Event, expected_last_id: u64) -> Result<u64, DatabaseError> {
+        self.collection.lock().unwrap().publish_expecting(event, expected_last_id)
+    }
+
+    /// Publishes a batch of events into the underlying collection atomically only if the `id` of the last event published
+    /// is the expected one, and returns the `id`s of the first and last events created, or a `DatabaseError` if a failure occurs.
+    pub fn publish_batch_expecting(&self, events: Vec<Event>, expected_last_id: u64) -> Result<(u64, u64), DatabaseError> {
+        self.collection.lock().unwrap().publish_batch_expecting(events, expected_last_id)
+    }
+
+    /// Returns the version of the last event published into the given stream of the underlying collection,
+    /// or 0 if the stream does not exist.
+    pub fn stream_version(&self, stream_id: &str) -> u64 {
+        self.collection.lock().unwrap().stream_version(stream_id)
+    }
+
+    /// Publishes an event into its stream of the underlying collection only if the current version of the stream
+    /// is the expected one, and returns the `id` for the event created or a `DatabaseError` if a failure occurs.
+    pub fn publish_expecting_stream_version(&self, event: Event, expected_version: u64) -> Result<u64, DatabaseError> {
+        self.collection.lock().unwrap().publish_expecting_stream_version(event, expected_version)
+    }
+
+    /// Publishes a batch of events into their stream of the underlying collection atomically only if the current version
+    /// of the stream is the expected one, and returns the `id`s of the first and last events created, or a `DatabaseError` if a failure occurs.
+    pub fn publish_batch_expecting_stream_version(&self, events: Vec<Event>, expected_version: u64) -> Result<(u64, u64), DatabaseError> {
+        self.collection.lock().unwrap().publish_batch_expecting_stream_version(events, expected_version)
+    }
+
     /// Subscribes to the underlying collection of events using the given query and returns an event stream
     /// or a `DatabaseError` if a failure occurs.
     pub fn subscribe(&self, query: Query) -> Result<EventStream, DatabaseError> {
         self.collection.lock().unwrap().subscribe(query)
     }
 
+    /// Subscribes to the underlying collection of events on behalf of the given durable consumer using the given query
+    /// and returns an event stream or a `DatabaseError` if a failure occurs.
+    pub fn subscribe_durable(&self, consumer_name: &str, query: Query) -> Result<EventStream, DatabaseError> {
+        self.collection.lock().unwrap().subscribe_durable(consumer_name, query)
+    }
+
+    /// Acknowledges the processing of the events up to the given `id` on behalf of the given durable consumer
+    /// or returns a `DatabaseError` if a failure occurs.
+    pub fn acknowledge(&self, consumer_name: &str, event_id: u64) -> Result<(), DatabaseError> {
+        self.collection.lock().unwrap().acknowledge(consumer_name, event_id)
+    }
+
+    /// Returns the checkpoints of the durable consumers of the underlying collection.
+    pub fn checkpoints(&self) -> Checkpoints {
+        self.collection.lock().unwrap().checkpoints().clone()
+    }
+
+    /// Resets the checkpoint of the given durable consumer to the given event `id`
+    /// or returns a `DatabaseError` if a failure occurs.
+    pub fn reset_checkpoint(&self, consumer_name: &str, event_id: u64) -> Result<(), DatabaseError> {
+        self.collection.lock().unwrap().reset_checkpoint(consumer_name, event_id)
+    }
+
+    /// Deletes the checkpoint of the given durable consumer, if any,
+    /// or returns a `DatabaseError` if a failure occurs.
+    pub fn delete_checkpoint(&self, consumer_name: &str) -> Result<(), DatabaseError> {
+        self.collection.lock().unwrap().delete_checkpoint(consumer_name)
+    }
+
+    /// Aggregates the events of the underlying collection targeted by the given query according to the given aggregation
+    /// and returns an aggregate stream or a `DatabaseError` if a failure occurs.
+    pub fn aggregate(&self, query: Query, aggregation: Aggregation) -> Result<AggregateStream, DatabaseError> {
+        self.collection.lock().unwrap().aggregate(query, aggregation)
+    }
+
+    /// Joins the consumer group of the underlying collection with the given name and returns the new member,
+    /// along with the event stream of the events handed to it, or a `DatabaseError` if a failure occurs.
+    pub fn join_consumer_group(&self, group_name: &str, query: Query,
+                               dead_letter_connection: Connection) -> Result<(ConsumerGroupMember, EventStream), DatabaseError> {
+        self.collection.lock().unwrap().join_consumer_group(group_name, query, dead_letter_connection)
+    }
+
+    /// Returns the description of the consumer group of the underlying collection with the given name
+    /// or a `DatabaseError` if the consumer group does not exist.
+    pub fn consumer_group_info(&self, group_name: &str) -> Result<ConsumerGroupInfo, DatabaseError> {
+        self.collection.lock().unwrap().consumer_group_info(group_name)
+    }
+
     /// Closes the connection.
     pub fn close(self) {
         drop(self)
@@ -72,6 +156,30 @@ mod tests {
         let expected_event = test_event.clone().with_id(1).with_timestamp(retrieved_events[0].timestamp);
         assert_eq!(retrieved_events, vec![expected_event]);
 
+        let test_events = vec![Event::new("data", vec!["tag1"]), Event::new("data", vec!["tag2"])];
+        assert_eq!(connection.publish_batch(test_events), Ok((2, 3)));
+
+        assert_eq!(connection.publish_expecting(Event::new("data", vec!["tag1"]), 2),
+                   Err(DatabaseError::WrongExpectedVersion { expected: 2, actual: 3 }));
+        assert_eq!(connection.publish_expecting_stream_version(Event::new("data", vec!["tag1"]).with_stream("stream1"), 1),
+                   Err(DatabaseError::WrongExpectedVersion { expected: 1, actual: 0 }));
+        assert_eq!(connection.stream_version("stream1"), 0);
+
+        let aggregation = Aggregation::new().group_by_tag();
+        let aggregates: Vec<_> = connection.aggregate(Query::current(), aggregation).unwrap().collect();
+        assert_eq!(aggregates.len(), 1);
+        assert_eq!(aggregates[0].count, 3);
+        assert_eq!(aggregates[0].groups.get(&AggregateGroup::new(Some("tag1"), None)), Some(&2));
+
+        assert_eq!(connection.acknowledge("consumer", 2), Ok(()));
+        let retrieved_events: Vec<_> = connection.subscribe_durable("consumer", Query::current()).unwrap().collect();
+        assert_eq!(retrieved_events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(connection.checkpoints().get("consumer"), Some(2));
+        assert_eq!(connection.reset_checkpoint("consumer", 1), Ok(()));
+        assert_eq!(connection.checkpoints().get("consumer"), Some(1));
+        assert_eq!(connection.delete_checkpoint("consumer"), Ok(()));
+        assert_eq!(connection.checkpoints(), Checkpoints::new());
+
         connection.close();
 
         assert!(db.drop_collection(collection_name).is_ok());
@@ -0,0 +1,574 @@
+use super::*;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{Display, Formatter, Result as DisplayResult};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Exar DB's consumer group.
+///
+/// It shares the event stream of a query between its members, handing each event to a single member,
+/// the one with the fewest events in flight. An event must be acknowledged by its member within the acknowledgement timeout,
+/// otherwise it is delivered again, as are the events negatively acknowledged and the events in flight to a member
+/// leaving the group. An event which has failed to be processed after the maximum number of deliveries
+/// is parked in the dead-letter collection of the group, tagged with `original-id:<id>`,
+/// or delivered again if it cannot be parked, so that it is never lost.
+///
+/// Each member has at most as many events in flight as the event stream buffer size, and the group stops receiving
+/// from the event stream of its query while it holds events it cannot hand to any member, so that slow members
+/// pause the subscription of the group.
+///
+/// The group stops once the event stream of its query has ended and all its events have been processed.
+///
+/// # Examples
+/// ```no_run
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+/// use std::sync::{Arc, Mutex};
+///
+/// let collection_config = CollectionConfig::default();
+/// let mut collection = Collection::new("test", &collection_config).unwrap();
+/// let dead_letter_collection = Collection::new("test.workers.dead-letters", &collection_config).unwrap();
+///
+/// let event_stream = collection.subscribe(Query::live()).unwrap();
+/// let dead_letter_connection = Connection::new(Arc::new(Mutex::new(dead_letter_collection)));
+/// let consumer_group = ConsumerGroup::new("workers", event_stream, dead_letter_connection,
+///                                         ConsumerGroupsConfig::default(), collection_config.event_stream_buffer_size);
+///
+/// let (member, event_stream) = consumer_group.join();
+/// for event in event_stream {
+///     println!("Received event: {}", event);
+///     member.ack(event.id);
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConsumerGroup {
+    state: Arc<Mutex<ConsumerGroupState>>
+}
+
+impl ConsumerGroup {
+    /// Creates a consumer group with the given name, sharing the given event stream between its members,
+    /// parking the events failing to be processed using the given dead-letter connection,
+    /// and starts the thread dispatching the events, up to the given event stream buffer size to each member.
+    pub fn new(name: &str, event_stream: EventStream, dead_letter_connection: Connection,
+               config: ConsumerGroupsConfig, event_stream_buffer_size: usize) -> ConsumerGroup {
+        let state = Arc::new(Mutex::new(ConsumerGroupState::new(name, dead_letter_connection, config, event_stream_buffer_size)));
+        let thread_state = state.clone();
+        thread::spawn(move || {
+            let dispatch_interval = Duration::from_millis(DISPATCH_INTERVAL_IN_MS);
+            loop {
+                let is_backlogged = !thread_state.lock().unwrap().pending.is_empty();
+                let received = if is_backlogged {
+                    thread::sleep(dispatch_interval);
+                    Err(EventStreamError::Empty)
+                } else {
+                    event_stream.recv_timeout(dispatch_interval)
+                };
+                let mut state = thread_state.lock().unwrap();
+                match received {
+                    Ok(event) => state.receive(event),
+                    Err(EventStreamError::Empty) => (),
+                    Err(EventStreamError::Closed) => state.active = false
+                }
+                state.redeliver_expired(Instant::now());
+                state.dispatch();
+                if !state.active && state.pending.is_empty() && state.in_flight.is_empty() {
+                    state.members.clear();
+                    break
+                }
+            }
+        });
+        ConsumerGroup {
+            state: state
+        }
+    }
+
+    /// Joins the consumer group and returns the new member,
+    /// along with the event stream of the events handed to it.
+    pub fn join(&self) -> (ConsumerGroupMember, EventStream) {
+        let (sender, receiver) = channel();
+        let mut state = self.state.lock().unwrap();
+        let member_id = state.next_member_id;
+        state.next_member_id += 1;
+        if state.is_running() {
+            state.members.insert(member_id, sender);
+            state.dispatch();
+        }
+        let member = ConsumerGroupMember {
+            id: member_id,
+            state: self.state.clone()
+        };
+        (member, EventStream::new(receiver))
+    }
+
+    /// Returns wether the consumer group is still dispatching events.
+    pub fn is_running(&self) -> bool {
+        self.state.lock().unwrap().is_running()
+    }
+
+    /// Returns the description of the consumer group, given the `id` of the last event published into the collection.
+    pub fn info(&self, last_event_id: u64) -> ConsumerGroupInfo {
+        let state = self.state.lock().unwrap();
+        let position = state.position();
+        ConsumerGroupInfo {
+            name: state.name.clone(),
+            members: state.members.keys().map(|&member_id| (member_id, state.nr_of_events_in_flight(member_id))).collect(),
+            position: position,
+            lag: last_event_id.saturating_sub(position),
+            nr_of_dead_letters: state.nr_of_dead_letters
+        }
+    }
+}
+
+/// Exar DB's consumer group member.
+///
+/// It acknowledges the events handed to it, and it leaves the consumer group once dropped.
+#[derive(Debug)]
+pub struct ConsumerGroupMember {
+    id: u64,
+    state: Arc<Mutex<ConsumerGroupState>>
+}
+
+impl ConsumerGroupMember {
+    /// Returns the `id` of the member within its consumer group.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Acknowledges the processing of the event with the given `id` and returns whether it was in flight to the member.
+    pub fn ack(&self, event_id: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.in_flight.get(&event_id) {
+            Some(in_flight_event) if in_flight_event.member_id == self.id => (),
+            _ => return false
+        };
+        state.in_flight.remove(&event_id);
+        true
+    }
+
+    /// Negatively acknowledges the event with the given `id`, which is delivered again
+    /// or parked in the dead-letter collection, and returns whether it was in flight to the member.
+    pub fn nack(&self, event_id: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.in_flight.get(&event_id) {
+            Some(in_flight_event) if in_flight_event.member_id == self.id => (),
+            _ => return false
+        };
+        if let Some(in_flight_event) = state.in_flight.remove(&event_id) {
+            state.fail(in_flight_event.event, in_flight_event.deliveries);
+            state.dispatch();
+        }
+        true
+    }
+}
+
+impl Drop for ConsumerGroupMember {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.leave(self.id);
+        state.dispatch();
+    }
+}
+
+/// Exar DB's consumer group description.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConsumerGroupInfo {
+    /// The name of the consumer group.
+    pub name: String,
+    /// The number of events in flight to each member, mapped by the `id` of the member.
+    pub members: BTreeMap<u64, u64>,
+    /// The `id` of the event preceding the oldest event not processed yet,
+    /// or the `id` of the last event received if all of them have been processed.
+    pub position: u64,
+    /// The number of events published into the collection after the position of the group.
+    pub lag: u64,
+    /// The number of events parked in the dead-letter collection.
+    pub nr_of_dead_letters: u64
+}
+
+impl ConsumerGroupInfo {
+    fn encoded_members(&self) -> Vec<String> {
+        self.members.iter().map(|(member_id, nr_of_events_in_flight)| format!("{}:{}", member_id, nr_of_events_in_flight)).collect()
+    }
+}
+
+impl Display for ConsumerGroupInfo {
+    fn fmt(&self, f: &mut Formatter) -> DisplayResult {
+        write!(f, "ConsumerGroupInfo({}, {}, {}, {}, [{}])", self.name, self.position, self.lag,
+               self.nr_of_dead_letters, self.encoded_members().join(", "))
+    }
+}
+
+impl ToTabSeparatedString for ConsumerGroupInfo {
+    fn to_tab_separated_string(&self) -> String {
+        tab_separated!(self.name, self.position, self.lag, self.nr_of_dead_letters, self.encoded_members().join(" "))
+    }
+}
+
+impl FromTabSeparatedStr for ConsumerGroupInfo {
+    fn from_tab_separated_str(s: &str) -> Result<ConsumerGroupInfo, ParseError> {
+        let mut parser = TabSeparatedParser::new(5, s);
+        let name = try!(parser.parse_next());
+        let position = try!(parser.parse_next());
+        let lag = try!(parser.parse_next());
+        let nr_of_dead_letters = try!(parser.parse_next());
+        let encoded_members: String = parser.parse_next().unwrap_or_default();
+        let mut members = BTreeMap::new();
+        for encoded_member in encoded_members.split(' ').filter(|encoded_member| !encoded_member.is_empty()) {
+            let parts: Vec<_> = encoded_member.splitn(2, ':').collect();
+            if parts.len() != 2 {
+                return Err(ParseError::ParseError(format!("invalid consumer group member: {}", encoded_member)))
+            }
+            let member_id = try!(parts[0].parse().map_err(|err| ParseError::ParseError(format!("{}", err))));
+            let nr_of_events_in_flight = try!(parts[1].parse().map_err(|err| ParseError::ParseError(format!("{}", err))));
+            members.insert(member_id, nr_of_events_in_flight);
+        }
+        Ok(ConsumerGroupInfo {
+            name: name,
+            members: members,
+            position: position,
+            lag: lag,
+            nr_of_dead_letters: nr_of_dead_letters
+        })
+    }
+}
+
+/// Returns the name of the dead-letter collection of the given consumer group of a collection.
+pub fn dead_letter_collection_name(collection_name: &str, group_name: &str) -> String {
+    format!("{}.{}.dead-letters", collection_name, group_name)
+}
+
+const DISPATCH_INTERVAL_IN_MS: u64 = 10;
+
+#[derive(Debug)]
+struct InFlightEvent {
+    event: Event,
+    member_id: u64,
+    deliveries: u64,
+    deadline: Instant
+}
+
+#[derive(Debug)]
+struct ConsumerGroupState {
+    name: String,
+    config: ConsumerGroupsConfig,
+    dead_letter_connection: Connection,
+    members: BTreeMap<u64, Sender<EventStreamMessage>>,
+    max_in_flight_per_member: u64,
+    next_member_id: u64,
+    pending: VecDeque<(Event, u64)>,
+    in_flight: BTreeMap<u64, InFlightEvent>,
+    last_received_id: u64,
+    nr_of_dead_letters: u64,
+    active: bool
+}
+
+impl ConsumerGroupState {
+    fn new(name: &str, dead_letter_connection: Connection, config: ConsumerGroupsConfig, event_stream_buffer_size: usize) -> ConsumerGroupState {
+        ConsumerGroupState {
+            name: name.to_owned(),
+            config: config,
+            dead_letter_connection: dead_letter_connection,
+            members: BTreeMap::new(),
+            max_in_flight_per_member: event_stream_buffer_size as u64,
+            next_member_id: 1,
+            pending: VecDeque::new(),
+            in_flight: BTreeMap::new(),
+            last_received_id: 0,
+            nr_of_dead_letters: 0,
+            active: true
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.active || !self.pending.is_empty() || !self.in_flight.is_empty()
+    }
+
+    fn receive(&mut self, event: Event) {
+        self.last_received_id = event.id;
+        self.pending.push_back((event, 0));
+    }
+
+    fn nr_of_events_in_flight(&self, member_id: u64) -> u64 {
+        self.in_flight.values().filter(|in_flight_event| in_flight_event.member_id == member_id).count() as u64
+    }
+
+    fn position(&self) -> u64 {
+        let oldest_in_flight_id = self.in_flight.keys().next().cloned();
+        let oldest_pending_id = self.pending.iter().map(|&(ref event, _)| event.id).min();
+        match (oldest_in_flight_id, oldest_pending_id) {
+            (Some(in_flight_id), Some(pending_id)) => in_flight_id.min(pending_id) - 1,
+            (Some(event_id), None) | (None, Some(event_id)) => event_id - 1,
+            (None, None) => self.last_received_id
+        }
+    }
+
+    fn dispatch(&mut self) {
+        while let Some((event, deliveries)) = self.pending.pop_front() {
+            let least_loaded_member = self.members.keys().map(|&member_id| {
+                (self.nr_of_events_in_flight(member_id), member_id)
+            }).min();
+            match least_loaded_member {
+                Some((nr_of_events_in_flight, member_id)) if nr_of_events_in_flight < self.max_in_flight_per_member => {
+                    let sent = self.members[&member_id].send(EventStreamMessage::Event(Arc::new(event.clone()))).is_ok();
+                    if sent {
+                        self.in_flight.insert(event.id, InFlightEvent {
+                            event: event,
+                            member_id: member_id,
+                            deliveries: deliveries + 1,
+                            deadline: Instant::now() + self.config.ack_timeout()
+                        });
+                    } else {
+                        self.pending.push_front((event, deliveries));
+                        self.leave(member_id);
+                    }
+                },
+                _ => {
+                    self.pending.push_front((event, deliveries));
+                    break
+                }
+            }
+        }
+    }
+
+    fn redeliver_expired(&mut self, now: Instant) {
+        let expired_ids: Vec<_> = self.in_flight.iter().filter(|&(_, in_flight_event)| in_flight_event.deadline <= now)
+                                                       .map(|(&event_id, _)| event_id).collect();
+        for event_id in expired_ids.into_iter().rev() {
+            if let Some(in_flight_event) = self.in_flight.remove(&event_id) {
+                self.fail(in_flight_event.event, in_flight_event.deliveries);
+            }
+        }
+    }
+
+    fn leave(&mut self, member_id: u64) {
+        self.members.remove(&member_id);
+        let member_event_ids: Vec<_> = self.in_flight.iter().filter(|&(_, in_flight_event)| in_flight_event.member_id == member_id)
+                                                            .map(|(&event_id, _)| event_id).collect();
+        for event_id in member_event_ids.into_iter().rev() {
+            if let Some(in_flight_event) = self.in_flight.remove(&event_id) {
+                self.fail(in_flight_event.event, in_flight_event.deliveries);
+            }
+        }
+    }
+
+    fn fail(&mut self, event: Event, deliveries: u64) {
+        if deliveries < self.config.max_deliveries {
+            self.pending.push_front((event, deliveries));
+        } else {
+            let original_id_tag = format!("original-id:{}", event.id);
+            let mut tags: Vec<_> = event.tags.iter().map(|tag| &tag[..]).collect();
+            tags.push(&original_id_tag);
+            let dead_letter = Event::from_bytes(event.data.clone(), tags).with_timestamp(event.timestamp);
+            match self.dead_letter_connection.publish(dead_letter) {
+                Ok(_) => self.nr_of_dead_letters += 1,
+                Err(err) => {
+                    error!("Unable to park event {} of consumer group '{}' in the dead-letter collection, delivering it again: {}",
+                           event.id, self.name, err);
+                    self.pending.push_front((event, deliveries));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use exar_testkit::*;
+
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::{channel, sync_channel, TrySendError};
+    use std::time::Duration;
+
+    fn create_dead_letter_collection() -> (String, Arc<Mutex<Collection>>) {
+        let collection_name = random_collection_name();
+        let collection = Collection::new(&collection_name, &CollectionConfig::default()).expect("Unable to create collection");
+        (collection_name, Arc::new(Mutex::new(collection)))
+    }
+
+    fn event(id: u64) -> Event {
+        Event::new("data", vec!["tag1"]).with_id(id).with_timestamp(id * 1000)
+    }
+
+    #[test]
+    fn test_dispatch_and_ack() {
+        let (_, dead_letter_collection) = create_dead_letter_collection();
+        let (sender, receiver) = channel();
+        let consumer_group = ConsumerGroup::new("group", EventStream::new(receiver),
+                                                Connection::new(dead_letter_collection.clone()), ConsumerGroupsConfig::default(), 1000);
+
+        let (member1, event_stream1) = consumer_group.join();
+        let (member2, event_stream2) = consumer_group.join();
+        for id in 1..5 {
+            assert!(sender.send(EventStreamMessage::Event(Arc::new(event(id)))).is_ok());
+        }
+
+        assert_eq!(event_stream1.recv(), Ok(event(1)));
+        assert_eq!(event_stream2.recv(), Ok(event(2)));
+        assert_eq!(event_stream1.recv(), Ok(event(3)));
+        assert_eq!(event_stream2.recv(), Ok(event(4)));
+
+        assert!(member1.ack(1));
+        assert!(!member1.ack(1));
+        assert!(!member1.ack(2));
+        assert!(member2.ack(2));
+        assert!(member2.ack(4));
+
+        let mut members = BTreeMap::new();
+        members.insert(1, 1);
+        members.insert(2, 0);
+        assert_eq!(consumer_group.info(10), ConsumerGroupInfo {
+            name: "group".to_owned(),
+            members: members,
+            position: 2,
+            lag: 8,
+            nr_of_dead_letters: 0
+        });
+
+        assert!(member1.ack(3));
+        assert_eq!(consumer_group.info(10).position, 4);
+
+        drop(sender);
+        assert_eq!(event_stream1.recv(), Err(EventStreamError::Closed));
+        assert_eq!(event_stream2.recv(), Err(EventStreamError::Closed));
+        assert!(!consumer_group.is_running());
+
+        assert!((*dead_letter_collection.lock().unwrap()).drop().is_ok());
+    }
+
+    #[test]
+    fn test_redelivery_and_dead_letters() {
+        let (_, dead_letter_collection) = create_dead_letter_collection();
+        let (sender, receiver) = channel();
+        let config = ConsumerGroupsConfig {
+            ack_timeout_in_ms: 100,
+            max_deliveries: 2
+        };
+        let consumer_group = ConsumerGroup::new("group", EventStream::new(receiver), Connection::new(dead_letter_collection.clone()), config, 1000);
+
+        let (member1, event_stream1) = consumer_group.join();
+        assert!(sender.send(EventStreamMessage::Event(Arc::new(event(1)))).is_ok());
+        assert!(sender.send(EventStreamMessage::Event(Arc::new(event(2)))).is_ok());
+
+        assert_eq!(event_stream1.recv(), Ok(event(1)));
+        assert_eq!(event_stream1.recv(), Ok(event(2)));
+        assert!(member1.nack(1));
+        assert_eq!(event_stream1.recv(), Ok(event(1)));
+
+        let (member2, event_stream2) = consumer_group.join();
+        drop(member1);
+        assert_eq!(event_stream2.recv_timeout(Duration::from_millis(100)), Ok(event(2)));
+        assert_eq!(event_stream1.recv(), Err(EventStreamError::Closed));
+
+        assert_eq!(event_stream2.recv_timeout(Duration::from_millis(100)), Err(EventStreamError::Empty));
+        assert_eq!(event_stream2.recv_timeout(Duration::from_millis(100)), Err(EventStreamError::Empty));
+        assert_eq!(consumer_group.info(2).nr_of_dead_letters, 2);
+        assert_eq!(consumer_group.info(2).position, 2);
+
+        let dead_letters: Vec<_> = dead_letter_collection.lock().unwrap().subscribe(Query::current()).unwrap().collect();
+        assert_eq!(dead_letters.iter().map(|e| e.tags.clone()).collect::<Vec<_>>(), vec![
+            vec!["tag1".to_owned(), "original-id:1".to_owned()],
+            vec!["tag1".to_owned(), "original-id:2".to_owned()]
+        ]);
+        assert_eq!(dead_letters.iter().map(|e| e.timestamp).collect::<Vec<_>>(), vec![1000, 2000]);
+
+        drop(member2);
+        assert!((*dead_letter_collection.lock().unwrap()).drop().is_ok());
+    }
+
+    #[test]
+    fn test_redelivery_of_events_failing_to_be_parked() {
+        let (_, dead_letter_collection) = create_dead_letter_collection();
+        let (sender, receiver) = channel();
+        let config = ConsumerGroupsConfig {
+            ack_timeout_in_ms: 1000,
+            max_deliveries: 1
+        };
+        let consumer_group = ConsumerGroup::new("group", EventStream::new(receiver), Connection::new(dead_letter_collection.clone()), config, 1000);
+
+        // the dead letter of an event with the maximum number of tags fails validation once tagged with its original id
+        let tags: Vec<_> = (0..u16::max_value()).map(|i| format!("tag{}", i)).collect();
+        let event = Event::new("data", tags.iter().map(|tag| &tag[..]).collect()).with_id(1).with_timestamp(1000);
+
+        let (member, event_stream) = consumer_group.join();
+        assert!(sender.send(EventStreamMessage::Event(Arc::new(event.clone()))).is_ok());
+
+        assert_eq!(event_stream.recv(), Ok(event.clone()));
+        assert!(member.nack(1));
+        assert_eq!(event_stream.recv_timeout(Duration::from_millis(100)), Ok(event.clone()));
+        assert!(member.nack(1));
+        assert_eq!(event_stream.recv_timeout(Duration::from_millis(100)), Ok(event));
+
+        assert_eq!(consumer_group.info(1).nr_of_dead_letters, 0);
+        assert_eq!(consumer_group.info(1).position, 0);
+
+        assert!(member.ack(1));
+        assert_eq!(consumer_group.info(1).position, 1);
+
+        drop(member);
+        assert!((*dead_letter_collection.lock().unwrap()).drop().is_ok());
+    }
+
+    #[test]
+    fn test_backpressure() {
+        let (_, dead_letter_collection) = create_dead_letter_collection();
+        let (sender, receiver) = sync_channel(1);
+        let consumer_group = ConsumerGroup::new("group", EventStream::new(receiver),
+                                                Connection::new(dead_letter_collection.clone()), ConsumerGroupsConfig::default(), 2);
+
+        let (member, event_stream) = consumer_group.join();
+        for id in 1..5 {
+            assert!(sender.send(EventStreamMessage::Event(Arc::new(event(id)))).is_ok());
+        }
+        assert_eq!(sender.try_send(EventStreamMessage::Event(Arc::new(event(5)))), Err(TrySendError::Full(EventStreamMessage::Event(Arc::new(event(5))))));
+
+        assert_eq!(event_stream.recv(), Ok(event(1)));
+        assert_eq!(event_stream.recv(), Ok(event(2)));
+        assert_eq!(event_stream.recv_timeout(Duration::from_millis(100)), Err(EventStreamError::Empty));
+
+        assert!(member.ack(1));
+        assert_eq!(event_stream.recv(), Ok(event(3)));
+        assert!(sender.send(EventStreamMessage::Event(Arc::new(event(5)))).is_ok());
+
+        assert!(member.ack(2));
+        assert!(member.ack(3));
+        assert_eq!(event_stream.recv(), Ok(event(4)));
+        assert_eq!(event_stream.recv(), Ok(event(5)));
+
+        drop(member);
+        assert!((*dead_letter_collection.lock().unwrap()).drop().is_ok());
+    }
+
+    #[test]
+    fn test_consumer_group_info_tab_separated_encoding() {
+        let mut members = BTreeMap::new();
+        members.insert(1, 3);
+        members.insert(2, 0);
+        let info = ConsumerGroupInfo {
+            name: "group".to_owned(),
+            members: members,
+            position: 10,
+            lag: 5,
+            nr_of_dead_letters: 1
+        };
+        let string = "group\t10\t5\t1\t1:3 2:0";
+        assert_eq!(info.to_tab_separated_string(), string);
+        assert_eq!(ConsumerGroupInfo::from_tab_separated_str(string), Ok(info.clone()));
+        assert_eq!(format!("{}", info), "ConsumerGroupInfo(group, 10, 5, 1, [1:3, 2:0])");
+
+        let info = ConsumerGroupInfo {
+            members: BTreeMap::new(),
+            ..info
+        };
+        assert_eq!(ConsumerGroupInfo::from_tab_separated_str("group\t10\t5\t1\t"), Ok(info.clone()));
+        assert_eq!(ConsumerGroupInfo::from_tab_separated_str("group\t10\t5\t1"), Ok(info));
+        assert!(ConsumerGroupInfo::from_tab_separated_str("group\t10\t5\t1\t1").is_err());
+    }
+}
@@ -77,6 +77,20 @@ impl Database {
         })
     }
 
+    /// Joins the consumer group with the given name of the collection with the given name and returns the new member,
+    /// along with the event stream of the events handed to it, or a `DatabaseError` if a failure occurs.
+    ///
+    /// The events failing to be processed by the consumer group are parked in its dead-letter collection,
+    /// named after the collection and the consumer group (i.e. `<collection_name>.<group_name>.dead-letters`).
+    pub fn join_consumer_group(&mut self, collection_name: &str, group_name: &str,
+                               query: Query) -> Result<(ConsumerGroupMember, EventStream), DatabaseError> {
+        try!(validate_consumer_name(group_name).map_err(DatabaseError::ValidationError));
+        let dead_letter_collection = try!(self.get_collection(&dead_letter_collection_name(collection_name, group_name)));
+        self.connect(collection_name).and_then(|connection| {
+            connection.join_consumer_group(group_name, query, Connection::new(dead_letter_collection))
+        })
+    }
+
     /// Returns wether a collection with the given name exists.
     pub fn contains_collection(&self, collection_name: &str) -> bool {
         self.collections.contains_key(collection_name)
@@ -116,6 +130,28 @@ mod tests {
         assert!(db.drop_collection(collection_name).is_err());
     }
 
+    #[test]
+    fn test_consumer_groups() {
+        let mut db = Database::new(DatabaseConfig::default());
+
+        let ref collection_name = random_collection_name();
+        let ref dead_letter_collection_name = dead_letter_collection_name(collection_name, "group");
+        let connection = db.connect(collection_name).expect("Unable to connect");
+        assert_eq!(connection.publish(Event::new("data", vec!["tag1"])), Ok(1));
+
+        let (member, event_stream) = db.join_consumer_group(collection_name, "group", Query::live()).expect("Unable to join consumer group");
+        assert!(db.contains_collection(dead_letter_collection_name));
+        assert_eq!(event_stream.recv().map(|event| event.id), Ok(1));
+        assert_eq!(connection.consumer_group_info("group").map(|info| info.position), Ok(0));
+        assert!(member.ack(1));
+        assert_eq!(connection.consumer_group_info("group").map(|info| info.position), Ok(1));
+
+        assert!(db.join_consumer_group(collection_name, "in valid", Query::live()).is_err());
+
+        assert!(db.drop_collection(collection_name).is_ok());
+        assert!(db.drop_collection(dead_letter_collection_name).is_ok());
+    }
+
     #[test]
     fn test_collection_management() {
         let mut db = Database::new(DatabaseConfig::default());
@@ -0,0 +1,147 @@
+use std::collections::{HashMap, VecDeque};
+
+/// Exar DB's deduplication window.
+///
+/// It maps the client `id`s of the last events published to their event `id`s, allowing to recognize an event
+/// published again by a client (for instance when retrying after a timeout) and to return the `id` of the original event.
+///
+/// The client `id`s are remembered as long as their event is within the last `window_size` events published,
+/// and for at most `max_age_in_ms` since they have been inserted.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let mut deduplication_window = DeduplicationWindow::new(2, 60000);
+/// deduplication_window.insert(1, "client1", 1000);
+/// deduplication_window.insert(3, "client3", 3000);
+///
+/// assert_eq!(deduplication_window.get("client1"), Some(1));
+///
+/// deduplication_window.remove_expired(3, 3000);
+///
+/// assert_eq!(deduplication_window.get("client1"), None);
+/// assert_eq!(deduplication_window.get("client3"), Some(3));
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeduplicationWindow {
+    event_ids: HashMap<String, u64>,
+    entries: VecDeque<(u64, u64, String)>,
+    window_size: u64,
+    max_age_in_ms: u64
+}
+
+impl DeduplicationWindow {
+    /// Creates a new empty deduplication window with the given size and maximum age.
+    pub fn new(window_size: u64, max_age_in_ms: u64) -> DeduplicationWindow {
+        DeduplicationWindow {
+            event_ids: HashMap::new(),
+            entries: VecDeque::new(),
+            window_size: window_size,
+            max_age_in_ms: max_age_in_ms
+        }
+    }
+
+    /// Remembers the given client `id` for the event with the given `id`, as of the given timestamp.
+    ///
+    /// Events must be inserted in `id` order, an event preceding the last one inserted is ignored,
+    /// and nothing is remembered if the window size is 0.
+    pub fn insert(&mut self, id: u64, client_id: &str, timestamp: u64) {
+        if self.window_size == 0 || self.entries.back().map_or(false, |&(last_id, _, _)| id <= last_id) {
+            return
+        }
+        self.event_ids.insert(client_id.to_owned(), id);
+        self.entries.push_back((id, timestamp, client_id.to_owned()));
+    }
+
+    /// Returns the `id` of the event published with the given client `id`, if it is remembered.
+    pub fn get(&self, client_id: &str) -> Option<u64> {
+        self.event_ids.get(client_id).cloned()
+    }
+
+    /// Forgets the client `id`s of the events outside the window, given the `id` of the last event published,
+    /// and the ones older than the maximum age, given the current timestamp.
+    pub fn remove_expired(&mut self, last_id: u64, timestamp: u64) {
+        while let Some(&(id, event_timestamp, _)) = self.entries.front() {
+            if id + self.window_size > last_id && event_timestamp + self.max_age_in_ms >= timestamp {
+                break
+            }
+            if let Some((id, _, client_id)) = self.entries.pop_front() {
+                if self.event_ids.get(&client_id) == Some(&id) {
+                    self.event_ids.remove(&client_id);
+                }
+            }
+        }
+    }
+
+    /// Returns the number of client `id`s remembered.
+    pub fn len(&self) -> usize {
+        self.event_ids.len()
+    }
+
+    /// Returns wether no client `id` is remembered.
+    pub fn is_empty(&self) -> bool {
+        self.event_ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn test_deduplication_window() {
+        let mut deduplication_window = DeduplicationWindow::new(5, 10000);
+
+        assert!(deduplication_window.is_empty());
+
+        deduplication_window.insert(1, "client1", 1000);
+        deduplication_window.insert(2, "client2", 2000);
+        deduplication_window.insert(4, "client4", 4000);
+        deduplication_window.insert(3, "client3", 3000);
+
+        assert_eq!(deduplication_window.len(), 3);
+        assert_eq!(deduplication_window.get("client1"), Some(1));
+        assert_eq!(deduplication_window.get("client3"), None);
+
+        deduplication_window.remove_expired(5, 5000);
+
+        assert_eq!(deduplication_window.get("client1"), Some(1));
+
+        deduplication_window.remove_expired(6, 5000);
+
+        assert_eq!(deduplication_window.get("client1"), None);
+        assert_eq!(deduplication_window.get("client2"), Some(2));
+
+        deduplication_window.remove_expired(6, 12500);
+
+        assert_eq!(deduplication_window.get("client2"), None);
+        assert_eq!(deduplication_window.get("client4"), Some(4));
+
+        deduplication_window.remove_expired(6, 15000);
+
+        assert!(deduplication_window.is_empty());
+    }
+
+    #[test]
+    fn test_reused_client_id() {
+        let mut deduplication_window = DeduplicationWindow::new(2, 10000);
+
+        deduplication_window.insert(1, "client1", 1000);
+        deduplication_window.insert(2, "client1", 2000);
+
+        assert_eq!(deduplication_window.get("client1"), Some(2));
+
+        deduplication_window.remove_expired(3, 2000);
+
+        assert_eq!(deduplication_window.get("client1"), Some(2));
+
+        deduplication_window.remove_expired(4, 2000);
+
+        assert!(deduplication_window.is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+#[cfg(feature = "rustc-serialization")] use rustc_serialize::{Encoder, Encodable, Decoder, Decodable};
+#[cfg(feature = "serde-serialization")] use serde::{Serialize, Serializer, Deserialize, Deserializer};
+#[cfg(feature = "serde-serialization")] use serde::de::{Error, Visitor};
+
+use std::fmt;
+use std::time::Duration;
+
+/// A list specifying categories of durability policy,
+/// used to decide when the events written to a log are synced to disk.
+///
+/// It is encoded as a string, i.e. `None`, `EveryEvent`, `EveryNEvents(100)` or `IntervalMs(50)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Durability {
+    /// The log is flushed to the OS after each event but never explicitly synced to disk.
+    None,
+    /// The log is synced to disk after each event, before the event is acknowledged.
+    EveryEvent,
+    /// The log is synced to disk after every `n` events.
+    EveryNEvents(u64),
+    /// The log is synced to disk in the background at the given interval (in milliseconds).
+    IntervalMs(u64)
+}
+
+impl Durability {
+    /// Parses a durability policy from its string representation, returning `None` if it is invalid.
+    pub fn parse(s: &str) -> Option<Durability> {
+        match s {
+            "None" => Some(Durability::None),
+            "EveryEvent" => Some(Durability::EveryEvent),
+            _ => {
+                let parse_argument = |prefix: &str| {
+                    if s.starts_with(prefix) && s.ends_with(')') {
+                        s[prefix.len()..s.len() - 1].parse::<u64>().ok().and_then(|n| if n > 0 { Some(n) } else { None })
+                    } else {
+                        None
+                    }
+                };
+                parse_argument("EveryNEvents(").map(Durability::EveryNEvents).or_else(|| {
+                    parse_argument("IntervalMs(").map(Durability::IntervalMs)
+                })
+            }
+        }
+    }
+
+    /// Returns the background sync interval as an instance of `Duration`, if any.
+    pub fn sync_interval(&self) -> Option<Duration> {
+        match *self {
+            Durability::IntervalMs(interval) => Some(Duration::from_millis(interval)),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Durability::None => write!(f, "None"),
+            Durability::EveryEvent => write!(f, "EveryEvent"),
+            Durability::EveryNEvents(n) => write!(f, "EveryNEvents({})", n),
+            Durability::IntervalMs(interval) => write!(f, "IntervalMs({})", interval)
+        }
+    }
+}
+
+#[cfg(feature = "rustc-serialization")]
+impl Encodable for Durability {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_str(&self.to_string())
+    }
+}
+
+#[cfg(feature = "rustc-serialization")]
+impl Decodable for Durability {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        let s = try!(d.read_str());
+        match Durability::parse(&s) {
+            Some(durability) => Ok(durability),
+            None => Err(d.error(&format!("invalid durability: {}", s)))
+        }
+    }
+}
+
+#[cfg(feature = "serde-serialization")]
+impl Serialize for Durability {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+#[cfg(feature = "serde-serialization")]
+impl Deserialize for Durability {
+    fn deserialize<D: Deserializer>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(DurabilityVisitor)
+    }
+}
+
+#[cfg(feature = "serde-serialization")]
+struct DurabilityVisitor;
+
+#[cfg(feature = "serde-serialization")]
+impl Visitor for DurabilityVisitor {
+    type Value = Durability;
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("None, EveryEvent, EveryNEvents(n) or IntervalMs(n)")
+    }
+    fn visit_str<E: Error>(self, s: &str) -> Result<Durability, E> {
+        Durability::parse(s).ok_or_else(|| E::custom(format!("invalid durability: {}", s)))
+    }
+}
+
+impl Default for Durability {
+    fn default() -> Self {
+        Durability::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[cfg(feature = "rustc-serialization")]
+    use rustc_serialize::json;
+
+    #[cfg(feature = "serde-serialization")]
+    extern crate serde_json;
+
+    #[test]
+    fn test_default() {
+        assert_eq!(Durability::default(), Durability::None);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Durability::parse("None"), Some(Durability::None));
+        assert_eq!(Durability::parse("EveryEvent"), Some(Durability::EveryEvent));
+        assert_eq!(Durability::parse("EveryNEvents(100)"), Some(Durability::EveryNEvents(100)));
+        assert_eq!(Durability::parse("IntervalMs(50)"), Some(Durability::IntervalMs(50)));
+        assert_eq!(Durability::parse("EveryNEvents(0)"), None);
+        assert_eq!(Durability::parse("IntervalMs(abc)"), None);
+        assert_eq!(Durability::parse("Always"), None);
+    }
+
+    #[test]
+    fn test_display() {
+        for durability in &[Durability::None, Durability::EveryEvent, Durability::EveryNEvents(100), Durability::IntervalMs(50)] {
+            assert_eq!(Durability::parse(&durability.to_string()).as_ref(), Some(durability));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rustc-serialization")]
+    fn test_rustc_serialization() {
+        let durability = Durability::EveryEvent;
+        assert_eq!(json::encode(&durability).unwrap(), "\"EveryEvent\"");
+        assert_eq!(json::decode::<Durability>("\"EveryEvent\"").unwrap(), durability);
+
+        let durability = Durability::IntervalMs(50);
+        assert_eq!(json::encode(&durability).unwrap(), "\"IntervalMs(50)\"");
+        assert_eq!(json::decode::<Durability>("\"IntervalMs(50)\"").unwrap(), durability);
+
+        assert!(json::decode::<Durability>("\"Always\"").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde-serialization")]
+    fn test_serde_serialization() {
+        let durability = Durability::EveryEvent;
+        assert_eq!(serde_json::to_string(&durability).unwrap(), "\"EveryEvent\"");
+        assert_eq!(serde_json::from_str::<Durability>("\"EveryEvent\"").unwrap(), durability);
+
+        let durability = Durability::EveryNEvents(100);
+        assert_eq!(serde_json::to_string(&durability).unwrap(), "\"EveryNEvents(100)\"");
+        assert_eq!(serde_json::from_str::<Durability>("\"EveryNEvents(100)\"").unwrap(), durability);
+
+        assert!(serde_json::from_str::<Durability>("\"Always\"").is_err());
+    }
+}
@@ -41,14 +41,17 @@ pub enum ParseError {
     /// The parsing failed because of the given reason.
     ParseError(String),
     /// The parsing failed because of a missing field at the given position.
-    MissingField(usize)
+    MissingField(usize),
+    /// The parsing failed at the given position (in bytes) because of the given reason.
+    SyntaxError(usize, String)
 }
 
 impl Display for ParseError {
     fn fmt(&self, f: &mut Formatter) -> DisplayResult {
         match *self {
             ParseError::ParseError(ref description) => write!(f, "{}", description),
-            ParseError::MissingField(index) => write!(f, "missing field at index {}", index)
+            ParseError::MissingField(index) => write!(f, "missing field at index {}", index),
+            ParseError::SyntaxError(position, ref description) => write!(f, "{} at position {}", description, position)
         }
     }
 }
@@ -16,10 +16,14 @@ pub enum DatabaseError {
     IoError(ErrorKind, String),
     /// The parsing of an event from the log file failed.
     ParseError(ParseError),
+    /// The requested events are no longer retained, it contains the `id` of the earliest retained event.
+    RetentionError(u64),
     /// The attempted subscription failed.
     SubscriptionError,
     /// The validation of the event failed.
-    ValidationError(ValidationError)
+    ValidationError(ValidationError),
+    /// The `id` of the last event published differs from the expected one, so the events have not been published.
+    WrongExpectedVersion { expected: u64, actual: u64 }
 }
 
 impl DatabaseError {
@@ -45,10 +49,15 @@ impl ToTabSeparatedString for DatabaseError {
             },
             DatabaseError::ParseError(ref error) => match *error {
                 ParseError::ParseError(ref description) => tab_separated!("ParseError", "ParseError", description),
-                ParseError::MissingField(index) => tab_separated!("ParseError", "MissingField", index)
+                ParseError::MissingField(index) => tab_separated!("ParseError", "MissingField", index),
+                ParseError::SyntaxError(position, ref description) => {
+                    tab_separated!("ParseError", "SyntaxError", position, description)
+                }
             },
+            DatabaseError::RetentionError(first_retained_id) => tab_separated!("RetentionError", first_retained_id),
             DatabaseError::SubscriptionError => tab_separated!("SubscriptionError"),
-            DatabaseError::ValidationError(ref error) => tab_separated!("ValidationError", error.description)
+            DatabaseError::ValidationError(ref error) => tab_separated!("ValidationError", error.description),
+            DatabaseError::WrongExpectedVersion { expected, actual } => tab_separated!("WrongExpectedVersion", expected, actual)
         }
     }
 }
@@ -89,14 +98,32 @@ impl FromTabSeparatedStr for DatabaseError {
                         let error = ParseError::MissingField(try!(parser.parse_next()));
                         Ok(DatabaseError::ParseError(error))
                     },
+                    "SyntaxError" => {
+                        let error_data: String = try!(parser.parse_next());
+                        let mut parser = TabSeparatedParser::new(2, &error_data);
+                        let position = try!(parser.parse_next());
+                        let error = ParseError::SyntaxError(position, try!(parser.parse_next()));
+                        Ok(DatabaseError::ParseError(error))
+                    },
                     x => Err(ParseError::ParseError(format!("unknown parse error: {}", x)))
                 }
             },
+            "RetentionError" => {
+                let first_retained_id = try!(parser.parse_next());
+                Ok(DatabaseError::RetentionError(first_retained_id))
+            },
             "SubscriptionError" => Ok(DatabaseError::SubscriptionError),
             "ValidationError" => {
                 let description: String = try!(parser.parse_next());
                 Ok(DatabaseError::ValidationError(ValidationError::new(&description)))
             },
+            "WrongExpectedVersion" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let expected = try!(parser.parse_next());
+                let actual = try!(parser.parse_next());
+                Ok(DatabaseError::WrongExpectedVersion { expected: expected, actual: actual })
+            },
             x => Err(ParseError::ParseError(format!("unknown database error: {}", x)))
         }
     }
@@ -163,8 +190,14 @@ impl Display for DatabaseError {
             DatabaseError::EventStreamError(EventStreamError::Empty) => write!(f, "event stream is empty"),
             DatabaseError::IoError(_, ref error) => write!(f, "{}", error),
             DatabaseError::ParseError(ref error) => write!(f, "{}", error),
+            DatabaseError::RetentionError(first_retained_id) => {
+                write!(f, "requested events are no longer retained, the earliest retained event is {}", first_retained_id)
+            },
             DatabaseError::SubscriptionError => write!(f, "subscription failure"),
-            DatabaseError::ValidationError(ref error) => write!(f, "{}", error)
+            DatabaseError::ValidationError(ref error) => write!(f, "{}", error),
+            DatabaseError::WrongExpectedVersion { expected, actual } => {
+                write!(f, "wrong expected version, the last event is {} instead of {}", actual, expected)
+            }
         }
     }
 }
@@ -184,8 +217,11 @@ mod tests {
         let io_error = DatabaseError::IoError(ErrorKind::Other, "error".to_owned());
         let parse_error = DatabaseError::ParseError(ParseError::ParseError("error".to_owned()));
         let missig_field = DatabaseError::ParseError(ParseError::MissingField(1));
+        let syntax_error = DatabaseError::ParseError(ParseError::SyntaxError(5, "error".to_owned()));
+        let retention_error = DatabaseError::RetentionError(100);
         let subscription_error = DatabaseError::SubscriptionError;
         let validation_error = DatabaseError::ValidationError(ValidationError { description: "error".to_owned() });
+        let wrong_expected_version = DatabaseError::WrongExpectedVersion { expected: 5, actual: 7 };
 
         assert_encoded_eq!(authentication_error, "AuthenticationError");
         assert_encoded_eq!(connection_error, "ConnectionError");
@@ -194,8 +230,11 @@ mod tests {
         assert_encoded_eq!(io_error, "IoError\tOther\terror");
         assert_encoded_eq!(parse_error, "ParseError\tParseError\terror");
         assert_encoded_eq!(missig_field, "ParseError\tMissingField\t1");
+        assert_encoded_eq!(syntax_error, "ParseError\tSyntaxError\t5\terror");
+        assert_encoded_eq!(retention_error, "RetentionError\t100");
         assert_encoded_eq!(subscription_error, "SubscriptionError");
         assert_encoded_eq!(validation_error, "ValidationError\terror");
+        assert_encoded_eq!(wrong_expected_version, "WrongExpectedVersion\t5\t7");
     }
 
     #[test]
@@ -207,8 +246,11 @@ mod tests {
         let io_error = DatabaseError::IoError(ErrorKind::Other, "error".to_owned());
         let parse_error = DatabaseError::ParseError(ParseError::ParseError("error".to_owned()));
         let missig_field = DatabaseError::ParseError(ParseError::MissingField(1));
+        let syntax_error = DatabaseError::ParseError(ParseError::SyntaxError(5, "error".to_owned()));
+        let retention_error = DatabaseError::RetentionError(100);
         let subscription_error = DatabaseError::SubscriptionError;
         let validation_error = DatabaseError::ValidationError(ValidationError { description: "error".to_owned() });
+        let wrong_expected_version = DatabaseError::WrongExpectedVersion { expected: 5, actual: 7 };
 
         assert_decoded_eq!("AuthenticationError", authentication_error);
         assert_decoded_eq!("ConnectionError", connection_error);
@@ -217,7 +259,10 @@ mod tests {
         assert_decoded_eq!("IoError\tOther\terror", io_error);
         assert_decoded_eq!("ParseError\tParseError\terror", parse_error);
         assert_decoded_eq!("ParseError\tMissingField\t1", missig_field);
+        assert_decoded_eq!("ParseError\tSyntaxError\t5\terror", syntax_error);
+        assert_decoded_eq!("RetentionError\t100", retention_error);
         assert_decoded_eq!("SubscriptionError", subscription_error);
         assert_decoded_eq!("ValidationError\terror", validation_error);
+        assert_decoded_eq!("WrongExpectedVersion\t5\t7", wrong_expected_version);
     }
 }
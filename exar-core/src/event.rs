@@ -1,10 +1,29 @@
 use super::*;
 
 use std::fmt::{Display, Formatter, Result as DisplayResult};
-use std::sync::mpsc::{Receiver, TryRecvError};
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::time::Duration;
 
 use time;
 
+/// The log record flag marking an event as part of a batch that continues with the following record.
+///
+/// The last event of a batch does not have the flag set, so a batch is only complete once its last record is.
+pub const BATCH_CONTINUATION_FLAG: u8 = 0b0000_0001;
+
+/// The log record flag marking an event as part of a stream, whose `id` and version follow the event tags.
+///
+/// It is set when encoding an event belonging to a stream, so that records of events outside streams are unchanged.
+pub const STREAM_FLAG: u8 = 0b0000_0010;
+
+/// The log record flag marking an event carrying a client `id`, which follows the event tags and stream.
+///
+/// The client `id` of an event is kept in the log so that the deduplication window can be restored from it.
+pub const CLIENT_ID_FLAG: u8 = 0b0000_0100;
+
 /// Exar DB's event.
 ///
 /// # Examples
@@ -15,6 +34,12 @@ use time;
 /// use exar::*;
 ///
 /// let event = Event::new("data", vec!["tag1", "tag2"]);
+///
+/// // appended to a stream, its stream version is set once published
+/// let stream_event = Event::new("data", vec!["order"]).with_stream("order-123");
+///
+/// // publishing it again returns the id of the original event instead of publishing a duplicate
+/// let idempotent_event = Event::new("data", vec!["tag1"]).with_client_id("8c9e5a1f-3c2b-4d6e-9f7a-1b2c3d4e5f60");
 /// # }
 /// ```
 #[cfg_attr(feature = "rustc-serialization", derive(RustcEncodable, RustcDecodable))]
@@ -23,25 +48,44 @@ use time;
 pub struct Event {
     /// The event `id` (or sequence number).
     pub id: u64,
-    /// The event data/payload.
-    pub data: String,
+    /// The event data/payload, which can be any sequence of bytes.
+    pub data: Vec<u8>,
     /// The event tags.
     pub tags: Vec<String>,
     /// The event timestamp.
-    pub timestamp: u64
+    pub timestamp: u64,
+    /// The `id` of the stream the event belongs to, if any.
+    pub stream_id: Option<String>,
+    /// The event version (or sequence number) within its stream, starting from 1.
+    pub stream_version: u64,
+    /// The unique `id` given to the event by the client publishing it, if any, used to deduplicate retries.
+    pub client_id: Option<String>
 }
 
 impl Event {
     /// Returns a new `Event` with the given data and tags.
     pub fn new(data: &str, tags: Vec<&str>) -> Event {
+        Event::from_bytes(data.as_bytes().to_vec(), tags)
+    }
+
+    /// Returns a new `Event` with the given binary data and tags.
+    pub fn from_bytes(data: Vec<u8>, tags: Vec<&str>) -> Event {
         Event {
             id: 0,
-            data: data.to_owned(),
+            data: data,
             tags: tags.iter().map(|x| x.to_string()).collect(),
-            timestamp: 0
+            timestamp: 0,
+            stream_id: None,
+            stream_version: 0,
+            client_id: None
         }
     }
 
+    /// Returns the event data as a string slice, or `None` if it is not valid UTF-8.
+    pub fn data_as_str(&self) -> Option<&str> {
+        str::from_utf8(&self.data).ok()
+    }
+
     /// Returns a modified version of the event by setting its `id` to the given value.
     pub fn with_id(mut self, id: u64) -> Self {
         self.id = id;
@@ -59,17 +103,91 @@ impl Event {
         self.timestamp = get_current_timestamp_in_ms();
         self
     }
+
+    /// Returns a modified version of the event by setting the `id` of the stream it belongs to.
+    pub fn with_stream(mut self, stream_id: &str) -> Self {
+        self.stream_id = Some(stream_id.to_owned());
+        self
+    }
+
+    /// Returns a modified version of the event by setting its version within its stream to the given value.
+    pub fn with_stream_version(mut self, stream_version: u64) -> Self {
+        self.stream_version = stream_version;
+        self
+    }
+
+    /// Returns a modified version of the event by setting the unique `id` given to it by the client publishing it.
+    pub fn with_client_id(mut self, client_id: &str) -> Self {
+        self.client_id = Some(client_id.to_owned());
+        self
+    }
+
+    /// Returns the binary log record for the event, including its header and the given record flags.
+    ///
+    /// The `STREAM_FLAG` is added to the given flags if the event belongs to a stream,
+    /// and the `CLIENT_ID_FLAG` if the event carries a client `id`.
+    pub fn to_log_record_with_flags(&self, flags: u8) -> Vec<u8> {
+        let mut encoder = LogRecordEncoder::new();
+        let flags = if self.stream_id.is_some() { flags | STREAM_FLAG } else { flags };
+        encoder.put_u8(if self.client_id.is_some() { flags | CLIENT_ID_FLAG } else { flags });
+        encoder.put_u64(self.id);
+        encoder.put_u64(self.timestamp);
+        encoder.put_u16(self.tags.len() as u16);
+        for tag in &self.tags {
+            encoder.put_short_str(tag);
+        }
+        if let Some(ref stream_id) = self.stream_id {
+            encoder.put_short_str(stream_id);
+            encoder.put_u64(self.stream_version);
+        }
+        if let Some(ref client_id) = self.client_id {
+            encoder.put_short_str(client_id);
+        }
+        encoder.put_bytes(&self.data);
+        encoder.into_log_record()
+    }
+
+    /// Returns an event and its record flags from a binary log record (including its header)
+    /// or a `ParseError` if the record is corrupted or a failure occurs while decoding it.
+    pub fn from_log_record_with_flags(record: &[u8]) -> Result<(Event, u8), ParseError> {
+        let mut decoder = try!(LogRecordDecoder::new(record));
+        let flags = try!(decoder.get_u8());
+        let id = try!(decoder.get_u64());
+        let timestamp = try!(decoder.get_u64());
+        let nr_of_tags = try!(decoder.get_u16());
+        let mut tags = Vec::with_capacity(nr_of_tags as usize);
+        for _ in 0..nr_of_tags {
+            tags.push(try!(decoder.get_short_str()));
+        }
+        let (stream_id, stream_version) = if flags & STREAM_FLAG == 0 {
+            (None, 0)
+        } else {
+            (Some(try!(decoder.get_short_str())), try!(decoder.get_u64()))
+        };
+        let client_id = if flags & CLIENT_ID_FLAG == 0 { None } else { Some(try!(decoder.get_short_str())) };
+        let data = try!(decoder.get_bytes()).to_vec();
+        Ok((Event {
+            id: id,
+            tags: tags,
+            data: data,
+            timestamp: timestamp,
+            stream_id: stream_id,
+            stream_version: stream_version,
+            client_id: client_id
+        }, flags))
+    }
 }
 
 impl Display for Event {
     fn fmt(&self, f: &mut Formatter) -> DisplayResult {
-        write!(f, "Event({}, {}, [{}], {})", self.id, self.timestamp, self.tags.join(", "), self.data)
+        write!(f, "Event({}, {}, [{}], {})", self.id, self.timestamp, self.tags.join(", "), String::from_utf8_lossy(&self.data))
     }
 }
 
+// the text protocol requires the data to be valid UTF-8, invalid sequences are replaced by U+FFFD
 impl ToTabSeparatedString for Event {
     fn to_tab_separated_string(&self) -> String {
-        tab_separated!(self.id, self.timestamp, self.tags.join(" "), self.data)
+        tab_separated!(self.id, self.timestamp, self.tags.join(" "), String::from_utf8_lossy(&self.data))
     }
 }
 
@@ -84,18 +202,47 @@ impl FromTabSeparatedStr for Event {
         Ok(Event {
             id: id,
             tags: tags,
-            data: data,
-            timestamp: timestamp
+            data: data.into_bytes(),
+            timestamp: timestamp,
+            stream_id: None,
+            stream_version: 0,
+            client_id: None
         })
     }
 }
 
+impl ToLogRecord for Event {
+    fn to_log_record(&self) -> Vec<u8> {
+        self.to_log_record_with_flags(0)
+    }
+}
+
+impl FromLogRecord for Event {
+    fn from_log_record(record: &[u8]) -> Result<Event, ParseError> {
+        Event::from_log_record_with_flags(record).map(|(event, _)| event)
+    }
+}
+
 impl Validation for Event {
     fn validate(&self) -> Result<(), ValidationError> {
         if self.tags.is_empty() {
             return Err(ValidationError::new("event must contain at least one tag"));
         } else if self.tags.iter().any(|t| t.is_empty()) {
             return Err(ValidationError::new("event must not contain empty tags"));
+        } else if self.tags.len() > u16::max_value() as usize {
+            return Err(ValidationError::new("event must not contain more than 65535 tags"));
+        } else if self.tags.iter().any(|t| t.len() > u16::max_value() as usize) {
+            return Err(ValidationError::new("event must not contain tags longer than 65535 bytes"));
+        } else if self.data.len() > u32::max_value() as usize {
+            return Err(ValidationError::new("event data must not be larger than 4294967295 bytes"));
+        } else if self.stream_id.as_ref().map_or(false, |stream_id| stream_id.is_empty()) {
+            return Err(ValidationError::new("event must not contain an empty stream id"));
+        } else if self.stream_id.as_ref().map_or(false, |stream_id| stream_id.len() > u16::max_value() as usize) {
+            return Err(ValidationError::new("event must not contain a stream id longer than 65535 bytes"));
+        } else if self.client_id.as_ref().map_or(false, |client_id| client_id.is_empty()) {
+            return Err(ValidationError::new("event must not contain an empty client id"));
+        } else if self.client_id.as_ref().map_or(false, |client_id| client_id.len() > u16::max_value() as usize) {
+            return Err(ValidationError::new("event must not contain a client id longer than 65535 bytes"));
         }
         Ok(())
     }
@@ -103,33 +250,59 @@ impl Validation for Event {
 
 /// Exar DB's event stream.
 ///
+/// Events shared with other event streams are cloned only once they are received.
+///
+/// It can be cancelled using its `CancellationHandle`, ending the event stream once its subscription has been removed,
+/// and it is cancelled once dropped.
+///
 /// # Examples
 /// ```
 /// extern crate exar;
 ///
 /// # fn main() {
 /// use exar::*;
+/// use std::sync::Arc;
 /// use std::sync::mpsc::channel;
 ///
 /// let (sender, receiver) = channel();
 /// let mut event_stream = EventStream::new(receiver);
 ///
 /// let event = Event::new("data", vec!["tag1", "tag2"]);
-/// let event_stream_message = EventStreamMessage::Event(event);
+/// let event_stream_message = EventStreamMessage::Event(Arc::new(event));
 /// sender.send(event_stream_message);
 /// # }
 /// ```
 pub struct EventStream {
-    event_stream_receiver: Receiver<EventStreamMessage>
+    event_stream_receiver: Receiver<EventStreamMessage>,
+    cancellation_handle: CancellationHandle
 }
 
 impl EventStream {
     /// Returns a new `EventStream` from the given `Receiver<EventStreamMessage>`.
     pub fn new(receiver: Receiver<EventStreamMessage>) -> EventStream {
         EventStream {
-            event_stream_receiver: receiver
+            event_stream_receiver: receiver,
+            cancellation_handle: CancellationHandle::new()
         }
     }
+
+    /// Returns the event stream using the given `CancellationHandle`, shared with its subscription.
+    pub fn with_cancellation_handle(mut self, cancellation_handle: CancellationHandle) -> Self {
+        self.cancellation_handle = cancellation_handle;
+        self
+    }
+
+    /// Returns a clone of the `CancellationHandle` of the event stream,
+    /// which can be used to cancel it from a different thread.
+    pub fn cancellation_handle(&self) -> CancellationHandle {
+        self.cancellation_handle.clone()
+    }
+
+    /// Cancels the event stream, which ends once its subscription has been removed.
+    pub fn cancel(&self) {
+        self.cancellation_handle.cancel()
+    }
+
     /// Attempts to wait for an event on this event stream,
     /// returning an `EventStreamError` if the corresponding channel has hung up.
     ///
@@ -137,17 +310,29 @@ impl EventStream {
     /// and it's possible for more data to be sent.
     pub fn recv(&self) -> Result<Event, EventStreamError> {
         match self.event_stream_receiver.recv() {
-            Ok(EventStreamMessage::Event(event)) => Ok(event),
+            Ok(EventStreamMessage::Event(event)) => Ok(unwrap_shared_event(event)),
             Ok(EventStreamMessage::End) | Err(_) => Err(EventStreamError::Closed)
         }
     }
+    /// Attempts to wait for an event on this event stream for at most the given duration,
+    /// returning an `EventStreamError` if the corresponding channel has hung up or if no event is received in time.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Event, EventStreamError> {
+        match self.event_stream_receiver.recv_timeout(timeout) {
+            Ok(EventStreamMessage::Event(event)) => Ok(unwrap_shared_event(event)),
+            Ok(EventStreamMessage::End) => Err(EventStreamError::Closed),
+            Err(err) => match err {
+                RecvTimeoutError::Timeout => Err(EventStreamError::Empty),
+                RecvTimeoutError::Disconnected => Err(EventStreamError::Closed)
+            }
+        }
+    }
     /// Attempts to return a pending event on this event stream without blocking.
     ///
     /// This method will never block the caller in order to wait for the next event to become available.
     /// Instead, this will always return immediately with a possible option of pending data on the channel.
     pub fn try_recv(&self) -> Result<Event, EventStreamError> {
         match self.event_stream_receiver.try_recv() {
-            Ok(EventStreamMessage::Event(event)) => Ok(event),
+            Ok(EventStreamMessage::Event(event)) => Ok(unwrap_shared_event(event)),
             Ok(EventStreamMessage::End) => Err(EventStreamError::Closed),
             Err(err) => match err {
                 TryRecvError::Empty => Err(EventStreamError::Empty),
@@ -157,6 +342,10 @@ impl EventStream {
     }
 }
 
+fn unwrap_shared_event(event: Arc<Event>) -> Event {
+    Arc::try_unwrap(event).unwrap_or_else(|event| (*event).clone())
+}
+
 impl Iterator for EventStream {
     type Item = Event;
     fn next(&mut self) -> Option<Self::Item> {
@@ -164,9 +353,68 @@ impl Iterator for EventStream {
     }
 }
 
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.cancel()
+    }
+}
+
+/// Exar DB's event stream cancellation handle.
+///
+/// It is shared between an event stream and its subscription, which is removed by the scanner handling it
+/// once the handle has been cancelled, cancelling the handle wakes the scanner up so that it does so straight away.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let cancellation_handle = CancellationHandle::new();
+/// let cloned_handle = cancellation_handle.clone();
+///
+/// cloned_handle.cancel();
+/// assert!(cancellation_handle.is_cancelled());
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationHandle {
+    cancelled: Arc<AtomicBool>,
+    waker: Arc<Mutex<Option<Sender<ScannerAction>>>>
+}
+
+impl CancellationHandle {
+    /// Returns a new `CancellationHandle` which has not been cancelled.
+    pub fn new() -> CancellationHandle {
+        CancellationHandle {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            waker: Arc::new(Mutex::new(None))
+        }
+    }
+
+    /// Cancels the handle and all its clones, waking up the scanner currently handling its subscription.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        if let Some(ref waker) = *self.waker.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) {
+            let _ = waker.send(ScannerAction::Wake);
+        }
+    }
+
+    /// Sets the channel sender used to wake up the scanner handling the subscription once the handle is cancelled.
+    pub fn set_waker(&self, sender: Sender<ScannerAction>) {
+        *self.waker.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(sender);
+    }
+
+    /// Returns wether the handle has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
 /// Exar DB's event stream message.
 ///
-/// It can either be a message containing an event
+/// It can either be a message containing an event, shared between the event streams it is sent to,
 /// or a message indicating the end of the event stream.
 ///
 /// # Examples
@@ -175,16 +423,17 @@ impl Iterator for EventStream {
 ///
 /// # fn main() {
 /// use exar::*;
+/// use std::sync::Arc;
 ///
 /// let event = Event::new("data", vec!["tag1", "tag2"]);
-/// let event_stream_message = EventStreamMessage::Event(event);
+/// let event_stream_message = EventStreamMessage::Event(Arc::new(event));
 /// let event_stream_end = EventStreamMessage::End;
 /// # }
 /// ```
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EventStreamMessage {
-    /// The message containing an `Event`.
-    Event(Event),
+    /// The message containing a shared `Event`.
+    Event(Arc<Event>),
     /// The message indicating the end of the `EventStream`.
     End
 }
@@ -208,13 +457,14 @@ mod tests {
     use super::get_current_timestamp_in_ms;
     use super::super::*;
 
+    use std::sync::Arc;
     use std::sync::mpsc::channel;
 
     #[test]
     fn test_event() {
         let event = Event::new("data", vec!["tag1", "tag2"]);
         assert_eq!(event.id, 0);
-        assert_eq!(event.data, "data".to_owned());
+        assert_eq!(event.data, b"data".to_vec());
         assert_eq!(event.tags, vec!["tag1".to_owned(), "tag2".to_owned()]);
         assert!(event.timestamp <= get_current_timestamp_in_ms());
 
@@ -227,6 +477,18 @@ mod tests {
         let event = event.with_current_timestamp();
         assert!(event.timestamp != 1234567890);
         assert!(event.timestamp <= get_current_timestamp_in_ms());
+
+        assert_eq!(event.stream_id, None);
+        assert_eq!(event.stream_version, 0);
+
+        let event = event.with_stream("stream1").with_stream_version(3);
+        assert_eq!(event.stream_id, Some("stream1".to_owned()));
+        assert_eq!(event.stream_version, 3);
+
+        assert_eq!(event.client_id, None);
+
+        let event = event.with_client_id("client1");
+        assert_eq!(event.client_id, Some("client1".to_owned()));
     }
 
     #[test]
@@ -241,6 +503,32 @@ mod tests {
         assert_decoded_eq!("1\t1234567890\ttag1 tag2\tdata", event);
     }
 
+    #[test]
+    fn test_event_log_record_encoding() {
+        let event = Event::new("{\n\t\"data\": true\n}", vec!["tag1", "tag2"]).with_id(1).with_timestamp(1234567890);
+        let record = event.to_log_record();
+        assert_eq!(record.len(), LOG_RECORD_HEADER_SIZE + 52);
+        assert_eq!(Event::from_log_record(&record), Ok(event.clone()));
+        assert_eq!(Event::from_log_record_with_flags(&record), Ok((event.clone(), 0)));
+
+        let record = event.to_log_record_with_flags(BATCH_CONTINUATION_FLAG);
+        assert_eq!(Event::from_log_record_with_flags(&record), Ok((event.clone(), BATCH_CONTINUATION_FLAG)));
+
+        let mut corrupted_record = record.clone();
+        corrupted_record[LOG_RECORD_HEADER_SIZE + 1] = 2;
+        assert!(Event::from_log_record(&corrupted_record).is_err());
+
+        let stream_event = event.clone().with_stream("stream1").with_stream_version(3);
+        let record = stream_event.to_log_record_with_flags(BATCH_CONTINUATION_FLAG);
+        assert_eq!(record.len(), LOG_RECORD_HEADER_SIZE + 52 + 17);
+        assert_eq!(Event::from_log_record_with_flags(&record), Ok((stream_event.clone(), BATCH_CONTINUATION_FLAG | STREAM_FLAG)));
+
+        let idempotent_event = stream_event.with_client_id("client1");
+        let record = idempotent_event.to_log_record();
+        assert_eq!(record.len(), LOG_RECORD_HEADER_SIZE + 52 + 17 + 9);
+        assert_eq!(Event::from_log_record_with_flags(&record), Ok((idempotent_event, STREAM_FLAG | CLIENT_ID_FLAG)));
+    }
+
     #[test]
     fn test_event_validation() {
         let event = Event::new("data", vec![]);
@@ -249,6 +537,12 @@ mod tests {
         let event = Event::new("data", vec![""]);
         assert_eq!(event.validate(), Err(ValidationError::new("event must not contain empty tags")));
 
+        let event = Event::new("data", vec!["tag1"]).with_stream("");
+        assert_eq!(event.validate(), Err(ValidationError::new("event must not contain an empty stream id")));
+
+        let event = Event::new("data", vec!["tag1"]).with_client_id("");
+        assert_eq!(event.validate(), Err(ValidationError::new("event must not contain an empty client id")));
+
         let event = Event::new("data", vec!["tag1", "tag2"]);
         assert_eq!(event.clone().validate(), Ok(()));
         assert_eq!(event.clone().validated(), Ok(event));
@@ -262,7 +556,7 @@ mod tests {
 
         let mut event_stream = EventStream::new(receiver);
 
-        assert!(sender.send(EventStreamMessage::Event(event.clone())).is_ok());
+        assert!(sender.send(EventStreamMessage::Event(Arc::new(event.clone()))).is_ok());
 
         assert_eq!(event_stream.next(), Some(event));
         assert_eq!(event_stream.try_recv(), Err(EventStreamError::Empty));
@@ -274,4 +568,24 @@ mod tests {
         assert_eq!(event_stream.recv(), Err(EventStreamError::Closed));
 
     }
+
+    #[test]
+    fn test_event_stream_cancellation() {
+        let (_, receiver) = channel();
+        let cancellation_handle = CancellationHandle::new();
+
+        let event_stream = EventStream::new(receiver).with_cancellation_handle(cancellation_handle.clone());
+        assert!(!cancellation_handle.is_cancelled());
+
+        event_stream.cancel();
+        assert!(cancellation_handle.is_cancelled());
+        assert!(event_stream.cancellation_handle().is_cancelled());
+
+        let (_, receiver) = channel();
+        let cancellation_handle = CancellationHandle::new();
+
+        let event_stream = EventStream::new(receiver).with_cancellation_handle(cancellation_handle.clone());
+        drop(event_stream);
+        assert!(cancellation_handle.is_cancelled());
+    }
 }
@@ -0,0 +1,200 @@
+use super::*;
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, ErrorKind, Seek, SeekFrom};
+
+/// Exar DB's log index.
+///
+/// It maps event ids to the byte offset at which the record following the event starts,
+/// storing a single entry every `granularity` events to allow fast seeking within a log segment file.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let mut index = LogIndex::new(100);
+/// index.insert(100, 4096);
+///
+/// assert_eq!(index.closest_entry(150), (100, 4096));
+/// assert_eq!(index.closest_entry(50), (0, 0));
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogIndex {
+    index: BTreeMap<u64, u64>,
+    granularity: u64,
+    last_id: u64,
+    byte_count: u64
+}
+
+impl LogIndex {
+    /// Creates a new empty index with the given granularity.
+    pub fn new(granularity: u64) -> LogIndex {
+        LogIndex {
+            index: BTreeMap::new(),
+            granularity: granularity,
+            last_id: 0,
+            byte_count: 0
+        }
+    }
+
+    /// Inserts an entry mapping the given event `id` to the byte offset following its record.
+    pub fn insert(&mut self, id: u64, byte_offset: u64) -> Option<u64> {
+        self.last_id = id;
+        self.byte_count = byte_offset;
+        self.index.insert(id, byte_offset)
+    }
+
+    /// Removes all the entries from the index.
+    pub fn clear(&mut self) {
+        self.index.clear();
+    }
+
+    /// Returns the granularity of the index.
+    pub fn granularity(&self) -> u64 {
+        self.granularity
+    }
+
+    /// Returns the `id` of the last event known to the index.
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// Returns the number of bytes known to the index.
+    pub fn byte_count(&self) -> u64 {
+        self.byte_count
+    }
+
+    /// Returns the byte offset stored for the given event `id`, if any.
+    pub fn byte_offset_at(&self, id: &u64) -> Option<u64> {
+        self.index.get(id).cloned()
+    }
+
+    /// Returns the `id` of the last indexed event, if any.
+    pub fn last_indexed_id(&self) -> Option<u64> {
+        self.index.keys().next_back().cloned()
+    }
+
+    /// Returns wether an entry should be stored for the given event `id` given the index granularity.
+    pub fn should_index(&self, id: u64) -> bool {
+        id >= self.last_indexed_id().unwrap_or(0) + self.granularity
+    }
+
+    /// Returns the closest entry preceding (or matching) the given event `id`
+    /// as a tuple of event `id` and byte offset, or `(0, 0)` if there is none.
+    pub fn closest_entry(&self, id: u64) -> (u64, u64) {
+        self.index.range(..id + 1).next_back().map(|(&id, &byte_offset)| (id, byte_offset)).unwrap_or((0, 0))
+    }
+
+    /// Computes the missing entries of the index by reading the log records
+    /// following the last indexed event, and returns the `id` of the last event found,
+    /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// An incomplete record or batch of records at the end of the log is ignored,
+    /// and entries are only stored at the end of a batch.
+    pub fn compute<T: BufRead + Seek>(&mut self, reader: &mut T) -> Result<u64, DatabaseError> {
+        let (mut last_id, mut byte_count) = match self.last_indexed_id() {
+            Some(id) => (id, self.byte_offset_at(&id).unwrap_or(0)),
+            None => (0, 0)
+        };
+        if let Err(err) = reader.seek(SeekFrom::Start(byte_count)) {
+            return Err(DatabaseError::from_io_error(err))
+        }
+        let mut byte_offset = byte_count;
+        loop {
+            match reader.read_log_record() {
+                Ok(Some(record)) => {
+                    let (event, flags) = try!(Event::from_log_record_with_flags(&record).map_err(DatabaseError::ParseError));
+                    byte_offset += record.len() as u64;
+                    if flags & BATCH_CONTINUATION_FLAG == 0 {
+                        last_id = event.id;
+                        byte_count = byte_offset;
+                        if self.should_index(last_id) {
+                            self.insert(last_id, byte_count);
+                        }
+                    }
+                },
+                Ok(None) => break,
+                Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(DatabaseError::from_io_error(err))
+            }
+        }
+        self.last_id = last_id;
+        self.byte_count = byte_count;
+        Ok(last_id)
+    }
+
+    /// Returns a reference to the underlying map of event ids and byte offsets.
+    pub fn get_ref(&self) -> &BTreeMap<u64, u64> {
+        &self.index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    use std::io::{BufWriter, Cursor};
+
+    fn create_log_bytes(nr_of_events: u64) -> Vec<u8> {
+        let mut writer = BufWriter::new(vec![]);
+        for id in 1..(nr_of_events + 1) {
+            let event = Event::new("data", vec!["tag1"]).with_id(id).with_timestamp(id);
+            assert!(writer.write_log_record(&event.to_log_record()).is_ok());
+        }
+        writer.into_inner().expect("Unable to extract inner context")
+    }
+
+    #[test]
+    fn test_insert_and_lookup() {
+        let mut index = LogIndex::new(10);
+
+        assert_eq!(index.last_indexed_id(), None);
+        assert_eq!(index.closest_entry(5), (0, 0));
+        assert!(!index.should_index(9));
+        assert!(index.should_index(10));
+
+        index.insert(10, 100);
+        index.insert(20, 200);
+
+        assert_eq!(index.last_id(), 20);
+        assert_eq!(index.byte_count(), 200);
+        assert_eq!(index.last_indexed_id(), Some(20));
+        assert_eq!(index.byte_offset_at(&10), Some(100));
+        assert_eq!(index.byte_offset_at(&15), None);
+        assert_eq!(index.closest_entry(9), (0, 0));
+        assert_eq!(index.closest_entry(10), (10, 100));
+        assert_eq!(index.closest_entry(19), (10, 100));
+        assert_eq!(index.closest_entry(25), (20, 200));
+        assert!(!index.should_index(29));
+        assert!(index.should_index(30));
+
+        index.clear();
+
+        assert_eq!(index.last_indexed_id(), None);
+    }
+
+    #[test]
+    fn test_compute() {
+        let bytes = create_log_bytes(25);
+        let record_size = bytes.len() as u64 / 25;
+
+        let mut index = LogIndex::new(10);
+        assert_eq!(index.compute(&mut Cursor::new(bytes.clone())), Ok(25));
+        assert_eq!(index.last_id(), 25);
+        assert_eq!(index.byte_count(), bytes.len() as u64);
+        assert_eq!(index.byte_offset_at(&10), Some(10 * record_size));
+        assert_eq!(index.byte_offset_at(&20), Some(20 * record_size));
+        assert_eq!(index.get_ref().len(), 2);
+
+        let mut truncated_index = LogIndex::new(10);
+        truncated_index.insert(10, 10 * record_size);
+        let truncated_bytes = bytes[..bytes.len() - 1].to_vec();
+        assert_eq!(truncated_index.compute(&mut Cursor::new(truncated_bytes)), Ok(24));
+        assert_eq!(truncated_index.byte_count(), 24 * record_size);
+        assert_eq!(truncated_index.get_ref(), index.get_ref());
+    }
+}
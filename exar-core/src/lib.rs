@@ -63,38 +63,74 @@ extern crate exar_testkit;
 #[macro_use]
 extern crate log as logging;
 
-extern crate indexed_line_reader;
 extern crate rand;
+extern crate regex;
+extern crate serde_json;
 extern crate time;
 
 mod logger;
+mod aggregate;
+mod checkpoint;
 mod config;
 mod collection;
+mod compaction;
 mod connection;
+mod consumer_group;
 mod database;
+mod deduplication;
+mod durability;
 mod encoding;
 mod error;
 mod event;
+mod index;
 mod log;
+mod payload_filter;
 mod query;
+mod query_language;
+mod reader;
+mod record;
+mod retention;
 mod scanner;
+mod segment;
 mod routing_strategy;
+mod stream_index;
 mod subscription;
+mod tag_expression;
+mod tag_index;
+mod tail_cache;
+mod timestamp_index;
 mod util;
 mod validation;
 
 pub use self::logger::*;
+pub use self::aggregate::*;
+pub use self::checkpoint::*;
 pub use self::config::*;
 pub use self::collection::*;
+pub use self::compaction::*;
 pub use self::connection::*;
+pub use self::consumer_group::*;
 pub use self::database::*;
+pub use self::deduplication::*;
+pub use self::durability::*;
 pub use self::encoding::*;
 pub use self::error::*;
 pub use self::event::*;
+pub use self::index::*;
 pub use self::log::*;
+pub use self::payload_filter::*;
 pub use self::query::*;
+pub use self::reader::*;
+pub use self::record::*;
+pub use self::retention::*;
 pub use self::routing_strategy::*;
 pub use self::scanner::*;
+pub use self::segment::*;
+pub use self::stream_index::*;
 pub use self::subscription::*;
+pub use self::tag_expression::*;
+pub use self::tag_index::*;
+pub use self::tail_cache::*;
+pub use self::timestamp_index::*;
 pub use self::util::*;
 pub use self::validation::*;
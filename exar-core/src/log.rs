@@ -1,14 +1,18 @@
 use super::*;
 
-use indexed_line_reader::*;
-
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as DisplayResult};
 use std::fs::*;
-use std::io::{BufReader, BufWriter, BufRead};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 
-/// Exar DB's log file abstraction.
+/// Exar DB's log abstraction.
+///
+/// A log is split into segments, each one stored in its own file named after the `id` of its first event
+/// (i.e. `<name>.<first_id>.log`), a new segment is started once the current one reaches the segment size limit.
 ///
-/// It offers helper methods to manage a log file and its index.
-/// It also allows to open readers and writers for the log file.
+/// It offers helper methods to manage the log segments and their indexes.
+/// It also allows to open readers spanning all the log segments.
 ///
 /// # Examples
 /// ```no_run
@@ -17,12 +21,13 @@ use std::io::{BufReader, BufWriter, BufRead};
 /// # fn main() {
 /// use exar::*;
 ///
-/// let log = Log::new("/path/to/logs", "test", 100);
+/// let log = Log::new("/path/to/logs", "test", 100).with_segment_size(SegmentSize::MaxEvents(1000));
 ///
 /// let exists = log.ensure_exists().unwrap();
-/// let writer = log.open_writer().unwrap();
-/// let reader = log.open_reader().unwrap();
-/// let index = log.compute_index().unwrap();
+/// let recovery_report = log.recover().unwrap();
+/// let segments = log.segments().unwrap();
+/// let reader = log.open_log_reader().unwrap();
+/// let indexes = log.restore_indexes().unwrap();
 /// log.remove().unwrap();
 /// # }
 /// ```
@@ -30,173 +35,542 @@ use std::io::{BufReader, BufWriter, BufRead};
 pub struct Log {
     path: String,
     name: String,
-    index_granularity: u64
+    index_granularity: u64,
+    segment_size: SegmentSize
 }
 
 impl Log {
-    /// Returns a new `Log` pointing to the given path/name and using the given index granularity.
+    /// Returns a new `Log` pointing to the given path/name and using the given index granularity
+    /// and the default segment size limit.
     pub fn new(path: &str, name: &str, index_granularity: u64) -> Log {
         Log {
             path: path.to_owned(),
             name: name.to_owned(),
-            index_granularity: index_granularity
+            index_granularity: index_granularity,
+            segment_size: SegmentSize::default()
         }
     }
 
-    /// Ensure the underlying log file exists and creates it if it does not exist,
-    /// it returns a `DatabaseError` if a failure occurs while creating the log file.
+    /// Returns a modified version of the log by setting its segment size limit to the given value.
+    pub fn with_segment_size(mut self, segment_size: SegmentSize) -> Self {
+        self.segment_size = segment_size;
+        self
+    }
+
+    /// Ensure the log has at least one segment and creates the first one if it does not exist,
+    /// it returns a `DatabaseError` if a failure occurs while creating the segment file.
     pub fn ensure_exists(&self) -> Result<(), DatabaseError> {
-        match OpenOptions::new().create(true).write(true).open(self.get_path()) {
-            Ok(_) => Ok(()),
-            Err(err) => Err(DatabaseError::from_io_error(err))
-        }
+        self.segments().and_then(|segments| {
+            if segments.is_empty() {
+                self.segment(1).ensure_exists()
+            } else {
+                Ok(())
+            }
+        })
     }
 
-    /// Returns a buffered reader for the underlying log file
+    /// Returns the existing log segments sorted by the `id` of their first event,
     /// or a `DatabaseError` if a failure occurs.
-    pub fn open_reader(&self) -> Result<BufReader<File>, DatabaseError> {
-        match OpenOptions::new().read(true).open(self.get_path()) {
-            Ok(file) => Ok(BufReader::new(file)),
+    pub fn segments(&self) -> Result<Vec<Segment>, DatabaseError> {
+        let base_path = PathBuf::from(self.segment(0).get_path());
+        let directory = match base_path.parent() {
+            Some(directory) if !directory.as_os_str().is_empty() => directory.to_path_buf(),
+            _ => PathBuf::from(".")
+        };
+        let prefix = format!("{}.", Path::new(&self.name).file_name().and_then(|name| name.to_str()).unwrap_or(""));
+        match read_dir(directory) {
+            Ok(entries) => {
+                let mut first_ids: Vec<u64> = entries.filter_map(|entry| entry.ok()).filter_map(|entry| {
+                    entry.file_name().to_str().and_then(|file_name| parse_segment_first_id(file_name, &prefix))
+                }).collect();
+                first_ids.sort();
+                Ok(first_ids.into_iter().map(|first_id| self.segment(first_id)).collect())
+            },
             Err(err) => Err(DatabaseError::from_io_error(err))
         }
     }
 
-    /// Returns an indexed line reader for the underlying log file
-    /// or a `DatabaseError` if a failure occurs.
-    pub fn open_line_reader(&self) -> Result<IndexedLineReader<BufReader<File>>, DatabaseError> {
-        match OpenOptions::new().read(true).open(self.get_path()) {
-            Ok(file) => Ok(IndexedLineReader::new(BufReader::new(file), self.index_granularity)),
-            Err(err) => Err(DatabaseError::from_io_error(err))
-        }
+    /// Returns the log segment starting at the given event `id`, which may not exist.
+    pub fn segment(&self, first_id: u64) -> Segment {
+        Segment::new(&self.path, &self.name, first_id, self.index_granularity)
     }
 
-    /// Returns an indexed line reader for the underlying log file and restores the index
-    /// using the given `LinesIndex` or a `DatabaseError` if a failure occurs.
-    pub fn open_line_reader_with_index(&self, index: LinesIndex) -> Result<IndexedLineReader<BufReader<File>>, DatabaseError> {
-        self.open_line_reader().and_then(|mut reader| {
-            reader.restore_index(index);
-            Ok(reader)
+    /// Returns the last log segment, creating the first one if the log has no segments,
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn last_segment(&self) -> Result<Segment, DatabaseError> {
+        self.ensure_exists().and_then(|_| {
+            self.segments().and_then(|mut segments| {
+                match segments.pop() {
+                    Some(segment) => Ok(segment),
+                    None => Err(DatabaseError::IoError(ErrorKind::NotFound, "log segment not found".to_owned()))
+                }
+            })
         })
     }
 
-    /// Returns a buffered writer for the underlying log file or a `DatabaseError` if a failure occurs.
-    pub fn open_writer(&self) -> Result<BufWriter<File>, DatabaseError> {
-        match OpenOptions::new().create(true).write(true).append(true).open(self.get_path()) {
-            Ok(file) => Ok(BufWriter::new(file)),
-            Err(err) => Err(DatabaseError::from_io_error(err))
-        }
+    /// Returns a `LogReader` spanning all the log segments
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn open_log_reader(&self) -> Result<LogReader, DatabaseError> {
+        self.open_log_reader_with_indexes(BTreeMap::new())
     }
 
-    /// Removes the underlying log file and its index or a `DatabaseError` if a failure occurs.
-    pub fn remove(&self) -> Result<(), DatabaseError> {
-        match remove_file(self.get_path()) {
-            Ok(()) => match remove_file(self.get_index_path()) {
-                Ok(()) | Err(_) => Ok(())
-            },
-            Err(err) => Err(DatabaseError::from_io_error(err))
-        }
+    /// Returns a `LogReader` spanning all the log segments using the given segment indexes,
+    /// mapped by the `id` of the first event of each segment, or a `DatabaseError` if a failure occurs.
+    pub fn open_log_reader_with_indexes(&self, indexes: BTreeMap<u64, LogIndex>) -> Result<LogReader, DatabaseError> {
+        self.ensure_exists().and_then(|_| {
+            LogReader::new(self.clone(), indexes)
+        })
     }
 
-    /// Computes and returns the `LinesIndex` for the underlying log file
-    /// or a `DatabaseError` if a failure occurs.
-    pub fn compute_index(&self) -> Result<LinesIndex, DatabaseError> {
+    /// Restores and returns the indexes of all the log segments, mapped by the `id`
+    /// of the first event of each segment, or a `DatabaseError` if a failure occurs.
+    pub fn restore_indexes(&self) -> Result<BTreeMap<u64, LogIndex>, DatabaseError> {
         self.ensure_exists().and_then(|_| {
-            self.open_line_reader().and_then(|mut reader| {
-                match reader.compute_index() {
-                    Ok(_) => Ok(reader.get_index().clone()),
-                    Err(err) => Err(DatabaseError::from_io_error(err))
-                }
-            })
+            let mut indexes = BTreeMap::new();
+            for segment in try!(self.segments()) {
+                indexes.insert(segment.first_id(), try!(segment.restore_index()));
+            }
+            Ok(indexes)
         })
     }
 
-    /// Returns a buffered reader for the log index file or a `DatabaseError` if a failure occurs.
-    pub fn open_index_reader(&self) -> Result<BufReader<File>, DatabaseError> {
-        match OpenOptions::new().read(true).open(self.get_index_path()) {
-            Ok(file) => Ok(BufReader::new(file)),
-            Err(err) => Err(DatabaseError::from_io_error(err))
-        }
+    /// Restores and returns the `TagIndex` of the log, given the `id` of the last event logged,
+    /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// The tag index file is truncated at the first incomplete or invalid entry, as well as at the first entry
+    /// following the last event logged, and the entries of the events missing from it are read from the log
+    /// and appended to it. The postings of the events removed by the retention policy are discarded.
+    pub fn restore_tag_index(&self, last_id: u64) -> Result<TagIndex, DatabaseError> {
+        self.restore_persisted_index(TagIndex::new(), &self.get_tag_index_path(), last_id)
     }
 
-    /// Returns a buffered writer for the log index file or a `DatabaseError` if a failure occurs.
-    pub fn open_index_writer(&self) -> Result<BufWriter<File>, DatabaseError> {
-        match OpenOptions::new().create(true).write(true).truncate(true).open(self.get_index_path()) {
-            Ok(file) => Ok(BufWriter::new(file)),
-            Err(err) => Err(DatabaseError::from_io_error(err))
+    /// Returns a buffered writer appending to the tag index file of the log
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn open_tag_index_writer(&self) -> Result<BufWriter<File>, DatabaseError> {
+        open_index_writer(&self.get_tag_index_path())
+    }
+
+    /// Restores and returns the `StreamIndex` of the log, given the `id` of the last event logged,
+    /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// The stream index file is truncated at the first incomplete or invalid entry, as well as at the first entry
+    /// following the last event logged, and the entries of the events belonging to a stream missing from it
+    /// are read from the log and appended to it, followed by an entry without stream for the last event logged
+    /// if it does not belong to any, so that the following restore starts reading the log from it.
+    /// The postings of the events removed by the retention policy are discarded, while the versions of the streams are kept.
+    pub fn restore_stream_index(&self, last_id: u64) -> Result<StreamIndex, DatabaseError> {
+        self.restore_persisted_index(StreamIndex::new(), &self.get_stream_index_path(), last_id)
+    }
+
+    /// Returns a buffered writer appending to the stream index file of the log
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn open_stream_index_writer(&self) -> Result<BufWriter<File>, DatabaseError> {
+        open_index_writer(&self.get_stream_index_path())
+    }
+
+    /// Restores and returns the `DeduplicationWindow` of the log, given the `id` of the last event logged,
+    /// the deduplication configuration and the current timestamp, or a `DatabaseError` if a failure occurs.
+    ///
+    /// The client `id`s are read from the events within the window, which are kept in the log along with them,
+    /// and they are remembered as of the given timestamp.
+    pub fn restore_deduplication_window(&self, last_id: u64, config: &DeduplicationConfig,
+                                        timestamp: u64) -> Result<DeduplicationWindow, DatabaseError> {
+        let mut deduplication_window = DeduplicationWindow::new(config.window_size, config.max_age_in_ms);
+        if !config.is_enabled() {
+            return Ok(deduplication_window)
+        }
+        let mut reader = try!(self.open_log_reader());
+        try!(reader.seek(last_id.saturating_sub(config.window_size)));
+        for event in reader {
+            let event = try!(event);
+            if event.id > last_id {
+                break
+            }
+            if let Some(ref client_id) = event.client_id {
+                deduplication_window.insert(event.id, client_id, timestamp);
+            }
         }
+        Ok(deduplication_window)
     }
 
-    /// Restores and returns the log `LinesIndex` from the log index file
+    /// Restores and returns the `TimestampIndex` of the log, given the `id` of the last event logged,
     /// or a `DatabaseError` if a failure occurs.
     ///
-    /// If the log index file does not exist it will be computed and persisted.
-    pub fn restore_index(&self) -> Result<LinesIndex, DatabaseError> {
-        match self.open_index_reader() {
-            Ok(reader) => {
-                let mut index = LinesIndex::new(self.index_granularity);
-                for line in reader.lines() {
-                    match line {
-                        Ok(line) => {
-                            let parts: Vec<_> = line.split(' ').collect();
-                            let line_count: u64 = parts[0].parse().unwrap();
-                            let byte_count: u64 = parts[1].parse().unwrap();
-                            index.insert(line_count, byte_count);
-                        },
-                        Err(err) => return Err(DatabaseError::from_io_error(err))
-                    }
+    /// The completed blocks are read from the timestamp index file up to the first invalid one,
+    /// or the first one following the last event logged, and the remaining events are read from the log.
+    /// The blocks of the events removed by the retention policy are discarded, and the index file is rewritten.
+    pub fn restore_timestamp_index(&self, last_id: u64) -> Result<TimestampIndex, DatabaseError> {
+        let mut timestamp_index = TimestampIndex::new(self.index_granularity);
+        if let Ok(file) = File::open(self.get_timestamp_index_path()) {
+            let mut blocks = vec![];
+            for line in BufReader::new(file).lines() {
+                match line.ok().and_then(|line| TimestampBlock::from_tab_separated_str(&line).ok()) {
+                    Some(ref block) if block.last_id > blocks.last().map_or(0, |b: &TimestampBlock| b.last_id) &&
+                                       block.last_id <= last_id && block.nr_of_events > 0 => blocks.push(block.clone()),
+                    _ => break
                 }
-                self.open_line_reader().and_then(|mut reader| {
-                    reader.restore_index(index);
-                    match reader.compute_index() {
-                        Ok(_) => Ok(reader.get_index().clone()),
-                        Err(err) => Err(DatabaseError::from_io_error(err))
+            }
+            timestamp_index = TimestampIndex::with_blocks(self.index_granularity, blocks);
+        }
+        let mut reader = try!(self.open_log_reader());
+        try!(reader.seek(timestamp_index.last_id()));
+        for event in reader {
+            let event = try!(event);
+            if event.id > last_id {
+                break
+            }
+            timestamp_index.insert(event.id, event.timestamp);
+        }
+        if let Some(segment) = try!(self.segments()).first() {
+            timestamp_index.remove_before(segment.first_id());
+        }
+        self.persist_timestamp_index(&timestamp_index).and_then(|_| Ok(timestamp_index))
+    }
+
+    /// Persists the completed blocks of the given `TimestampIndex` to the timestamp index file of the log
+    /// or returns a `DatabaseError` if a failure occurs.
+    pub fn persist_timestamp_index(&self, timestamp_index: &TimestampIndex) -> Result<(), DatabaseError> {
+        match OpenOptions::new().create(true).write(true).truncate(true).open(self.get_timestamp_index_path()) {
+            Ok(file) => {
+                let mut writer = BufWriter::new(file);
+                for block in timestamp_index.completed_blocks() {
+                    if let Err(err) = writer.write_line(&block.to_tab_separated_string()) {
+                        return Err(DatabaseError::from_io_error(err))
                     }
-                })
+                }
+                Ok(())
             },
-            Err(_) => self.compute_index().and_then(|index| {
-                self.persist_index(&index).and_then(|_| {
-                    Ok(index)
-                })
-            })
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    /// Restores and returns the durable subscription `Checkpoints` of the log
+    /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// The checkpoints file is read up to the first invalid entry, a missing file results in empty checkpoints.
+    pub fn restore_checkpoints(&self) -> Result<Checkpoints, DatabaseError> {
+        let mut checkpoints = Checkpoints::new();
+        if let Ok(file) = File::open(self.get_checkpoints_path()) {
+            for line in BufReader::new(file).lines() {
+                let line = try!(line.map_err(DatabaseError::from_io_error));
+                let mut parser = TabSeparatedParser::new(2, &line);
+                match (parser.parse_next::<String>(), parser.parse_next()) {
+                    (Ok(ref consumer_name), Ok(event_id)) if checkpoints.reset(consumer_name, event_id).is_ok() => (),
+                    _ => break
+                }
+            }
         }
+        Ok(checkpoints)
     }
 
-    /// Persists the given `LinesIndex` to a log index file
+    /// Persists the given `Checkpoints` to the checkpoints file of the log
     /// or returns a `DatabaseError` if a failure occurs.
-    pub fn persist_index(&self, index: &LinesIndex) -> Result<(), DatabaseError> {
-        self.open_index_writer().and_then(|mut writer| {
-            for (line_count, byte_count) in index.get_ref() {
-                match writer.write_line(&format!("{} {}", line_count, byte_count)) {
-                    Ok(_) => (),
-                    Err(err) => return Err(DatabaseError::from_io_error(err))
-                };
+    ///
+    /// The checkpoints are written and synced to a temporary file first, which then replaces the checkpoints file,
+    /// so that a failure never leaves a partially written checkpoints file behind.
+    pub fn persist_checkpoints(&self, checkpoints: &Checkpoints) -> Result<(), DatabaseError> {
+        let temp_path = format!("{}.tmp", self.get_checkpoints_path());
+        let result = File::create(&temp_path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            for (consumer_name, event_id) in checkpoints.get_ref() {
+                try!(writer.write_line(&tab_separated!(consumer_name, event_id)));
+            }
+            try!(writer.flush());
+            try!(writer.get_ref().sync_all());
+            rename(&temp_path, self.get_checkpoints_path())
+        });
+        result.map_err(DatabaseError::from_io_error)
+    }
+
+    /// Recovers the log from an unclean shutdown and returns a `RecoveryReport`
+    /// describing what has been repaired, or a `DatabaseError` if a failure occurs.
+    ///
+    /// Only the last segment is recovered, since all the previous ones have been completed.
+    pub fn recover(&self) -> Result<RecoveryReport, DatabaseError> {
+        self.last_segment().and_then(|segment| segment.recover())
+    }
+
+    /// Removes the oldest log segments expired according to the given `RetentionConfig`,
+    /// given the `id` of the last event logged and the current timestamp,
+    /// and returns the removed segments or a `DatabaseError` if a failure occurs.
+    ///
+    /// Segments are only removed from the beginning of the log, stopping at the first segment which is not expired,
+    /// a segment is expired once its last event is older than the maximum age, or once the segments following it
+    /// contain at least the maximum number of bytes or events. The last segment is never removed.
+    pub fn apply_retention(&self, retention: &RetentionConfig, last_id: u64, current_timestamp: u64) -> Result<Vec<Segment>, DatabaseError> {
+        let segments = try!(self.segments());
+        let mut segments_sizes = vec![];
+        for segment in &segments {
+            segments_sizes.push(try!(segment.size()));
+        }
+        let mut retained_bytes: u64 = segments_sizes.iter().sum();
+        let mut removed_segments = vec![];
+        for (i, segment) in segments.iter().enumerate().take(segments.len().saturating_sub(1)) {
+            retained_bytes -= segments_sizes[i];
+            let retained_events = (last_id + 1).saturating_sub(segments[i + 1].first_id());
+            let is_expired = retention.max_bytes.map_or(false, |max_bytes| retained_bytes >= max_bytes) ||
+                             retention.max_events.map_or(false, |max_events| retained_events >= max_events) ||
+                             match retention.max_age_in_ms {
+                                 Some(max_age) => match try!(segment.last_event()) {
+                                     Some(event) => event.timestamp.saturating_add(max_age) < current_timestamp,
+                                     None => false
+                                 },
+                                 None => false
+                             };
+            if !is_expired {
+                break
+            }
+            try!(segment.remove());
+            removed_segments.push(segment.clone());
+        }
+        Ok(removed_segments)
+    }
+
+    /// Removes all the log segments, their indexes, the log tag, stream and timestamp indexes and the checkpoints
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn remove(&self) -> Result<(), DatabaseError> {
+        self.segments().and_then(|segments| {
+            if segments.is_empty() {
+                return Err(DatabaseError::IoError(ErrorKind::NotFound, "log segment not found".to_owned()))
+            }
+            for segment in segments {
+                try!(segment.remove());
             }
+            let _ = remove_file(self.get_tag_index_path());
+            let _ = remove_file(self.get_stream_index_path());
+            let _ = remove_file(self.get_timestamp_index_path());
+            let _ = remove_file(self.get_checkpoints_path());
             Ok(())
         })
     }
 
-    /// Returns the path to the log file.
-    pub fn get_path(&self) -> String {
+    // restores the given index from its file, truncated at the first invalid entry, and from the events missing from it,
+    // whose entries are appended to the file (the entry of the last event is appended even if it is not indexed)
+    fn restore_persisted_index<I: PersistedIndex>(&self, mut index: I, path: &str, last_id: u64) -> Result<I, DatabaseError> {
+        let mut valid_byte_count = 0;
+        if let Ok(file) = File::open(path) {
+            let mut reader = BufReader::new(file);
+            loop {
+                match reader.read_log_record() {
+                    Ok(Some(record)) => match I::Entry::from_log_record(&record) {
+                        Ok(ref entry) if I::entry_id(entry) > index.last_id() && I::entry_id(entry) <= last_id => {
+                            index.insert_entry(entry);
+                            valid_byte_count += record.len() as u64;
+                        },
+                        _ => break
+                    },
+                    Ok(None) => break,
+                    Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                    Err(err) => return Err(DatabaseError::from_io_error(err))
+                }
+            }
+        }
+        let truncation = OpenOptions::new().create(true).write(true).open(path).and_then(|file| {
+            file.set_len(valid_byte_count)
+        });
+        if let Err(err) = truncation {
+            return Err(DatabaseError::from_io_error(err))
+        }
+        let mut writer = try!(open_index_writer(path));
+        let mut reader = try!(self.open_log_reader());
+        try!(reader.seek(index.last_id()));
+        let mut last_entry_not_indexed = None;
+        for event in reader {
+            let event = try!(event);
+            if event.id > last_id {
+                break
+            }
+            let entry = I::to_entry(event);
+            index.insert_entry(&entry);
+            if I::is_indexed(&entry) {
+                if let Err(err) = writer.write_all(&entry.to_log_record()) {
+                    return Err(DatabaseError::from_io_error(err))
+                }
+                last_entry_not_indexed = None;
+            } else {
+                last_entry_not_indexed = Some(entry);
+            }
+        }
+        if let Some(entry) = last_entry_not_indexed {
+            if let Err(err) = writer.write_all(&entry.to_log_record()) {
+                return Err(DatabaseError::from_io_error(err))
+            }
+        }
+        if let Err(err) = writer.flush() {
+            return Err(DatabaseError::from_io_error(err))
+        }
+        if let Some(segment) = try!(self.segments()).first() {
+            index.remove_before(segment.first_id());
+        }
+        Ok(index)
+    }
+
+    /// Returns the name of the log.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the path to the tag index file of the log.
+    pub fn get_tag_index_path(&self) -> String {
         if self.path.is_empty() {
-            format!("{}.log", self.name)
+            format!("{}.tags.log", self.name)
         } else {
-            format!("{}/{}.log", self.path, self.name)
+            format!("{}/{}.tags.log", self.path, self.name)
         }
     }
 
-    /// Returns the path to the log index file.
-    pub fn get_index_path(&self) -> String {
+    /// Returns the path to the stream index file of the log.
+    pub fn get_stream_index_path(&self) -> String {
         if self.path.is_empty() {
-            format!("{}.index.log", self.name)
+            format!("{}.streams.log", self.name)
         } else {
-            format!("{}/{}.index.log", self.path, self.name)
+            format!("{}/{}.streams.log", self.path, self.name)
         }
     }
 
-    /// Returns the lines index granularity for the log file.
+    /// Returns the path to the timestamp index file of the log.
+    pub fn get_timestamp_index_path(&self) -> String {
+        if self.path.is_empty() {
+            format!("{}.timestamps.log", self.name)
+        } else {
+            format!("{}/{}.timestamps.log", self.path, self.name)
+        }
+    }
+
+    /// Returns the path to the durable subscription checkpoints file of the log.
+    pub fn get_checkpoints_path(&self) -> String {
+        if self.path.is_empty() {
+            format!("{}.checkpoints.log", self.name)
+        } else {
+            format!("{}/{}.checkpoints.log", self.path, self.name)
+        }
+    }
+
+    /// Returns the index granularity for the log segments.
     pub fn get_index_granularity(&self) -> u64 {
         self.index_granularity
     }
+
+    /// Returns the segment size limit for the log.
+    pub fn get_segment_size(&self) -> &SegmentSize {
+        &self.segment_size
+    }
+}
+
+// an index of the log persisted in its own file, as a binary log record for each event
+trait PersistedIndex {
+    type Entry: ToLogRecord + FromLogRecord;
+
+    fn to_entry(event: Event) -> Self::Entry;
+
+    fn entry_id(entry: &Self::Entry) -> u64;
+
+    // the entries of the events not indexed are not persisted while publishing
+    fn is_indexed(_entry: &Self::Entry) -> bool {
+        true
+    }
+
+    fn insert_entry(&mut self, entry: &Self::Entry);
+
+    fn last_id(&self) -> u64;
+
+    fn remove_before(&mut self, id: u64);
+}
+
+impl PersistedIndex for TagIndex {
+    type Entry = TagIndexEntry;
+
+    fn to_entry(event: Event) -> TagIndexEntry {
+        TagIndexEntry::new(event.id, event.tags)
+    }
+
+    fn entry_id(entry: &TagIndexEntry) -> u64 {
+        entry.id
+    }
+
+    fn insert_entry(&mut self, entry: &TagIndexEntry) {
+        self.insert(entry.id, &entry.tags);
+    }
+
+    fn last_id(&self) -> u64 {
+        TagIndex::last_id(self)
+    }
+
+    fn remove_before(&mut self, id: u64) {
+        TagIndex::remove_before(self, id);
+    }
+}
+
+impl PersistedIndex for StreamIndex {
+    type Entry = StreamIndexEntry;
+
+    fn to_entry(event: Event) -> StreamIndexEntry {
+        StreamIndexEntry::new(event.id, event.stream_id, event.stream_version)
+    }
+
+    fn entry_id(entry: &StreamIndexEntry) -> u64 {
+        entry.id
+    }
+
+    fn is_indexed(entry: &StreamIndexEntry) -> bool {
+        entry.stream_id.is_some()
+    }
+
+    fn insert_entry(&mut self, entry: &StreamIndexEntry) {
+        self.insert(entry.id, entry.stream_id.as_ref().map(|stream_id| &stream_id[..]), entry.version);
+    }
+
+    fn last_id(&self) -> u64 {
+        StreamIndex::last_id(self)
+    }
+
+    fn remove_before(&mut self, id: u64) {
+        StreamIndex::remove_before(self, id);
+    }
+}
+
+fn open_index_writer(path: &str) -> Result<BufWriter<File>, DatabaseError> {
+    match OpenOptions::new().create(true).write(true).append(true).open(path) {
+        Ok(file) => Ok(BufWriter::new(file)),
+        Err(err) => Err(DatabaseError::from_io_error(err))
+    }
+}
+
+/// Exar DB's log recovery report.
+///
+/// It describes the repairs performed on a log by `Log::recover`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// The `id` of the last valid event found in the log.
+    pub last_id: u64,
+    /// The number of bytes of incomplete or corrupted records truncated from the end of the log.
+    pub truncated_bytes: u64,
+    /// The number of invalid entries discarded from the log index file.
+    pub discarded_index_entries: u64
+}
+
+impl RecoveryReport {
+    /// Returns wether the log has been repaired.
+    pub fn is_repaired(&self) -> bool {
+        self.truncated_bytes > 0 || self.discarded_index_entries > 0
+    }
+}
+
+impl Display for RecoveryReport {
+    fn fmt(&self, f: &mut Formatter) -> DisplayResult {
+        write!(f, "truncated {} bytes of incomplete or corrupted records, discarded {} invalid index entries, last event id is {}",
+               self.truncated_bytes, self.discarded_index_entries, self.last_id)
+    }
+}
+
+fn parse_segment_first_id(file_name: &str, prefix: &str) -> Option<u64> {
+    if file_name.starts_with(prefix) && file_name.ends_with(".log") && file_name.len() > prefix.len() + 4 {
+        let first_id = &file_name[prefix.len()..file_name.len() - 4];
+        if first_id.chars().all(|c| c.is_digit(10)) {
+            return first_id.parse().ok().and_then(|first_id| if first_id > 0 { Some(first_id) } else { None })
+        }
+    }
+    None
 }
 
 #[cfg(test)]
@@ -204,60 +578,313 @@ mod tests {
     use super::super::*;
     use exar_testkit::*;
 
-    use indexed_line_reader::*;
+    use std::fs::{metadata, remove_file, File, OpenOptions};
+    use std::io::{BufReader, Write};
 
-    #[test]
-    fn test_get_path() {
+    fn create_log_with_events(segment_size: SegmentSize, nr_of_events: u64) -> Log {
         let ref collection_name = random_collection_name();
-        let log = Log::new("", collection_name, 100);
-        assert_eq!(log.get_path(), format!("{}.log", collection_name));
-        let log = Log::new("path/to/log", collection_name, 100);
-        assert_eq!(log.get_path(), format!("path/to/log/{}.log", collection_name));
+        let log = Log::new("", collection_name, 10).with_segment_size(segment_size);
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for _ in 0..nr_of_events {
+            assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+        }
+        log
+    }
+
+    fn segments_first_ids(log: &Log) -> Vec<u64> {
+        log.segments().expect("Unable to list segments").iter().map(|s| s.first_id()).collect()
     }
 
     #[test]
-    fn test_log_and_index_management() {
+    fn test_segments() {
         let ref collection_name = random_collection_name();
         let log = Log::new("", collection_name, 10);
 
+        assert_eq!(log.segments(), Ok(vec![]));
+        assert!(log.remove().is_err());
+
         assert!(log.ensure_exists().is_ok());
-        assert!(log.open_writer().is_ok());
-        assert!(log.open_reader().is_ok());
+        assert_eq!(log.segments(), Ok(vec![Segment::new("", collection_name, 1, 10)]));
+        assert_eq!(log.last_segment(), Ok(Segment::new("", collection_name, 1, 10)));
+
+        for first_id in &[21, 101, 11] {
+            assert!(log.segment(*first_id).ensure_exists().is_ok());
+        }
+        assert!(OpenOptions::new().create(true).write(true).open(format!("{}.11.index.log", collection_name)).is_ok());
+        assert!(OpenOptions::new().create(true).write(true).open(format!("{}.other.log", collection_name)).is_ok());
 
-        let index = log.compute_index().expect("Unable to compute index");
-        assert_eq!(index.line_count(), 0);
+        assert_eq!(segments_first_ids(&log), vec![1, 11, 21, 101]);
+        assert_eq!(log.last_segment().map(|s| s.first_id()), Ok(101));
 
-        let mut writer = log.open_writer().expect("Unable to open writer");
-        for _ in 0..100 {
-            assert!(writer.write_line("data").is_ok());
+        assert!(log.remove().is_ok());
+        assert_eq!(log.segments(), Ok(vec![]));
+        assert!(remove_file(format!("{}.other.log", collection_name)).is_ok());
+
+        let log = Log::new("", &invalid_collection_name(), 10);
+        assert!(log.segments().is_err());
+        assert!(log.ensure_exists().is_err());
+    }
+
+    #[test]
+    fn test_rolling_segments() {
+        let log = create_log_with_events(SegmentSize::MaxEvents(10), 25);
+        assert_eq!(segments_first_ids(&log), vec![1, 11, 21]);
+
+        let indexes = log.restore_indexes().expect("Unable to restore indexes");
+        assert_eq!(indexes.keys().cloned().collect::<Vec<_>>(), vec![1, 11, 21]);
+        assert_eq!(indexes.get(&11).map(|index| index.last_id()), Some(20));
+
+        let ids: Vec<_> = log.open_log_reader().expect("Unable to open reader")
+                             .map(|e| e.expect("Unable to read event").id).collect();
+        assert_eq!(ids, (1..26).collect::<Vec<_>>());
+
+        assert!(log.remove().is_ok());
+
+        let event_size = Event::new("data", vec!["tag1"]).to_log_record().len() as u64;
+        let log = create_log_with_events(SegmentSize::MaxBytes(event_size * 4), 10);
+        assert_eq!(segments_first_ids(&log), vec![1, 5, 9]);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_retention() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(SegmentSize::MaxEvents(10));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for id in 1..46 {
+            assert!(logger.log(Event::new("data", vec!["tag1"]).with_timestamp(id * 1000)).is_ok());
+        }
+        let event_size = Event::new("data", vec!["tag1"]).to_log_record().len() as u64;
+
+        let no_retention = RetentionConfig::default();
+        assert_eq!(log.apply_retention(&no_retention, 45, 100000), Ok(vec![]));
+
+        let max_age = RetentionConfig { max_age_in_ms: Some(5000), ..RetentionConfig::default() };
+        assert_eq!(log.apply_retention(&max_age, 45, 15500), Ok(vec![log.segment(1)]));
+        assert_eq!(segments_first_ids(&log), vec![11, 21, 31, 41]);
+
+        let max_events = RetentionConfig { max_events: Some(16), ..RetentionConfig::default() };
+        assert_eq!(log.apply_retention(&max_events, 45, 15500), Ok(vec![log.segment(11)]));
+        assert_eq!(segments_first_ids(&log), vec![21, 31, 41]);
+
+        let max_bytes = RetentionConfig { max_bytes: Some(event_size * 5), ..RetentionConfig::default() };
+        assert_eq!(log.apply_retention(&max_bytes, 45, 15500), Ok(vec![log.segment(21), log.segment(31)]));
+        assert_eq!(segments_first_ids(&log), vec![41]);
+
+        let max_events = RetentionConfig { max_events: Some(1), ..RetentionConfig::default() };
+        assert_eq!(log.apply_retention(&max_events, 45, 100000), Ok(vec![]));
+        assert_eq!(segments_first_ids(&log), vec![41]);
+
+        let ids: Vec<_> = log.open_log_reader().expect("Unable to open reader")
+                             .map(|e| e.expect("Unable to read event").id).collect();
+        assert_eq!(ids, (41..46).collect::<Vec<_>>());
+
+        drop(logger);
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        assert_eq!(logger.log(Event::new("data", vec!["tag1"])), Ok(46));
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_restore_tag_index() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(SegmentSize::MaxEvents(10));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for id in 1..26 {
+            let tags = if id % 5 == 0 { vec!["tag1", "rare"] } else { vec!["tag1"] };
+            assert!(logger.log(Event::new("data", tags)).is_ok());
+        }
+
+        let tag_index = log.restore_tag_index(25).expect("Unable to restore tag index");
+        assert_eq!(tag_index.last_id(), 25);
+        assert_eq!(tag_index.ids_after("rare", 0), &[5, 10, 15, 20, 25]);
+        assert_eq!(log.restore_tag_index(25), Ok(tag_index.clone()));
+
+        let tag_index_size = metadata(log.get_tag_index_path()).expect("Unable to read tag index metadata").len();
+        let file = OpenOptions::new().write(true).open(log.get_tag_index_path()).expect("Unable to open tag index file");
+        assert!(file.set_len(tag_index_size - 1).is_ok());
+        assert!(OpenOptions::new().append(true).open(log.get_tag_index_path()).and_then(|mut file| file.write_all(b"garbage")).is_ok());
+
+        assert_eq!(log.restore_tag_index(25), Ok(tag_index));
+        assert_eq!(metadata(log.get_tag_index_path()).map(|metadata| metadata.len()).ok(), Some(tag_index_size));
+
+        let tag_index = log.restore_tag_index(12).expect("Unable to restore tag index");
+        assert_eq!(tag_index.last_id(), 12);
+        assert_eq!(tag_index.ids_after("rare", 0), &[5, 10]);
+
+        assert!(log.segment(1).remove().is_ok());
+        let tag_index = log.restore_tag_index(25).expect("Unable to restore tag index");
+        assert_eq!(tag_index.last_id(), 25);
+        assert_eq!(tag_index.ids_after("rare", 0), &[15, 20, 25]);
+
+        assert!(log.remove().is_ok());
+        assert!(metadata(log.get_tag_index_path()).is_err());
+    }
+
+    #[test]
+    fn test_restore_stream_index() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(SegmentSize::MaxEvents(10));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for id in 1..26 {
+            let event = Event::new("data", vec!["tag1"]);
+            let event = if id % 5 == 0 { event.with_stream("stream1").with_stream_version(id / 5) } else { event };
+            assert!(logger.log(event).is_ok());
+        }
+
+        let stream_index = log.restore_stream_index(25).expect("Unable to restore stream index");
+        assert_eq!(stream_index.last_id(), 25);
+        assert_eq!(stream_index.version("stream1"), 5);
+        assert_eq!(stream_index.ids_after("stream1", 0), &[5, 10, 15, 20, 25]);
+        assert_eq!(log.restore_stream_index(25), Ok(stream_index.clone()));
+        assert_eq!(read_stream_index_entries(&log).iter().map(|entry| entry.id).collect::<Vec<_>>(), vec![5, 10, 15, 20, 25]);
+
+        let stream_index_size = metadata(log.get_stream_index_path()).expect("Unable to read stream index metadata").len();
+        let file = OpenOptions::new().write(true).open(log.get_stream_index_path()).expect("Unable to open stream index file");
+        assert!(file.set_len(stream_index_size - 1).is_ok());
+
+        assert_eq!(log.restore_stream_index(25), Ok(stream_index));
+        assert_eq!(metadata(log.get_stream_index_path()).map(|metadata| metadata.len()).ok(), Some(stream_index_size));
+
+        let stream_index = log.restore_stream_index(12).expect("Unable to restore stream index");
+        assert_eq!(stream_index.last_id(), 12);
+        assert_eq!(stream_index.version("stream1"), 2);
+
+        assert!(log.segment(1).remove().is_ok());
+        let stream_index = log.restore_stream_index(25).expect("Unable to restore stream index");
+        assert_eq!(stream_index.ids_after("stream1", 0), &[15, 20, 25]);
+        assert_eq!(stream_index.ids_from_version("stream1", 1), &[15, 20, 25]);
+        assert_eq!(stream_index.version("stream1"), 5);
+
+        // the last event is persisted even if it does not belong to a stream, so that it is not read again
+        assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+        let stream_index = log.restore_stream_index(26).expect("Unable to restore stream index");
+        assert_eq!(stream_index.last_id(), 26);
+        assert_eq!(read_stream_index_entries(&log).last(), Some(&StreamIndexEntry::new(26, None, 0)));
+        assert_eq!(log.restore_stream_index(26), Ok(stream_index));
+        assert_eq!(read_stream_index_entries(&log).iter().map(|entry| entry.id).collect::<Vec<_>>(), vec![5, 10, 12, 15, 20, 25, 26]);
+
+        assert!(log.remove().is_ok());
+        assert!(metadata(log.get_stream_index_path()).is_err());
+    }
+
+    fn read_stream_index_entries(log: &Log) -> Vec<StreamIndexEntry> {
+        let file = File::open(log.get_stream_index_path()).expect("Unable to open stream index file");
+        let mut reader = BufReader::new(file);
+        let mut entries = vec![];
+        while let Ok(Some(record)) = reader.read_log_record() {
+            entries.push(StreamIndexEntry::from_log_record(&record).expect("Unable to decode stream index entry"));
+        }
+        entries
+    }
+
+    #[test]
+    fn test_restore_deduplication_window() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(SegmentSize::MaxEvents(10));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for id in 1..26 {
+            let event = Event::new("data", vec!["tag1"]);
+            let event = if id % 2 == 0 { event.with_client_id(&format!("client{}", id)) } else { event };
+            assert!(logger.log(event).is_ok());
         }
+        let config = DeduplicationConfig { window_size: 10, max_age_in_ms: 60000 };
+
+        let deduplication_window = log.restore_deduplication_window(25, &config, 30000).expect("Unable to restore deduplication window");
+        assert_eq!(deduplication_window.len(), 5);
+        assert_eq!(deduplication_window.get("client16"), Some(16));
+        assert_eq!(deduplication_window.get("client24"), Some(24));
+        assert_eq!(deduplication_window.get("client14"), None);
 
-        let index = log.compute_index().expect("Unable to compute index");
-        assert_eq!(index.line_count(), 100);
+        let deduplication_window = log.restore_deduplication_window(20, &config, 30000).expect("Unable to restore deduplication window");
+        assert_eq!(deduplication_window.get("client12"), Some(12));
+        assert_eq!(deduplication_window.get("client22"), None);
 
-        let reader = log.open_line_reader().expect("Unable to open reader");
-        assert_eq!(*reader.get_index(), LinesIndex::new(10));
+        let config = DeduplicationConfig { window_size: 0, max_age_in_ms: 60000 };
+        assert_eq!(log.restore_deduplication_window(25, &config, 30000).map(|window| window.is_empty()), Ok(true));
 
-        let reader = log.open_line_reader_with_index(index.clone()).expect("Unable to open reader");
-        assert_eq!(*reader.get_index(), index);
+        assert!(log.remove().is_ok());
+    }
 
-        assert!(log.open_index_reader().is_err());
+    #[test]
+    fn test_restore_timestamp_index() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(SegmentSize::MaxEvents(20));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        let mut expected_timestamp_index = TimestampIndex::new(10);
+        for id in 1..46 {
+            assert!(logger.log(Event::new("data", vec!["tag1"]).with_timestamp(id * 1000)).is_ok());
+            expected_timestamp_index.insert(id, id * 1000);
+        }
 
-        let restored_index = log.restore_index().expect("Unable to compute, persist and restore index");
-        assert_eq!(restored_index, index);
+        assert_eq!(log.restore_timestamp_index(45), Ok(expected_timestamp_index.clone()));
+        assert_eq!(log.restore_timestamp_index(45), Ok(expected_timestamp_index.clone()));
 
-        assert!(log.open_index_reader().is_ok());
+        let timestamp_index_size = metadata(log.get_timestamp_index_path()).expect("Unable to read timestamp index metadata").len();
+        let file = OpenOptions::new().write(true).open(log.get_timestamp_index_path()).expect("Unable to open timestamp index file");
+        assert!(file.set_len(timestamp_index_size - 3).is_ok());
 
-        let restored_index = log.restore_index().expect("Unable to restore persisted index");
-        assert_eq!(restored_index, index);
+        assert_eq!(log.restore_timestamp_index(45), Ok(expected_timestamp_index.clone()));
+        assert_eq!(metadata(log.get_timestamp_index_path()).map(|metadata| metadata.len()).ok(), Some(timestamp_index_size));
 
-        assert!(log.persist_index(&LinesIndex::new(10)).is_ok());
+        let timestamp_index = log.restore_timestamp_index(25).expect("Unable to restore timestamp index");
+        assert_eq!(timestamp_index.last_id(), 25);
+        assert_eq!(timestamp_index.completed_blocks().len(), 2);
 
-        let restored_index = log.restore_index().expect("Unable to restore persisted index");
-        assert_eq!(restored_index, index);
+        assert!(log.segment(1).remove().is_ok());
+        let timestamp_index = log.restore_timestamp_index(45).expect("Unable to restore timestamp index");
+        assert_eq!(timestamp_index.completed_blocks().iter().map(|block| block.last_id).collect::<Vec<_>>(), vec![30, 40]);
+        assert_eq!(timestamp_index.interval(Some(33000), Some(36000)), Interval::new(30, 40));
 
         assert!(log.remove().is_ok());
+        assert!(metadata(log.get_timestamp_index_path()).is_err());
+    }
 
-        assert!(log.open_reader().is_err());
+    #[test]
+    fn test_restore_and_persist_checkpoints() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10);
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+
+        assert_eq!(log.restore_checkpoints(), Ok(Checkpoints::new()));
+
+        let mut checkpoints = Checkpoints::new();
+        assert!(checkpoints.acknowledge("consumer1", 10).is_ok());
+        assert!(checkpoints.acknowledge("consumer2", 20).is_ok());
+        assert!(log.persist_checkpoints(&checkpoints).is_ok());
+        assert_eq!(log.restore_checkpoints(), Ok(checkpoints.clone()));
+
+        assert!(OpenOptions::new().append(true).open(log.get_checkpoints_path()).and_then(|mut file| file.write_all(b"garbage\nconsumer3\t30\n")).is_ok());
+        assert_eq!(log.restore_checkpoints(), Ok(checkpoints.clone()));
+
+        assert!(checkpoints.remove("consumer1"));
+        assert!(log.persist_checkpoints(&checkpoints).is_ok());
+        assert_eq!(log.restore_checkpoints(), Ok(checkpoints));
+
+        assert!(log.remove().is_ok());
+        assert!(metadata(log.get_checkpoints_path()).is_err());
+    }
+
+    #[test]
+    fn test_recovery() {
+        let log = create_log_with_events(SegmentSize::MaxEvents(10), 15);
+
+        let report = log.recover().expect("Unable to recover log");
+        assert_eq!(report, RecoveryReport { last_id: 15, truncated_bytes: 0, discarded_index_entries: 0 });
+        assert!(!report.is_repaired());
+
+        let segment = log.last_segment().expect("Unable to get last segment");
+        let segment_size = segment.size().expect("Unable to read segment size");
+        let file = OpenOptions::new().write(true).open(segment.get_path()).expect("Unable to open segment file");
+        assert!(file.set_len(segment_size - 1).is_ok());
+
+        let report = log.recover().expect("Unable to recover log");
+        assert_eq!(report.last_id, 14);
+        assert!(report.is_repaired());
+
+        assert!(log.remove().is_ok());
     }
 }
@@ -1,10 +1,20 @@
 use super::*;
 
+use std::cmp::max;
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
 
 /// Exar DB's event logger.
 ///
+/// It appends events to the last segment of a log, starting a new segment
+/// once the current one would exceed the log segment size limit.
+///
 /// # Examples
 /// ```no_run
 /// extern crate exar;
@@ -15,26 +25,47 @@ use std::io::BufWriter;
 /// let log = Log::new("/path/to/logs", "test", 100);
 /// let event = Event::new("data", vec!["tag1", "tag2"]);
 ///
-/// let mut logger = Logger::new(log).unwrap();
+/// let mut logger = Logger::with_durability(log, Durability::EveryEvent).unwrap();
 /// let event_id = logger.log(event).unwrap();
 /// # }
 /// ```
 #[derive(Debug)]
 pub struct Logger {
+    log: Log,
+    segment: Segment,
     writer: BufWriter<File>,
     offset: u64,
-    bytes_written: u64
+    bytes_written: u64,
+    durability: Durability,
+    unsynced_events: u64,
+    syncer: Option<BackgroundSyncer>
 }
 
 impl Logger {
     /// Creates a new logger for the given `Log` or returns a `DatabaseError` if a failure occurs.
+    ///
+    /// The logger uses the default durability policy, which never explicitly syncs the log to disk.
     pub fn new(log: Log) -> Result<Logger, DatabaseError> {
-        log.restore_index().and_then(|index| {
-            log.open_writer().and_then(|writer| {
-                Ok(Logger {
-                    writer: writer,
-                    offset: index.line_count() + 1,
-                    bytes_written: index.byte_count()
+        Logger::with_durability(log, Durability::default())
+    }
+
+    /// Creates a new logger for the given `Log` using the given durability policy
+    /// or returns a `DatabaseError` if a failure occurs.
+    pub fn with_durability(log: Log, durability: Durability) -> Result<Logger, DatabaseError> {
+        log.last_segment().and_then(|segment| {
+            segment.restore_index().and_then(|index| {
+                segment.open_writer().and_then(|writer| {
+                    let syncer = try!(BackgroundSyncer::for_writer(&writer, &durability));
+                    Ok(Logger {
+                        offset: max(index.last_id() + 1, segment.first_id()),
+                        log: log,
+                        segment: segment,
+                        writer: writer,
+                        bytes_written: index.byte_count(),
+                        durability: durability,
+                        unsynced_events: 0,
+                        syncer: syncer
+                    })
                 })
             })
         })
@@ -42,32 +73,187 @@ impl Logger {
 
     /// Appends the given event to the log and returns the event `id`
     /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// Depending on the durability policy the log may be synced to disk before returning,
+    /// if the sync fails the event may have been written nonetheless.
     pub fn log(&mut self, event: Event) -> Result<u64, DatabaseError> {
-        match event.validated() {
-            Ok(event) => {
-                let event_id = self.offset;
-                let mut event = event.with_id(event_id);
-                if event.timestamp == 0 {
-                    event = event.with_current_timestamp();
-                }
-                let event_string = event.to_tab_separated_string();
-                match self.writer.write_line(&event_string) {
-                    Ok(bytes_written) => {
-                        self.offset += 1;
-                        self.bytes_written += bytes_written as u64;
-                        Ok(event_id)
-                    },
-                    Err(err) => Err(DatabaseError::from_io_error(err))
-                }
+        self.log_batch(vec![event]).map(|(event_id, _)| event_id)
+    }
+
+    /// Appends the given events to the log as a single batch with contiguous ids
+    /// and returns the `id`s of the first and last events, or a `DatabaseError` if a failure occurs.
+    ///
+    /// The events are all validated before any of them is written, and the batch is written at once,
+    /// with each record but the last one flagged as continuing the batch, so that log readers
+    /// and log recovery treat the batch as a single atomic unit.
+    ///
+    /// If writing the batch fails, any part of it already buffered or written is discarded
+    /// and the log is left as it was before the batch was appended.
+    pub fn log_batch(&mut self, events: Vec<Event>) -> Result<(u64, u64), DatabaseError> {
+        if events.is_empty() {
+            return Err(DatabaseError::ValidationError(ValidationError::new("event batch must contain at least one event")))
+        }
+        let events = match events.into_iter().map(|event| event.validated()).collect::<Result<Vec<_>, _>>() {
+            Ok(events) => events,
+            Err(err) => return Err(DatabaseError::ValidationError(err))
+        };
+        let first_event_id = self.offset;
+        let last_event_id = first_event_id + events.len() as u64 - 1;
+        let events_count = events.len() as u64;
+        let mut records = vec![];
+        for (event_id, event) in (first_event_id..).zip(events) {
+            let mut event = event.with_id(event_id);
+            if event.timestamp == 0 {
+                event = event.with_current_timestamp();
+            }
+            let flags = if event_id < last_event_id { BATCH_CONTINUATION_FLAG } else { 0 };
+            records.extend_from_slice(&event.to_log_record_with_flags(flags));
+        }
+        if self.bytes_written > 0 && self.log.get_segment_size().is_exceeded_by(self.bytes_written, first_event_id - self.segment.first_id(),
+                                                                                records.len() as u64, events_count) {
+            try!(self.roll_segment());
+        }
+        match self.writer.write_all(&records).and_then(|_| self.writer.flush()) {
+            Ok(()) => {
+                let nr_of_events = last_event_id - first_event_id + 1;
+                self.offset += nr_of_events;
+                self.bytes_written += records.len() as u64;
+                self.apply_durability(nr_of_events).and_then(|_| Ok((first_event_id, last_event_id)))
             },
-            Err(err) => Err(DatabaseError::ValidationError(err))
+            Err(err) => {
+                try!(self.discard_unflushed_records());
+                Err(DatabaseError::from_io_error(err))
+            }
         }
     }
 
-    /// Returns the total number of bytes logged.
+    /// Returns the `id` of the last event logged.
+    pub fn last_event_id(&self) -> u64 {
+        self.offset - 1
+    }
+
+    /// Returns the number of bytes logged into the current log segment.
     pub fn bytes_written(&self) -> u64 {
         self.bytes_written
     }
+
+    /// Returns the `id` of the first event of the current log segment.
+    pub fn segment_first_id(&self) -> u64 {
+        self.segment.first_id()
+    }
+
+    /// Returns the durability policy used by the logger.
+    pub fn durability(&self) -> &Durability {
+        &self.durability
+    }
+
+    /// Syncs the events logged so far to disk or returns a `DatabaseError` if a failure occurs.
+    pub fn sync(&mut self) -> Result<(), DatabaseError> {
+        match self.writer.get_ref().sync_data() {
+            Ok(()) => {
+                self.unsynced_events = 0;
+                Ok(())
+            },
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    fn roll_segment(&mut self) -> Result<(), DatabaseError> {
+        if self.durability != Durability::None {
+            try!(self.sync());
+        }
+        let segment = self.log.segment(self.offset);
+        try!(segment.ensure_exists());
+        let writer = try!(segment.open_writer());
+        self.syncer = try!(BackgroundSyncer::for_writer(&writer, &self.durability));
+        self.segment = segment;
+        self.writer = writer;
+        self.bytes_written = 0;
+        Ok(())
+    }
+
+    fn discard_unflushed_records(&mut self) -> Result<(), DatabaseError> {
+        let writer = try!(self.segment.open_writer());
+        let _ = mem::replace(&mut self.writer, writer).into_parts();
+        match self.writer.get_ref().set_len(self.bytes_written) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    fn apply_durability(&mut self, nr_of_events: u64) -> Result<(), DatabaseError> {
+        self.unsynced_events += nr_of_events;
+        match self.durability {
+            Durability::None => Ok(()),
+            Durability::EveryEvent => self.sync(),
+            Durability::EveryNEvents(n) if self.unsynced_events >= n => self.sync(),
+            Durability::EveryNEvents(_) => Ok(()),
+            Durability::IntervalMs(_) => {
+                if let Some(ref syncer) = self.syncer {
+                    syncer.mark_unsynced();
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A background thread syncing a log file to disk at a fixed interval,
+/// only if events have been written since the previous sync.
+///
+/// The thread performs a last sync and stops when the syncer is dropped.
+#[derive(Debug)]
+struct BackgroundSyncer {
+    unsynced: Arc<AtomicBool>,
+    stop_sender: Sender<()>
+}
+
+impl BackgroundSyncer {
+    fn new(file: File, interval: Duration) -> BackgroundSyncer {
+        let unsynced = Arc::new(AtomicBool::new(false));
+        let (stop_sender, stop_receiver) = channel::<()>();
+        let thread_unsynced = unsynced.clone();
+        thread::spawn(move || {
+            loop {
+                let stopped = match stop_receiver.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => false,
+                    _ => true
+                };
+                if thread_unsynced.swap(false, Ordering::SeqCst) {
+                    if let Err(err) = file.sync_data() {
+                        error!("Unable to sync log file: {}", err);
+                    }
+                }
+                if stopped {
+                    break
+                }
+            }
+        });
+        BackgroundSyncer {
+            unsynced: unsynced,
+            stop_sender: stop_sender
+        }
+    }
+
+    fn for_writer(writer: &BufWriter<File>, durability: &Durability) -> Result<Option<BackgroundSyncer>, DatabaseError> {
+        match durability.sync_interval() {
+            Some(interval) => match writer.get_ref().try_clone() {
+                Ok(file) => Ok(Some(BackgroundSyncer::new(file, interval))),
+                Err(err) => Err(DatabaseError::from_io_error(err))
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn mark_unsynced(&self) {
+        self.unsynced.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for BackgroundSyncer {
+    fn drop(&mut self) {
+        let _ = self.stop_sender.send(());
+    }
 }
 
 #[cfg(test)]
@@ -75,7 +261,8 @@ mod tests {
     use super::super::*;
     use exar_testkit::*;
 
-    use std::io::{BufRead, BufReader};
+    use std::fs::File;
+    use std::io::BufWriter;
 
     fn create_log() -> Log {
         let ref collection_name = random_collection_name();
@@ -99,7 +286,7 @@ mod tests {
 
         assert_eq!(logger.writer.get_ref().metadata().unwrap().is_file(), true);
         assert_eq!(logger.offset, 2);
-        assert_eq!(logger.bytes_written, 31);
+        assert_eq!(logger.bytes_written, 47);
 
         assert!(log.remove().is_ok());
     }
@@ -123,40 +310,196 @@ mod tests {
 
         assert_eq!(logger.log(event.clone()), Ok(1));
         assert_eq!(logger.offset, 2);
-        assert_eq!(logger.bytes_written, 31);
+        assert_eq!(logger.bytes_written, 47);
         assert_eq!(logger.log(event.clone()), Ok(2));
         assert_eq!(logger.offset, 3);
-        assert_eq!(logger.bytes_written, 62);
-
-        let reader = log.open_reader().expect("Unable to open reader");
+        assert_eq!(logger.bytes_written, 94);
 
-        let mut lines = BufReader::new(reader).lines();
+        let mut reader = log.open_log_reader().expect("Unable to open reader");
 
-        let line = lines.next().expect("Unable to read next line")
-                               .expect("Unable to read next line");
-
-        let event = Event::from_tab_separated_str(&line).expect("Unable to decode event");
+        let event = reader.next().expect("Unable to read next event")
+                                 .expect("Unable to read next event");
 
         assert_eq!(event.id, 1);
-        assert_eq!(event.data, "data");
+        assert_eq!(event.data, b"data");
         assert_eq!(event.tags, vec!["tag1", "tag2"]);
         assert!(event.timestamp > 0);
 
-        let line = lines.next().expect("Unable to read next line")
-                               .expect("Unable to read next line");
-
-        let event = Event::from_tab_separated_str(&line).expect("Unable to decode event");
+        let event = reader.next().expect("Unable to read next event")
+                                 .expect("Unable to read next event");
 
         assert_eq!(event.id, 2);
-        assert_eq!(event.data, "data");
+        assert_eq!(event.data, b"data");
         assert_eq!(event.tags, vec!["tag1", "tag2"]);
         assert!(event.timestamp > 0);
 
-        assert!(lines.next().is_none());
+        assert!(reader.next().is_none());
 
         assert!(log.remove().is_ok());
     }
 
+    #[test]
+    fn test_log_data_with_tabs_and_new_lines() {
+        let log = create_log();
+        let event = Event::new("{\n\t\"key\": \"value\"\n}", vec!["tag1"]);
+
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+
+        assert_eq!(logger.log(event.clone()), Ok(1));
+        assert_eq!(logger.log(event.clone()), Ok(2));
+
+        let events: Vec<_> = log.open_log_reader().expect("Unable to open reader")
+                                .map(|e| e.expect("Unable to read event")).collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].data, event.data);
+        assert_eq!(events[1].id, 2);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_log_binary_data() {
+        let log = create_log();
+        let event = Event::from_bytes(vec![0xff, 0x00, 0xfe, b'\n', b'\t', 0xc3], vec!["tag1"]);
+
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+
+        assert_eq!(logger.log(event.clone()), Ok(1));
+
+        let events: Vec<_> = log.open_log_reader().expect("Unable to open reader")
+                                .map(|e| e.expect("Unable to read event")).collect();
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, vec![0xff, 0x00, 0xfe, b'\n', b'\t', 0xc3]);
+        assert_eq!(events[0].data_as_str(), None);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_log_batch() {
+        let log = create_log();
+        let events = vec![Event::new("data", vec!["tag1"]), Event::new("data", vec!["tag2"]), Event::new("data", vec!["tag3"])];
+
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+
+        assert_eq!(logger.log(Event::new("data", vec!["tag1"])), Ok(1));
+        assert_eq!(logger.log_batch(events.clone()), Ok((2, 4)));
+        assert_eq!(logger.offset, 5);
+        assert_eq!(logger.bytes_written, 4 * 41);
+        assert_eq!(logger.log(Event::new("data", vec!["tag1"])), Ok(5));
+
+        let events: Vec<_> = log.open_log_reader().expect("Unable to open reader")
+                                .map(|e| e.expect("Unable to read event")).collect();
+
+        assert_eq!(events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(events[3].tags, vec!["tag3"]);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_log_batch_write_failure() {
+        let log = create_log();
+        let event = Event::new("data", vec!["tag1"]);
+
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+
+        assert_eq!(logger.log(event.clone()), Ok(1));
+
+        let read_only_file = File::open(logger.segment.get_path()).expect("Unable to open segment file");
+        logger.writer = BufWriter::new(read_only_file);
+
+        assert!(logger.log_batch(vec![event.clone(); 2]).is_err());
+        assert_eq!(logger.offset, 2);
+        assert_eq!(logger.bytes_written, 41);
+        assert_eq!(logger.segment.size(), Ok(41));
+
+        assert_eq!(logger.log(event.clone()), Ok(2));
+
+        let events: Vec<_> = log.open_log_reader().expect("Unable to open reader")
+                                .map(|e| e.expect("Unable to read event")).collect();
+
+        assert_eq!(events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 2]);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_log_batch_validation_failure() {
+        let log = create_log();
+        let events = vec![Event::new("data", vec!["tag1"]), Event::new("data", vec![])];
+
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+
+        let expected_validation_error = ValidationError::new("event must contain at least one tag");
+        assert_eq!(logger.log_batch(events), Err(DatabaseError::ValidationError(expected_validation_error)));
+
+        let expected_validation_error = ValidationError::new("event batch must contain at least one event");
+        assert_eq!(logger.log_batch(vec![]), Err(DatabaseError::ValidationError(expected_validation_error)));
+
+        assert_eq!(logger.offset, 1);
+        assert_eq!(logger.bytes_written, 0);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_segment_rolling() {
+        let log = create_log().with_segment_size(SegmentSize::MaxEvents(3));
+        let event = Event::new("data", vec!["tag1"]);
+
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+
+        assert_eq!(logger.log_batch(vec![event.clone(); 2]), Ok((1, 2)));
+        assert_eq!(logger.segment_first_id(), 1);
+        assert_eq!(logger.log_batch(vec![event.clone(); 2]), Ok((3, 4)));
+        assert_eq!(logger.segment_first_id(), 3);
+        assert_eq!(logger.bytes_written, 2 * 41);
+        assert_eq!(logger.log(event.clone()), Ok(5));
+        assert_eq!(logger.segment_first_id(), 3);
+
+        let logger = Logger::new(log.clone()).expect("Unable to create logger");
+
+        assert_eq!(logger.offset, 6);
+        assert_eq!(logger.last_event_id(), 5);
+        assert_eq!(logger.segment_first_id(), 3);
+        assert_eq!(logger.bytes_written, 3 * 41);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_durability() {
+        for durability in vec![Durability::None, Durability::EveryEvent, Durability::EveryNEvents(2), Durability::IntervalMs(1)] {
+            let log = create_log();
+            let event = Event::new("data", vec!["tag1"]);
+
+            let mut logger = Logger::with_durability(log.clone(), durability.clone()).expect("Unable to create logger");
+
+            assert_eq!(logger.durability(), &durability);
+            assert_eq!(logger.syncer.is_some(), durability.sync_interval().is_some());
+
+            assert_eq!(logger.log(event.clone()), Ok(1));
+            assert_eq!(logger.unsynced_events, if durability == Durability::EveryEvent { 0 } else { 1 });
+            assert_eq!(logger.log(event.clone()), Ok(2));
+            assert_eq!(logger.unsynced_events, match durability {
+                Durability::EveryEvent | Durability::EveryNEvents(_) => 0,
+                _ => 2
+            });
+
+            assert_eq!(logger.sync(), Ok(()));
+            assert_eq!(logger.unsynced_events, 0);
+
+            drop(logger);
+
+            assert_eq!(log.open_log_reader().expect("Unable to open reader").count(), 2);
+
+            assert!(log.remove().is_ok());
+        }
+    }
+
     #[test]
     fn test_event_validation_failure() {
         let log = create_log();
@@ -0,0 +1,316 @@
+use super::*;
+
+use regex::Regex;
+use serde_json::{self, Value};
+
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter, Result as DisplayResult};
+use std::str::FromStr;
+
+/// Exar DB's event payload filter.
+///
+/// It describes a condition on the data of an event, which can be a substring, a regular expression,
+/// or a comparison between the value found at a given path of a JSON payload and a JSON value.
+///
+/// The textual syntax of a payload filter is one of:
+///
+/// - `contains:<text>`
+/// - `regex:<pattern>`
+/// - `json:<path> <operator> <value>`, where the path uses the `$.field.items[0]` notation,
+///   the operator is one of `==`, `!=`, `<`, `<=`, `>`, `>=` and the value is a JSON value.
+///
+/// A JSON comparison never matches events whose data is not a JSON document or has no value at the given path,
+/// and ordering comparisons only match numbers, strings or booleans of the same type.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let filter: PayloadFilter = r#"json:$.order.amount >= 100"#.parse().unwrap();
+///
+/// assert!(filter.matches(r#"{ "order": { "amount": 120 } }"#));
+/// assert!(!filter.matches(r#"{ "order": { "amount": 80 } }"#));
+///
+/// // or using the constructors
+/// let contains_filter = PayloadFilter::contains("refund");
+/// let regex_filter = PayloadFilter::regex("^audit-[0-9]+$").unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub enum PayloadFilter {
+    /// Matches the events whose data contains the given text.
+    Contains(String),
+    /// Matches the events whose data matches the given regular expression.
+    Regex(Regex),
+    /// Matches the events whose data is a JSON document containing a value at the given path,
+    /// which compares to the given JSON value according to the given operator.
+    Json(String, ComparisonOperator, Value)
+}
+
+impl PayloadFilter {
+    /// Returns a new `PayloadFilter` matching the events whose data contains the given text.
+    pub fn contains(text: &str) -> PayloadFilter {
+        PayloadFilter::Contains(text.to_owned())
+    }
+
+    /// Returns a new `PayloadFilter` matching the events whose data matches the given regular expression
+    /// or a `ParseError` if the regular expression is invalid.
+    pub fn regex(pattern: &str) -> Result<PayloadFilter, ParseError> {
+        match Regex::new(pattern) {
+            Ok(regex) => Ok(PayloadFilter::Regex(regex)),
+            Err(err) => Err(ParseError::ParseError(format!("invalid payload filter regex: {}", err)))
+        }
+    }
+
+    /// Returns a new `PayloadFilter` comparing the value at the given path of a JSON payload to the given JSON value,
+    /// or a `ParseError` if the path is invalid.
+    pub fn json(path: &str, operator: ComparisonOperator, value: Value) -> Result<PayloadFilter, ParseError> {
+        json_pointer(path).and_then(|_| Ok(PayloadFilter::Json(path.to_owned(), operator, value)))
+    }
+
+    /// Returns wether the given event data matches the filter.
+    pub fn matches(&self, data: &str) -> bool {
+        match *self {
+            PayloadFilter::Contains(ref text) => data.contains(&text[..]),
+            PayloadFilter::Regex(ref regex) => regex.is_match(data),
+            PayloadFilter::Json(ref path, ref operator, ref value) => {
+                match (serde_json::from_str::<Value>(data), json_pointer(path)) {
+                    (Ok(document), Ok(pointer)) => document.pointer(&pointer).map_or(false, |actual| operator.compare(actual, value)),
+                    _ => false
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq for PayloadFilter {
+    fn eq(&self, other: &PayloadFilter) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for PayloadFilter {}
+
+impl Display for PayloadFilter {
+    fn fmt(&self, f: &mut Formatter) -> DisplayResult {
+        match *self {
+            PayloadFilter::Contains(ref text) => write!(f, "contains:{}", text),
+            PayloadFilter::Regex(ref regex) => write!(f, "regex:{}", regex.as_str()),
+            PayloadFilter::Json(ref path, ref operator, ref value) => write!(f, "json:{} {} {}", path, operator, value)
+        }
+    }
+}
+
+impl FromStr for PayloadFilter {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<PayloadFilter, ParseError> {
+        if s.starts_with("contains:") {
+            Ok(PayloadFilter::contains(&s["contains:".len()..]))
+        } else if s.starts_with("regex:") {
+            PayloadFilter::regex(&s["regex:".len()..])
+        } else if s.starts_with("json:") {
+            let (path, rest) = split_first_word(&s["json:".len()..]);
+            let (operator, value) = split_first_word(rest);
+            let operator = try!(operator.parse());
+            match serde_json::from_str(value) {
+                Ok(value) => PayloadFilter::json(path, operator, value),
+                Err(err) => Err(ParseError::ParseError(format!("invalid payload filter JSON value: {}", err)))
+            }
+        } else {
+            Err(ParseError::ParseError(format!("unknown payload filter: {}", s)))
+        }
+    }
+}
+
+/// A list specifying the operators of a JSON payload filter comparison.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComparisonOperator {
+    /// The values are equal (`==`).
+    Equal,
+    /// The values are not equal (`!=`).
+    NotEqual,
+    /// The value is less than the given one (`<`).
+    LessThan,
+    /// The value is less than or equal to the given one (`<=`).
+    LessThanOrEqual,
+    /// The value is greater than the given one (`>`).
+    GreaterThan,
+    /// The value is greater than or equal to the given one (`>=`).
+    GreaterThanOrEqual
+}
+
+impl ComparisonOperator {
+    /// Returns wether the given actual JSON value compares to the given expected one according to the operator.
+    pub fn compare(&self, actual: &Value, expected: &Value) -> bool {
+        let ordering = match (actual, expected) {
+            (&Value::Number(ref actual), &Value::Number(ref expected)) => {
+                actual.as_f64().and_then(|actual| expected.as_f64().and_then(|expected| actual.partial_cmp(&expected)))
+            },
+            (&Value::String(ref actual), &Value::String(ref expected)) => Some(actual.cmp(expected)),
+            (&Value::Bool(actual), &Value::Bool(expected)) => Some(actual.cmp(&expected)),
+            (actual, expected) if actual == expected => Some(Ordering::Equal),
+            _ => None
+        };
+        match *self {
+            ComparisonOperator::Equal => ordering == Some(Ordering::Equal),
+            ComparisonOperator::NotEqual => ordering != Some(Ordering::Equal),
+            ComparisonOperator::LessThan => ordering == Some(Ordering::Less),
+            ComparisonOperator::LessThanOrEqual => ordering == Some(Ordering::Less) || ordering == Some(Ordering::Equal),
+            ComparisonOperator::GreaterThan => ordering == Some(Ordering::Greater),
+            ComparisonOperator::GreaterThanOrEqual => ordering == Some(Ordering::Greater) || ordering == Some(Ordering::Equal)
+        }
+    }
+}
+
+impl Display for ComparisonOperator {
+    fn fmt(&self, f: &mut Formatter) -> DisplayResult {
+        match *self {
+            ComparisonOperator::Equal => write!(f, "=="),
+            ComparisonOperator::NotEqual => write!(f, "!="),
+            ComparisonOperator::LessThan => write!(f, "<"),
+            ComparisonOperator::LessThanOrEqual => write!(f, "<="),
+            ComparisonOperator::GreaterThan => write!(f, ">"),
+            ComparisonOperator::GreaterThanOrEqual => write!(f, ">=")
+        }
+    }
+}
+
+impl FromStr for ComparisonOperator {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<ComparisonOperator, ParseError> {
+        match s {
+            "==" => Ok(ComparisonOperator::Equal),
+            "!=" => Ok(ComparisonOperator::NotEqual),
+            "<" => Ok(ComparisonOperator::LessThan),
+            "<=" => Ok(ComparisonOperator::LessThanOrEqual),
+            ">" => Ok(ComparisonOperator::GreaterThan),
+            ">=" => Ok(ComparisonOperator::GreaterThanOrEqual),
+            x => Err(ParseError::ParseError(format!("unknown comparison operator: {}", x)))
+        }
+    }
+}
+
+fn split_first_word(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], s[i..].trim()),
+        None => (s, "")
+    }
+}
+
+// converts a `$.field.items[0]` path to a JSON pointer
+fn json_pointer(path: &str) -> Result<String, ParseError> {
+    let invalid_path = || ParseError::ParseError(format!("invalid payload filter JSON path: {}", path));
+    if !path.starts_with('$') {
+        return Err(invalid_path())
+    }
+    let mut pointer = String::new();
+    let mut rest = &path[1..];
+    while !rest.is_empty() {
+        if rest.starts_with('.') {
+            let end = rest[1..].find(|c| c == '.' || c == '[').map_or(rest.len(), |i| i + 1);
+            let key = &rest[1..end];
+            if key.is_empty() || key.contains(']') {
+                return Err(invalid_path())
+            }
+            pointer.push('/');
+            pointer.push_str(&key.replace("~", "~0").replace("/", "~1"));
+            rest = &rest[end..];
+        } else if rest.starts_with('[') {
+            let end = try!(rest.find(']').ok_or_else(|| invalid_path()));
+            let index = &rest[1..end];
+            if index.is_empty() || !index.chars().all(|c| c.is_digit(10)) {
+                return Err(invalid_path())
+            }
+            pointer.push('/');
+            pointer.push_str(index);
+            rest = &rest[end + 1..];
+        } else {
+            return Err(invalid_path())
+        }
+    }
+    Ok(pointer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    use serde_json::Value;
+
+    fn json(s: &str) -> Value {
+        ::serde_json::from_str(s).expect("Unable to parse JSON value")
+    }
+
+    #[test]
+    fn test_contains() {
+        let filter = PayloadFilter::contains("refund");
+
+        assert!(filter.matches("order refunded"));
+        assert!(!filter.matches("order shipped"));
+    }
+
+    #[test]
+    fn test_regex() {
+        let filter = PayloadFilter::regex("^audit-[0-9]+$").expect("Unable to create regex filter");
+
+        assert!(filter.matches("audit-123"));
+        assert!(!filter.matches("audit-abc"));
+        assert!(PayloadFilter::regex("(").is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        let data = r#"{ "order": { "amount": 120, "currency": "EUR", "items": [{ "sku": "a1" }], "paid": true } }"#;
+
+        let matches = |path: &str, operator: ComparisonOperator, value: &str| {
+            PayloadFilter::json(path, operator, json(value)).expect("Unable to create JSON filter").matches(data)
+        };
+
+        assert!(matches("$.order.amount", ComparisonOperator::Equal, "120"));
+        assert!(matches("$.order.amount", ComparisonOperator::Equal, "120.0"));
+        assert!(matches("$.order.amount", ComparisonOperator::GreaterThanOrEqual, "100"));
+        assert!(!matches("$.order.amount", ComparisonOperator::LessThan, "100"));
+        assert!(matches("$.order.currency", ComparisonOperator::NotEqual, r#""USD""#));
+        assert!(matches("$.order.currency", ComparisonOperator::LessThan, r#""USD""#));
+        assert!(!matches("$.order.currency", ComparisonOperator::LessThan, "100"));
+        assert!(matches("$.order.items[0].sku", ComparisonOperator::Equal, r#""a1""#));
+        assert!(matches("$.order.paid", ComparisonOperator::Equal, "true"));
+        assert!(!matches("$.order.missing", ComparisonOperator::NotEqual, "1"));
+        assert!(!matches("$.order.items[1].sku", ComparisonOperator::Equal, r#""a1""#));
+
+        let filter = PayloadFilter::json("$.amount", ComparisonOperator::Equal, json("1")).expect("Unable to create JSON filter");
+        assert!(!filter.matches("not json"));
+
+        assert!(PayloadFilter::json("order.amount", ComparisonOperator::Equal, json("1")).is_err());
+        assert!(PayloadFilter::json("$.order..amount", ComparisonOperator::Equal, json("1")).is_err());
+        assert!(PayloadFilter::json("$.items[a]", ComparisonOperator::Equal, json("1")).is_err());
+    }
+
+    #[test]
+    fn test_parsing_and_display() {
+        let filters = vec![
+            "contains:order refunded",
+            "regex:^audit-[0-9]+$",
+            "json:$.order.amount >= 100",
+            r#"json:$.order.currency == "EUR EUR""#,
+            "json:$ == null"
+        ];
+        for filter in filters {
+            assert_eq!(filter.parse::<PayloadFilter>().map(|filter| filter.to_string()), Ok(filter.to_owned()));
+        }
+
+        assert_eq!("json:$.order.amount  >=  100".parse(),
+                   PayloadFilter::json("$.order.amount", ComparisonOperator::GreaterThanOrEqual, json("100")));
+
+        assert!("unknown:value".parse::<PayloadFilter>().is_err());
+        assert!("json:$.order.amount => 100".parse::<PayloadFilter>().is_err());
+        assert!("json:$.order.amount == EUR".parse::<PayloadFilter>().is_err());
+        assert!("regex:(".parse::<PayloadFilter>().is_err());
+    }
+}
@@ -2,6 +2,18 @@ use super::*;
 
 /// Exar DB's subscription query.
 ///
+/// A query can also be written in a textual query language, with case insensitive keywords:
+///
+/// ```text
+/// [LIVE | CURRENT] [FROM <offset>] [LIMIT <limit> | LAST <limit>] [REVERSE] [WHERE <condition>]
+/// ```
+///
+/// A condition is one of `tag = '<tag>'`, `tag != '<tag>'`, `ts <operator> <timestamp>`, `stream = '<stream>'`,
+/// `stream_version >= <version>`, `data CONTAINS '<text>'`, `data MATCHES '<regex>'` or `data<path> <operator> <value>`,
+/// where the path uses the `.field.items[0]` notation and the value is a quoted string, a number, `true`, `false` or `null`.
+/// Conditions are combined with `AND`, while tag conditions can also be combined with `OR`, `NOT` and parentheses.
+/// Single quotes within a quoted string are escaped by doubling them.
+///
 /// # Examples
 /// ```
 /// extern crate exar;
@@ -13,6 +25,28 @@ use super::*;
 /// 
 /// // or using the fluent API
 /// let fluent_query = Query::live().offset(100).limit(20).by_tag("tag");
+///
+/// // or using the query language
+/// let text_query: Query = "LIVE FROM 100 LIMIT 20 WHERE tag = 'tag'".parse().unwrap();
+///
+/// assert_eq!(query, fluent_query);
+/// assert_eq!(query, text_query);
+/// assert_eq!(text_query.to_string(), "LIVE FROM 100 LIMIT 20 WHERE tag = 'tag'");
+///
+/// // targeting the events matching a tag expression
+/// let tag_expression_query = Query::live().by_tag_expression("(order AND eu) OR refund NOT test".parse().unwrap());
+///
+/// // targeting the events with a JSON payload matching a filter
+/// let payload_filter_query = Query::current().by_payload_filter("json:$.order.amount >= 100".parse().unwrap());
+///
+/// // targeting the latest 20 events, in descending order
+/// let latest_query = Query::last(20);
+///
+/// // targeting a time range (timestamps in ms)
+/// let time_range_query = Query::current().from_timestamp(1483228800000).to_timestamp(1483232400000);
+///
+/// // targeting the events of a stream, starting from a stream version
+/// let stream_query = Query::current().by_stream("order-123").from_stream_version(5);
 /// # }
 /// ```
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -25,7 +59,22 @@ pub struct Query {
     pub limit: Option<u64>,
     /// Indicates the query target event tag, if specified.
     pub tag: Option<String>,
+    /// Indicates the tag expression the query target events must match, if specified.
+    pub tag_expression: Option<TagExpression>,
+    /// Indicates the minimum timestamp (in ms) of the events targeted by the query, if specified.
+    pub from_timestamp: Option<u64>,
+    /// Indicates the maximum timestamp (in ms) of the events targeted by the query, if specified.
+    pub to_timestamp: Option<u64>,
+    /// Indicates the filter the data of the query target events must match, if specified.
+    pub payload_filter: Option<PayloadFilter>,
+    /// Indicates the `id` of the stream the query target events belong to, if specified.
+    pub stream_id: Option<String>,
+    /// Indicates the minimum stream version of the events targeted by the query, if specified.
+    pub from_stream_version: Option<u64>,
+    /// Indicates wether the query targets events in descending order, starting from the latest one.
+    pub reverse: bool,
     position: u64,
+    end_position: u64,
     count: u64
 }
 
@@ -36,8 +85,16 @@ impl Query {
             offset: offset,
             limit: limit,
             tag: tag,
+            tag_expression: None,
+            from_timestamp: None,
+            to_timestamp: None,
+            payload_filter: None,
+            stream_id: None,
+            from_stream_version: None,
+            reverse: false,
             live_stream: live_stream,
             position: offset,
+            end_position: u64::max_value(),
             count: 0
         }
     }
@@ -52,6 +109,11 @@ impl Query {
         Query::new(true, 0, None, None)
     }
 
+    /// Initializes a `Query` targeting the latest `n` events in the event log, in descending order.
+    pub fn last(n: u64) -> Query {
+        Query::current().reverse().limit(n)
+    }
+
     /// Mutates and returns the query by updating its target offset.
     pub fn offset(mut self, offset: u64) -> Query {
         self.offset = offset;
@@ -71,12 +133,90 @@ impl Query {
         self
     }
 
+    /// Mutates and returns the query by updating the tag expression its target events must match.
+    pub fn by_tag_expression(mut self, tag_expression: TagExpression) -> Query {
+        self.tag_expression = Some(tag_expression);
+        self
+    }
+
+    /// Mutates and returns the query by updating the minimum timestamp (in ms) of its target events, inclusive.
+    pub fn from_timestamp(mut self, timestamp: u64) -> Query {
+        self.from_timestamp = Some(timestamp);
+        self
+    }
+
+    /// Mutates and returns the query by updating the maximum timestamp (in ms) of its target events, inclusive.
+    pub fn to_timestamp(mut self, timestamp: u64) -> Query {
+        self.to_timestamp = Some(timestamp);
+        self
+    }
+
+    /// Mutates and returns the query by updating the filter the data of its target events must match.
+    pub fn by_payload_filter(mut self, payload_filter: PayloadFilter) -> Query {
+        self.payload_filter = Some(payload_filter);
+        self
+    }
+
+    /// Mutates and returns the query by updating the `id` of the stream its target events belong to.
+    pub fn by_stream(mut self, stream_id: &str) -> Query {
+        self.stream_id = Some(stream_id.to_owned());
+        self
+    }
+
+    /// Mutates and returns the query by updating the minimum stream version of its target events, inclusive.
+    pub fn from_stream_version(mut self, version: u64) -> Query {
+        self.from_stream_version = Some(version);
+        self
+    }
+
+    /// Mutates and returns the query so that it targets events in descending order, starting from the latest one.
+    ///
+    /// A reverse query never targets real-time events, it ends once the events preceding it have been returned
+    /// (down to its target offset, if any).
+    pub fn reverse(mut self) -> Query {
+        self.reverse = true;
+        self.live_stream = false;
+        self
+    }
+
+    /// Returns the tag expression combining the query target event tag and tag expression, if any.
+    pub fn combined_tag_expression(&self) -> Option<TagExpression> {
+        match (self.tag.clone(), self.tag_expression.clone()) {
+            (Some(tag), Some(tag_expression)) => Some(TagExpression::Tag(tag).and(tag_expression)),
+            (Some(tag), None) => Some(TagExpression::Tag(tag)),
+            (None, tag_expression) => tag_expression
+        }
+    }
+
+    /// Returns a tag contained by all the events matching the query, if any,
+    /// which can be used to find them through the `TagIndex` of the log.
+    pub fn required_tag(&self) -> Option<&str> {
+        self.tag.as_ref().map(|tag| &tag[..]).or_else(|| {
+            self.tag_expression.as_ref().and_then(|tag_expression| tag_expression.required_tag())
+        })
+    }
+
     /// Returns wether a given `Event` matches the query.
     pub fn matches(&self, event: &Event) -> bool {
-        match self.tag {
-            Some(ref tag) => self.position < event.id && event.tags.contains(tag),
-            None => self.position < event.id
-        }
+        let matches_tag = match self.tag {
+            Some(ref tag) => event.tags.contains(tag),
+            None => true
+        } && self.tag_expression.as_ref().map_or(true, |tag_expression| tag_expression.matches(&event.tags));
+        let matches_time_range = self.from_timestamp.map_or(true, |from_timestamp| event.timestamp >= from_timestamp) &&
+                                 self.to_timestamp.map_or(true, |to_timestamp| event.timestamp <= to_timestamp);
+        let matches_stream = self.stream_id.as_ref().map_or(true, |stream_id| event.stream_id.as_ref() == Some(stream_id)) &&
+                             self.from_stream_version.map_or(true, |version| event.stream_id.is_some() && event.stream_version >= version);
+        self.position < event.id && event.id < self.end_position && matches_tag && matches_time_range && matches_stream
+    }
+
+    /// Returns wether the data of a given `Event` matches the query payload filter, if any.
+    ///
+    /// It is checked separately from the other query parameters, since it may require parsing the event data.
+    /// Binary data, that is not valid UTF-8, never matches a payload filter.
+    pub fn matches_payload(&self, event: &Event) -> bool {
+        self.payload_filter.as_ref().map_or(true, |payload_filter| {
+            event.data_as_str().map_or(false, |data| payload_filter.matches(data))
+        })
     }
 
     /// Returns wether the query is still active.
@@ -89,20 +229,30 @@ impl Query {
 
     /// Updates the internal state of the query given the last matching event `id`.
     pub fn update(&mut self, event_id: u64) {
-        self.position = event_id;
+        if self.reverse {
+            self.end_position = event_id;
+        } else {
+            self.position = event_id;
+        }
         self.count += 1;
     }
 
     /// Returns the offsets interval the query targets.
     pub fn interval(&self) -> Interval<u64> {
         let start = self.position;
-        let end = if self.limit.is_none() || self.tag.is_some() {
+        let end = if self.limit.is_none() || self.is_filtered() {
             u64::max_value()
         } else {
             start + self.limit.unwrap()
         };
         Interval::new(start, end)
     }
+
+    // the limit of a filtered query does not bound the portion of the log it targets
+    fn is_filtered(&self) -> bool {
+        self.tag.is_some() || self.tag_expression.is_some() || self.from_timestamp.is_some() ||
+        self.to_timestamp.is_some() || self.payload_filter.is_some() || self.stream_id.is_some() || self.from_stream_version.is_some()
+    }
 }
 
 #[cfg(test)]
@@ -137,6 +287,30 @@ mod tests {
 
         let query = query.by_tag("tag");
         assert_eq!(query.tag, Some("tag".to_owned()));
+
+        let query = query.from_timestamp(1000).to_timestamp(2000);
+        assert_eq!(query.from_timestamp, Some(1000));
+        assert_eq!(query.to_timestamp, Some(2000));
+
+        let query = query.by_tag_expression(TagExpression::tag("tag1").not());
+        assert_eq!(query.tag_expression, Some(TagExpression::tag("tag1").not()));
+
+        let query = query.by_payload_filter(PayloadFilter::contains("data"));
+        assert_eq!(query.payload_filter, Some(PayloadFilter::contains("data")));
+
+        let query = query.by_stream("stream1").from_stream_version(5);
+        assert_eq!(query.stream_id, Some("stream1".to_owned()));
+        assert_eq!(query.from_stream_version, Some(5));
+
+        let query = query.reverse();
+        assert_eq!(query.reverse, true);
+        assert_eq!(query.live_stream, false);
+
+        let query = Query::last(20);
+        assert_eq!(query.live_stream, false);
+        assert_eq!(query.offset, 0);
+        assert_eq!(query.limit, Some(20));
+        assert_eq!(query.reverse, true);
     }
 
     #[test]
@@ -157,6 +331,78 @@ mod tests {
         query.update(1);
 
         assert!(!query.matches(&Event::new("data", vec!["tag1"]).with_id(1)));
+
+        let query = Query::current().from_timestamp(1000).to_timestamp(2000);
+
+        assert!(query.matches(&Event::new("data", vec!["tag1"]).with_id(1).with_timestamp(1000)));
+        assert!(query.matches(&Event::new("data", vec!["tag1"]).with_id(1).with_timestamp(2000)));
+        assert!(!query.matches(&Event::new("data", vec!["tag1"]).with_id(1).with_timestamp(999)));
+        assert!(!query.matches(&Event::new("data", vec!["tag1"]).with_id(1).with_timestamp(2001)));
+
+        let query = Query::current().by_tag("tag1").by_tag_expression(TagExpression::tag("tag2").or(TagExpression::tag("tag3")));
+
+        assert!(query.matches(&Event::new("data", vec!["tag1", "tag3"]).with_id(1)));
+        assert!(!query.matches(&Event::new("data", vec!["tag1"]).with_id(1)));
+        assert!(!query.matches(&Event::new("data", vec!["tag2"]).with_id(1)));
+
+        let query = Query::current().by_stream("stream1").from_stream_version(2);
+
+        assert!(query.matches(&Event::new("data", vec!["tag1"]).with_id(1).with_stream("stream1").with_stream_version(2)));
+        assert!(!query.matches(&Event::new("data", vec!["tag1"]).with_id(1).with_stream("stream1").with_stream_version(1)));
+        assert!(!query.matches(&Event::new("data", vec!["tag1"]).with_id(1).with_stream("stream2").with_stream_version(2)));
+        assert!(!query.matches(&Event::new("data", vec!["tag1"]).with_id(1)));
+
+        let mut query = Query::current().offset(1).reverse();
+
+        assert!(query.matches(&Event::new("data", vec!["tag1"]).with_id(3)));
+        assert!(!query.matches(&Event::new("data", vec!["tag1"]).with_id(1)));
+
+        query.update(3);
+
+        assert!(query.matches(&Event::new("data", vec!["tag1"]).with_id(2)));
+        assert!(!query.matches(&Event::new("data", vec!["tag1"]).with_id(3)));
+        assert!(!query.matches(&Event::new("data", vec!["tag1"]).with_id(4)));
+    }
+
+    #[test]
+    fn test_payload_matching() {
+        let query = Query::current();
+
+        assert!(query.matches_payload(&Event::new("data", vec!["tag1"]).with_id(1)));
+
+        let query = Query::current().by_payload_filter(PayloadFilter::contains("refund"));
+
+        assert!(query.matches_payload(&Event::new("order refunded", vec!["tag1"]).with_id(1)));
+        assert!(!query.matches_payload(&Event::new("order shipped", vec!["tag1"]).with_id(1)));
+    }
+
+    #[test]
+    fn test_tag_expressions() {
+        let tag_expression = TagExpression::tag("tag2").not().and(TagExpression::tag("tag3"));
+
+        assert_eq!(Query::current().combined_tag_expression(), None);
+        assert_eq!(Query::current().by_tag("tag1").combined_tag_expression(), Some(TagExpression::tag("tag1")));
+        assert_eq!(Query::current().by_tag_expression(tag_expression.clone()).combined_tag_expression(), Some(tag_expression.clone()));
+        assert_eq!(Query::current().by_tag("tag1").by_tag_expression(tag_expression.clone()).combined_tag_expression(),
+                   Some(TagExpression::tag("tag1").and(tag_expression.clone())));
+
+        assert_eq!(Query::current().required_tag(), None);
+        assert_eq!(Query::current().by_tag("tag1").by_tag_expression(tag_expression.clone()).required_tag(), Some("tag1"));
+        assert_eq!(Query::current().by_tag_expression(tag_expression).required_tag(), Some("tag3"));
+        assert_eq!(Query::current().by_tag_expression(TagExpression::tag("tag2").not()).required_tag(), None);
+    }
+
+    #[test]
+    fn test_interval() {
+        assert_eq!(Query::current().offset(10).interval(), Interval::new(10, u64::max_value()));
+        assert_eq!(Query::current().offset(10).limit(5).interval(), Interval::new(10, 15));
+        assert_eq!(Query::current().offset(10).limit(5).by_tag("tag1").interval(), Interval::new(10, u64::max_value()));
+        assert_eq!(Query::current().offset(10).limit(5).from_timestamp(1000).interval(), Interval::new(10, u64::max_value()));
+        assert_eq!(Query::current().offset(10).limit(5).by_tag_expression(TagExpression::tag("tag1").not()).interval(),
+                   Interval::new(10, u64::max_value()));
+        assert_eq!(Query::current().offset(10).limit(5).by_payload_filter(PayloadFilter::contains("data")).interval(),
+                   Interval::new(10, u64::max_value()));
+        assert_eq!(Query::current().offset(10).limit(5).by_stream("stream1").interval(), Interval::new(10, u64::max_value()));
     }
 
     #[test]
@@ -0,0 +1,612 @@
+use super::*;
+
+use serde_json::{self, Value};
+
+use std::cmp::{max, min};
+use std::fmt::{Display, Formatter, Result as DisplayResult};
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+impl FromStr for Query {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Query, ParseError> {
+        let tokens = try!(Tokenizer::new(s).tokenize());
+        let mut parser = QueryParser::new(tokens, s.len());
+        parser.parse_query()
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut Formatter) -> DisplayResult {
+        try!(write!(f, "{}", if self.live_stream { "LIVE" } else { "CURRENT" }));
+        if self.offset > 0 {
+            try!(write!(f, " FROM {}", self.offset));
+        }
+        if let Some(limit) = self.limit {
+            try!(write!(f, " LIMIT {}", limit));
+        }
+        if self.reverse {
+            try!(write!(f, " REVERSE"));
+        }
+        let mut conditions = vec![];
+        if let Some(ref tag_expression) = self.combined_tag_expression() {
+            conditions.push(tag_condition(tag_expression, 1));
+        }
+        if let Some(from_timestamp) = self.from_timestamp {
+            conditions.push(format!("ts >= {}", from_timestamp));
+        }
+        if let Some(to_timestamp) = self.to_timestamp {
+            conditions.push(format!("ts <= {}", to_timestamp));
+        }
+        if let Some(ref stream_id) = self.stream_id {
+            conditions.push(format!("stream = {}", quote(stream_id)));
+        }
+        if let Some(from_stream_version) = self.from_stream_version {
+            conditions.push(format!("stream_version >= {}", from_stream_version));
+        }
+        if let Some(ref payload_filter) = self.payload_filter {
+            conditions.push(payload_condition(payload_filter));
+        }
+        if !conditions.is_empty() {
+            try!(write!(f, " WHERE {}", conditions.join(" AND ")));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Number(String),
+    Text(String),
+    Symbol(&'static str)
+}
+
+impl Token {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match *self {
+            Token::Word(ref word) => word.eq_ignore_ascii_case(keyword),
+            _ => false
+        }
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> DisplayResult {
+        match *self {
+            Token::Word(ref word) => write!(f, "{}", word),
+            Token::Number(ref number) => write!(f, "{}", number),
+            Token::Text(ref text) => write!(f, "{}", quote(text)),
+            Token::Symbol(symbol) => write!(f, "{}", symbol)
+        }
+    }
+}
+
+struct Tokenizer<'a> {
+    chars: Peekable<CharIndices<'a>>
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(s: &'a str) -> Tokenizer<'a> {
+        Tokenizer {
+            chars: s.char_indices().peekable()
+        }
+    }
+
+    fn next_char_is<P>(&mut self, predicate: P) -> bool where P: Fn(char) -> bool {
+        self.chars.peek().map_or(false, |&(_, c)| predicate(c))
+    }
+
+    fn take_while<P>(&mut self, first_char: char, predicate: P) -> String where P: Fn(char) -> bool {
+        let mut token = first_char.to_string();
+        while self.next_char_is(&predicate) {
+            token.push(self.chars.next().unwrap().1);
+        }
+        token
+    }
+
+    fn tokenize(&mut self) -> Result<Vec<(usize, Token)>, ParseError> {
+        let mut tokens = vec![];
+        while let Some((position, c)) = self.chars.next() {
+            let token = match c {
+                c if c.is_whitespace() => continue,
+                '\'' => Token::Text(try!(self.text(position))),
+                c if c.is_digit(10) || c == '-' => {
+                    Token::Number(self.take_while(c, |c| c.is_digit(10) || "+-.eE".contains(c)))
+                },
+                c if is_word_char(c) => Token::Word(self.take_while(c, is_word_char)),
+                '(' => Token::Symbol("("),
+                ')' => Token::Symbol(")"),
+                '=' => Token::Symbol("="),
+                '!' if self.next_char_is(|c| c == '=') => { self.chars.next(); Token::Symbol("!=") },
+                '<' if self.next_char_is(|c| c == '=') => { self.chars.next(); Token::Symbol("<=") },
+                '<' => Token::Symbol("<"),
+                '>' if self.next_char_is(|c| c == '=') => { self.chars.next(); Token::Symbol(">=") },
+                '>' => Token::Symbol(">"),
+                c => return Err(syntax_error(position, format!("unexpected character: {}", c)))
+            };
+            tokens.push((position, token));
+        }
+        Ok(tokens)
+    }
+
+    // single quotes within a quoted string are escaped by doubling them
+    fn text(&mut self, position: usize) -> Result<String, ParseError> {
+        let mut text = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '\'')) if self.next_char_is(|c| c == '\'') => {
+                    self.chars.next();
+                    text.push('\'');
+                },
+                Some((_, '\'')) => return Ok(text),
+                Some((_, c)) => text.push(c),
+                None => return Err(syntax_error(position, "unterminated string".to_owned()))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Conditions {
+    tag_expression: Option<TagExpression>,
+    from_timestamp: Option<u64>,
+    to_timestamp: Option<u64>,
+    stream_id: Option<String>,
+    from_stream_version: Option<u64>,
+    payload_filter: Option<PayloadFilter>
+}
+
+impl Conditions {
+    fn tag_expression(tag_expression: TagExpression) -> Conditions {
+        Conditions { tag_expression: Some(tag_expression), ..Conditions::default() }
+    }
+
+    fn into_tag_expression(self) -> Option<TagExpression> {
+        match self {
+            Conditions { tag_expression, from_timestamp: None, to_timestamp: None, stream_id: None,
+                         from_stream_version: None, payload_filter: None } => tag_expression,
+            _ => None
+        }
+    }
+
+    fn and(self, other: Conditions, position: usize) -> Result<Conditions, ParseError> {
+        let tag_expression = match (self.tag_expression, other.tag_expression) {
+            (Some(left), Some(right)) => Some(left.and(right)),
+            (left, right) => left.or(right)
+        };
+        let payload_filter = match (self.payload_filter, other.payload_filter) {
+            (Some(_), Some(_)) => return Err(syntax_error(position, "only one data condition is supported".to_owned())),
+            (left, right) => left.or(right)
+        };
+        let stream_id = match (self.stream_id, other.stream_id) {
+            (Some(_), Some(_)) => return Err(syntax_error(position, "only one stream condition is supported".to_owned())),
+            (left, right) => left.or(right)
+        };
+        Ok(Conditions {
+            tag_expression: tag_expression,
+            from_timestamp: max(self.from_timestamp, other.from_timestamp),
+            to_timestamp: match (self.to_timestamp, other.to_timestamp) {
+                (Some(left), Some(right)) => Some(min(left, right)),
+                (left, right) => left.or(right)
+            },
+            stream_id: stream_id,
+            from_stream_version: max(self.from_stream_version, other.from_stream_version),
+            payload_filter: payload_filter
+        })
+    }
+}
+
+struct QueryParser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    end_position: usize
+}
+
+impl QueryParser {
+    fn new(tokens: Vec<(usize, Token)>, end_position: usize) -> QueryParser {
+        QueryParser {
+            tokens: tokens,
+            index: 0,
+            end_position: end_position
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|&(_, ref token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.index).map_or(self.end_position, |&(position, _)| position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.index += 1;
+        token
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        self.peek().map_or(false, |token| token.is_keyword(keyword))
+    }
+
+    fn next_is_symbol(&self, symbol: &'static str) -> bool {
+        self.peek() == Some(&Token::Symbol(symbol))
+    }
+
+    fn expected(&self, expected: &str) -> ParseError {
+        let description = match self.peek() {
+            Some(token) => format!("expected {}, found {}", expected, token),
+            None => format!("expected {}, found end of query", expected)
+        };
+        syntax_error(self.position(), description)
+    }
+
+    fn parse_integer(&mut self) -> Result<u64, ParseError> {
+        let integer = match self.peek() {
+            Some(&Token::Number(ref number)) => number.parse().ok(),
+            _ => None
+        };
+        match integer {
+            Some(integer) => {
+                self.index += 1;
+                Ok(integer)
+            },
+            None => Err(self.expected("a non-negative integer"))
+        }
+    }
+
+    fn parse_text(&mut self, expected: &str) -> Result<String, ParseError> {
+        match self.peek().cloned() {
+            Some(Token::Text(text)) => {
+                self.index += 1;
+                Ok(text)
+            },
+            _ => Err(self.expected(expected))
+        }
+    }
+
+    fn parse_operator(&mut self, operators: &[&str]) -> Result<&'static str, ParseError> {
+        match self.peek().cloned() {
+            Some(Token::Symbol(symbol)) if operators.contains(&symbol) => {
+                self.index += 1;
+                Ok(symbol)
+            },
+            _ => Err(self.expected(&format!("one of {}", operators.join(" "))))
+        }
+    }
+
+    fn parse_query(&mut self) -> Result<Query, ParseError> {
+        let mut query = Query::current();
+        if self.next_is_keyword("LIVE") {
+            self.index += 1;
+            query.live_stream = true;
+        } else if self.next_is_keyword("CURRENT") {
+            self.index += 1;
+        }
+        let mut clauses = vec![];
+        loop {
+            let position = self.position();
+            let clause = match self.peek() {
+                Some(&Token::Word(ref word)) => word.to_uppercase(),
+                _ => break
+            };
+            let filled_clauses: &[&str] = match &clause[..] {
+                "FROM" => &["FROM"],
+                "LIMIT" => &["LIMIT"],
+                "REVERSE" => &["REVERSE"],
+                "LAST" => &["LIMIT", "REVERSE"],
+                _ => break
+            };
+            if let Some(duplicate_clause) = filled_clauses.iter().find(|filled_clause| clauses.contains(*filled_clause)) {
+                return Err(syntax_error(position, format!("duplicate {} clause", duplicate_clause)))
+            }
+            if query.live_stream && filled_clauses.contains(&"REVERSE") {
+                return Err(syntax_error(position, "live queries cannot be reversed".to_owned()))
+            }
+            clauses.extend_from_slice(filled_clauses);
+            self.index += 1;
+            match &clause[..] {
+                "FROM" => query = query.offset(try!(self.parse_integer())),
+                "LIMIT" => query = query.limit(try!(self.parse_integer())),
+                "REVERSE" => query = query.reverse(),
+                _ => query = query.reverse().limit(try!(self.parse_integer()))
+            }
+        }
+        if self.next_is_keyword("WHERE") {
+            self.index += 1;
+            let conditions = try!(self.parse_or());
+            match conditions.tag_expression {
+                Some(TagExpression::Tag(tag)) => query.tag = Some(tag),
+                Some(tag_expression) => query = query.by_tag_expression(tag_expression),
+                None => ()
+            }
+            query.from_timestamp = conditions.from_timestamp;
+            query.to_timestamp = conditions.to_timestamp;
+            query.stream_id = conditions.stream_id;
+            query.from_stream_version = conditions.from_stream_version;
+            query.payload_filter = conditions.payload_filter;
+        }
+        match self.peek() {
+            Some(_) => Err(self.expected("a clause or the end of query")),
+            None => Ok(query)
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Conditions, ParseError> {
+        let mut conditions = try!(self.parse_and());
+        while self.next_is_keyword("OR") {
+            let position = self.position();
+            self.index += 1;
+            let other_conditions = try!(self.parse_and());
+            match (conditions.into_tag_expression(), other_conditions.into_tag_expression()) {
+                (Some(left), Some(right)) => conditions = Conditions::tag_expression(left.or(right)),
+                _ => return Err(syntax_error(position, "only tag conditions can be combined with OR".to_owned()))
+            }
+        }
+        Ok(conditions)
+    }
+
+    fn parse_and(&mut self) -> Result<Conditions, ParseError> {
+        let mut conditions = try!(self.parse_not());
+        while self.next_is_keyword("AND") {
+            let position = self.position();
+            self.index += 1;
+            let other_conditions = try!(self.parse_not());
+            conditions = try!(conditions.and(other_conditions, position));
+        }
+        Ok(conditions)
+    }
+
+    fn parse_not(&mut self) -> Result<Conditions, ParseError> {
+        if self.next_is_keyword("NOT") {
+            let position = self.position();
+            self.index += 1;
+            match try!(self.parse_not()).into_tag_expression() {
+                Some(tag_expression) => Ok(Conditions::tag_expression(tag_expression.not())),
+                None => Err(syntax_error(position, "only tag conditions can be negated with NOT".to_owned()))
+            }
+        } else {
+            self.parse_condition()
+        }
+    }
+
+    fn parse_condition(&mut self) -> Result<Conditions, ParseError> {
+        if self.next_is_symbol("(") {
+            self.index += 1;
+            let conditions = try!(self.parse_or());
+            if !self.next_is_symbol(")") {
+                return Err(self.expected(")"))
+            }
+            self.index += 1;
+            return Ok(conditions)
+        }
+        let position = self.position();
+        let field = match self.peek() {
+            Some(&Token::Word(ref word)) => word.clone(),
+            _ => return Err(self.expected("a condition"))
+        };
+        if field.eq_ignore_ascii_case("tag") {
+            self.index += 1;
+            let operator = try!(self.parse_operator(&["=", "!="]));
+            let tag_expression = TagExpression::Tag(try!(self.parse_text("a quoted tag")));
+            Ok(Conditions::tag_expression(if operator == "=" { tag_expression } else { tag_expression.not() }))
+        } else if field.eq_ignore_ascii_case("ts") {
+            self.index += 1;
+            let operator = try!(self.parse_operator(&["=", "<", "<=", ">", ">="]));
+            let timestamp = try!(self.parse_integer());
+            let (from_timestamp, to_timestamp) = match operator {
+                "=" => (Some(timestamp), Some(timestamp)),
+                "<" => (None, Some(timestamp.saturating_sub(1))),
+                "<=" => (None, Some(timestamp)),
+                ">" => (Some(timestamp.saturating_add(1)), None),
+                _ => (Some(timestamp), None)
+            };
+            Ok(Conditions { from_timestamp: from_timestamp, to_timestamp: to_timestamp, ..Conditions::default() })
+        } else if field.eq_ignore_ascii_case("stream") {
+            self.index += 1;
+            try!(self.parse_operator(&["="]));
+            let stream_id = try!(self.parse_text("a quoted stream id"));
+            Ok(Conditions { stream_id: Some(stream_id), ..Conditions::default() })
+        } else if field.eq_ignore_ascii_case("stream_version") {
+            self.index += 1;
+            let operator = try!(self.parse_operator(&[">", ">="]));
+            let version = try!(self.parse_integer());
+            let from_stream_version = if operator == ">" { version.saturating_add(1) } else { version };
+            Ok(Conditions { from_stream_version: Some(from_stream_version), ..Conditions::default() })
+        } else if is_data_field(&field) {
+            self.index += 1;
+            let payload_filter = try!(self.parse_payload_filter(&field, position));
+            Ok(Conditions { payload_filter: Some(payload_filter), ..Conditions::default() })
+        } else {
+            Err(self.expected("a condition"))
+        }
+    }
+
+    fn parse_payload_filter(&mut self, field: &str, field_position: usize) -> Result<PayloadFilter, ParseError> {
+        if field.len() == "data".len() && self.next_is_keyword("CONTAINS") {
+            self.index += 1;
+            return self.parse_text("a quoted string").map(|text| PayloadFilter::contains(&text))
+        }
+        if field.len() == "data".len() && self.next_is_keyword("MATCHES") {
+            self.index += 1;
+            let position = self.position();
+            let pattern = try!(self.parse_text("a quoted regular expression"));
+            return PayloadFilter::regex(&pattern).map_err(|err| syntax_error(position, err.to_string()))
+        }
+        let operator = match try!(self.parse_operator(&["=", "!=", "<", "<=", ">", ">="])) {
+            "=" => ComparisonOperator::Equal,
+            operator => operator.parse().unwrap()
+        };
+        let position = self.position();
+        let value = match self.next() {
+            Some(Token::Text(text)) => Value::String(text),
+            Some(Token::Number(number)) => match serde_json::from_str(&number) {
+                Ok(value @ Value::Number(_)) => value,
+                _ => return Err(syntax_error(position, format!("invalid number: {}", number)))
+            },
+            Some(ref token) if token.is_keyword("true") => Value::Bool(true),
+            Some(ref token) if token.is_keyword("false") => Value::Bool(false),
+            Some(ref token) if token.is_keyword("null") => Value::Null,
+            _ => {
+                self.index -= 1;
+                return Err(self.expected("a quoted string, a number, true, false or null"))
+            }
+        };
+        let path = format!("${}", &field["data".len()..]);
+        PayloadFilter::json(&path, operator, value).map_err(|err| syntax_error(field_position, err.to_string()))
+    }
+}
+
+fn syntax_error(position: usize, description: String) -> ParseError {
+    ParseError::SyntaxError(position, description)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || "_.[]$".contains(c)
+}
+
+fn is_data_field(word: &str) -> bool {
+    word.len() >= "data".len() && word.is_char_boundary("data".len()) &&
+    word[.."data".len()].eq_ignore_ascii_case("data") &&
+    (word.len() == "data".len() || word["data".len()..].starts_with('.') || word["data".len()..].starts_with('['))
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace("'", "''"))
+}
+
+// the tag conditions are parenthesized following the precedence of the operators, as in `TagExpression`
+fn tag_condition(tag_expression: &TagExpression, min_precedence: u8) -> String {
+    let (precedence, condition) = match *tag_expression {
+        TagExpression::Tag(ref tag) => (3, format!("tag = {}", quote(tag))),
+        TagExpression::Not(ref tag_expression) => match **tag_expression {
+            TagExpression::Tag(ref tag) => (3, format!("tag != {}", quote(tag))),
+            ref tag_expression => (2, format!("NOT {}", tag_condition(tag_expression, 2)))
+        },
+        TagExpression::And(ref left, ref right) => (1, format!("{} AND {}", tag_condition(left, 1), tag_condition(right, 2))),
+        TagExpression::Or(ref left, ref right) => (0, format!("{} OR {}", tag_condition(left, 0), tag_condition(right, 1)))
+    };
+    if precedence < min_precedence { format!("({})", condition) } else { condition }
+}
+
+fn payload_condition(payload_filter: &PayloadFilter) -> String {
+    match *payload_filter {
+        PayloadFilter::Contains(ref text) => format!("data CONTAINS {}", quote(text)),
+        PayloadFilter::Regex(ref regex) => format!("data MATCHES {}", quote(regex.as_str())),
+        PayloadFilter::Json(ref path, operator, ref value) => {
+            let operator = match operator {
+                ComparisonOperator::Equal => "=".to_owned(),
+                operator => operator.to_string()
+            };
+            let value = match *value {
+                Value::String(ref text) => quote(text),
+                ref value => value.to_string()
+            };
+            format!("data{} {} {}", &path[1..], operator, value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    fn tag(tag: &str) -> TagExpression {
+        TagExpression::tag(tag)
+    }
+
+    fn syntax_error(position: usize, description: &str) -> Result<Query, ParseError> {
+        Err(ParseError::SyntaxError(position, description.to_owned()))
+    }
+
+    #[test]
+    fn test_parsing() {
+        assert_eq!("".parse(), Ok(Query::current()));
+        assert_eq!("LIVE".parse(), Ok(Query::live()));
+        assert_eq!("current from 100 limit 10".parse(), Ok(Query::current().offset(100).limit(10)));
+        assert_eq!("LIMIT 10 FROM 100".parse(), Ok(Query::current().offset(100).limit(10)));
+        assert_eq!("LAST 20".parse(), Ok(Query::last(20)));
+        assert_eq!("CURRENT FROM 5 REVERSE".parse(), Ok(Query::current().offset(5).reverse()));
+
+        assert_eq!("LIVE FROM 100 LIMIT 10 WHERE tag = 'a' AND ts > 1700000000000".parse(),
+                   Ok(Query::live().offset(100).limit(10).by_tag("a").from_timestamp(1700000000001)));
+        assert_eq!("WHERE ts >= 1000 AND ts < 2000 AND ts <= 3000".parse(),
+                   Ok(Query::current().from_timestamp(1000).to_timestamp(1999)));
+        assert_eq!("WHERE ts = 1000".parse(), Ok(Query::current().from_timestamp(1000).to_timestamp(1000)));
+        assert_eq!("WHERE (tag = 'order' AND tag = 'eu' OR tag = 'refund') AND tag != 'test'".parse(),
+                   Ok(Query::current().by_tag_expression(tag("order").and(tag("eu")).or(tag("refund")).and(tag("test").not()))));
+        assert_eq!("WHERE NOT (tag = 'a' OR tag = 'it''s')".parse(),
+                   Ok(Query::current().by_tag_expression(tag("a").or(tag("it's")).not())));
+
+        assert_eq!("WHERE data CONTAINS 'refund'".parse(), Ok(Query::current().by_payload_filter(PayloadFilter::contains("refund"))));
+        assert_eq!("WHERE data MATCHES '^audit-[0-9]+$'".parse(),
+                   Ok(Query::current().by_payload_filter(PayloadFilter::regex("^audit-[0-9]+$").unwrap())));
+        assert_eq!("WHERE tag = 'order' AND data.order.amount >= 100".parse(),
+                   Ok(Query::current().by_tag("order").by_payload_filter("json:$.order.amount >= 100".parse().unwrap())));
+        assert_eq!("WHERE data.items[0].sku = 'a1'".parse(),
+                   Ok(Query::current().by_payload_filter(r#"json:$.items[0].sku == "a1""#.parse().unwrap())));
+        assert_eq!("WHERE data != null".parse(), Ok(Query::current().by_payload_filter("json:$ != null".parse().unwrap())));
+
+        assert_eq!("WHERE stream = 'order-123' AND stream_version > 4".parse(),
+                   Ok(Query::current().by_stream("order-123").from_stream_version(5)));
+        assert_eq!("WHERE tag = 'order' AND STREAM = 'order-123'".parse(), Ok(Query::current().by_tag("order").by_stream("order-123")));
+    }
+
+    #[test]
+    fn test_parsing_errors() {
+        assert_eq!("LIVE FROM".parse::<Query>(), syntax_error(9, "expected a non-negative integer, found end of query"));
+        assert_eq!("LIMIT -1".parse::<Query>(), syntax_error(6, "expected a non-negative integer, found -1"));
+        assert_eq!("LIMIT 1 LAST 2".parse::<Query>(), syntax_error(8, "duplicate LIMIT clause"));
+        assert_eq!("LIVE REVERSE".parse::<Query>(), syntax_error(5, "live queries cannot be reversed"));
+        assert_eq!("CURRENT LIVE".parse::<Query>(), syntax_error(8, "expected a clause or the end of query, found LIVE"));
+        assert_eq!("WHERE tag = a".parse::<Query>(), syntax_error(12, "expected a quoted tag, found a"));
+        assert_eq!("WHERE tag > 'a'".parse::<Query>(), syntax_error(10, "expected one of = !=, found >"));
+        assert_eq!("WHERE tag = 'a".parse::<Query>(), syntax_error(12, "unterminated string"));
+        assert_eq!("WHERE (tag = 'a'".parse::<Query>(), syntax_error(16, "expected ), found end of query"));
+        assert_eq!("WHERE size > 1".parse::<Query>(), syntax_error(6, "expected a condition, found size"));
+        assert_eq!("WHERE tag = 'a' OR ts > 1".parse::<Query>(), syntax_error(16, "only tag conditions can be combined with OR"));
+        assert_eq!("WHERE NOT ts > 1".parse::<Query>(), syntax_error(6, "only tag conditions can be negated with NOT"));
+        assert_eq!("WHERE data CONTAINS 'a' AND data CONTAINS 'b'".parse::<Query>(),
+                   syntax_error(24, "only one data condition is supported"));
+        assert_eq!("WHERE data.order..amount = 1".parse::<Query>(),
+                   syntax_error(6, "invalid payload filter JSON path: $.order..amount"));
+        assert_eq!("WHERE data.amount = 1-2".parse::<Query>(), syntax_error(20, "invalid number: 1-2"));
+        assert_eq!("WHERE data.amount = EUR".parse::<Query>(),
+                   syntax_error(20, "expected a quoted string, a number, true, false or null, found EUR"));
+        assert_eq!("WHERE stream = 'a' AND stream = 'b'".parse::<Query>(), syntax_error(19, "only one stream condition is supported"));
+        assert_eq!("WHERE stream != 'a'".parse::<Query>(), syntax_error(13, "expected one of =, found !="));
+        assert_eq!("WHERE stream_version < 5".parse::<Query>(), syntax_error(21, "expected one of > >=, found <"));
+        assert_eq!("WHERE tag = 'a' OR stream = 'b'".parse::<Query>(), syntax_error(16, "only tag conditions can be combined with OR"));
+        assert_eq!("LIMIT 1; DROP".parse::<Query>(), syntax_error(7, "unexpected character: ;"));
+        assert!(match "WHERE data MATCHES '('".parse::<Query>() {
+            Err(ParseError::SyntaxError(19, _)) => true,
+            _ => false
+        });
+    }
+
+    #[test]
+    fn test_display() {
+        let queries = vec![
+            "CURRENT",
+            "LIVE FROM 100 LIMIT 10 WHERE tag = 'a' AND ts >= 1700000000001",
+            "CURRENT LIMIT 20 REVERSE",
+            "CURRENT WHERE (tag = 'order' AND tag = 'eu' OR tag = 'refund') AND tag != 'test'",
+            "CURRENT WHERE NOT (tag = 'a' OR tag = 'it''s')",
+            "CURRENT WHERE ts >= 1000 AND ts <= 2000 AND data CONTAINS 'refund'",
+            "CURRENT WHERE data MATCHES '^audit-[0-9]+$'",
+            "CURRENT WHERE data.items[0].sku = 'a1'",
+            "CURRENT WHERE data.order.amount >= 100.5",
+            "CURRENT WHERE tag = 'order' AND stream = 'order-123' AND stream_version >= 5"
+        ];
+        for query in queries {
+            assert_eq!(query.parse::<Query>().map(|query| query.to_string()), Ok(query.to_owned()));
+        }
+
+        let query = Query::live().by_tag("tag1").by_tag_expression(tag("tag2").or(tag("tag3")));
+        assert_eq!(query.to_string(), "LIVE WHERE tag = 'tag1' AND (tag = 'tag2' OR tag = 'tag3')");
+        assert_eq!(query.to_string().parse::<Query>().map(|query| query.combined_tag_expression()),
+                   Ok(query.combined_tag_expression()));
+    }
+}
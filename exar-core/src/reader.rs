@@ -0,0 +1,428 @@
+use super::*;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Seek, SeekFrom};
+
+/// Exar DB's log reader.
+///
+/// It reads events from all the segments of a log, moving to the next segment once the current one is complete,
+/// and it uses the `LogIndex` of each segment to seek to specific events.
+///
+/// # Examples
+/// ```no_run
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let log = Log::new("/path/to/logs", "test", 100);
+///
+/// let mut reader = log.open_log_reader().unwrap();
+/// reader.seek(100).unwrap();
+/// for event in reader {
+///     println!("Read event: {}", event.unwrap());
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LogReader {
+    log: Log,
+    indexes: BTreeMap<u64, LogIndex>,
+    segment_first_id: u64,
+    reader: BufReader<File>,
+    byte_offset: u64,
+    next_event_id: u64,
+    pending_events: VecDeque<Event>,
+    is_segment_stale: bool
+}
+
+impl LogReader {
+    /// Creates a new log reader positioned at the beginning of the given `Log`, using the given segment indexes
+    /// (mapped by the `id` of the first event of each segment), or returns a `DatabaseError` if a failure occurs.
+    ///
+    /// Empty indexes are used for the segments whose index is not given.
+    pub fn new(log: Log, mut indexes: BTreeMap<u64, LogIndex>) -> Result<LogReader, DatabaseError> {
+        for segment in try!(log.segments()) {
+            indexes.entry(segment.first_id()).or_insert_with(|| LogIndex::new(log.get_index_granularity()));
+        }
+        let segment_first_id = match indexes.keys().next() {
+            Some(&segment_first_id) => segment_first_id,
+            None => return Err(DatabaseError::IoError(ErrorKind::NotFound, "log segment not found".to_owned()))
+        };
+        log.segment(segment_first_id).open_reader().and_then(|reader| {
+            Ok(LogReader {
+                log: log,
+                indexes: indexes,
+                segment_first_id: segment_first_id,
+                reader: reader,
+                byte_offset: 0,
+                next_event_id: segment_first_id,
+                pending_events: VecDeque::new(),
+                is_segment_stale: false
+            })
+        })
+    }
+
+    /// Returns a reference to the indexes of the log segments known to the reader,
+    /// mapped by the `id` of the first event of each segment.
+    pub fn get_indexes(&self) -> &BTreeMap<u64, LogIndex> {
+        &self.indexes
+    }
+
+    /// Returns a reference to the `LogIndex` of the segment starting at the given event `id`, if known to the reader.
+    pub fn get_index(&self, segment_first_id: u64) -> Option<&LogIndex> {
+        self.indexes.get(&segment_first_id)
+    }
+
+    /// Adds an entry to the `LogIndex` of the segment starting at the given event `id`.
+    pub fn add_index_entry(&mut self, segment_first_id: u64, id: u64, byte_offset: u64) {
+        let index_granularity = self.log.get_index_granularity();
+        self.indexes.entry(segment_first_id).or_insert_with(|| LogIndex::new(index_granularity)).insert(id, byte_offset);
+    }
+
+    /// Discards the entries of the `LogIndex` of the segment starting at the given event `id`,
+    /// so that seeking within the segment starts from its beginning.
+    pub fn discard_index(&mut self, segment_first_id: u64) {
+        if let Some(index) = self.indexes.get_mut(&segment_first_id) {
+            *index = LogIndex::new(index.granularity());
+        }
+    }
+
+    /// Replaces the `LogIndex` of the segment starting at the given event `id` after the segment file
+    /// has been rewritten, and discovers the segments not yet known to the reader,
+    /// or returns a `DatabaseError` if a failure occurs.
+    ///
+    /// The segment file is reopened the next time the reader seeks within it.
+    pub fn replace_index(&mut self, segment_first_id: u64, index: LogIndex) -> Result<(), DatabaseError> {
+        self.indexes.insert(segment_first_id, index);
+        if segment_first_id == self.segment_first_id {
+            self.is_segment_stale = true;
+        }
+        self.log.segments().and_then(|segments| {
+            let index_granularity = self.log.get_index_granularity();
+            for segment in segments.iter().filter(|s| s.first_id() > segment_first_id) {
+                self.indexes.entry(segment.first_id()).or_insert_with(|| LogIndex::new(index_granularity));
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the `id` of the first event of the segment currently being read.
+    pub fn segment_first_id(&self) -> u64 {
+        self.segment_first_id
+    }
+
+    /// Returns the current byte offset of the reader within the segment file currently being read.
+    ///
+    /// Events of a batch are read all at once, so the offset may be past events not yet returned by the reader.
+    pub fn byte_offset(&self) -> u64 {
+        self.byte_offset
+    }
+
+    /// Seeks to the first event following the given event `id` (or position),
+    /// or returns a `DatabaseError` if a failure occurs.
+    ///
+    /// If the event has been removed from the log by the retention policy,
+    /// it seeks to the earliest event retained instead.
+    pub fn seek(&mut self, position: u64) -> Result<(), DatabaseError> {
+        if self.indexes.keys().next().map_or(false, |&first_id| !self.log.segment(first_id).exists()) {
+            try!(self.discard_removed_segments());
+        }
+        let segment_first_id = match self.indexes.range(..position + 2).next_back().or(self.indexes.iter().next()) {
+            Some((&segment_first_id, _)) => segment_first_id,
+            None => self.segment_first_id
+        };
+        let (event_id, byte_offset) = match self.indexes.get(&segment_first_id) {
+            Some(index) => index.closest_entry(position),
+            None => (0, 0)
+        };
+        let next_event_id = if byte_offset == 0 { segment_first_id } else { event_id + 1 };
+        self.pending_events.clear();
+        try!(self.open_segment(segment_first_id, byte_offset, next_event_id));
+        loop {
+            match self.read_event() {
+                Ok(Some(ref event)) if event.id <= position => continue,
+                Ok(Some(event)) => {
+                    self.pending_events.push_front(event);
+                    return Ok(())
+                },
+                Ok(None) => return Ok(()),
+                Err(DatabaseError::ParseError(err)) => warn!("Unable to decode log record: {}", err),
+                Err(err) => return Err(err)
+            }
+        }
+    }
+
+    /// Reads the next event from the log, returning `Ok(None)` if the end of the log has been reached,
+    /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// An incomplete record or batch of records at the end of the log is not consumed,
+    /// so that it can be read again once complete.
+    pub fn read_event(&mut self) -> Result<Option<Event>, DatabaseError> {
+        if let Some(event) = self.pending_events.pop_front() {
+            return Ok(Some(event))
+        }
+        let (batch_byte_offset, batch_next_event_id) = (self.byte_offset, self.next_event_id);
+        let mut is_segment_complete = false;
+        loop {
+            match self.read_record() {
+                Ok(Some((event, flags))) => {
+                    self.pending_events.push_back(event);
+                    if flags & BATCH_CONTINUATION_FLAG == 0 {
+                        return Ok(self.pending_events.pop_front())
+                    }
+                },
+                Ok(None) if self.pending_events.is_empty() => {
+                    let byte_offset = self.byte_offset;
+                    try!(self.seek_to_byte_offset(byte_offset));
+                    match self.next_segment_first_id() {
+                        Some(next_segment_first_id) if is_segment_complete => {
+                            try!(self.open_segment(next_segment_first_id, 0, next_segment_first_id));
+                            is_segment_complete = false;
+                        },
+                        // the current segment is complete once the next one exists,
+                        // it is read once more to make sure that its last records are not skipped
+                        Some(_) => is_segment_complete = true,
+                        None => return Ok(None)
+                    }
+                },
+                Ok(None) => {
+                    self.pending_events.clear();
+                    self.next_event_id = batch_next_event_id;
+                    return self.seek_to_byte_offset(batch_byte_offset).and_then(|_| Ok(None))
+                },
+                Err(err) => {
+                    self.pending_events.clear();
+                    return Err(err)
+                }
+            }
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<(Event, u8)>, DatabaseError> {
+        match self.reader.read_log_record() {
+            Ok(Some(record)) => {
+                self.byte_offset += record.len() as u64;
+                match Event::from_log_record_with_flags(&record) {
+                    Ok((event, flags)) => {
+                        self.next_event_id = event.id + 1;
+                        Ok(Some((event, flags)))
+                    },
+                    Err(err) => Err(DatabaseError::ParseError(err))
+                }
+            },
+            Ok(None) => Ok(None),
+            Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    fn discard_removed_segments(&mut self) -> Result<(), DatabaseError> {
+        self.log.segments().and_then(|segments| {
+            if let Some(segment) = segments.first() {
+                self.indexes = self.indexes.split_off(&segment.first_id());
+                let index_granularity = self.log.get_index_granularity();
+                self.indexes.entry(segment.first_id()).or_insert_with(|| LogIndex::new(index_granularity));
+            }
+            Ok(())
+        })
+    }
+
+    fn next_segment_first_id(&self) -> Option<u64> {
+        // segments known to the reader are checked first, since compaction may remove the last events of a segment
+        match self.indexes.range(self.segment_first_id + 1..).map(|(&id, _)| id).find(|&id| self.log.segment(id).exists()) {
+            Some(segment_first_id) => Some(segment_first_id),
+            None if self.next_event_id > self.segment_first_id && self.log.segment(self.next_event_id).exists() => {
+                Some(self.next_event_id)
+            },
+            None => None
+        }
+    }
+
+    fn open_segment(&mut self, segment_first_id: u64, byte_offset: u64, next_event_id: u64) -> Result<(), DatabaseError> {
+        if segment_first_id != self.segment_first_id || self.is_segment_stale {
+            self.reader = try!(self.log.segment(segment_first_id).open_reader());
+            self.segment_first_id = segment_first_id;
+            self.is_segment_stale = false;
+            let index_granularity = self.log.get_index_granularity();
+            self.indexes.entry(segment_first_id).or_insert_with(|| LogIndex::new(index_granularity));
+        }
+        self.next_event_id = next_event_id;
+        self.seek_to_byte_offset(byte_offset)
+    }
+
+    fn seek_to_byte_offset(&mut self, byte_offset: u64) -> Result<(), DatabaseError> {
+        match self.reader.seek(SeekFrom::Start(byte_offset)) {
+            Ok(_) => {
+                self.byte_offset = byte_offset;
+                Ok(())
+            },
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+}
+
+impl Iterator for LogReader {
+    type Item = Result<Event, DatabaseError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_event() {
+            Ok(Some(event)) => Some(Ok(event)),
+            Ok(None) => None,
+            Err(err) => Some(Err(err))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use exar_testkit::*;
+
+    use std::collections::BTreeMap;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    fn create_log(nr_of_events: u64) -> Log {
+        create_log_with_segment_size(SegmentSize::default(), nr_of_events)
+    }
+
+    fn create_log_with_segment_size(segment_size: SegmentSize, nr_of_events: u64) -> Log {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(segment_size);
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for _ in 0..nr_of_events {
+            assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+        }
+        log
+    }
+
+    #[test]
+    fn test_read_events() {
+        let log = create_log(3);
+
+        let reader = log.open_log_reader().expect("Unable to open log reader");
+        let ids: Vec<_> = reader.map(|e| e.expect("Unable to read event").id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_seek() {
+        for segment_size in vec![SegmentSize::Unlimited, SegmentSize::MaxEvents(7)] {
+            let log = create_log_with_segment_size(segment_size.clone(), 25);
+
+            let indexes = log.restore_indexes().expect("Unable to restore indexes");
+
+            for &(indexed, ref reader_indexes) in &[(false, BTreeMap::new()), (true, indexes)] {
+                let mut reader = log.open_log_reader_with_indexes(reader_indexes.clone()).expect("Unable to open log reader");
+                for position in (0..26).chain((0..26).rev()) {
+                    assert!(reader.seek(position).is_ok());
+                    let next_id = reader.next().map(|e| e.expect("Unable to read event").id);
+                    let expected_id = if position < 25 { Some(position + 1) } else { None };
+                    assert_eq!(next_id, expected_id, "seek to {} (segment size: {}, indexed: {})", position, segment_size, indexed);
+                }
+            }
+
+            assert!(log.remove().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_read_across_segments() {
+        let log = create_log_with_segment_size(SegmentSize::MaxEvents(10), 15);
+
+        let mut reader = log.open_log_reader().expect("Unable to open log reader");
+        let ids: Vec<_> = reader.by_ref().map(|e| e.expect("Unable to read event").id).collect();
+        assert_eq!(ids, (1..16).collect::<Vec<_>>());
+        assert_eq!(reader.segment_first_id(), 11);
+        assert_eq!(reader.get_indexes().keys().cloned().collect::<Vec<_>>(), vec![1, 11]);
+
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for _ in 0..10 {
+            assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+        }
+
+        let ids: Vec<_> = reader.by_ref().map(|e| e.expect("Unable to read event").id).collect();
+        assert_eq!(ids, (16..26).collect::<Vec<_>>());
+        assert_eq!(reader.segment_first_id(), 21);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_seek_after_retention() {
+        let log = create_log_with_segment_size(SegmentSize::MaxEvents(10), 25);
+
+        let mut reader = log.open_log_reader_with_indexes(log.restore_indexes().expect("Unable to restore indexes"))
+                            .expect("Unable to open log reader");
+
+        let retention = RetentionConfig { max_events: Some(5), ..RetentionConfig::default() };
+        assert_eq!(log.apply_retention(&retention, 25, 0).map(|segments| segments.len()), Ok(2));
+
+        for position in vec![0, 5, 20, 22] {
+            assert!(reader.seek(position).is_ok());
+            let next_id = reader.next().map(|e| e.expect("Unable to read event").id);
+            assert_eq!(next_id, Some(if position < 20 { 21 } else { position + 1 }));
+        }
+        assert_eq!(reader.get_indexes().keys().cloned().collect::<Vec<_>>(), vec![21]);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_incomplete_record() {
+        let log = create_log(1);
+
+        let event = Event::new("data", vec!["tag1"]).with_id(2).with_timestamp(1234567890);
+        let record = event.to_log_record();
+        let (first_half, second_half) = record.split_at(record.len() / 2);
+
+        let mut file = OpenOptions::new().append(true).open(log.last_segment().expect("Unable to get last segment").get_path()).expect("Unable to open log file");
+        assert!(file.write_all(first_half).is_ok());
+
+        let mut reader = log.open_log_reader().expect("Unable to open log reader");
+        assert_eq!(reader.read_event().map(|e| e.map(|e| e.id)), Ok(Some(1)));
+        let byte_offset = reader.byte_offset();
+        assert_eq!(reader.read_event(), Ok(None));
+        assert_eq!(reader.byte_offset(), byte_offset);
+
+        assert!(file.write_all(second_half).is_ok());
+
+        assert_eq!(reader.read_event(), Ok(Some(event)));
+        assert_eq!(reader.read_event(), Ok(None));
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_incomplete_batch() {
+        let log = create_log(1);
+
+        let batch: Vec<_> = (2..5).map(|id| Event::new("data", vec!["tag1"]).with_id(id).with_timestamp(1234567890)).collect();
+        let records: Vec<_> = batch.iter().enumerate().map(|(i, event)| {
+            event.to_log_record_with_flags(if i < batch.len() - 1 { BATCH_CONTINUATION_FLAG } else { 0 })
+        }).collect();
+
+        let mut file = OpenOptions::new().append(true).open(log.last_segment().expect("Unable to get last segment").get_path()).expect("Unable to open log file");
+        assert!(file.write_all(&records[0]).is_ok());
+        assert!(file.write_all(&records[1]).is_ok());
+
+        let mut reader = log.open_log_reader().expect("Unable to open log reader");
+        assert_eq!(reader.read_event().map(|e| e.map(|e| e.id)), Ok(Some(1)));
+        let byte_offset = reader.byte_offset();
+        assert_eq!(reader.read_event(), Ok(None));
+        assert_eq!(reader.byte_offset(), byte_offset);
+
+        assert!(file.write_all(&records[2]).is_ok());
+
+        assert_eq!(reader.read_event(), Ok(Some(batch[0].clone())));
+        assert!(reader.seek(2).is_ok());
+        assert_eq!(reader.read_event(), Ok(Some(batch[1].clone())));
+        assert_eq!(reader.read_event(), Ok(Some(batch[2].clone())));
+        assert_eq!(reader.read_event(), Ok(None));
+
+        assert!(log.remove().is_ok());
+    }
+}
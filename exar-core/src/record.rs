@@ -0,0 +1,334 @@
+use super::*;
+
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufWriter, Error, ErrorKind};
+
+/// The size in bytes of a log record header (length prefix and checksum).
+pub const LOG_RECORD_HEADER_SIZE: usize = 8;
+
+/// A trait for serializing a type to a binary log record.
+///
+/// A log record has the following layout (all integers are little-endian):
+///
+/// ```text
+/// +------------+------------+--------------------+
+/// | length u32 | crc32 u32  | body (length bytes) |
+/// +------------+------------+--------------------+
+/// ```
+///
+/// The checksum is computed on the record body only.
+pub trait ToLogRecord {
+    /// Returns the binary log record for the value, including its header.
+    fn to_log_record(&self) -> Vec<u8>;
+}
+
+/// A trait for deserializing a type from a binary log record.
+pub trait FromLogRecord {
+    /// Returns an instance of `Self` from a binary log record (including its header)
+    /// or a `ParseError` if the record is corrupted or a failure occurs while decoding it.
+    fn from_log_record(record: &[u8]) -> Result<Self, ParseError> where Self: Sized;
+}
+
+/// A trait for reading raw binary log records from a stream.
+pub trait ReadLogRecord {
+    /// Reads the next raw log record (including its header) from this reader.
+    ///
+    /// It returns `Ok(None)` if the end of the stream has been reached cleanly,
+    /// or an `std::io::Error` of kind `UnexpectedEof` if the stream ends in the middle of a record.
+    fn read_log_record(&mut self) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// A trait for writing raw binary log records into a stream.
+pub trait WriteLogRecord {
+    /// Writes a raw log record into this writer, returning the number of bytes written.
+    fn write_log_record(&mut self, record: &[u8]) -> io::Result<usize>;
+}
+
+impl<T: BufRead> ReadLogRecord for T {
+    fn read_log_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut header = Vec::with_capacity(LOG_RECORD_HEADER_SIZE);
+        try!(self.by_ref().take(LOG_RECORD_HEADER_SIZE as u64).read_to_end(&mut header));
+        if header.is_empty() {
+            return Ok(None)
+        } else if header.len() < LOG_RECORD_HEADER_SIZE {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "incomplete log record header"))
+        }
+        let length = read_u32(&header[0..4]) as u64;
+        let mut record = header;
+        try!(self.by_ref().take(length).read_to_end(&mut record));
+        if (record.len() as u64) < LOG_RECORD_HEADER_SIZE as u64 + length {
+            Err(Error::new(ErrorKind::UnexpectedEof, "incomplete log record body"))
+        } else {
+            Ok(Some(record))
+        }
+    }
+}
+
+impl<T: Write> WriteLogRecord for BufWriter<T> {
+    fn write_log_record(&mut self, record: &[u8]) -> io::Result<usize> {
+        self.write_all(record).and_then(|_| {
+            self.flush().and_then(|_| {
+                Ok(record.len())
+            })
+        })
+    }
+}
+
+/// An encoder for the body of a binary log record.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let mut encoder = LogRecordEncoder::new();
+/// encoder.put_u64(1);
+/// encoder.put_bytes(b"data");
+///
+/// let record = encoder.into_log_record();
+/// let mut decoder = LogRecordDecoder::new(&record).unwrap();
+///
+/// assert_eq!(decoder.get_u64(), Ok(1));
+/// assert_eq!(decoder.get_bytes(), Ok(&b"data"[..]));
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogRecordEncoder {
+    body: Vec<u8>
+}
+
+impl LogRecordEncoder {
+    /// Creates a new encoder with an empty record body.
+    pub fn new() -> LogRecordEncoder {
+        LogRecordEncoder {
+            body: vec![]
+        }
+    }
+
+    /// Appends a `u8` to the record body.
+    pub fn put_u8(&mut self, value: u8) {
+        self.body.push(value);
+    }
+
+    /// Appends a little-endian `u16` to the record body.
+    pub fn put_u16(&mut self, value: u16) {
+        self.put_uint(value as u64, 2);
+    }
+
+    /// Appends a little-endian `u32` to the record body.
+    pub fn put_u32(&mut self, value: u32) {
+        self.put_uint(value as u64, 4);
+    }
+
+    /// Appends a little-endian `u64` to the record body.
+    pub fn put_u64(&mut self, value: u64) {
+        self.put_uint(value, 8);
+    }
+
+    /// Appends a string slice to the record body, prefixed by its `u16` length.
+    pub fn put_short_str(&mut self, value: &str) {
+        self.put_u16(value.len() as u16);
+        self.body.extend_from_slice(value.as_bytes());
+    }
+
+    /// Appends a byte slice to the record body, prefixed by its `u32` length.
+    pub fn put_bytes(&mut self, value: &[u8]) {
+        self.put_u32(value.len() as u32);
+        self.body.extend_from_slice(value);
+    }
+
+    /// Returns the complete log record, prefixing the body with its length and checksum.
+    pub fn into_log_record(self) -> Vec<u8> {
+        let mut record = Vec::with_capacity(LOG_RECORD_HEADER_SIZE + self.body.len());
+        write_u32(&mut record, self.body.len() as u32);
+        write_u32(&mut record, crc32(&self.body));
+        record.extend_from_slice(&self.body);
+        record
+    }
+
+    fn put_uint(&mut self, value: u64, size: usize) {
+        for i in 0..size {
+            self.body.push((value >> (8 * i)) as u8);
+        }
+    }
+}
+
+/// A decoder for the body of a binary log record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogRecordDecoder<'a> {
+    body: &'a [u8],
+    pos: usize
+}
+
+impl<'a> LogRecordDecoder<'a> {
+    /// Creates a decoder for the given log record (including its header),
+    /// or returns a `ParseError` if its length or checksum do not match the record body.
+    pub fn new(record: &'a [u8]) -> Result<LogRecordDecoder<'a>, ParseError> {
+        if record.len() < LOG_RECORD_HEADER_SIZE {
+            return Err(ParseError::ParseError("log record header is incomplete".to_owned()))
+        }
+        let length = read_u32(&record[0..4]) as usize;
+        let checksum = read_u32(&record[4..8]);
+        let body = &record[LOG_RECORD_HEADER_SIZE..];
+        if body.len() != length {
+            Err(ParseError::ParseError(format!("log record length mismatch: expected {} bytes, found {}", length, body.len())))
+        } else if crc32(body) != checksum {
+            Err(ParseError::ParseError("log record checksum mismatch".to_owned()))
+        } else {
+            Ok(LogRecordDecoder {
+                body: body,
+                pos: 0
+            })
+        }
+    }
+
+    /// Decodes the next `u8` from the record body.
+    pub fn get_u8(&mut self) -> Result<u8, ParseError> {
+        self.get_uint(1).map(|value| value as u8)
+    }
+
+    /// Decodes the next little-endian `u16` from the record body.
+    pub fn get_u16(&mut self) -> Result<u16, ParseError> {
+        self.get_uint(2).map(|value| value as u16)
+    }
+
+    /// Decodes the next little-endian `u32` from the record body.
+    pub fn get_u32(&mut self) -> Result<u32, ParseError> {
+        self.get_uint(4).map(|value| value as u32)
+    }
+
+    /// Decodes the next little-endian `u64` from the record body.
+    pub fn get_u64(&mut self) -> Result<u64, ParseError> {
+        self.get_uint(8)
+    }
+
+    /// Decodes the next string prefixed by its `u16` length from the record body.
+    pub fn get_short_str(&mut self) -> Result<String, ParseError> {
+        self.get_u16().and_then(|length| {
+            self.take(length as usize).and_then(|bytes| {
+                match String::from_utf8(bytes.to_vec()) {
+                    Ok(value) => Ok(value),
+                    Err(err) => Err(ParseError::ParseError(format!("{}", err)))
+                }
+            })
+        })
+    }
+
+    /// Decodes the next byte slice prefixed by its `u32` length from the record body.
+    pub fn get_bytes(&mut self) -> Result<&'a [u8], ParseError> {
+        self.get_u32().and_then(|length| self.take(length as usize))
+    }
+
+    fn get_uint(&mut self, size: usize) -> Result<u64, ParseError> {
+        self.take(size).and_then(|bytes| {
+            Ok(bytes.iter().enumerate().fold(0, |value, (i, &byte)| value | (byte as u64) << (8 * i)))
+        })
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8], ParseError> {
+        if self.pos + size > self.body.len() {
+            Err(ParseError::ParseError(format!("log record is too short, missing field at byte {}", self.pos)))
+        } else {
+            let bytes = &self.body[self.pos..self.pos + size];
+            self.pos += size;
+            Ok(bytes)
+        }
+    }
+}
+
+/// Computes the CRC-32 (IEEE 802.3) checksum of the given bytes.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes.iter().take(4).enumerate().fold(0, |value, (i, &byte)| value | (byte as u32) << (8 * i))
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: u32) {
+    for i in 0..4 {
+        bytes.push((value >> (8 * i)) as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    use std::io::{BufWriter, Cursor, ErrorKind};
+
+    fn create_record() -> Vec<u8> {
+        let mut encoder = LogRecordEncoder::new();
+        encoder.put_u8(1);
+        encoder.put_u16(2);
+        encoder.put_u32(3);
+        encoder.put_u64(4);
+        encoder.put_short_str("tag");
+        encoder.put_bytes(b"data\twith\ntabs and new-lines");
+        encoder.into_log_record()
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_log_record_encoding_and_decoding() {
+        let record = create_record();
+        let mut decoder = LogRecordDecoder::new(&record).expect("Unable to create decoder");
+
+        assert_eq!(decoder.get_u8(), Ok(1));
+        assert_eq!(decoder.get_u16(), Ok(2));
+        assert_eq!(decoder.get_u32(), Ok(3));
+        assert_eq!(decoder.get_u64(), Ok(4));
+        assert_eq!(decoder.get_short_str(), Ok("tag".to_owned()));
+        assert_eq!(decoder.get_bytes(), Ok(&b"data\twith\ntabs and new-lines"[..]));
+        assert!(decoder.get_u8().is_err());
+    }
+
+    #[test]
+    fn test_log_record_corruption() {
+        let mut record = create_record();
+        let last_byte = record.len() - 1;
+        record[last_byte] ^= 0xFF;
+        assert_eq!(LogRecordDecoder::new(&record), Err(ParseError::ParseError("log record checksum mismatch".to_owned())));
+
+        let record = create_record();
+        assert!(LogRecordDecoder::new(&record[..record.len() - 1]).is_err());
+        assert!(LogRecordDecoder::new(&record[..4]).is_err());
+    }
+
+    #[test]
+    fn test_read_and_write_log_records() {
+        let record = create_record();
+
+        let mut writer = BufWriter::new(vec![]);
+        assert_eq!(writer.write_log_record(&record).ok(), Some(record.len()));
+        assert_eq!(writer.write_log_record(&record).ok(), Some(record.len()));
+
+        let bytes = writer.into_inner().expect("Unable to extract inner context");
+        let mut reader = Cursor::new(bytes.clone());
+
+        assert_eq!(reader.read_log_record().ok(), Some(Some(record.clone())));
+        assert_eq!(reader.read_log_record().ok(), Some(Some(record.clone())));
+        assert_eq!(reader.read_log_record().ok(), Some(None));
+
+        for truncated_length in (bytes.len() - record.len() + 1)..bytes.len() {
+            let mut reader = Cursor::new(bytes[..truncated_length].to_vec());
+            assert_eq!(reader.read_log_record().ok(), Some(Some(record.clone())));
+            assert_eq!(reader.read_log_record().map_err(|err| err.kind()), Err(ErrorKind::UnexpectedEof));
+        }
+    }
+}
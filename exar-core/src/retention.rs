@@ -0,0 +1,215 @@
+use super::*;
+
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+
+use time;
+
+/// Exar DB's log retention task.
+///
+/// It runs a background thread periodically removing the log segments expired
+/// according to the given `RetentionConfig`, the thread stops when the task is dropped.
+///
+/// The postings of the removed events are also removed from the given `TagIndex`, if any.
+///
+/// # Examples
+/// ```no_run
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let log = Log::new("/path/to/logs", "test", 100);
+/// let retention_config = RetentionConfig {
+///     max_events: Some(1000000),
+///     ..RetentionConfig::default()
+/// };
+///
+/// let retention_task = RetentionTask::new(log, retention_config, 0);
+/// retention_task.set_last_event_id(100);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RetentionTask {
+    log: Log,
+    config: RetentionConfig,
+    tag_index: Option<Arc<RwLock<TagIndex>>>,
+    last_event_id: Arc<AtomicU64>,
+    stop_sender: Sender<()>
+}
+
+impl RetentionTask {
+    /// Creates a new retention task for the given `Log` and `RetentionConfig`,
+    /// given the `id` of the last event logged, and starts its background thread.
+    pub fn new(log: Log, config: RetentionConfig, last_event_id: u64) -> RetentionTask {
+        RetentionTask::spawn(log, config, last_event_id, None)
+    }
+
+    /// Creates a new retention task for the given `Log` and `RetentionConfig`,
+    /// given the `id` of the last event logged, and starts its background thread,
+    /// which also prunes the given shared `TagIndex`.
+    pub fn with_tag_index(log: Log, config: RetentionConfig, last_event_id: u64, tag_index: Arc<RwLock<TagIndex>>) -> RetentionTask {
+        RetentionTask::spawn(log, config, last_event_id, Some(tag_index))
+    }
+
+    fn spawn(log: Log, config: RetentionConfig, last_event_id: u64, tag_index: Option<Arc<RwLock<TagIndex>>>) -> RetentionTask {
+        let last_event_id = Arc::new(AtomicU64::new(last_event_id));
+        let (stop_sender, stop_receiver) = channel::<()>();
+        let (thread_log, thread_config, thread_tag_index) = (log.clone(), config.clone(), tag_index.clone());
+        let thread_last_event_id = last_event_id.clone();
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(thread_config.check_interval()) {
+                let last_event_id = thread_last_event_id.load(Ordering::SeqCst);
+                match apply_retention(&thread_log, &thread_config, last_event_id, thread_tag_index.as_ref()) {
+                    Ok(ref removed_segments) if !removed_segments.is_empty() => {
+                        info!("Removed {} expired segments from log '{}'", removed_segments.len(), thread_log.get_name());
+                    },
+                    Ok(_) => (),
+                    Err(err) => error!("Unable to apply retention to log '{}': {}", thread_log.get_name(), err)
+                }
+            }
+        });
+        RetentionTask {
+            log: log,
+            config: config,
+            tag_index: tag_index,
+            last_event_id: last_event_id,
+            stop_sender: stop_sender
+        }
+    }
+
+    /// Updates the `id` of the last event logged.
+    pub fn set_last_event_id(&self, last_event_id: u64) {
+        self.last_event_id.store(last_event_id, Ordering::SeqCst);
+    }
+
+    /// Applies the retention policy once, without waiting for the background thread,
+    /// and returns the removed segments or a `DatabaseError` if a failure occurs.
+    pub fn apply(&self) -> Result<Vec<Segment>, DatabaseError> {
+        apply_retention(&self.log, &self.config, self.last_event_id.load(Ordering::SeqCst), self.tag_index.as_ref())
+    }
+}
+
+impl Drop for RetentionTask {
+    fn drop(&mut self) {
+        let _ = self.stop_sender.send(());
+    }
+}
+
+fn apply_retention(log: &Log, config: &RetentionConfig, last_event_id: u64,
+                   tag_index: Option<&Arc<RwLock<TagIndex>>>) -> Result<Vec<Segment>, DatabaseError> {
+    let removed_segments = try!(log.apply_retention(config, last_event_id, get_current_timestamp_in_ms()));
+    if !removed_segments.is_empty() {
+        if let Some(tag_index) = tag_index {
+            if let Some(segment) = try!(log.segments()).into_iter().next() {
+                let mut tag_index = tag_index.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+                tag_index.remove_before(segment.first_id());
+            }
+        }
+    }
+    Ok(removed_segments)
+}
+
+fn get_current_timestamp_in_ms() -> u64 {
+    let timespec = time::get_time();
+    timespec.sec as u64 * 1000 + timespec.nsec as u64 / 1000 / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use exar_testkit::*;
+
+    use std::sync::{Arc, RwLock};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_retention_task() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(SegmentSize::MaxEvents(10));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for _ in 0..25 {
+            assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+        }
+
+        let retention_config = RetentionConfig {
+            max_events: Some(10),
+            check_interval_in_ms: 10,
+            ..RetentionConfig::default()
+        };
+        let retention_task = RetentionTask::new(log.clone(), retention_config, 15);
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(log.segments().map(|segments| segments.len()), Ok(3));
+
+        retention_task.set_last_event_id(25);
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(log.segments().map(|segments| segments.iter().map(|s| s.first_id()).collect()), Ok(vec![11, 21]));
+
+        drop(retention_task);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_retention_task_with_tag_index() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(SegmentSize::MaxEvents(10));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        let mut tag_index = TagIndex::new();
+        for id in 1..26 {
+            assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+            tag_index.insert(id, &["tag1".to_owned()]);
+        }
+        let tag_index = Arc::new(RwLock::new(tag_index));
+
+        let retention_config = RetentionConfig {
+            max_events: Some(10),
+            check_interval_in_ms: 10,
+            ..RetentionConfig::default()
+        };
+        let retention_task = RetentionTask::with_tag_index(log.clone(), retention_config, 25, tag_index.clone());
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(tag_index.read().unwrap().ids_after("tag1", 0), &(11..26).collect::<Vec<_>>()[..]);
+
+        drop(retention_task);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_apply() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(SegmentSize::MaxEvents(10));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        let mut tag_index = TagIndex::new();
+        for id in 1..26 {
+            assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+            tag_index.insert(id, &["tag1".to_owned()]);
+        }
+        let tag_index = Arc::new(RwLock::new(tag_index));
+
+        let retention_config = RetentionConfig {
+            max_events: Some(10),
+            ..RetentionConfig::default()
+        };
+        let retention_task = RetentionTask::with_tag_index(log.clone(), retention_config, 15, tag_index.clone());
+
+        assert_eq!(retention_task.apply().map(|segments| segments.len()), Ok(0));
+
+        retention_task.set_last_event_id(25);
+
+        assert_eq!(retention_task.apply().map(|segments| segments.iter().map(|s| s.first_id()).collect()), Ok(vec![1]));
+        assert_eq!(log.segments().map(|segments| segments.iter().map(|s| s.first_id()).collect()), Ok(vec![11, 21]));
+        assert_eq!(tag_index.read().unwrap().ids_after("tag1", 0), &(11..26).collect::<Vec<_>>()[..]);
+
+        drop(retention_task);
+
+        assert!(log.remove().is_ok());
+    }
+}
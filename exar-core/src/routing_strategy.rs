@@ -9,7 +9,12 @@ pub enum RoutingStrategy {
     /// The next element is picked at random.
     Random,
     /// The next element is picked using the round-robin algorithm.
-    RoundRobin(usize)
+    RoundRobin(usize),
+    /// The next element is the one with the fewest active subscriptions.
+    LeastLoaded,
+    /// The next element is the one whose subscriptions target the portion of the log overlapping most
+    /// with the one targeted by the subscription, or the least loaded one if none overlaps.
+    IntervalAffinity
 }
 
 #[cfg(feature = "rustc-serialization")]
@@ -17,7 +22,9 @@ impl Encodable for RoutingStrategy {
     fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
         match *self {
             RoutingStrategy::Random => s.emit_str("Random"),
-            RoutingStrategy::RoundRobin(_) => s.emit_str("RoundRobin")
+            RoutingStrategy::RoundRobin(_) => s.emit_str("RoundRobin"),
+            RoutingStrategy::LeastLoaded => s.emit_str("LeastLoaded"),
+            RoutingStrategy::IntervalAffinity => s.emit_str("IntervalAffinity")
         }
     }
 }
@@ -29,6 +36,8 @@ impl Decodable for RoutingStrategy {
             match s.as_ref() {
                 "Random" => RoutingStrategy::Random,
                 "RoundRobin" => RoutingStrategy::RoundRobin(0),
+                "LeastLoaded" => RoutingStrategy::LeastLoaded,
+                "IntervalAffinity" => RoutingStrategy::IntervalAffinity,
                 _ => RoutingStrategy::default()
             }
         })
@@ -40,7 +49,9 @@ impl Serialize for RoutingStrategy {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         match *self {
             RoutingStrategy::Random => serializer.serialize_str("Random"),
-            RoutingStrategy::RoundRobin(_) => serializer.serialize_str("RoundRobin")
+            RoutingStrategy::RoundRobin(_) => serializer.serialize_str("RoundRobin"),
+            RoutingStrategy::LeastLoaded => serializer.serialize_str("LeastLoaded"),
+            RoutingStrategy::IntervalAffinity => serializer.serialize_str("IntervalAffinity")
         }
     }
 }
@@ -59,12 +70,14 @@ struct RoutingStrategyVisitor;
 impl Visitor for RoutingStrategyVisitor {
     type Value = RoutingStrategy;
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("Random or RoundRobin")
+        formatter.write_str("Random, RoundRobin, LeastLoaded or IntervalAffinity")
     }
     fn visit_str<E: Error>(self, s: &str) -> Result<RoutingStrategy, E> {
         match s {
             "Random" => Ok(RoutingStrategy::Random),
             "RoundRobin" => Ok(RoutingStrategy::RoundRobin(0)),
+            "LeastLoaded" => Ok(RoutingStrategy::LeastLoaded),
+            "IntervalAffinity" => Ok(RoutingStrategy::IntervalAffinity),
             _ => Ok(RoutingStrategy::default())
         }
     }
@@ -101,6 +114,14 @@ mod tests {
         let routing_strategy = RoutingStrategy::RoundRobin(0);
         assert_eq!(json::encode(&routing_strategy).unwrap(), "\"RoundRobin\"");
         assert_eq!(json::decode::<RoutingStrategy>("\"RoundRobin\"").unwrap(), routing_strategy);
+
+        let routing_strategy = RoutingStrategy::LeastLoaded;
+        assert_eq!(json::encode(&routing_strategy).unwrap(), "\"LeastLoaded\"");
+        assert_eq!(json::decode::<RoutingStrategy>("\"LeastLoaded\"").unwrap(), routing_strategy);
+
+        let routing_strategy = RoutingStrategy::IntervalAffinity;
+        assert_eq!(json::encode(&routing_strategy).unwrap(), "\"IntervalAffinity\"");
+        assert_eq!(json::decode::<RoutingStrategy>("\"IntervalAffinity\"").unwrap(), routing_strategy);
     }
 
     #[test]
@@ -113,5 +134,13 @@ mod tests {
         let routing_strategy = RoutingStrategy::RoundRobin(0);
         assert_eq!(serde_json::to_string(&routing_strategy).unwrap(), "\"RoundRobin\"");
         assert_eq!(serde_json::from_str::<RoutingStrategy>("\"RoundRobin\"").unwrap(), routing_strategy);
+
+        let routing_strategy = RoutingStrategy::LeastLoaded;
+        assert_eq!(serde_json::to_string(&routing_strategy).unwrap(), "\"LeastLoaded\"");
+        assert_eq!(serde_json::from_str::<RoutingStrategy>("\"LeastLoaded\"").unwrap(), routing_strategy);
+
+        let routing_strategy = RoutingStrategy::IntervalAffinity;
+        assert_eq!(serde_json::to_string(&routing_strategy).unwrap(), "\"IntervalAffinity\"");
+        assert_eq!(serde_json::from_str::<RoutingStrategy>("\"IntervalAffinity\"").unwrap(), routing_strategy);
     }
 }
@@ -1,18 +1,38 @@
 use super::*;
 
-use indexed_line_reader::*;
-
-use std::fs::File;
-use std::io::{BufRead, BufReader, Seek, SeekFrom};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::cmp::{max, min};
+use std::iter::once;
+use std::mem;
+use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::Duration;
 
 /// Exar DB's log file scanner.
 ///
-/// It manages event stream subscriptions and continuously scans
-/// portions of the log file depending on the subscriptions query parameters.
+/// It manages event stream subscriptions and scans portions of the log file depending on the subscriptions
+/// query parameters whenever it is woken up, either by a new subscription or by the publishing of new events,
+/// and it waits without consuming any CPU otherwise.
+///
+/// If it is given the `TagIndex` of the log, subscriptions targeting an event tag (or a tag expression
+/// requiring a tag) are served by seeking straight to the events containing the tag, if it is given
+/// the `StreamIndex` of the log, subscriptions targeting a stream are served by seeking straight to the events
+/// of the stream, starting from the stream version targeted, and if it is given the `TimestampIndex` of the log, subscriptions targeting a time range only scan
+/// the portion of the log which may contain events within the time range.
+///
+/// If it is given the `TailCache` of the log, subscriptions positioned within the events it contains are served
+/// from memory, sharing the cached events across subscriptions, and the log is only read for older events.
+///
+/// Subscriptions targeting events in descending order are served by reading the log backwards from its tail,
+/// a window of events at a time, using the `LogIndex` of each segment.
+///
+/// Subscriptions whose event stream buffer is full are paused for the rest of a scan, without blocking the others,
+/// and they resume from their last position on the following one, which takes place after the sleep duration.
+///
+/// It keeps track of its `ScannerLoad`, which is used to route new subscriptions.
+///
+/// Subscriptions whose event stream has been cancelled are removed before the following scan, ending their event stream.
 ///
 /// # Examples
 /// ```no_run
@@ -20,16 +40,16 @@ use std::time::Duration;
 ///
 /// # fn main() {
 /// use exar::*;
-/// use std::sync::mpsc::channel;
+/// use std::sync::mpsc::sync_channel;
 /// use std::time::Duration;
 ///
 /// let log = Log::new("/path/to/logs", "test", 100);
 /// let event = Event::new("data", vec!["tag1", "tag2"]);
 ///
-/// let line_reader = log.open_line_reader().unwrap();
-/// let mut scanner = Scanner::new(line_reader, Duration::from_millis(10));
+/// let log_reader = log.open_log_reader().unwrap();
+/// let mut scanner = Scanner::new(log_reader, Duration::from_millis(10));
 ///
-/// let (sender, _) = channel();
+/// let (sender, _) = sync_channel(1000);
 /// let subscription = Subscription::new(sender, Query::live());
 /// scanner.handle_subscription(subscription).unwrap();
 ///
@@ -38,30 +58,72 @@ use std::time::Duration;
 /// ```
 #[derive(Clone, Debug)]
 pub struct Scanner {
-    action_sender: Sender<ScannerAction>
+    action_sender: Sender<ScannerAction>,
+    load: Arc<RwLock<ScannerLoad>>
 }
 
 impl Scanner {
-    /// Creates a new log scanner using the given `IndexedLineReader` and sleep duration.
-    pub fn new(reader: IndexedLineReader<BufReader<File>>, sleep_duration: Duration) -> Scanner {
+    /// Creates a new log scanner using the given `LogReader` and sleep duration,
+    /// which is the amount of time to wait before resuming paused subscriptions.
+    pub fn new(reader: LogReader, sleep_duration: Duration) -> Scanner {
+        let (sender, receiver) = channel();
+        let load = Arc::new(RwLock::new(ScannerLoad::default()));
+        ScannerThread::new(reader, receiver).with_waker(sender.clone()).with_load(load.clone()).run(sleep_duration);
+        Scanner {
+            action_sender: sender,
+            load: load
+        }
+    }
+
+    /// Creates a new log scanner using the given `LogReader`, shared `TagIndex`, `StreamIndex`, `TimestampIndex`
+    /// and `TailCache`, and sleep duration, which is the amount of time to wait before resuming paused subscriptions.
+    pub fn with_indexes(reader: LogReader, tag_index: Arc<RwLock<TagIndex>>, stream_index: Arc<RwLock<StreamIndex>>,
+                        timestamp_index: Arc<RwLock<TimestampIndex>>, tail_cache: Arc<RwLock<TailCache>>,
+                        sleep_duration: Duration) -> Scanner {
         let (sender, receiver) = channel();
-        ScannerThread::new(reader, receiver).run(sleep_duration);
+        let load = Arc::new(RwLock::new(ScannerLoad::default()));
+        ScannerThread::new(reader, receiver).with_waker(sender.clone()).with_load(load.clone()).with_tag_index(tag_index)
+                                            .with_stream_index(stream_index).with_timestamp_index(timestamp_index)
+                                            .with_tail_cache(tail_cache).run(sleep_duration);
         Scanner {
-            action_sender: sender
+            action_sender: sender,
+            load: load
         }
     }
 
     /// Handles the given `Subscription` or returns a `DatabaseError` if a failure occurs.
+    ///
+    /// The subscription is added to the load of the scanner straight away,
+    /// so that it is taken into account when routing the following subscriptions.
     pub fn handle_subscription(&self, subscription: Subscription) -> Result<(), DatabaseError> {
+        let interval = subscription.query.interval();
         match self.action_sender.send(ScannerAction::HandleSubscription(subscription)) {
+            Ok(()) => {
+                self.load.write().unwrap_or_else(|poisoned| poisoned.into_inner()).add(interval);
+                Ok(())
+            },
+            Err(_) => Err(DatabaseError::EventStreamError(EventStreamError::Closed))
+        }
+    }
+
+    /// Returns the current `ScannerLoad` of the scanner.
+    pub fn load(&self) -> ScannerLoad {
+        self.load.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Wakes up the scanner so that it scans the log for new events
+    /// or returns a `DatabaseError` if a failure occurs.
+    pub fn wake(&self) -> Result<(), DatabaseError> {
+        match self.action_sender.send(ScannerAction::Wake) {
             Ok(()) => Ok(()),
             Err(_) => Err(DatabaseError::EventStreamError(EventStreamError::Closed))
         }
     }
 
-    /// Adds the given line to the `LinesIndex` or returns a `DatabaseError` if a failure occurs.
-    pub fn add_line_index(&self, line: u64, byte_count: u64) -> Result<(), DatabaseError> {
-        match self.action_sender.send(ScannerAction::AddLineIndex(line, byte_count)) {
+    /// Adds the given entry to the `LogIndex` of the log segment starting at the given event `id`
+    /// or returns a `DatabaseError` if a failure occurs.
+    pub fn add_index_entry(&self, segment_first_id: u64, event_id: u64, byte_offset: u64) -> Result<(), DatabaseError> {
+        match self.action_sender.send(ScannerAction::AddIndexEntry(segment_first_id, event_id, byte_offset)) {
             Ok(()) => Ok(()),
             Err(_) => Err(DatabaseError::EventStreamError(EventStreamError::Closed))
         }
@@ -101,39 +163,110 @@ impl Drop for Scanner {
 /// Exar DB's log file scanner thread.
 ///
 /// It uses a channel receiver to receive actions to be performed between scans,
-/// and it manages the thread that continuously scans portions of the log file
-/// depending on the subscriptions query parameters.
+/// and it manages the thread that scans portions of the log file depending on the subscriptions query parameters
+/// every time it receives an action waking it up.
 #[derive(Debug)]
 pub struct ScannerThread {
-    index: LinesIndex,
-    reader: IndexedLineReader<BufReader<File>>,
+    reader: LogReader,
     action_receiver: Receiver<ScannerAction>,
     tail_scanner_sender: Option<Sender<ScannerAction>>,
+    waker: Option<Sender<ScannerAction>>,
+    tag_index: Option<Arc<RwLock<TagIndex>>>,
+    stream_index: Option<Arc<RwLock<StreamIndex>>>,
+    timestamp_index: Option<Arc<RwLock<TimestampIndex>>>,
+    tail_cache: Option<Arc<RwLock<TailCache>>>,
+    load: Option<Arc<RwLock<ScannerLoad>>>,
     subscriptions: Vec<Subscription>
 }
 
 impl ScannerThread {
-    fn new(reader: IndexedLineReader<BufReader<File>>, receiver: Receiver<ScannerAction>) -> ScannerThread {
+    fn new(reader: LogReader, receiver: Receiver<ScannerAction>) -> ScannerThread {
         ScannerThread {
-            index: reader.get_index().clone(),
             reader: reader,
             action_receiver: receiver,
             tail_scanner_sender: None,
+            waker: None,
+            tag_index: None,
+            stream_index: None,
+            timestamp_index: None,
+            tail_cache: None,
+            load: None,
             subscriptions: vec![]
         }
     }
 
+    fn with_waker(mut self, waker: Sender<ScannerAction>) -> Self {
+        self.waker = Some(waker);
+        self
+    }
+
+    fn with_load(mut self, load: Arc<RwLock<ScannerLoad>>) -> Self {
+        self.load = Some(load);
+        self
+    }
+
+    fn with_tag_index(mut self, tag_index: Arc<RwLock<TagIndex>>) -> Self {
+        self.tag_index = Some(tag_index);
+        self
+    }
+
+    fn with_stream_index(mut self, stream_index: Arc<RwLock<StreamIndex>>) -> Self {
+        self.stream_index = Some(stream_index);
+        self
+    }
+
+    fn with_timestamp_index(mut self, timestamp_index: Arc<RwLock<TimestampIndex>>) -> Self {
+        self.timestamp_index = Some(timestamp_index);
+        self
+    }
+
+    fn with_tail_cache(mut self, tail_cache: Arc<RwLock<TailCache>>) -> Self {
+        self.tail_cache = Some(tail_cache);
+        self
+    }
+
     fn run(mut self, sleep_duration: Duration) -> JoinHandle<Self> {
         thread::spawn(move || {
             'main: loop {
-                while let Ok(action) = self.action_receiver.try_recv() {
+                // paused subscriptions are resumed after the sleep duration, otherwise the thread waits to be woken up
+                let has_paused_subscriptions = self.subscriptions.iter().any(|s| s.is_paused());
+                let first_action = if has_paused_subscriptions {
+                    match self.action_receiver.recv_timeout(sleep_duration) {
+                        Ok(action) => Some(action),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break 'main
+                    }
+                } else {
+                    match self.action_receiver.recv() {
+                        Ok(action) => Some(action),
+                        Err(_) => break 'main
+                    }
+                };
+                let actions: Vec<_> = first_action.into_iter().chain(self.action_receiver.try_iter()).collect();
+                let mut should_scan = has_paused_subscriptions;
+                for action in actions {
                     match action {
                         ScannerAction::HandleSubscription(subscription) => {
+                            if let Some(ref waker) = self.waker {
+                                subscription.cancellation_handle().set_waker(waker.clone());
+                            }
                             self.subscriptions.push(subscription);
+                            should_scan = true;
                         },
-                        ScannerAction::AddLineIndex(line, byte_count) => {
-                            self.index.insert(line, byte_count);
-                            self.reader.restore_index(self.index.clone());
+                        ScannerAction::Wake => {
+                            should_scan = true;
+                        },
+                        ScannerAction::AddIndexEntry(segment_first_id, event_id, byte_offset) => {
+                            self.reader.add_index_entry(segment_first_id, event_id, byte_offset);
+                        },
+                        ScannerAction::DiscardIndex(segment_first_id, ack_sender) => {
+                            self.reader.discard_index(segment_first_id);
+                            let _ = ack_sender.send(());
+                        },
+                        ScannerAction::ReplaceIndex(segment_first_id, index) => {
+                            if let Err(err) = self.reader.replace_index(segment_first_id, index) {
+                                error!("Unable to replace log segment index: {}", err);
+                            }
                         },
                         ScannerAction::SetTailScannerSender(sender) => {
                             self.tail_scanner_sender = Some(sender);
@@ -141,28 +274,49 @@ impl ScannerThread {
                         ScannerAction::Stop => break 'main
                     }
                 }
-                if !self.subscriptions.is_empty() {
+                self.remove_cancelled_subscriptions();
+                if should_scan && !self.subscriptions.is_empty() {
                     match self.scan() {
                         Ok(_) => self.retain_active_subscriptions(),
                         Err(err) => error!("Unable to scan log: {}", err)
                     }
                 }
-                thread::sleep(sleep_duration);
+                self.update_load();
             };
             self.subscriptions.truncate(0);
             self
         })
     }
 
+    fn update_load(&self) {
+        if let Some(ref load) = self.load {
+            let intervals: Vec<_> = self.subscriptions.iter().map(|s| s.query.interval()).collect();
+            *load.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = ScannerLoad {
+                nr_of_subscriptions: self.subscriptions.len(),
+                intervals: intervals.merged()
+            };
+        }
+    }
+
+    fn remove_cancelled_subscriptions(&mut self) {
+        for subscription in self.subscriptions.iter_mut().filter(|s| s.is_cancelled()) {
+            subscription.end();
+        }
+        self.subscriptions.retain(|s| !s.is_cancelled());
+    }
+
     fn retain_active_subscriptions(&mut self) {
         match self.tail_scanner_sender {
             Some(ref tail_scanner_sender) => {
+                // paused subscriptions have not caught up yet, so they are kept until they do
                 for subscription in self.subscriptions.iter().filter(|s| {
-                    s.is_active() && s.query.live_stream && s.query.is_active()
+                    s.is_active() && !s.is_paused() && s.query.live_stream && s.query.is_active()
                 }) {
                     let _ = tail_scanner_sender.send(ScannerAction::HandleSubscription(subscription.clone()));
                 }
-                self.subscriptions.truncate(0);
+                self.subscriptions.retain(|s| {
+                    s.is_active() && s.is_paused() && s.query.is_active()
+                })
             },
             None => self.subscriptions.retain(|s| {
                 s.is_active() && s.query.is_active()
@@ -170,43 +324,302 @@ impl ScannerThread {
         }
     }
 
-    fn subscriptions_intervals(&self) -> Vec<Interval<u64>> {
-        self.subscriptions.iter().map(|s| s.query.interval()).collect()
+    fn timestamp_intervals(&self) -> Vec<Interval<u64>> {
+        let timestamp_index = self.timestamp_index.as_ref().map(|timestamp_index| {
+            timestamp_index.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+        });
+        self.subscriptions.iter().map(|s| match timestamp_index {
+            Some(ref timestamp_index) if s.query.from_timestamp.is_some() || s.query.to_timestamp.is_some() => {
+                timestamp_index.interval(s.query.from_timestamp, s.query.to_timestamp)
+            },
+            _ => Interval::new(0, u64::max_value())
+        }).collect()
+    }
+
+    fn subscriptions_intervals(&self, timestamp_intervals: &[Interval<u64>]) -> Vec<Interval<u64>> {
+        let indexes = self.available_indexes();
+        self.subscriptions.iter().zip(timestamp_intervals).filter(|&(s, _)| uses_intervals(s, indexes)).map(|(s, timestamp_interval)| {
+            let interval = s.query.interval();
+            Interval::new(max(interval.start, timestamp_interval.start), min(interval.end, timestamp_interval.end))
+        }).filter(|interval| interval.start < interval.end).collect()
     }
 
     fn scan(&mut self) -> Result<(), DatabaseError> {
-        for interval in self.subscriptions_intervals().merged() {
-            match self.reader.seek(SeekFrom::Start(interval.start)) {
-                Ok(_) => {
-                    for line in (&mut self.reader).lines() {
-                        match line {
-                            Ok(line) => match Event::from_tab_separated_str(&line) {
-                                Ok(ref event) => {
-                                    for subscription in self.subscriptions.iter_mut().filter(|s| s.matches_event(event)) {
-                                        let _ = subscription.send(event.clone());
-                                    }
-                                    if interval.end == event.id || self.subscriptions.iter().all(|s| !s.query.is_active()) {
-                                        break;
-                                    }
-                                },
-                                Err(err) => warn!("Unable to deserialize log line: {}", err)
-                            },
-                            Err(err) => warn!("Unable to read log line: {}", err)
+        for subscription in &mut self.subscriptions {
+            subscription.resume();
+        }
+        let tail_cache_subscriptions = match self.tail_cache.clone() {
+            Some(tail_cache) => self.scan_tail_cache(&tail_cache),
+            None => vec![]
+        };
+        let result = self.scan_log();
+        self.subscriptions.extend(tail_cache_subscriptions);
+        result
+    }
+
+    // the subscriptions served from the tail cache are set aside for the rest of the scan, so that they are not
+    // served from the log as well, while the others are served from the log even if they reach the tail cache
+    fn scan_tail_cache(&mut self, tail_cache: &Arc<RwLock<TailCache>>) -> Vec<Subscription> {
+        let tail_cache = tail_cache.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let subscriptions = mem::replace(&mut self.subscriptions, vec![]);
+        let (mut tail_cache_subscriptions, log_subscriptions): (Vec<_>, Vec<_>) = subscriptions.into_iter().partition(|s| {
+            !s.query.reverse && tail_cache.covers(s.query.interval().start)
+        });
+        self.subscriptions = log_subscriptions;
+        for subscription in &mut tail_cache_subscriptions {
+            for event in tail_cache.events_after(subscription.query.interval().start) {
+                if !subscription.is_active() || subscription.is_paused() || !subscription.query.is_active() {
+                    break
+                }
+                if subscription.matches_event(&event) {
+                    let _ = subscription.send(event);
+                }
+            }
+        }
+        tail_cache_subscriptions
+    }
+
+    fn scan_log(&mut self) -> Result<(), DatabaseError> {
+        let timestamp_intervals = self.timestamp_intervals();
+        try!(self.scan_backwards(&timestamp_intervals));
+        try!(self.scan_postings(&timestamp_intervals));
+        let indexes = self.available_indexes();
+        for interval in self.subscriptions_intervals(&timestamp_intervals).merged() {
+            try!(self.reader.seek(interval.start));
+            for event in &mut self.reader {
+                match event {
+                    Ok(event) => {
+                        let event = Arc::new(event);
+                        for subscription in self.subscriptions.iter_mut().filter(|s| {
+                            uses_intervals(s, indexes) && s.matches_event(&event)
+                        }) {
+                            let _ = subscription.send(event.clone());
                         }
+                        // events may be missing from compacted logs, so the end of the interval is only reached
+                        // once the subscriptions scanning it are no longer active or past their time range
+                        let is_interval_end = interval.end <= event.id && self.subscriptions.iter().zip(&timestamp_intervals).all(|(s, timestamp_interval)| {
+                            !uses_intervals(s, indexes) || !s.is_active() || s.is_paused() || !s.query.is_active() ||
+                            s.query.interval().start > event.id || timestamp_interval.end <= event.id
+                        });
+                        if is_interval_end || self.subscriptions.iter().all(|s| !s.query.is_active() || s.is_paused()) {
+                            break;
+                        }
+                    },
+                    Err(err) => warn!("Unable to read log record: {}", err)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn scan_backwards(&mut self, timestamp_intervals: &[Interval<u64>]) -> Result<(), DatabaseError> {
+        if self.subscriptions.iter().all(|s| !s.query.reverse) {
+            return Ok(())
+        }
+        // the log is read backwards a window at a time, each window starting at a position the reader can seek to
+        let mut positions: Vec<u64> = self.reader.get_indexes().iter().flat_map(|(&segment_first_id, index)| {
+            once(segment_first_id - 1).chain(index.get_ref().keys().cloned())
+        }).collect();
+        positions.dedup();
+        for (subscription, timestamp_interval) in self.subscriptions.iter_mut().zip(timestamp_intervals).filter(|&(ref s, _)| s.query.reverse) {
+            let start = max(subscription.query.interval().start, timestamp_interval.start);
+            let window_starts: Vec<u64> = once(start).chain(positions.iter().cloned().filter(|&position| position > start)).collect();
+            let mut window_end = None;
+            for window_start in window_starts.into_iter().rev() {
+                if !subscription.is_active() || subscription.is_paused() || !subscription.query.is_active() {
+                    break
+                }
+                if window_start < timestamp_interval.end {
+                    for event in try!(read_window(&mut self.reader, window_start, window_end)).into_iter().rev() {
+                        if subscription.matches_event(&event) {
+                            let _ = subscription.send(Arc::new(event));
+                        }
+                    }
+                }
+                window_end = Some(window_start);
+            }
+        }
+        Ok(())
+    }
+
+    fn available_indexes(&self) -> AvailableIndexes {
+        AvailableIndexes { tag_index: self.tag_index.is_some(), stream_index: self.stream_index.is_some() }
+    }
+
+    // subscriptions targeting a stream read the postings of the stream, the others the postings of their required tag
+    fn scan_postings(&mut self, timestamp_intervals: &[Interval<u64>]) -> Result<(), DatabaseError> {
+        let indexes = self.available_indexes();
+        let tag_index = self.tag_index.clone();
+        let stream_index = self.stream_index.clone();
+        // events close to the last one read are reached by reading forward rather than seeking
+        let max_read_ahead = self.reader.get_index(self.reader.segment_first_id()).map_or(0, |index| index.granularity());
+        let mut last_read_id = None;
+        for (subscription, timestamp_interval) in self.subscriptions.iter_mut().zip(timestamp_intervals).filter(|&(ref s, _)| uses_postings(s, indexes)) {
+            let mut position = max(subscription.query.interval().start, timestamp_interval.start);
+            let postings = match (stream_index.as_ref(), subscription.query.stream_id.clone()) {
+                (Some(stream_index), Some(stream_id)) => {
+                    let version = subscription.query.from_stream_version.unwrap_or(0);
+                    let stream_index_ref = stream_index.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+                    if let Some(&first_id) = stream_index_ref.ids_from_version(&stream_id, version).first() {
+                        position = max(position, first_id - 1);
                     }
+                    Postings::Stream(stream_index.clone(), stream_id)
                 },
-                Err(err) => return Err(DatabaseError::from_io_error(err))
+                _ => match tag_index.as_ref() {
+                    Some(tag_index) => Postings::Tag(tag_index.clone(), subscription.query.required_tag().unwrap_or_default().to_owned()),
+                    None => continue
+                }
+            };
+            let mut subscription_read_id = 0;
+            'postings: while subscription.is_active() && !subscription.is_paused() && subscription.query.is_active() {
+                let ids = postings.ids_after(position);
+                if ids.is_empty() {
+                    break
+                }
+                for id in ids {
+                    if !subscription.is_active() || subscription.is_paused() || !subscription.query.is_active() {
+                        break 'postings
+                    }
+                    if id > timestamp_interval.end {
+                        break 'postings
+                    }
+                    position = id;
+                    // the event may have been removed by compaction, in which case the following one has been read
+                    if id <= subscription_read_id {
+                        continue
+                    }
+                    if last_read_id.map_or(true, |last_read_id| id <= last_read_id || id - last_read_id > max_read_ahead) {
+                        try!(self.reader.seek(id - 1));
+                    }
+                    loop {
+                        match self.reader.read_event() {
+                            Ok(Some(event)) => {
+                                last_read_id = Some(event.id);
+                                if event.id < id {
+                                    continue
+                                }
+                                subscription_read_id = event.id;
+                                if subscription.matches_event(&event) {
+                                    let _ = subscription.send(Arc::new(event));
+                                }
+                                break
+                            },
+                            Ok(None) => {
+                                last_read_id = None;
+                                break 'postings
+                            },
+                            Err(DatabaseError::ParseError(err)) => warn!("Unable to decode log record: {}", err),
+                            Err(err) => return Err(err)
+                        }
+                    }
+                }
             }
         }
         Ok(())
     }
 }
 
+const POSTINGS_SCAN_BATCH_SIZE: usize = 1000;
+
+#[derive(Clone, Copy)]
+struct AvailableIndexes {
+    tag_index: bool,
+    stream_index: bool
+}
+
+enum Postings {
+    Tag(Arc<RwLock<TagIndex>>, String),
+    Stream(Arc<RwLock<StreamIndex>>, String)
+}
+
+impl Postings {
+    fn ids_after(&self, position: u64) -> Vec<u64> {
+        match *self {
+            Postings::Tag(ref tag_index, ref tag) => {
+                let tag_index = tag_index.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+                tag_index.ids_after(tag, position).iter().take(POSTINGS_SCAN_BATCH_SIZE).cloned().collect()
+            },
+            Postings::Stream(ref stream_index, ref stream_id) => {
+                let stream_index = stream_index.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+                stream_index.ids_after(stream_id, position).iter().take(POSTINGS_SCAN_BATCH_SIZE).cloned().collect()
+            }
+        }
+    }
+}
+
+fn uses_postings(subscription: &Subscription, indexes: AvailableIndexes) -> bool {
+    !subscription.query.reverse && (indexes.stream_index && subscription.query.stream_id.is_some() ||
+                                    indexes.tag_index && subscription.query.required_tag().is_some())
+}
+
+fn uses_intervals(subscription: &Subscription, indexes: AvailableIndexes) -> bool {
+    !subscription.query.reverse && !uses_postings(subscription, indexes)
+}
+
+fn read_window(reader: &mut LogReader, start: u64, end: Option<u64>) -> Result<Vec<Event>, DatabaseError> {
+    let mut events = vec![];
+    try!(reader.seek(start));
+    loop {
+        match reader.read_event() {
+            Ok(Some(ref event)) if end.map_or(false, |end| event.id > end) => break,
+            Ok(Some(event)) => events.push(event),
+            Ok(None) => break,
+            Err(DatabaseError::ParseError(err)) => warn!("Unable to decode log record: {}", err),
+            Err(err) => return Err(err)
+        }
+    }
+    Ok(events)
+}
+
+/// Exar DB's log scanner load.
+///
+/// It holds the number of subscriptions handled by a scanner and the merged intervals of the log they target.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let mut load = ScannerLoad::default();
+/// load.add(Interval::new(0, 100));
+/// load.add(Interval::new(50, 150));
+///
+/// assert_eq!(load.nr_of_subscriptions, 2);
+/// assert_eq!(load.overlap(&Interval::new(100, 200)), 50);
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScannerLoad {
+    /// The number of subscriptions handled by the scanner.
+    pub nr_of_subscriptions: usize,
+    /// The merged intervals of the log targeted by the subscriptions.
+    pub intervals: Vec<Interval<u64>>
+}
+
+impl ScannerLoad {
+    /// Adds a subscription targeting the given interval to the load.
+    pub fn add(&mut self, interval: Interval<u64>) {
+        self.nr_of_subscriptions += 1;
+        self.intervals.push(interval);
+        self.intervals.merge();
+    }
+
+    /// Returns the length of the portion of the given interval overlapping with the intervals of the load.
+    pub fn overlap(&self, interval: &Interval<u64>) -> u64 {
+        self.intervals.iter().map(|i| min(i.end, interval.end).saturating_sub(max(i.start, interval.start))).sum()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum ScannerAction {
     HandleSubscription(Subscription),
-    AddLineIndex(u64, u64),
+    AddIndexEntry(u64, u64, u64),
+    DiscardIndex(u64, Sender<()>),
+    ReplaceIndex(u64, LogIndex),
     SetTailScannerSender(Sender<ScannerAction>),
+    Wake,
     Stop
 }
 
@@ -215,11 +628,8 @@ mod tests {
     use super::super::*;
     use exar_testkit::*;
 
-    use indexed_line_reader::*;
-
-    use std::fs::*;
-    use std::io::BufReader;
-    use std::sync::mpsc::{channel, TryRecvError};
+    use std::sync::{Arc, RwLock};
+    use std::sync::mpsc::{channel, sync_channel, Receiver, TryRecvError};
     use std::thread;
     use std::time::Duration;
 
@@ -230,34 +640,34 @@ mod tests {
     fn create_log() -> Log {
         let ref collection_name = random_collection_name();
         let log = Log::new("", collection_name, 100);
-        assert!(log.open_writer().is_ok());
+        assert!(log.ensure_exists().is_ok());
         log
     }
 
-    fn create_log_and_line_reader() -> (Log, IndexedLineReader<BufReader<File>>) {
+    fn create_log_and_reader() -> (Log, LogReader) {
         let log = create_log();
-        let line_reader = log.open_line_reader().expect("Unable to open line reader");
-        (log, line_reader)
+        let log_reader = log.open_log_reader().expect("Unable to open log reader");
+        (log, log_reader)
     }
 
     #[test]
     fn test_scanner_constructor() {
-        let (log, line_reader) = create_log_and_line_reader();
+        let (log, log_reader) = create_log_and_reader();
 
-        let _ = Scanner::new(line_reader, sleep_duration());
+        let _ = Scanner::new(log_reader, sleep_duration());
 
         assert!(log.remove().is_ok());
     }
 
     #[test]
     fn test_scanner_message_passing() {
-        let (log, line_reader) = create_log_and_line_reader();
+        let (log, log_reader) = create_log_and_reader();
 
-        let mut scanner = Scanner::new(line_reader, sleep_duration());
+        let mut scanner = Scanner::new(log_reader, sleep_duration());
 
         assert!(scanner.stop().is_ok());
 
-        let (sender, _) = channel();
+        let (sender, _) = sync_channel(1000);
         let subscription = Subscription::new(sender, Query::live());
 
         let (sender, receiver) = channel();
@@ -272,14 +682,22 @@ mod tests {
             _ => panic!("Expected to receive an HandleSubscription message")
         }
 
-        assert!(scanner.add_line_index(100, 1000).is_ok());
+        assert!(scanner.add_index_entry(1, 100, 1000).is_ok());
 
         match receiver.recv() {
-            Ok(ScannerAction::AddLineIndex(pos, byte_count)) => {
-                assert_eq!(pos, 100);
-                assert_eq!(byte_count, 1000);
+            Ok(ScannerAction::AddIndexEntry(segment_first_id, event_id, byte_offset)) => {
+                assert_eq!(segment_first_id, 1);
+                assert_eq!(event_id, 100);
+                assert_eq!(byte_offset, 1000);
             },
-            _ => panic!("Expected to receive an HandleSubscription message")
+            _ => panic!("Expected to receive an AddIndexEntry message")
+        }
+
+        assert!(scanner.wake().is_ok());
+
+        match receiver.recv() {
+            Ok(ScannerAction::Wake) => (),
+            _ => panic!("Expected to receive a Wake message")
         }
 
         let (tail_scanner_sender, tail_scanner_receiver) = channel();
@@ -313,12 +731,45 @@ mod tests {
         assert!(log.remove().is_ok());
     }
 
+    #[test]
+    fn test_scanner_load() {
+        let log = create_log();
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        let log_reader = log.open_log_reader().expect("Unable to open log reader");
+
+        for _ in 0..5 {
+            assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+        }
+
+        let scanner = Scanner::new(log_reader, sleep_duration());
+        assert_eq!(scanner.load(), ScannerLoad::default());
+
+        let (sender, receiver) = sync_channel(1);
+        let subscription = Subscription::new(sender, Query::current().offset(2));
+
+        assert!(scanner.handle_subscription(subscription).is_ok());
+        assert_eq!(scanner.load().nr_of_subscriptions, 1);
+        thread::sleep(sleep_duration() * 2);
+
+        assert_eq!(scanner.load(), ScannerLoad {
+            nr_of_subscriptions: 1,
+            intervals: vec![Interval::new(3, u64::max_value())]
+        });
+
+        drop(receiver);
+        thread::sleep(sleep_duration() * 2);
+
+        assert_eq!(scanner.load(), ScannerLoad::default());
+
+        assert!(log.remove().is_ok());
+    }
+
     #[test]
     fn test_scanner_thread_stop() {
-        let (log, line_reader) = create_log_and_line_reader();
+        let (log, log_reader) = create_log_and_reader();
 
         let (sender, receiver) = channel();
-        let scanner_thread = ScannerThread::new(line_reader, receiver);
+        let scanner_thread = ScannerThread::new(log_reader, receiver);
         let handle = scanner_thread.run(sleep_duration());
 
         assert!(sender.send(ScannerAction::Stop).is_ok());
@@ -330,29 +781,30 @@ mod tests {
     }
 
     #[test]
-    fn test_scanner_thread_new_indexed_line() {
-        let (log, line_reader) = create_log_and_line_reader();
+    fn test_scanner_thread_add_index_entry() {
+        let (log, log_reader) = create_log_and_reader();
 
         let (sender, receiver) = channel();
-        let scanner_thread = ScannerThread::new(line_reader, receiver);
+        let scanner_thread = ScannerThread::new(log_reader, receiver);
         let handle = scanner_thread.run(sleep_duration());
 
-        assert!(sender.send(ScannerAction::AddLineIndex(100, 1234)).is_ok());
+        assert!(sender.send(ScannerAction::AddIndexEntry(1, 100, 1234)).is_ok());
+        assert!(sender.send(ScannerAction::AddIndexEntry(101, 200, 2345)).is_ok());
         assert!(sender.send(ScannerAction::Stop).is_ok());
 
         let scanner_thread = handle.join().expect("Unable to join scanner thread");
-        assert_eq!(scanner_thread.index.byte_count_at_pos(&100), Some(1234));
-        assert_eq!(scanner_thread.reader.get_index().clone().byte_count_at_pos(&100), Some(1234));
+        assert_eq!(scanner_thread.reader.get_index(1).and_then(|index| index.byte_offset_at(&100)), Some(1234));
+        assert_eq!(scanner_thread.reader.get_index(101).and_then(|index| index.byte_offset_at(&200)), Some(2345));
 
         assert!(log.remove().is_ok());
     }
 
     #[test]
     fn test_scanner_thread_set_tail_scanner_sender() {
-        let (log, line_reader) = create_log_and_line_reader();
+        let (log, log_reader) = create_log_and_reader();
 
         let (sender, receiver) = channel();
-        let scanner_thread = ScannerThread::new(line_reader, receiver);
+        let scanner_thread = ScannerThread::new(log_reader, receiver);
         let handle = scanner_thread.run(sleep_duration());
 
         let (tail_scanner_sender, _) = channel();
@@ -370,17 +822,17 @@ mod tests {
     fn test_scanner_thread_subscriptions_management() {
         let log = create_log();
         let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
-        let line_reader = log.open_line_reader().expect("Unable to open line reader");
+        let log_reader = log.open_log_reader().expect("Unable to open log reader");
         let event = Event::new("data", vec!["tag1", "tag2"]);
         let sleep_duration = Duration::from_millis(10);
 
         assert!(logger.log(event).is_ok());
 
         let (thread_sender, thread_receiver) = channel();
-        let scanner_thread = ScannerThread::new(line_reader, thread_receiver);
+        let scanner_thread = ScannerThread::new(log_reader, thread_receiver);
         scanner_thread.run(sleep_duration);
 
-        let (sender, receiver) = channel();
+        let (sender, receiver) = sync_channel(1000);
         let live_subscription = Subscription::new(sender, Query::live());
 
         let (tail_scanner_sender, tail_scanner_receiver) = channel();
@@ -401,7 +853,7 @@ mod tests {
         }
         assert_eq!(receiver.try_recv().err(), Some(TryRecvError::Empty));
 
-        let (sender, receiver) = channel();
+        let (sender, receiver) = sync_channel(1000);
         let current_subscription = Subscription::new(sender, Query::current());
 
         assert!(thread_sender.send(ScannerAction::HandleSubscription(current_subscription)).is_ok());
@@ -420,17 +872,17 @@ mod tests {
     fn test_tail_scanner_thread_subscriptions_management() {
         let log = create_log();
         let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
-        let line_reader = log.open_line_reader().expect("Unable to open line reader");
+        let log_reader = log.open_log_reader().expect("Unable to open log reader");
         let event = Event::new("data", vec!["tag1", "tag2"]);
         let sleep_duration = Duration::from_millis(10);
 
         assert!(logger.log(event).is_ok());
 
         let (thread_sender, thread_receiver) = channel();
-        let scanner_thread = ScannerThread::new(line_reader, thread_receiver);
+        let scanner_thread = ScannerThread::new(log_reader, thread_receiver);
         scanner_thread.run(sleep_duration);
 
-        let (sender, receiver) = channel();
+        let (sender, receiver) = sync_channel(1000);
         let live_subscription = Subscription::new(sender, Query::live());
 
         assert!(thread_sender.send(ScannerAction::HandleSubscription(live_subscription.clone())).is_ok());
@@ -444,4 +896,390 @@ mod tests {
 
         assert!(log.remove().is_ok());
     }
+
+    #[test]
+    fn test_scanner_thread_wake() {
+        let log = create_log();
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        let log_reader = log.open_log_reader().expect("Unable to open log reader");
+        let sleep_duration = Duration::from_millis(10);
+
+        assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+
+        let (thread_sender, thread_receiver) = channel();
+        let scanner_thread = ScannerThread::new(log_reader, thread_receiver);
+        scanner_thread.run(sleep_duration);
+
+        let (sender, receiver) = sync_channel(1000);
+        let live_subscription = Subscription::new(sender, Query::live());
+        let event_stream = EventStream::new(receiver);
+
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(live_subscription)).is_ok());
+        assert_eq!(event_stream.recv().map(|e| e.id), Ok(1));
+        thread::sleep(sleep_duration * 2);
+
+        assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+        assert_eq!(event_stream.recv_timeout(sleep_duration * 5), Err(EventStreamError::Empty));
+
+        assert!(thread_sender.send(ScannerAction::Wake).is_ok());
+        assert_eq!(event_stream.recv().map(|e| e.id), Ok(2));
+
+        assert!(thread_sender.send(ScannerAction::Stop).is_ok());
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_scanner_thread_tail_cache() {
+        let log = create_log();
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        let log_reader = log.open_log_reader().expect("Unable to open log reader");
+        let sleep_duration = Duration::from_millis(10);
+
+        for _ in 0..5 {
+            assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+        }
+        let mut tail_cache = TailCache::new(10, 3);
+        for id in 4..6 {
+            tail_cache.push(Event::new("cached", vec!["tag1"]).with_id(id));
+        }
+        let tail_cache = Arc::new(RwLock::new(tail_cache));
+
+        let (thread_sender, thread_receiver) = channel();
+        let scanner_thread = ScannerThread::new(log_reader, thread_receiver).with_tail_cache(tail_cache.clone());
+        scanner_thread.run(sleep_duration);
+
+        let (sender, tail_receiver) = sync_channel(1000);
+        let tail_subscription = Subscription::new(sender, Query::live().offset(3));
+        let (sender, receiver) = sync_channel(1000);
+        let subscription = Subscription::new(sender, Query::live());
+
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(tail_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(subscription)).is_ok());
+        thread::sleep(sleep_duration * 2);
+
+        let events = |receiver: &Receiver<EventStreamMessage>| -> Vec<(u64, String)> {
+            receiver.try_iter().filter_map(|message| match message {
+                EventStreamMessage::Event(e) => Some((e.id, String::from_utf8_lossy(&e.data).into_owned())),
+                _ => None
+            }).collect()
+        };
+        assert_eq!(events(&tail_receiver), vec![(4, "cached".to_owned()), (5, "cached".to_owned())]);
+        assert_eq!(events(&receiver), (1..6).map(|id| (id, "data".to_owned())).collect::<Vec<_>>());
+
+        tail_cache.write().unwrap().push(Event::new("cached", vec!["tag1"]).with_id(6));
+        assert!(thread_sender.send(ScannerAction::Wake).is_ok());
+        thread::sleep(sleep_duration * 2);
+
+        assert_eq!(events(&tail_receiver), vec![(6, "cached".to_owned())]);
+        assert_eq!(events(&receiver), vec![(6, "cached".to_owned())]);
+
+        assert!(thread_sender.send(ScannerAction::Stop).is_ok());
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_scanner_thread_backpressure() {
+        let log = create_log();
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        let log_reader = log.open_log_reader().expect("Unable to open log reader");
+        let sleep_duration = Duration::from_millis(10);
+
+        for _ in 0..10 {
+            assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+        }
+
+        let (thread_sender, thread_receiver) = channel();
+        let scanner_thread = ScannerThread::new(log_reader, thread_receiver);
+        scanner_thread.run(sleep_duration);
+
+        let (sender, slow_receiver) = sync_channel(3);
+        let slow_subscription = Subscription::new(sender, Query::live());
+        let (sender, fast_receiver) = sync_channel(1000);
+        let fast_subscription = Subscription::new(sender, Query::live());
+
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(slow_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(fast_subscription)).is_ok());
+        thread::sleep(sleep_duration * 5);
+
+        let event_ids = |receiver: &Receiver<EventStreamMessage>| -> Vec<u64> {
+            receiver.try_iter().filter_map(|message| match message {
+                EventStreamMessage::Event(e) => Some(e.id),
+                _ => None
+            }).collect()
+        };
+        assert_eq!(event_ids(&fast_receiver), (1..11).collect::<Vec<_>>());
+        assert_eq!(event_ids(&slow_receiver), vec![1, 2, 3]);
+        thread::sleep(sleep_duration * 5);
+
+        assert_eq!(event_ids(&slow_receiver), vec![4, 5, 6]);
+        thread::sleep(sleep_duration * 5);
+
+        assert_eq!(event_ids(&slow_receiver), vec![7, 8, 9]);
+        thread::sleep(sleep_duration * 5);
+
+        assert_eq!(event_ids(&slow_receiver), vec![10]);
+
+        assert!(thread_sender.send(ScannerAction::Stop).is_ok());
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_scanner_thread_cancellation() {
+        let log = create_log();
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        let log_reader = log.open_log_reader().expect("Unable to open log reader");
+        let sleep_duration = Duration::from_millis(10);
+
+        assert!(logger.log(Event::new("data", vec!["tag1"])).is_ok());
+
+        let (thread_sender, thread_receiver) = channel();
+        let scanner_thread = ScannerThread::new(log_reader, thread_receiver).with_waker(thread_sender.clone());
+        let handle = scanner_thread.run(sleep_duration);
+
+        let (sender, receiver) = sync_channel(1000);
+        let live_subscription = Subscription::new(sender, Query::live());
+        let event_stream = EventStream::new(receiver).with_cancellation_handle(live_subscription.cancellation_handle());
+
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(live_subscription)).is_ok());
+        assert_eq!(event_stream.recv().map(|e| e.id), Ok(1));
+
+        event_stream.cancel();
+        assert_eq!(event_stream.recv(), Err(EventStreamError::Closed));
+
+        assert!(thread_sender.send(ScannerAction::Stop).is_ok());
+
+        let scanner_thread = handle.join().expect("Unable to join scanner thread");
+        assert_eq!(scanner_thread.subscriptions.len(), 0);
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_scanner_thread_tag_index_subscriptions() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(SegmentSize::MaxEvents(20));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for id in 1..51 {
+            let tags = if id % 7 == 0 { vec!["tag1", "rare"] } else { vec!["tag1"] };
+            assert!(logger.log(Event::new("data", tags)).is_ok());
+        }
+        let mut tag_index = log.restore_tag_index(50).expect("Unable to restore tag index");
+        tag_index.insert(51, &["rare".to_owned()]);
+        let log_reader = log.open_log_reader().expect("Unable to open log reader");
+        let sleep_duration = Duration::from_millis(10);
+
+        let (thread_sender, thread_receiver) = channel();
+        let scanner_thread = ScannerThread::new(log_reader, thread_receiver).with_tag_index(Arc::new(RwLock::new(tag_index)));
+        scanner_thread.run(sleep_duration);
+
+        let (sender, receiver) = sync_channel(1000);
+        let rare_subscription = Subscription::new(sender, Query::current().offset(7).limit(3).by_tag("rare"));
+        let (sender, all_receiver) = sync_channel(1000);
+        let all_subscription = Subscription::new(sender, Query::current().offset(40).by_tag("tag1"));
+        let (sender, not_rare_receiver) = sync_channel(1000);
+        let not_rare_expression = TagExpression::tag("tag1").and(TagExpression::tag("rare").not());
+        let not_rare_subscription = Subscription::new(sender, Query::current().offset(40).by_tag_expression(not_rare_expression));
+        let (sender, unindexed_receiver) = sync_channel(1000);
+        let unindexed_expression = TagExpression::tag("rare").or(TagExpression::tag("tag1").not());
+        let unindexed_subscription = Subscription::new(sender, Query::current().offset(30).by_tag_expression(unindexed_expression));
+
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(rare_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(all_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(not_rare_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(unindexed_subscription)).is_ok());
+        thread::sleep(sleep_duration * 2);
+
+        let event_ids = |receiver: &Receiver<EventStreamMessage>| -> Vec<u64> {
+            receiver.try_iter().filter_map(|message| match message {
+                EventStreamMessage::Event(e) => Some(e.id),
+                _ => None
+            }).collect()
+        };
+        assert_eq!(event_ids(&receiver), vec![14, 21, 28]);
+        assert_eq!(event_ids(&all_receiver), (41..51).collect::<Vec<_>>());
+        assert_eq!(event_ids(&not_rare_receiver), vec![41, 43, 44, 45, 46, 47, 48, 50]);
+        assert_eq!(event_ids(&unindexed_receiver), vec![35, 42, 49]);
+
+        let (sender, receiver) = sync_channel(1000);
+        let pending_subscription = Subscription::new(sender, Query::live().offset(42).by_tag("rare"));
+
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(pending_subscription)).is_ok());
+        thread::sleep(sleep_duration * 2);
+
+        assert_eq!(event_ids(&receiver), vec![49]);
+
+        assert!(logger.log(Event::new("data", vec!["rare"])).is_ok());
+        assert!(thread_sender.send(ScannerAction::Wake).is_ok());
+        thread::sleep(sleep_duration * 2);
+
+        assert_eq!(event_ids(&receiver), vec![51]);
+
+        assert!(thread_sender.send(ScannerAction::Stop).is_ok());
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_scanner_thread_stream_index_subscriptions() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(SegmentSize::MaxEvents(20));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for id in 1..31 {
+            let stream_id = if id % 3 == 0 { "order-1" } else { "order-2" };
+            let version = if id % 3 == 0 { id / 3 } else { id - id / 3 };
+            assert!(logger.log(Event::new("data", vec!["tag1"]).with_stream(stream_id).with_stream_version(version)).is_ok());
+        }
+        let stream_index = log.restore_stream_index(30).expect("Unable to restore stream index");
+        let log_reader = log.open_log_reader().expect("Unable to open log reader");
+        let sleep_duration = Duration::from_millis(10);
+
+        let (thread_sender, thread_receiver) = channel();
+        let stream_index = Arc::new(RwLock::new(stream_index));
+        let scanner_thread = ScannerThread::new(log_reader, thread_receiver).with_stream_index(stream_index.clone());
+        scanner_thread.run(sleep_duration);
+
+        let (sender, receiver) = sync_channel(1000);
+        let stream_subscription = Subscription::new(sender, Query::current().by_stream("order-1"));
+        let (sender, version_receiver) = sync_channel(1000);
+        let version_subscription = Subscription::new(sender, Query::current().by_stream("order-1").from_stream_version(8));
+        let (sender, offset_receiver) = sync_channel(1000);
+        let offset_subscription = Subscription::new(sender, Query::current().offset(25).by_stream("order-2").from_stream_version(3));
+        let (sender, unknown_receiver) = sync_channel(1000);
+        let unknown_subscription = Subscription::new(sender, Query::current().by_stream("order-3"));
+
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(stream_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(version_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(offset_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(unknown_subscription)).is_ok());
+        thread::sleep(sleep_duration * 2);
+
+        let event_ids = |receiver: &Receiver<EventStreamMessage>| -> Vec<u64> {
+            receiver.try_iter().filter_map(|message| match message {
+                EventStreamMessage::Event(e) => Some(e.id),
+                _ => None
+            }).collect()
+        };
+        assert_eq!(event_ids(&receiver), (1..11).map(|version| version * 3).collect::<Vec<_>>());
+        assert_eq!(event_ids(&version_receiver), vec![24, 27, 30]);
+        assert_eq!(event_ids(&offset_receiver), vec![26, 28, 29]);
+        assert_eq!(event_ids(&unknown_receiver), Vec::<u64>::new());
+
+        let (sender, receiver) = sync_channel(1000);
+        let pending_subscription = Subscription::new(sender, Query::live().by_stream("order-1").from_stream_version(10));
+
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(pending_subscription)).is_ok());
+        thread::sleep(sleep_duration * 2);
+
+        assert_eq!(event_ids(&receiver), vec![30]);
+
+        assert!(logger.log(Event::new("data", vec!["tag1"]).with_stream("order-1").with_stream_version(11)).is_ok());
+        stream_index.write().unwrap().insert(31, Some("order-1"), 11);
+        assert!(thread_sender.send(ScannerAction::Wake).is_ok());
+        thread::sleep(sleep_duration * 2);
+
+        assert_eq!(event_ids(&receiver), vec![31]);
+
+        assert!(thread_sender.send(ScannerAction::Stop).is_ok());
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_scanner_thread_timestamp_index_subscriptions() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10);
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for id in 1..101 {
+            let tags = if id % 2 == 0 { vec!["tag1", "even"] } else { vec!["tag1"] };
+            assert!(logger.log(Event::new("data", tags).with_timestamp(id * 1000)).is_ok());
+        }
+        let tag_index = log.restore_tag_index(100).expect("Unable to restore tag index");
+        let timestamp_index = log.restore_timestamp_index(100).expect("Unable to restore timestamp index");
+        let log_reader = log.open_log_reader().expect("Unable to open log reader");
+        let sleep_duration = Duration::from_millis(10);
+
+        let (thread_sender, thread_receiver) = channel();
+        let scanner_thread = ScannerThread::new(log_reader, thread_receiver).with_tag_index(Arc::new(RwLock::new(tag_index)))
+                                                                            .with_timestamp_index(Arc::new(RwLock::new(timestamp_index)));
+        scanner_thread.run(sleep_duration);
+
+        let (sender, receiver) = sync_channel(1000);
+        let time_range_subscription = Subscription::new(sender, Query::current().from_timestamp(35000).to_timestamp(42000));
+        let (sender, tag_receiver) = sync_channel(1000);
+        let tag_time_range_subscription = Subscription::new(sender, Query::current().by_tag("even").from_timestamp(35000).to_timestamp(42000));
+        let (sender, empty_receiver) = sync_channel(1000);
+        let empty_subscription = Subscription::new(sender, Query::current().from_timestamp(200000));
+
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(time_range_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(tag_time_range_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(empty_subscription)).is_ok());
+        thread::sleep(sleep_duration * 2);
+
+        let event_ids = |receiver: &Receiver<EventStreamMessage>| -> Vec<u64> {
+            receiver.try_iter().filter_map(|message| match message {
+                EventStreamMessage::Event(e) => Some(e.id),
+                _ => None
+            }).collect()
+        };
+        assert_eq!(event_ids(&receiver), (35..43).collect::<Vec<_>>());
+        assert_eq!(event_ids(&tag_receiver), vec![36, 38, 40, 42]);
+        assert_eq!(event_ids(&empty_receiver), Vec::<u64>::new());
+
+        assert!(thread_sender.send(ScannerAction::Stop).is_ok());
+
+        assert!(log.remove().is_ok());
+    }
+
+    #[test]
+    fn test_scanner_thread_reverse_subscriptions() {
+        let ref collection_name = random_collection_name();
+        let log = Log::new("", collection_name, 10).with_segment_size(SegmentSize::MaxEvents(30));
+        let mut logger = Logger::new(log.clone()).expect("Unable to create logger");
+        for id in 1..101 {
+            let tags = if id % 2 == 0 { vec!["tag1", "even"] } else { vec!["tag1"] };
+            assert!(logger.log(Event::new("data", tags).with_timestamp(id * 1000)).is_ok());
+        }
+        let log_reader = log.open_log_reader_with_indexes(log.restore_indexes().expect("Unable to restore indexes"))
+                            .expect("Unable to open log reader");
+        let timestamp_index = log.restore_timestamp_index(100).expect("Unable to restore timestamp index");
+        let sleep_duration = Duration::from_millis(10);
+
+        let (thread_sender, thread_receiver) = channel();
+        let scanner_thread = ScannerThread::new(log_reader, thread_receiver).with_tag_index(Arc::new(RwLock::new(TagIndex::new())))
+                                                                             .with_timestamp_index(Arc::new(RwLock::new(timestamp_index)));
+        scanner_thread.run(sleep_duration);
+
+        let (sender, last_receiver) = sync_channel(1000);
+        let last_subscription = Subscription::new(sender, Query::last(15));
+        let (sender, tag_receiver) = sync_channel(1000);
+        let tag_subscription = Subscription::new(sender, Query::current().offset(84).reverse().by_tag("even"));
+        let (sender, time_range_receiver) = sync_channel(1000);
+        let time_range_subscription = Subscription::new(sender, Query::last(3).from_timestamp(20000).to_timestamp(45000));
+        let (sender, all_receiver) = sync_channel(1000);
+        let all_subscription = Subscription::new(sender, Query::current().reverse());
+
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(last_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(tag_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(time_range_subscription)).is_ok());
+        assert!(thread_sender.send(ScannerAction::HandleSubscription(all_subscription)).is_ok());
+        thread::sleep(sleep_duration * 2);
+
+        let event_ids = |receiver: &Receiver<EventStreamMessage>| -> Vec<u64> {
+            receiver.try_iter().filter_map(|message| match message {
+                EventStreamMessage::Event(e) => Some(e.id),
+                _ => None
+            }).collect()
+        };
+        assert_eq!(event_ids(&last_receiver), (86..101).rev().collect::<Vec<_>>());
+        assert_eq!(event_ids(&tag_receiver), vec![100, 98, 96, 94, 92, 90, 88, 86]);
+        assert_eq!(event_ids(&time_range_receiver), vec![45, 44, 43]);
+        assert_eq!(event_ids(&all_receiver), (1..101).rev().collect::<Vec<_>>());
+
+        assert!(thread_sender.send(ScannerAction::Stop).is_ok());
+
+        assert!(log.remove().is_ok());
+    }
 }
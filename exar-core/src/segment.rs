@@ -0,0 +1,876 @@
+use super::*;
+
+#[cfg(feature = "rustc-serialization")] use rustc_serialize::{Encoder, Encodable, Decoder, Decodable};
+#[cfg(feature = "serde-serialization")] use serde::{Serialize, Serializer, Deserialize, Deserializer};
+#[cfg(feature = "serde-serialization")] use serde::de::{Error, Visitor};
+
+use std::fmt;
+use std::fs::*;
+use std::io;
+use std::io::{BufReader, BufWriter, BufRead, ErrorKind, Read, Seek, SeekFrom, Write};
+
+/// Exar DB's log segment file abstraction.
+///
+/// A log is split into segments, each one stored in a file named after the `id` of its first event
+/// (i.e. `<name>.<first_id>.log`) and indexed separately (i.e. `<name>.<first_id>.index.log`).
+///
+/// It offers helper methods to manage a segment file and its index.
+/// It also allows to open readers and writers for the segment file.
+///
+/// # Examples
+/// ```no_run
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let segment = Segment::new("/path/to/logs", "test", 1, 100);
+///
+/// let exists = segment.ensure_exists().unwrap();
+/// let recovery_report = segment.recover().unwrap();
+/// let writer = segment.open_writer().unwrap();
+/// let index = segment.compute_index().unwrap();
+/// segment.remove().unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    path: String,
+    name: String,
+    first_id: u64,
+    index_granularity: u64
+}
+
+impl Segment {
+    /// Returns a new `Segment` of the log with the given path/name, starting at the given event `id`
+    /// and using the given index granularity.
+    pub fn new(path: &str, name: &str, first_id: u64, index_granularity: u64) -> Segment {
+        Segment {
+            path: path.to_owned(),
+            name: name.to_owned(),
+            first_id: first_id,
+            index_granularity: index_granularity
+        }
+    }
+
+    /// Returns the `id` of the first event stored in the segment.
+    pub fn first_id(&self) -> u64 {
+        self.first_id
+    }
+
+    /// Ensure the underlying segment file exists and creates it if it does not exist,
+    /// it returns a `DatabaseError` if a failure occurs while creating the segment file.
+    pub fn ensure_exists(&self) -> Result<(), DatabaseError> {
+        match OpenOptions::new().create(true).write(true).open(self.get_path()) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    /// Returns wether the underlying segment file exists.
+    pub fn exists(&self) -> bool {
+        metadata(self.get_path()).map(|metadata| metadata.is_file()).unwrap_or(false)
+    }
+
+    /// Returns the size in bytes of the underlying segment file
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn size(&self) -> Result<u64, DatabaseError> {
+        match metadata(self.get_path()) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    /// Returns a buffered reader for the underlying segment file
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn open_reader(&self) -> Result<BufReader<File>, DatabaseError> {
+        match OpenOptions::new().read(true).open(self.get_path()) {
+            Ok(file) => Ok(BufReader::new(file)),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    /// Returns a buffered writer for the underlying segment file or a `DatabaseError` if a failure occurs.
+    pub fn open_writer(&self) -> Result<BufWriter<File>, DatabaseError> {
+        match OpenOptions::new().create(true).write(true).append(true).open(self.get_path()) {
+            Ok(file) => Ok(BufWriter::new(file)),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    /// Removes the underlying segment file and its index or a `DatabaseError` if a failure occurs.
+    pub fn remove(&self) -> Result<(), DatabaseError> {
+        match remove_file(self.get_path()) {
+            Ok(()) => {
+                let _ = remove_file(self.get_compacted_path());
+                match remove_file(self.get_index_path()) {
+                    Ok(()) | Err(_) => Ok(())
+                }
+            },
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    /// Computes and returns the `LogIndex` for the underlying segment file
+    /// or a `DatabaseError` if a failure occurs.
+    pub fn compute_index(&self) -> Result<LogIndex, DatabaseError> {
+        self.ensure_exists().and_then(|_| {
+            self.open_reader().and_then(|mut reader| {
+                let mut index = LogIndex::new(self.index_granularity);
+                index.compute(&mut reader).and_then(|_| Ok(index))
+            })
+        })
+    }
+
+    /// Returns the last event stored in the segment, ignoring an incomplete record or batch of records
+    /// at the end of the segment, or a `DatabaseError` if a failure occurs.
+    pub fn last_event(&self) -> Result<Option<Event>, DatabaseError> {
+        self.restore_index().and_then(|index| {
+            self.open_reader().and_then(|mut reader| {
+                let (_, byte_offset) = index.closest_entry(index.last_id().saturating_sub(1));
+                if let Err(err) = reader.seek(SeekFrom::Start(byte_offset)) {
+                    return Err(DatabaseError::from_io_error(err))
+                }
+                let mut last_event = None;
+                loop {
+                    match reader.read_log_record() {
+                        Ok(Some(record)) => {
+                            let (event, flags) = try!(Event::from_log_record_with_flags(&record).map_err(DatabaseError::ParseError));
+                            if flags & BATCH_CONTINUATION_FLAG == 0 {
+                                last_event = Some(event);
+                            }
+                        },
+                        Ok(None) => return Ok(last_event),
+                        Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(last_event),
+                        Err(err) => return Err(DatabaseError::from_io_error(err))
+                    }
+                }
+            })
+        })
+    }
+
+    /// Writes a compacted copy of the segment file, retaining only the events matching the given predicate,
+    /// and returns the number of events removed or a `DatabaseError` if a failure occurs.
+    ///
+    /// Retained events keep their `id`, timestamp and batch. The copy is only kept if at least one event
+    /// has been removed, and it replaces the segment file once `replace_with_compacted` is called.
+    pub fn write_compacted<F: Fn(&Event) -> bool>(&self, retain: F) -> Result<u64, DatabaseError> {
+        match self.copy_retained_events(retain) {
+            Ok(removed_events) if removed_events > 0 => Ok(removed_events),
+            result => {
+                let _ = remove_file(self.get_compacted_path());
+                result
+            }
+        }
+    }
+
+    /// Replaces the segment file with its compacted copy and returns the recomputed segment index,
+    /// which is also persisted, or a `DatabaseError` if a failure occurs.
+    ///
+    /// The directory containing the segment is synced after the rename, so that the replacement survives a crash.
+    pub fn replace_with_compacted(&self) -> Result<LogIndex, DatabaseError> {
+        match rename(self.get_compacted_path(), self.get_path()).and_then(|_| sync_directory(&self.path)) {
+            Ok(()) => self.compute_index().and_then(|index| {
+                self.persist_index(&index).and_then(|_| Ok(index))
+            }),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    /// Removes the compacted copy of the segment file or a `DatabaseError` if a failure occurs.
+    pub fn remove_compacted(&self) -> Result<(), DatabaseError> {
+        match remove_file(self.get_compacted_path()) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    fn copy_retained_events<F: Fn(&Event) -> bool>(&self, retain: F) -> Result<u64, DatabaseError> {
+        let mut reader = try!(self.open_reader());
+        let mut writer = match OpenOptions::new().create(true).write(true).truncate(true).open(self.get_compacted_path()) {
+            Ok(file) => BufWriter::new(file),
+            Err(err) => return Err(DatabaseError::from_io_error(err))
+        };
+        let mut batch = vec![];
+        let mut removed_events = 0;
+        loop {
+            match reader.read_log_record() {
+                Ok(Some(record)) => {
+                    let (event, flags) = try!(Event::from_log_record_with_flags(&record).map_err(DatabaseError::ParseError));
+                    if retain(&event) {
+                        batch.push(event);
+                    } else {
+                        removed_events += 1;
+                    }
+                    if flags & BATCH_CONTINUATION_FLAG == 0 {
+                        let batch_size = batch.len();
+                        for (i, event) in batch.drain(..).enumerate() {
+                            let flags = if i + 1 < batch_size { BATCH_CONTINUATION_FLAG } else { 0 };
+                            if let Err(err) = writer.write_all(&event.to_log_record_with_flags(flags)) {
+                                return Err(DatabaseError::from_io_error(err))
+                            }
+                        }
+                    }
+                },
+                Ok(None) => break,
+                Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(DatabaseError::from_io_error(err))
+            }
+        }
+        match writer.flush().and_then(|_| writer.get_ref().sync_all()) {
+            Ok(()) => Ok(removed_events),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    /// Returns a buffered reader for the segment index file or a `DatabaseError` if a failure occurs.
+    pub fn open_index_reader(&self) -> Result<BufReader<File>, DatabaseError> {
+        match OpenOptions::new().read(true).open(self.get_index_path()) {
+            Ok(file) => Ok(BufReader::new(file)),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    /// Returns a buffered writer for the segment index file or a `DatabaseError` if a failure occurs.
+    pub fn open_index_writer(&self) -> Result<BufWriter<File>, DatabaseError> {
+        match OpenOptions::new().create(true).write(true).truncate(true).open(self.get_index_path()) {
+            Ok(file) => Ok(BufWriter::new(file)),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        }
+    }
+
+    /// Restores and returns the `LogIndex` from the segment index file
+    /// or a `DatabaseError` if a failure occurs.
+    ///
+    /// If the segment index file does not exist it will be computed and persisted.
+    pub fn restore_index(&self) -> Result<LogIndex, DatabaseError> {
+        match self.open_index_reader() {
+            Ok(reader) => {
+                let mut index = LogIndex::new(self.index_granularity);
+                for line in reader.lines() {
+                    match line {
+                        Ok(line) => match parse_index_entry(&line) {
+                            Some((event_id, byte_offset)) => {
+                                index.insert(event_id, byte_offset);
+                            },
+                            None => warn!("Unable to parse log index entry: {}", line)
+                        },
+                        Err(err) => return Err(DatabaseError::from_io_error(err))
+                    }
+                }
+                self.open_reader().and_then(|mut reader| {
+                    index.compute(&mut reader).and_then(|_| Ok(index))
+                })
+            },
+            Err(_) => self.compute_index().and_then(|index| {
+                self.persist_index(&index).and_then(|_| {
+                    Ok(index)
+                })
+            })
+        }
+    }
+
+    /// Persists the given `LogIndex` to the segment index file
+    /// or returns a `DatabaseError` if a failure occurs.
+    pub fn persist_index(&self, index: &LogIndex) -> Result<(), DatabaseError> {
+        self.open_index_writer().and_then(|mut writer| {
+            for (event_id, byte_offset) in index.get_ref() {
+                match writer.write_line(&format!("{} {}", event_id, byte_offset)) {
+                    Ok(_) => (),
+                    Err(err) => return Err(DatabaseError::from_io_error(err))
+                };
+            }
+            Ok(())
+        })
+    }
+
+    /// Recovers the segment from an unclean shutdown and returns a `RecoveryReport`
+    /// describing what has been repaired, or a `DatabaseError` if a failure occurs.
+    ///
+    /// Incomplete or corrupted records at the end of the segment are truncated, as well as the records
+    /// of an incomplete batch, and the segment index file is reconciled with the actual content of the segment.
+    ///
+    /// A corrupted record followed by other records is not truncated, since it would discard the events following it,
+    /// a `DatabaseError::IoError` of kind `InvalidData` describing the corruption is returned instead.
+    /// This includes a corrupted record header declaring a length that runs past the end of the segment,
+    /// which is only treated as a torn write if no complete record can be found in the remaining bytes.
+    pub fn recover(&self) -> Result<RecoveryReport, DatabaseError> {
+        try!(self.ensure_exists());
+        let mut reader = try!(self.open_reader());
+        let byte_count = try!(self.size());
+
+        let mut index_entries = vec![];
+        let mut discarded_index_entries = 0;
+        if let Ok(index_reader) = self.open_index_reader() {
+            for line in index_reader.lines() {
+                match line.ok().as_ref().and_then(|line| parse_index_entry(line)) {
+                    Some((event_id, byte_offset)) => {
+                        let is_ordered = match index_entries.last() {
+                            Some(&(last_event_id, last_byte_offset)) => event_id > last_event_id && byte_offset > last_byte_offset,
+                            None => true
+                        };
+                        if is_ordered && byte_offset <= byte_count {
+                            index_entries.push((event_id, byte_offset));
+                        } else {
+                            discarded_index_entries += 1;
+                        }
+                    },
+                    None => discarded_index_entries += 1
+                }
+            }
+        }
+        while let Some(&(event_id, byte_offset)) = index_entries.last() {
+            if try!(is_valid_index_entry(&mut reader, event_id, byte_offset, byte_count)) {
+                break;
+            }
+            index_entries.pop();
+            discarded_index_entries += 1;
+        }
+
+        let (mut last_id, mut valid_byte_count) = index_entries.last().cloned().unwrap_or((self.first_id.saturating_sub(1), 0));
+        if let Err(err) = reader.seek(SeekFrom::Start(valid_byte_count)) {
+            return Err(DatabaseError::from_io_error(err))
+        }
+        let (mut last_read_id, mut byte_offset) = (last_id, valid_byte_count);
+        loop {
+            match reader.read_log_record() {
+                Ok(Some(record)) => {
+                    let corruption = match Event::from_log_record_with_flags(&record) {
+                        Ok((ref event, flags)) if event.id > last_read_id => {
+                            last_read_id = event.id;
+                            byte_offset += record.len() as u64;
+                            if flags & BATCH_CONTINUATION_FLAG == 0 {
+                                last_id = last_read_id;
+                                valid_byte_count = byte_offset;
+                            }
+                            continue
+                        },
+                        Ok((ref event, _)) => format!("event id {} does not follow event id {}", event.id, last_read_id),
+                        Err(err) => format!("{}", err)
+                    };
+                    if byte_offset + (record.len() as u64) < byte_count {
+                        return Err(DatabaseError::IoError(ErrorKind::InvalidData, format!(
+                            "corrupted record at byte {} of segment '{}' followed by other records: {}", byte_offset, self.get_path(), corruption
+                        )))
+                    }
+                    break
+                },
+                Ok(None) => break,
+                Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => {
+                    let mut remaining_bytes = vec![];
+                    if let Err(err) = reader.seek(SeekFrom::Start(byte_offset)).and_then(|_| reader.read_to_end(&mut remaining_bytes)) {
+                        return Err(DatabaseError::from_io_error(err))
+                    }
+                    if contains_event_record(&remaining_bytes[1..]) {
+                        return Err(DatabaseError::IoError(ErrorKind::InvalidData, format!(
+                            "corrupted record at byte {} of segment '{}' followed by other records: {}", byte_offset, self.get_path(), err
+                        )))
+                    }
+                    break
+                },
+                Err(err) => return Err(DatabaseError::from_io_error(err))
+            }
+        }
+
+        let truncated_bytes = byte_count - valid_byte_count;
+        if truncated_bytes > 0 {
+            let truncation = OpenOptions::new().write(true).open(self.get_path()).and_then(|file| {
+                file.set_len(valid_byte_count).and_then(|_| file.sync_all())
+            });
+            if let Err(err) = truncation {
+                return Err(DatabaseError::from_io_error(err))
+            }
+        }
+
+        let mut index = LogIndex::new(self.index_granularity);
+        for (event_id, byte_offset) in index_entries {
+            index.insert(event_id, byte_offset);
+        }
+        try!(index.compute(&mut reader));
+        try!(self.persist_index(&index));
+
+        Ok(RecoveryReport {
+            last_id: last_id,
+            truncated_bytes: truncated_bytes,
+            discarded_index_entries: discarded_index_entries
+        })
+    }
+
+    /// Returns the path to the segment file.
+    pub fn get_path(&self) -> String {
+        if self.path.is_empty() {
+            format!("{}.{}.log", self.name, self.first_id)
+        } else {
+            format!("{}/{}.{}.log", self.path, self.name, self.first_id)
+        }
+    }
+
+    /// Returns the path to the segment index file.
+    pub fn get_index_path(&self) -> String {
+        if self.path.is_empty() {
+            format!("{}.{}.index.log", self.name, self.first_id)
+        } else {
+            format!("{}/{}.{}.index.log", self.path, self.name, self.first_id)
+        }
+    }
+
+    /// Returns the path to the compacted copy of the segment file.
+    pub fn get_compacted_path(&self) -> String {
+        format!("{}.compacted", self.get_path())
+    }
+
+    /// Returns the index granularity for the segment file.
+    pub fn get_index_granularity(&self) -> u64 {
+        self.index_granularity
+    }
+}
+
+/// A list specifying categories of segment size limit,
+/// used to decide when a log rolls over to a new segment.
+///
+/// A batch of events is never split across segments, so a segment can exceed its limit
+/// if a single batch does not fit into an empty segment.
+///
+/// It is encoded as a string, i.e. `Unlimited`, `MaxBytes(1073741824)` or `MaxEvents(1000000)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SegmentSize {
+    /// The log is stored in a single segment.
+    Unlimited,
+    /// A new segment is started when the current one would exceed the given size in bytes.
+    MaxBytes(u64),
+    /// A new segment is started when the current one would exceed the given number of events.
+    MaxEvents(u64)
+}
+
+impl SegmentSize {
+    /// Parses a segment size limit from its string representation, returning `None` if it is invalid.
+    pub fn parse(s: &str) -> Option<SegmentSize> {
+        match s {
+            "Unlimited" => Some(SegmentSize::Unlimited),
+            _ => {
+                let parse_argument = |prefix: &str| {
+                    if s.starts_with(prefix) && s.ends_with(')') {
+                        s[prefix.len()..s.len() - 1].parse::<u64>().ok().and_then(|n| if n > 0 { Some(n) } else { None })
+                    } else {
+                        None
+                    }
+                };
+                parse_argument("MaxBytes(").map(SegmentSize::MaxBytes).or_else(|| {
+                    parse_argument("MaxEvents(").map(SegmentSize::MaxEvents)
+                })
+            }
+        }
+    }
+
+    /// Returns wether a segment containing the given number of bytes and events would exceed the limit
+    /// after appending the given number of bytes and events.
+    pub fn is_exceeded_by(&self, segment_bytes: u64, segment_events: u64, bytes: u64, events: u64) -> bool {
+        match *self {
+            SegmentSize::Unlimited => false,
+            SegmentSize::MaxBytes(max_bytes) => segment_bytes + bytes > max_bytes,
+            SegmentSize::MaxEvents(max_events) => segment_events + events > max_events
+        }
+    }
+}
+
+impl fmt::Display for SegmentSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SegmentSize::Unlimited => write!(f, "Unlimited"),
+            SegmentSize::MaxBytes(max_bytes) => write!(f, "MaxBytes({})", max_bytes),
+            SegmentSize::MaxEvents(max_events) => write!(f, "MaxEvents({})", max_events)
+        }
+    }
+}
+
+#[cfg(feature = "rustc-serialization")]
+impl Encodable for SegmentSize {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_str(&self.to_string())
+    }
+}
+
+#[cfg(feature = "rustc-serialization")]
+impl Decodable for SegmentSize {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        let s = try!(d.read_str());
+        match SegmentSize::parse(&s) {
+            Some(segment_size) => Ok(segment_size),
+            None => Err(d.error(&format!("invalid segment size: {}", s)))
+        }
+    }
+}
+
+#[cfg(feature = "serde-serialization")]
+impl Serialize for SegmentSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+#[cfg(feature = "serde-serialization")]
+impl Deserialize for SegmentSize {
+    fn deserialize<D: Deserializer>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(SegmentSizeVisitor)
+    }
+}
+
+#[cfg(feature = "serde-serialization")]
+struct SegmentSizeVisitor;
+
+#[cfg(feature = "serde-serialization")]
+impl Visitor for SegmentSizeVisitor {
+    type Value = SegmentSize;
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("Unlimited, MaxBytes(n) or MaxEvents(n)")
+    }
+    fn visit_str<E: Error>(self, s: &str) -> Result<SegmentSize, E> {
+        SegmentSize::parse(s).ok_or_else(|| E::custom(format!("invalid segment size: {}", s)))
+    }
+}
+
+impl Default for SegmentSize {
+    fn default() -> Self {
+        SegmentSize::MaxBytes(1073741824)
+    }
+}
+
+#[cfg(unix)]
+fn sync_directory(path: &str) -> io::Result<()> {
+    File::open(if path.is_empty() { "." } else { path }).and_then(|directory| directory.sync_all())
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_: &str) -> io::Result<()> {
+    Ok(())
+}
+
+fn parse_index_entry(line: &str) -> Option<(u64, u64)> {
+    let parts: Vec<_> = line.split(' ').collect();
+    match (parts.get(0).and_then(|p| p.parse().ok()), parts.get(1).and_then(|p| p.parse().ok())) {
+        (Some(event_id), Some(byte_offset)) if parts.len() == 2 => Some((event_id, byte_offset)),
+        _ => None
+    }
+}
+
+fn contains_event_record(bytes: &[u8]) -> bool {
+    (0..bytes.len()).any(|start| {
+        let record = &bytes[start..];
+        if record.len() < LOG_RECORD_HEADER_SIZE {
+            return false
+        }
+        let length = record.iter().take(4).enumerate().fold(0, |value, (i, &byte)| value | (byte as usize) << (8 * i));
+        record.len() - LOG_RECORD_HEADER_SIZE >= length && Event::from_log_record(&record[..LOG_RECORD_HEADER_SIZE + length]).is_ok()
+    })
+}
+
+fn is_valid_index_entry(reader: &mut BufReader<File>, event_id: u64, byte_offset: u64, byte_count: u64) -> Result<bool, DatabaseError> {
+    if byte_offset == byte_count {
+        return Ok(true)
+    }
+    match reader.seek(SeekFrom::Start(byte_offset)) {
+        Ok(_) => match reader.read_log_record() {
+            Ok(Some(record)) => Ok(Event::from_log_record(&record).map(|event| event.id > event_id).unwrap_or(false)),
+            Ok(None) => Ok(true),
+            Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => Ok(true),
+            Err(err) => Err(DatabaseError::from_io_error(err))
+        },
+        Err(err) => Err(DatabaseError::from_io_error(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use exar_testkit::*;
+
+    #[cfg(feature = "rustc-serialization")]
+    use rustc_serialize::json;
+
+    #[cfg(feature = "serde-serialization")]
+    extern crate serde_json;
+
+    use std::fs::OpenOptions;
+    use std::io::{ErrorKind, Seek, SeekFrom, Write};
+
+    fn create_segment_with_events(first_id: u64, index_granularity: u64, nr_of_events: u64) -> Segment {
+        let ref collection_name = random_collection_name();
+        let segment = Segment::new("", collection_name, first_id, index_granularity);
+        let mut writer = segment.open_writer().expect("Unable to open writer");
+        for id in first_id..(first_id + nr_of_events) {
+            let event = Event::new("data", vec!["tag1"]).with_id(id).with_timestamp(1234567890);
+            assert!(writer.write_log_record(&event.to_log_record()).is_ok());
+        }
+        let index = segment.compute_index().expect("Unable to compute index");
+        assert!(segment.persist_index(&index).is_ok());
+        segment
+    }
+
+    fn truncate_segment(segment: &Segment, byte_count: u64) {
+        let file = OpenOptions::new().write(true).open(segment.get_path()).expect("Unable to open segment file");
+        assert!(file.set_len(byte_count).is_ok());
+    }
+
+    #[test]
+    fn test_get_path() {
+        let ref collection_name = random_collection_name();
+        let segment = Segment::new("", collection_name, 1, 100);
+        assert_eq!(segment.get_path(), format!("{}.1.log", collection_name));
+        assert_eq!(segment.get_index_path(), format!("{}.1.index.log", collection_name));
+        assert_eq!(segment.get_compacted_path(), format!("{}.1.log.compacted", collection_name));
+        let segment = Segment::new("path/to/log", collection_name, 1001, 100);
+        assert_eq!(segment.get_path(), format!("path/to/log/{}.1001.log", collection_name));
+        assert_eq!(segment.get_index_path(), format!("path/to/log/{}.1001.index.log", collection_name));
+    }
+
+    #[test]
+    fn test_segment_and_index_management() {
+        let ref collection_name = random_collection_name();
+        let segment = Segment::new("", collection_name, 1, 10);
+
+        assert!(!segment.exists());
+        assert!(segment.ensure_exists().is_ok());
+        assert!(segment.exists());
+        assert!(segment.open_writer().is_ok());
+        assert!(segment.open_reader().is_ok());
+
+        let index = segment.compute_index().expect("Unable to compute index");
+        assert_eq!(index.last_id(), 0);
+
+        let mut writer = segment.open_writer().expect("Unable to open writer");
+        for id in 1..101 {
+            let event = Event::new("data", vec!["tag1"]).with_id(id).with_timestamp(1234567890);
+            assert!(writer.write_log_record(&event.to_log_record()).is_ok());
+        }
+
+        let index = segment.compute_index().expect("Unable to compute index");
+        assert_eq!(index.last_id(), 100);
+        assert_eq!(index.get_ref().len(), 10);
+        assert_eq!(segment.size(), Ok(index.byte_count()));
+
+        assert!(segment.open_index_reader().is_err());
+
+        let restored_index = segment.restore_index().expect("Unable to compute, persist and restore index");
+        assert_eq!(restored_index, index);
+
+        assert!(segment.open_index_reader().is_ok());
+
+        let restored_index = segment.restore_index().expect("Unable to restore persisted index");
+        assert_eq!(restored_index, index);
+
+        assert!(segment.persist_index(&LogIndex::new(10)).is_ok());
+
+        let restored_index = segment.restore_index().expect("Unable to restore persisted index");
+        assert_eq!(restored_index, index);
+
+        assert!(segment.remove().is_ok());
+
+        assert!(!segment.exists());
+        assert!(segment.open_reader().is_err());
+    }
+
+    #[test]
+    fn test_recovery_of_clean_segment() {
+        let segment = create_segment_with_events(1, 10, 25);
+
+        let report = segment.recover().expect("Unable to recover segment");
+        assert_eq!(report, RecoveryReport { last_id: 25, truncated_bytes: 0, discarded_index_entries: 0 });
+        assert!(!report.is_repaired());
+
+        assert!(segment.remove().is_ok());
+
+        let segment = Segment::new("", &random_collection_name(), 101, 10);
+
+        let report = segment.recover().expect("Unable to recover segment");
+        assert_eq!(report, RecoveryReport { last_id: 100, truncated_bytes: 0, discarded_index_entries: 0 });
+
+        assert!(segment.remove().is_ok());
+    }
+
+    #[test]
+    fn test_recovery_from_torn_writes() {
+        let event = Event::new("data", vec!["tag1"]).with_id(3).with_timestamp(1234567890);
+        let record_size = event.to_log_record().len() as u64;
+
+        for remaining_bytes in 0..record_size {
+            let segment = create_segment_with_events(1, 1, 3);
+            truncate_segment(&segment, 2 * record_size + remaining_bytes);
+
+            let report = segment.recover().expect("Unable to recover segment");
+            assert_eq!(report, RecoveryReport { last_id: 2, truncated_bytes: remaining_bytes, discarded_index_entries: 1 });
+            assert!(report.is_repaired());
+            assert_eq!(segment.size(), Ok(2 * record_size));
+
+            let index = segment.restore_index().expect("Unable to restore index");
+            assert_eq!(index.last_id(), 2);
+            assert_eq!(index.byte_count(), 2 * record_size);
+
+            assert!(segment.remove().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_recovery_from_incomplete_batches() {
+        let segment = create_segment_with_events(1, 1, 2);
+        let byte_count = segment.size().expect("Unable to read segment size");
+
+        let mut file = OpenOptions::new().append(true).open(segment.get_path()).expect("Unable to open segment file");
+        let mut incomplete_batch_size = 0;
+        for id in 3..5 {
+            let record = Event::new("data", vec!["tag1"]).with_id(id).with_timestamp(1234567890)
+                                                          .to_log_record_with_flags(BATCH_CONTINUATION_FLAG);
+            assert!(file.write_all(&record).is_ok());
+            incomplete_batch_size += record.len() as u64;
+        }
+
+        let report = segment.recover().expect("Unable to recover segment");
+        assert_eq!(report, RecoveryReport { last_id: 2, truncated_bytes: incomplete_batch_size, discarded_index_entries: 0 });
+        assert_eq!(segment.size(), Ok(byte_count));
+
+        assert!(!segment.recover().expect("Unable to recover segment").is_repaired());
+
+        assert!(segment.remove().is_ok());
+    }
+
+    #[test]
+    fn test_recovery_from_corrupted_records_and_index() {
+        let segment = create_segment_with_events(1, 5, 20);
+        let byte_count = segment.size().expect("Unable to read segment size");
+
+        let mut file = OpenOptions::new().append(true).open(segment.get_path()).expect("Unable to open segment file");
+        assert!(file.write_all(b"\x04\x00\x00\x00\x00\x00\x00\x00data").is_ok());
+
+        let mut index_file = OpenOptions::new().append(true).open(segment.get_index_path()).expect("Unable to open index file");
+        assert!(index_file.write_all(format!("invalid entry\n25 {}\n", byte_count + 100).as_bytes()).is_ok());
+
+        // the last valid entry is discarded as well, since it points to the corrupted record
+        let report = segment.recover().expect("Unable to recover segment");
+        assert_eq!(report, RecoveryReport { last_id: 20, truncated_bytes: 12, discarded_index_entries: 3 });
+        assert_eq!(segment.size(), Ok(byte_count));
+
+        let index = segment.restore_index().expect("Unable to restore index");
+        assert_eq!(index, segment.compute_index().expect("Unable to compute index"));
+        assert_eq!(index.get_ref().len(), 4);
+
+        assert!(!segment.recover().expect("Unable to recover segment").is_repaired());
+
+        assert!(segment.remove().is_ok());
+    }
+
+    #[test]
+    fn test_recovery_from_corrupted_records_followed_by_valid_records() {
+        let segment = create_segment_with_events(1, 10, 5);
+        let byte_count = segment.size().expect("Unable to read segment size");
+        let record_size = byte_count / 5;
+
+        let mut file = OpenOptions::new().write(true).open(segment.get_path()).expect("Unable to open segment file");
+        assert!(file.seek(SeekFrom::Start(2 * record_size - 1)).is_ok());
+        assert!(file.write_all(b"x").is_ok());
+
+        match segment.recover() {
+            Err(DatabaseError::IoError(ErrorKind::InvalidData, description)) => {
+                assert!(description.contains(&format!("corrupted record at byte {}", record_size)));
+            },
+            result => panic!("Unexpected recovery result: {:?}", result)
+        }
+        assert_eq!(segment.size(), Ok(byte_count));
+
+        assert!(segment.remove().is_ok());
+    }
+
+    #[test]
+    fn test_recovery_from_corrupted_record_headers_followed_by_valid_records() {
+        let segment = create_segment_with_events(1, 10, 5);
+        let byte_count = segment.size().expect("Unable to read segment size");
+        let record_size = byte_count / 5;
+
+        let mut file = OpenOptions::new().write(true).open(segment.get_path()).expect("Unable to open segment file");
+        assert!(file.seek(SeekFrom::Start(record_size + 2)).is_ok());
+        assert!(file.write_all(b"x").is_ok());
+
+        match segment.recover() {
+            Err(DatabaseError::IoError(ErrorKind::InvalidData, description)) => {
+                assert!(description.contains(&format!("corrupted record at byte {}", record_size)));
+            },
+            result => panic!("Unexpected recovery result: {:?}", result)
+        }
+        assert_eq!(segment.size(), Ok(byte_count));
+
+        assert!(segment.remove().is_ok());
+    }
+
+    #[test]
+    fn test_compaction() {
+        let ref collection_name = random_collection_name();
+        let segment = Segment::new("", collection_name, 1, 2);
+        let mut writer = segment.open_writer().expect("Unable to open writer");
+        for id in 1..9 {
+            let flags = if id == 3 || id == 4 { BATCH_CONTINUATION_FLAG } else { 0 };
+            let event = Event::new("data", vec!["tag1"]).with_id(id).with_timestamp(id * 1000);
+            assert!(writer.write_log_record(&event.to_log_record_with_flags(flags)).is_ok());
+        }
+
+        assert_eq!(segment.write_compacted(|_| true), Ok(0));
+        assert!(segment.remove_compacted().is_err());
+
+        assert_eq!(segment.write_compacted(|event| event.id % 2 == 0 && event.id != 6), Ok(5));
+        assert_eq!(segment.open_reader().map(|mut reader| reader.read_log_record().ok().and_then(|r| r).is_some()), Ok(true));
+
+        let index = segment.replace_with_compacted().expect("Unable to replace segment");
+        assert_eq!(index.get_ref().keys().cloned().collect::<Vec<_>>(), vec![2, 4, 8]);
+        assert_eq!(segment.restore_index(), Ok(index));
+        assert!(segment.remove_compacted().is_err());
+
+        let mut reader = segment.open_reader().expect("Unable to open reader");
+        let mut events = vec![];
+        while let Ok(Some(record)) = reader.read_log_record() {
+            events.push(Event::from_log_record_with_flags(&record).expect("Unable to decode record"));
+        }
+        assert_eq!(events.iter().map(|&(ref e, flags)| (e.id, e.timestamp, flags)).collect::<Vec<_>>(),
+                   vec![(2, 2000, 0), (4, 4000, 0), (8, 8000, 0)]);
+        assert_eq!(segment.last_event().map(|e| e.map(|e| e.id)), Ok(Some(8)));
+
+        assert!(segment.remove().is_ok());
+    }
+
+    #[test]
+    fn test_segment_size() {
+        assert_eq!(SegmentSize::default(), SegmentSize::MaxBytes(1073741824));
+
+        assert_eq!(SegmentSize::parse("Unlimited"), Some(SegmentSize::Unlimited));
+        assert_eq!(SegmentSize::parse("MaxBytes(1024)"), Some(SegmentSize::MaxBytes(1024)));
+        assert_eq!(SegmentSize::parse("MaxEvents(100)"), Some(SegmentSize::MaxEvents(100)));
+        assert_eq!(SegmentSize::parse("MaxEvents(0)"), None);
+        assert_eq!(SegmentSize::parse("MaxBytes"), None);
+
+        for segment_size in &[SegmentSize::Unlimited, SegmentSize::MaxBytes(1024), SegmentSize::MaxEvents(100)] {
+            assert_eq!(SegmentSize::parse(&segment_size.to_string()).as_ref(), Some(segment_size));
+        }
+
+        assert!(!SegmentSize::Unlimited.is_exceeded_by(1024, 100, 1024, 100));
+        assert!(!SegmentSize::MaxBytes(1024).is_exceeded_by(1000, 100, 24, 100));
+        assert!(SegmentSize::MaxBytes(1024).is_exceeded_by(1000, 0, 25, 1));
+        assert!(!SegmentSize::MaxEvents(100).is_exceeded_by(1024, 90, 1024, 10));
+        assert!(SegmentSize::MaxEvents(100).is_exceeded_by(0, 90, 0, 11));
+    }
+
+    #[test]
+    #[cfg(feature = "rustc-serialization")]
+    fn test_segment_size_rustc_serialization() {
+        let segment_size = SegmentSize::MaxEvents(100);
+        assert_eq!(json::encode(&segment_size).unwrap(), "\"MaxEvents(100)\"");
+        assert_eq!(json::decode::<SegmentSize>("\"MaxEvents(100)\"").unwrap(), segment_size);
+
+        assert!(json::decode::<SegmentSize>("\"MaxEvent(100)\"").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde-serialization")]
+    fn test_segment_size_serde_serialization() {
+        let segment_size = SegmentSize::MaxBytes(1024);
+        assert_eq!(serde_json::to_string(&segment_size).unwrap(), "\"MaxBytes(1024)\"");
+        assert_eq!(serde_json::from_str::<SegmentSize>("\"MaxBytes(1024)\"").unwrap(), segment_size);
+
+        assert!(serde_json::from_str::<SegmentSize>("\"MaxByte(1024)\"").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use super::*;
+
+use std::collections::HashMap;
+
+/// Exar DB's stream index.
+///
+/// It maps each stream to the ascending `id`s of its events (its postings) along with their stream versions,
+/// allowing to find the events of a stream without scanning the log, and it keeps track of the version
+/// of the last event of each stream, even once its events have been removed by the retention policy.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let mut stream_index = StreamIndex::new();
+/// stream_index.insert(1, Some("stream1"), 1);
+/// stream_index.insert(2, None, 0);
+/// stream_index.insert(3, Some("stream1"), 2);
+///
+/// assert_eq!(stream_index.version("stream1"), 2);
+/// assert_eq!(stream_index.ids_after("stream1", 1), &[3]);
+/// assert_eq!(stream_index.ids_from_version("stream1", 1), &[1, 3]);
+/// assert_eq!(stream_index.ids_after("stream2", 0), &[] as &[u64]);
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamIndex {
+    postings: HashMap<String, StreamPostings>,
+    last_id: u64
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct StreamPostings {
+    ids: Vec<u64>,
+    versions: Vec<u64>,
+    version: u64
+}
+
+impl StreamIndex {
+    /// Creates a new empty stream index.
+    pub fn new() -> StreamIndex {
+        StreamIndex {
+            postings: HashMap::new(),
+            last_id: 0
+        }
+    }
+
+    /// Adds the given event `id` to the postings of the given stream, if any, with the given stream version.
+    ///
+    /// Events must be inserted in `id` order, an event preceding the last one inserted is ignored.
+    pub fn insert(&mut self, id: u64, stream_id: Option<&str>, version: u64) {
+        if id <= self.last_id {
+            return
+        }
+        if let Some(stream_id) = stream_id {
+            let postings = self.postings.entry(stream_id.to_owned()).or_insert_with(StreamPostings::default);
+            postings.ids.push(id);
+            postings.versions.push(version);
+            postings.version = version;
+        }
+        self.last_id = id;
+    }
+
+    /// Returns the `id` of the last event known to the index.
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// Returns the version of the last event of the given stream, or 0 if the stream does not exist.
+    pub fn version(&self, stream_id: &str) -> u64 {
+        self.postings.get(stream_id).map_or(0, |postings| postings.version)
+    }
+
+    /// Returns the ascending `id`s of the events of the given stream following the given event `id` (or position).
+    pub fn ids_after(&self, stream_id: &str, position: u64) -> &[u64] {
+        match self.postings.get(stream_id) {
+            Some(postings) => match postings.ids.binary_search(&position) {
+                Ok(i) => &postings.ids[i + 1..],
+                Err(i) => &postings.ids[i..]
+            },
+            None => &[]
+        }
+    }
+
+    /// Returns the ascending `id`s of the events of the given stream starting from the given stream version.
+    pub fn ids_from_version(&self, stream_id: &str, version: u64) -> &[u64] {
+        match self.postings.get(stream_id) {
+            Some(postings) => match postings.versions.binary_search(&version) {
+                Ok(i) | Err(i) => &postings.ids[i..]
+            },
+            None => &[]
+        }
+    }
+
+    /// Removes the postings of the events preceding the given event `id`, keeping the version of each stream.
+    pub fn remove_before(&mut self, id: u64) {
+        for postings in self.postings.values_mut() {
+            let removed_ids = match postings.ids.binary_search(&id) {
+                Ok(i) | Err(i) => i
+            };
+            postings.ids.drain(..removed_ids);
+            postings.versions.drain(..removed_ids);
+        }
+    }
+}
+
+/// Exar DB's stream index entry.
+///
+/// It is the persisted form of the stream of an event, stored as a binary log record
+/// in the stream index file of a log, events outside streams are only stored (without a stream `id`)
+/// when they are the last event read from the log while restoring the stream index.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let entry = StreamIndexEntry::new(1, Some("stream1".to_owned()), 1);
+/// let record = entry.to_log_record();
+///
+/// assert_eq!(StreamIndexEntry::from_log_record(&record), Ok(entry));
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamIndexEntry {
+    /// The event `id`.
+    pub id: u64,
+    /// The `id` of the stream the event belongs to, if any.
+    pub stream_id: Option<String>,
+    /// The event version within its stream.
+    pub version: u64
+}
+
+impl StreamIndexEntry {
+    /// Returns a new `StreamIndexEntry` for the given event `id`, stream `id` and stream version.
+    pub fn new(id: u64, stream_id: Option<String>, version: u64) -> StreamIndexEntry {
+        StreamIndexEntry {
+            id: id,
+            stream_id: stream_id,
+            version: version
+        }
+    }
+}
+
+impl ToLogRecord for StreamIndexEntry {
+    fn to_log_record(&self) -> Vec<u8> {
+        let mut encoder = LogRecordEncoder::new();
+        encoder.put_u64(self.id);
+        encoder.put_short_str(self.stream_id.as_ref().map_or("", |stream_id| &stream_id[..]));
+        encoder.put_u64(self.version);
+        encoder.into_log_record()
+    }
+}
+
+impl FromLogRecord for StreamIndexEntry {
+    fn from_log_record(record: &[u8]) -> Result<StreamIndexEntry, ParseError> {
+        let mut decoder = try!(LogRecordDecoder::new(record));
+        let id = try!(decoder.get_u64());
+        let stream_id = try!(decoder.get_short_str());
+        let version = try!(decoder.get_u64());
+        Ok(StreamIndexEntry {
+            id: id,
+            stream_id: if stream_id.is_empty() { None } else { Some(stream_id) },
+            version: version
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn test_insert_and_lookup() {
+        let mut stream_index = StreamIndex::new();
+
+        stream_index.insert(1, Some("stream1"), 1);
+        stream_index.insert(2, Some("stream2"), 1);
+        stream_index.insert(3, None, 0);
+        stream_index.insert(4, Some("stream1"), 2);
+        stream_index.insert(2, Some("stream1"), 3);
+
+        assert_eq!(stream_index.last_id(), 4);
+        assert_eq!(stream_index.version("stream1"), 2);
+        assert_eq!(stream_index.version("stream2"), 1);
+        assert_eq!(stream_index.version("stream3"), 0);
+        assert_eq!(stream_index.ids_after("stream1", 0), &[1, 4]);
+        assert_eq!(stream_index.ids_after("stream1", 1), &[4]);
+        assert_eq!(stream_index.ids_after("stream2", 2), &[] as &[u64]);
+        assert_eq!(stream_index.ids_from_version("stream1", 0), &[1, 4]);
+        assert_eq!(stream_index.ids_from_version("stream1", 2), &[4]);
+        assert_eq!(stream_index.ids_from_version("stream1", 3), &[] as &[u64]);
+        assert_eq!(stream_index.ids_from_version("stream3", 1), &[] as &[u64]);
+    }
+
+    #[test]
+    fn test_remove_before() {
+        let mut stream_index = StreamIndex::new();
+        for id in 1..11 {
+            stream_index.insert(id, Some(if id % 2 == 0 { "even" } else { "odd" }), (id + 1) / 2);
+        }
+
+        stream_index.remove_before(6);
+
+        assert_eq!(stream_index.ids_after("even", 0), &[6, 8, 10]);
+        assert_eq!(stream_index.ids_from_version("even", 4), &[8, 10]);
+        assert_eq!(stream_index.ids_after("odd", 0), &[7, 9]);
+
+        stream_index.remove_before(11);
+
+        assert_eq!(stream_index.last_id(), 10);
+        assert_eq!(stream_index.ids_after("even", 0), &[] as &[u64]);
+        assert_eq!(stream_index.version("even"), 5);
+        assert_eq!(stream_index.version("odd"), 5);
+    }
+
+    #[test]
+    fn test_entry_log_record_encoding() {
+        let entry = StreamIndexEntry::new(1, Some("stream1".to_owned()), 1);
+        let record = entry.to_log_record();
+
+        assert_eq!(StreamIndexEntry::from_log_record(&record), Ok(entry));
+        assert!(StreamIndexEntry::from_log_record(&record[..record.len() - 1]).is_err());
+
+        let entry = StreamIndexEntry::new(2, None, 0);
+        assert_eq!(StreamIndexEntry::from_log_record(&entry.to_log_record()), Ok(entry));
+    }
+}
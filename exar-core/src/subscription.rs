@@ -1,19 +1,26 @@
 use super::*;
 
-use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::mpsc::{SyncSender, TrySendError};
 
 /// Exar DB's subscription.
 ///
+/// It streams events through a bounded channel: when the channel buffer is full the subscription is paused,
+/// keeping its position, until it is resumed.
+///
+/// It shares a `CancellationHandle` with its event stream, once cancelled it no longer sends events.
+///
 /// # Examples
 /// ```
 /// extern crate exar;
 ///
 /// # fn main() {
 /// use exar::*;
-/// use std::sync::mpsc::channel;
+/// use std::sync::Arc;
+/// use std::sync::mpsc::sync_channel;
 ///
-/// let (sender, receiver) = channel();
-/// let event = Event::new("data", vec!["tag1", "tag2"]);
+/// let (sender, receiver) = sync_channel(1000);
+/// let event = Arc::new(Event::new("data", vec!["tag1", "tag2"]));
 ///
 /// let mut subscription = Subscription::new(sender, Query::current());
 /// subscription.send(event).unwrap();
@@ -23,39 +30,50 @@ use std::sync::mpsc::Sender;
 #[derive(Clone, Debug)]
 pub struct Subscription {
     active: bool,
-    /// The channel sender used to stream `EventStreamMessage`s back to the subscriber.
-    pub event_stream_sender: Sender<EventStreamMessage>,
+    paused: bool,
+    cancellation_handle: CancellationHandle,
+    /// The bounded channel sender used to stream `EventStreamMessage`s back to the subscriber.
+    pub event_stream_sender: SyncSender<EventStreamMessage>,
     /// The query associated to this subscription.
     pub query: Query
 }
 
 impl Subscription {
-    /// Creates a new `Subscription` with the given channel sender and query.
-    pub fn new(sender: Sender<EventStreamMessage>, query: Query) -> Subscription {
+    /// Creates a new `Subscription` with the given bounded channel sender and query.
+    pub fn new(sender: SyncSender<EventStreamMessage>, query: Query) -> Subscription {
         Subscription {
             active: true,
+            paused: false,
+            cancellation_handle: CancellationHandle::new(),
             event_stream_sender: sender,
             query: query
         }
     }
 
-    /// Sends an `Event` to the subscriber or returns a `DatabaseError` if a failure occurs.
-    pub fn send(&mut self, event: Event) -> Result<(), DatabaseError> {
+    /// Sends a shared `Event` to the subscriber or returns a `DatabaseError` if a failure occurs.
+    ///
+    /// If the channel buffer is full the event is not sent and the subscription is paused.
+    pub fn send(&mut self, event: Arc<Event>) -> Result<(), DatabaseError> {
         let event_id = event.id;
-        match self.event_stream_sender.send(EventStreamMessage::Event(event)) {
+        match self.event_stream_sender.try_send(EventStreamMessage::Event(event)) {
             Ok(_) => {
                 self.query.update(event_id);
                 if !self.is_active() || !self.query.is_active() {
                     self.active = false;
-                    match self.event_stream_sender.send(EventStreamMessage::End) {
-                        Ok(_) => Ok(()),
-                        Err(_) => Err(DatabaseError::EventStreamError(EventStreamError::Closed))
+                    // a full buffer is drained before the end of the event stream, which ends once the sender is dropped
+                    match self.event_stream_sender.try_send(EventStreamMessage::End) {
+                        Ok(_) | Err(TrySendError::Full(_)) => Ok(()),
+                        Err(TrySendError::Disconnected(_)) => Err(DatabaseError::EventStreamError(EventStreamError::Closed))
                     }
                 } else {
                     Ok(())
                 }
             },
-            Err(_) => {
+            Err(TrySendError::Full(_)) => {
+                self.paused = true;
+                Ok(())
+            },
+            Err(TrySendError::Disconnected(_)) => {
                 self.active = false;
                 Err(DatabaseError::EventStreamError(EventStreamError::Closed))
             }
@@ -67,9 +85,36 @@ impl Subscription {
         self.active
     }
 
+    /// Ends the event stream of the subscription, which is no longer active.
+    pub fn end(&mut self) {
+        self.active = false;
+        let _ = self.event_stream_sender.try_send(EventStreamMessage::End);
+    }
+
+    /// Returns a clone of the `CancellationHandle` shared with the event stream of the subscription.
+    pub fn cancellation_handle(&self) -> CancellationHandle {
+        self.cancellation_handle.clone()
+    }
+
+    /// Returns wether the event stream of the subscription has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation_handle.is_cancelled()
+    }
+
+    /// Returns wether the subscription has been paused because its channel buffer is full.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Resumes the subscription, so that it attempts to send events again.
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
     /// Returns wether the subscription is interested in the given `Event`.
     pub fn matches_event(&self, event: &Event) -> bool {
-        self.is_active() && self.query.is_active() && self.query.matches(event)
+        self.is_active() && !self.is_paused() && !self.is_cancelled() && self.query.is_active() &&
+        self.query.matches(event) && self.query.matches_payload(event)
     }
 }
 
@@ -77,12 +122,13 @@ impl Subscription {
 mod tests {
     use super::super::*;
 
-    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::sync::mpsc::sync_channel;
 
     #[test]
     fn test_simple_subscription() {
-        let (sender, receiver) = channel();
-        let event = Event::new("data", vec!["tag1", "tag2"]).with_id(1);
+        let (sender, receiver) = sync_channel(10);
+        let event = Arc::new(Event::new("data", vec!["tag1", "tag2"]).with_id(1));
 
         let mut subscription = Subscription::new(sender, Query::current());
 
@@ -100,8 +146,8 @@ mod tests {
 
     #[test]
     fn test_subscription_event_stream_end() {
-        let (sender, receiver) = channel();
-        let event = Event::new("data", vec!["tag1", "tag2"]).with_id(1);
+        let (sender, receiver) = sync_channel(10);
+        let event = Arc::new(Event::new("data", vec!["tag1", "tag2"]).with_id(1));
 
         let mut subscription = Subscription::new(sender, Query::current().limit(1));
 
@@ -117,4 +163,66 @@ mod tests {
         assert_eq!(subscription.query.interval().start, 1);
         assert!(!subscription.is_active());
     }
+
+    #[test]
+    fn test_subscription_backpressure() {
+        let (sender, receiver) = sync_channel(1);
+        let event = Event::new("data", vec!["tag1", "tag2"]);
+
+        let mut subscription = Subscription::new(sender, Query::current().limit(2));
+
+        assert!(subscription.send(Arc::new(event.clone().with_id(1))).is_ok());
+        assert!(!subscription.is_paused());
+
+        assert!(subscription.send(Arc::new(event.clone().with_id(2))).is_ok());
+        assert!(subscription.is_paused());
+        assert_eq!(subscription.query.interval().start, 1);
+        assert!(!subscription.matches_event(&event.clone().with_id(2)));
+
+        assert_eq!(receiver.recv(), Ok(EventStreamMessage::Event(Arc::new(event.clone().with_id(1)))));
+
+        subscription.resume();
+        assert!(subscription.matches_event(&event.clone().with_id(2)));
+        assert!(subscription.send(Arc::new(event.clone().with_id(2))).is_ok());
+        assert!(!subscription.is_paused());
+        assert_eq!(subscription.query.interval().start, 2);
+        assert!(!subscription.is_active());
+
+        drop(subscription);
+
+        assert_eq!(receiver.recv(), Ok(EventStreamMessage::Event(Arc::new(event.with_id(2)))));
+        assert!(receiver.recv().is_err());
+    }
+
+    #[test]
+    fn test_subscription_cancellation() {
+        let (sender, receiver) = sync_channel(10);
+        let event = Arc::new(Event::new("data", vec!["tag1", "tag2"]).with_id(1));
+
+        let mut subscription = Subscription::new(sender, Query::live());
+        let event_stream = EventStream::new(receiver).with_cancellation_handle(subscription.cancellation_handle());
+
+        assert!(subscription.matches_event(&event));
+        assert!(!subscription.is_cancelled());
+
+        event_stream.cancel();
+        assert!(subscription.is_cancelled());
+        assert!(!subscription.matches_event(&event));
+
+        subscription.end();
+        assert!(!subscription.is_active());
+        assert_eq!(event_stream.recv(), Err(EventStreamError::Closed));
+    }
+
+    #[test]
+    fn test_subscription_payload_filter() {
+        let (sender, _receiver) = sync_channel(10);
+        let payload_filter = "json:$.amount > 100".parse().expect("Unable to parse payload filter");
+
+        let subscription = Subscription::new(sender, Query::current().by_payload_filter(payload_filter));
+
+        assert!(subscription.matches_event(&Event::new(r#"{ "amount": 120 }"#, vec!["tag1"]).with_id(1)));
+        assert!(!subscription.matches_event(&Event::new(r#"{ "amount": 80 }"#, vec!["tag1"]).with_id(1)));
+        assert!(!subscription.matches_event(&Event::new("data", vec!["tag1"]).with_id(1)));
+    }
 }
@@ -0,0 +1,288 @@
+use super::*;
+
+use std::fmt::{Display, Formatter, Result as DisplayResult};
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::vec::IntoIter;
+
+/// Exar DB's tag expression.
+///
+/// It describes a boolean condition on the tags of an event, combining tags with the `AND`, `OR` and `NOT` operators.
+///
+/// The textual syntax of a tag expression uses parentheses for grouping, `NOT` binds tighter than `AND`,
+/// which binds tighter than `OR`, and a `NOT` following an expression is an implicit `AND NOT`.
+/// The operators are case sensitive, so tags such as `and` or `not` can still be used.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let expression: TagExpression = "(order AND eu) OR refund NOT test".parse().unwrap();
+///
+/// // or using the fluent API
+/// let fluent_expression = TagExpression::tag("order").and(TagExpression::tag("eu"))
+///                        .or(TagExpression::tag("refund").and(TagExpression::tag("test").not()));
+///
+/// assert_eq!(expression, fluent_expression);
+/// assert!(expression.matches(&["refund".to_owned()]));
+/// assert!(!expression.matches(&["refund".to_owned(), "test".to_owned()]));
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TagExpression {
+    /// Matches the events containing the given tag.
+    Tag(String),
+    /// Matches the events not matching the given expression.
+    Not(Box<TagExpression>),
+    /// Matches the events matching both the given expressions.
+    And(Box<TagExpression>, Box<TagExpression>),
+    /// Matches the events matching either of the given expressions.
+    Or(Box<TagExpression>, Box<TagExpression>)
+}
+
+impl TagExpression {
+    /// Returns a new `TagExpression` matching the events containing the given tag.
+    pub fn tag(tag: &str) -> TagExpression {
+        TagExpression::Tag(tag.to_owned())
+    }
+
+    /// Returns the negation of the expression.
+    pub fn not(self) -> TagExpression {
+        TagExpression::Not(Box::new(self))
+    }
+
+    /// Returns the conjunction of the expression and the given one.
+    pub fn and(self, other: TagExpression) -> TagExpression {
+        TagExpression::And(Box::new(self), Box::new(other))
+    }
+
+    /// Returns the disjunction of the expression and the given one.
+    pub fn or(self, other: TagExpression) -> TagExpression {
+        TagExpression::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Returns wether the given event tags match the expression.
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match *self {
+            TagExpression::Tag(ref tag) => tags.contains(tag),
+            TagExpression::Not(ref expression) => !expression.matches(tags),
+            TagExpression::And(ref left, ref right) => left.matches(tags) && right.matches(tags),
+            TagExpression::Or(ref left, ref right) => left.matches(tags) || right.matches(tags)
+        }
+    }
+
+    /// Returns a tag contained by all the events matching the expression, if any.
+    pub fn required_tag(&self) -> Option<&str> {
+        match *self {
+            TagExpression::Tag(ref tag) => Some(tag),
+            TagExpression::And(ref left, ref right) => left.required_tag().or_else(|| right.required_tag()),
+            _ => None
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match *self {
+            TagExpression::Or(_, _) => 0,
+            TagExpression::And(_, _) => 1,
+            TagExpression::Not(_) => 2,
+            TagExpression::Tag(_) => 3
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut Formatter, min_precedence: u8) -> DisplayResult {
+        if self.precedence() < min_precedence {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+impl Display for TagExpression {
+    fn fmt(&self, f: &mut Formatter) -> DisplayResult {
+        // binary operators are left associative, so right operands with the same precedence are parenthesized
+        match *self {
+            TagExpression::Tag(ref tag) => write!(f, "{}", tag),
+            TagExpression::Not(ref expression) => {
+                try!(write!(f, "NOT "));
+                expression.fmt_operand(f, 2)
+            },
+            TagExpression::And(ref left, ref right) => {
+                try!(left.fmt_operand(f, 1));
+                try!(write!(f, " AND "));
+                right.fmt_operand(f, 2)
+            },
+            TagExpression::Or(ref left, ref right) => {
+                try!(left.fmt_operand(f, 0));
+                try!(write!(f, " OR "));
+                right.fmt_operand(f, 1)
+            }
+        }
+    }
+}
+
+impl FromStr for TagExpression {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<TagExpression, ParseError> {
+        let mut parser = TagExpressionParser::new(s);
+        let expression = try!(parser.parse_or());
+        match parser.tokens.next() {
+            Some(token) => Err(ParseError::ParseError(format!("unexpected token in tag expression: {}", token))),
+            None => Ok(expression)
+        }
+    }
+}
+
+struct TagExpressionParser {
+    tokens: Peekable<IntoIter<String>>
+}
+
+impl TagExpressionParser {
+    fn new(s: &str) -> TagExpressionParser {
+        let mut tokens = vec![];
+        let mut token = String::new();
+        for c in s.chars() {
+            if c.is_whitespace() || c == '(' || c == ')' {
+                if !token.is_empty() {
+                    tokens.push(token);
+                    token = String::new();
+                }
+                if !c.is_whitespace() {
+                    tokens.push(c.to_string());
+                }
+            } else {
+                token.push(c);
+            }
+        }
+        if !token.is_empty() {
+            tokens.push(token);
+        }
+        TagExpressionParser {
+            tokens: tokens.into_iter().peekable()
+        }
+    }
+
+    fn next_token_is(&mut self, token: &str) -> bool {
+        self.tokens.peek().map_or(false, |next_token| next_token == token)
+    }
+
+    fn parse_or(&mut self) -> Result<TagExpression, ParseError> {
+        let mut expression = try!(self.parse_and());
+        while self.next_token_is("OR") {
+            self.tokens.next();
+            expression = expression.or(try!(self.parse_and()));
+        }
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<TagExpression, ParseError> {
+        let mut expression = try!(self.parse_not());
+        loop {
+            if self.next_token_is("AND") {
+                self.tokens.next();
+            } else if !self.next_token_is("NOT") {
+                return Ok(expression)
+            }
+            expression = expression.and(try!(self.parse_not()));
+        }
+    }
+
+    fn parse_not(&mut self) -> Result<TagExpression, ParseError> {
+        if self.next_token_is("NOT") {
+            self.tokens.next();
+            self.parse_not().map(|expression| expression.not())
+        } else {
+            self.parse_operand()
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<TagExpression, ParseError> {
+        match self.tokens.next() {
+            Some(ref token) if token == "(" => {
+                let expression = try!(self.parse_or());
+                match self.tokens.next() {
+                    Some(ref token) if token == ")" => Ok(expression),
+                    Some(token) => Err(ParseError::ParseError(format!("unexpected token in tag expression: {}", token))),
+                    None => Err(ParseError::ParseError("missing closing parenthesis in tag expression".to_owned()))
+                }
+            },
+            Some(ref token) if token == ")" || token == "AND" || token == "OR" => {
+                Err(ParseError::ParseError(format!("unexpected token in tag expression: {}", token)))
+            },
+            Some(token) => Ok(TagExpression::Tag(token)),
+            None => Err(ParseError::ParseError("unexpected end of tag expression".to_owned()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    fn tag(tag: &str) -> TagExpression {
+        TagExpression::tag(tag)
+    }
+
+    #[test]
+    fn test_matches() {
+        let expression = tag("order").and(tag("eu")).or(tag("refund").and(tag("test").not()));
+
+        assert!(expression.matches(&tags(&["order", "eu"])));
+        assert!(expression.matches(&tags(&["refund"])));
+        assert!(expression.matches(&tags(&["order", "eu", "test"])));
+        assert!(!expression.matches(&tags(&["order", "us"])));
+        assert!(!expression.matches(&tags(&["refund", "test"])));
+        assert!(!expression.matches(&[]));
+    }
+
+    #[test]
+    fn test_required_tag() {
+        assert_eq!(tag("tag1").required_tag(), Some("tag1"));
+        assert_eq!(tag("tag1").not().and(tag("tag2")).required_tag(), Some("tag2"));
+        assert_eq!(tag("tag1").or(tag("tag2")).required_tag(), None);
+        assert_eq!(tag("tag1").not().required_tag(), None);
+    }
+
+    #[test]
+    fn test_parsing() {
+        assert_eq!("tag1".parse(), Ok(tag("tag1")));
+        assert_eq!("(order AND eu) OR refund NOT test".parse(),
+                   Ok(tag("order").and(tag("eu")).or(tag("refund").and(tag("test").not()))));
+        assert_eq!("a OR b AND c".parse(), Ok(tag("a").or(tag("b").and(tag("c")))));
+        assert_eq!("NOT NOT a AND (b OR c)".parse(), Ok(tag("a").not().not().and(tag("b").or(tag("c")))));
+        assert_eq!("and AND not".parse(), Ok(tag("and").and(tag("not"))));
+
+        assert!("".parse::<TagExpression>().is_err());
+        assert!("a AND".parse::<TagExpression>().is_err());
+        assert!("(a OR b".parse::<TagExpression>().is_err());
+        assert!("a OR b)".parse::<TagExpression>().is_err());
+        assert!("AND a".parse::<TagExpression>().is_err());
+        assert!("a b".parse::<TagExpression>().is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let expressions = vec![
+            tag("tag1"),
+            tag("order").and(tag("eu")).or(tag("refund").and(tag("test").not())),
+            tag("a").and(tag("b").or(tag("c"))),
+            tag("a").or(tag("b").or(tag("c"))),
+            tag("a").and(tag("b").and(tag("c"))),
+            tag("a").and(tag("b")).not()
+        ];
+        for expression in expressions {
+            assert_eq!(expression.to_string().parse(), Ok(expression));
+        }
+        assert_eq!(tag("order").and(tag("eu")).or(tag("refund").and(tag("test").not())).to_string(),
+                   "order AND eu OR refund AND NOT test");
+        assert_eq!(tag("a").and(tag("b")).not().to_string(), "NOT (a AND b)");
+    }
+}
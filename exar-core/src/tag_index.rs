@@ -0,0 +1,241 @@
+use super::*;
+
+use std::collections::HashMap;
+
+/// Exar DB's inverted tag index.
+///
+/// It maps each event tag to the ascending `id`s of the events containing it (its postings),
+/// allowing to find the events with a given tag without scanning the log.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let mut tag_index = TagIndex::new();
+/// tag_index.insert(1, &["tag1".to_owned(), "tag2".to_owned()]);
+/// tag_index.insert(2, &["tag2".to_owned()]);
+///
+/// assert_eq!(tag_index.ids_after("tag2", 1), &[2]);
+/// assert_eq!(tag_index.ids_after("tag3", 0), &[] as &[u64]);
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagIndex {
+    postings: HashMap<String, Vec<u64>>,
+    last_id: u64
+}
+
+impl TagIndex {
+    /// Creates a new empty tag index.
+    pub fn new() -> TagIndex {
+        TagIndex {
+            postings: HashMap::new(),
+            last_id: 0
+        }
+    }
+
+    /// Adds the given event `id` to the postings of the given tags.
+    ///
+    /// Events must be inserted in `id` order, an event preceding the last one inserted is ignored.
+    pub fn insert(&mut self, id: u64, tags: &[String]) {
+        if id <= self.last_id {
+            return
+        }
+        for tag in tags {
+            let ids = self.postings.entry(tag.to_owned()).or_insert_with(Vec::new);
+            if ids.last() != Some(&id) {
+                ids.push(id);
+            }
+        }
+        self.last_id = id;
+    }
+
+    /// Returns the `id` of the last event known to the index.
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// Returns the ascending `id`s of the events containing the given tag
+    /// and following the given event `id` (or position).
+    pub fn ids_after(&self, tag: &str, position: u64) -> &[u64] {
+        match self.postings.get(tag) {
+            Some(ids) => match ids.binary_search(&position) {
+                Ok(i) => &ids[i + 1..],
+                Err(i) => &ids[i..]
+            },
+            None => &[]
+        }
+    }
+
+    /// Removes the given event `id` from the postings of the given tags,
+    /// for instance once the event has been removed by compaction.
+    pub fn remove(&mut self, id: u64, tags: &[String]) {
+        for tag in tags {
+            let is_empty = match self.postings.get_mut(tag) {
+                Some(ids) => {
+                    if let Ok(i) = ids.binary_search(&id) {
+                        ids.remove(i);
+                    }
+                    ids.is_empty()
+                },
+                None => false
+            };
+            if is_empty {
+                self.postings.remove(tag);
+            }
+        }
+    }
+
+    /// Removes the postings of the events preceding the given event `id`.
+    pub fn remove_before(&mut self, id: u64) {
+        for ids in self.postings.values_mut() {
+            let removed_ids = match ids.binary_search(&id) {
+                Ok(i) | Err(i) => i
+            };
+            ids.drain(..removed_ids);
+        }
+        self.postings.retain(|_, ids| !ids.is_empty());
+    }
+}
+
+/// Exar DB's tag index entry.
+///
+/// It is the persisted form of the tags of an event, stored as a binary log record
+/// in the tag index file of a log.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let entry = TagIndexEntry::new(1, vec!["tag1".to_owned(), "tag2".to_owned()]);
+/// let record = entry.to_log_record();
+///
+/// assert_eq!(TagIndexEntry::from_log_record(&record), Ok(entry));
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TagIndexEntry {
+    /// The event `id`.
+    pub id: u64,
+    /// The event tags.
+    pub tags: Vec<String>
+}
+
+impl TagIndexEntry {
+    /// Returns a new `TagIndexEntry` for the given event `id` and tags.
+    pub fn new(id: u64, tags: Vec<String>) -> TagIndexEntry {
+        TagIndexEntry {
+            id: id,
+            tags: tags
+        }
+    }
+}
+
+impl ToLogRecord for TagIndexEntry {
+    fn to_log_record(&self) -> Vec<u8> {
+        let mut encoder = LogRecordEncoder::new();
+        encoder.put_u64(self.id);
+        encoder.put_u16(self.tags.len() as u16);
+        for tag in &self.tags {
+            encoder.put_short_str(tag);
+        }
+        encoder.into_log_record()
+    }
+}
+
+impl FromLogRecord for TagIndexEntry {
+    fn from_log_record(record: &[u8]) -> Result<TagIndexEntry, ParseError> {
+        let mut decoder = try!(LogRecordDecoder::new(record));
+        let id = try!(decoder.get_u64());
+        let nr_of_tags = try!(decoder.get_u16());
+        let mut tags = Vec::with_capacity(nr_of_tags as usize);
+        for _ in 0..nr_of_tags {
+            tags.push(try!(decoder.get_short_str()));
+        }
+        Ok(TagIndexEntry {
+            id: id,
+            tags: tags
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    use std::collections::HashMap;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_insert_and_lookup() {
+        let mut tag_index = TagIndex::new();
+
+        tag_index.insert(1, &tags(&["tag1", "tag2"]));
+        tag_index.insert(2, &tags(&["tag2"]));
+        tag_index.insert(3, &tags(&["tag1", "tag1"]));
+        tag_index.insert(2, &tags(&["tag3"]));
+
+        assert_eq!(tag_index.last_id(), 3);
+        assert_eq!(tag_index.ids_after("tag1", 0), &[1, 3]);
+        assert_eq!(tag_index.ids_after("tag1", 1), &[3]);
+        assert_eq!(tag_index.ids_after("tag2", 1), &[2]);
+        assert_eq!(tag_index.ids_after("tag2", 2), &[] as &[u64]);
+        assert_eq!(tag_index.ids_after("tag3", 0), &[] as &[u64]);
+    }
+
+    #[test]
+    fn test_remove_before() {
+        let mut tag_index = TagIndex::new();
+        for id in 1..11 {
+            tag_index.insert(id, &tags(if id % 2 == 0 { &["even", "all"] } else { &["odd", "all"] }));
+        }
+        tag_index.insert(11, &tags(&["rare"]));
+
+        tag_index.remove_before(6);
+
+        assert_eq!(tag_index.last_id(), 11);
+        assert_eq!(tag_index.ids_after("all", 0), &[6, 7, 8, 9, 10]);
+        assert_eq!(tag_index.ids_after("even", 0), &[6, 8, 10]);
+        assert_eq!(tag_index.ids_after("rare", 0), &[11]);
+
+        tag_index.remove_before(12);
+
+        assert_eq!(tag_index, TagIndex { postings: HashMap::new(), last_id: 11 });
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tag_index = TagIndex::new();
+        for id in 1..6 {
+            tag_index.insert(id, &tags(&["key:1", "all"]));
+        }
+        tag_index.insert(6, &tags(&["rare"]));
+
+        tag_index.remove(2, &tags(&["key:1", "all"]));
+        tag_index.remove(3, &tags(&["key:1"]));
+        tag_index.remove(6, &tags(&["rare"]));
+
+        assert_eq!(tag_index.last_id(), 6);
+        assert_eq!(tag_index.ids_after("key:1", 0), &[1, 4, 5]);
+        assert_eq!(tag_index.ids_after("all", 0), &[1, 3, 4, 5]);
+        assert!(!tag_index.postings.contains_key("rare"));
+    }
+
+    #[test]
+    fn test_entry_log_record_encoding() {
+        let entry = TagIndexEntry::new(1, tags(&["tag1", "tag2"]));
+        let record = entry.to_log_record();
+
+        assert_eq!(TagIndexEntry::from_log_record(&record), Ok(entry));
+        assert!(TagIndexEntry::from_log_record(&record[..record.len() - 1]).is_err());
+    }
+}
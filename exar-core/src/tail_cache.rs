@@ -0,0 +1,161 @@
+use super::*;
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Exar DB's in-memory cache of the most recent events of a log.
+///
+/// It keeps up to `capacity` of the last events published, shared as `Arc<Event>`s,
+/// allowing the scanners to serve subscriptions positioned near the tail of the log without reading it.
+///
+/// It also keeps track of the `id` of the last event published, so that it covers subscriptions
+/// positioned at the tail of the log even when it is empty.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let mut tail_cache = TailCache::new(2, 10);
+/// for id in 11..14 {
+///     tail_cache.push(Event::new("data", vec!["tag1"]).with_id(id));
+/// }
+///
+/// assert!(tail_cache.covers(11));
+/// assert!(!tail_cache.covers(10));
+/// assert_eq!(tail_cache.events_after(11).iter().map(|e| e.id).collect::<Vec<_>>(), vec![12, 13]);
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct TailCache {
+    events: VecDeque<Arc<Event>>,
+    capacity: usize,
+    last_id: u64
+}
+
+impl TailCache {
+    /// Creates a new empty tail cache with the given capacity, given the `id` of the last event logged.
+    pub fn new(capacity: usize, last_id: u64) -> TailCache {
+        TailCache {
+            events: VecDeque::with_capacity(capacity),
+            capacity: capacity,
+            last_id: last_id
+        }
+    }
+
+    /// Adds the given event to the cache, evicting the oldest one if the cache is full.
+    ///
+    /// Events must be pushed in `id` order, an event preceding the last one pushed is ignored.
+    pub fn push(&mut self, event: Event) {
+        if event.id <= self.last_id {
+            return
+        }
+        self.last_id = event.id;
+        if self.capacity == 0 {
+            return
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(Arc::new(event));
+    }
+
+    /// Removes the events preceding the given event `id` from the cache.
+    pub fn discard_before(&mut self, id: u64) {
+        while self.events.front().map_or(false, |event| event.id < id) {
+            self.events.pop_front();
+        }
+    }
+
+    /// Returns the `id` of the first event in the cache, or the `id` following the last event published if it is empty.
+    pub fn first_id(&self) -> u64 {
+        self.events.front().map_or(self.last_id + 1, |event| event.id)
+    }
+
+    /// Returns the `id` of the last event published.
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// Returns wether the cache contains all the events published after the given event `id`.
+    pub fn covers(&self, id: u64) -> bool {
+        id + 1 >= self.first_id()
+    }
+
+    /// Returns the cached events published after the given event `id`.
+    pub fn events_after(&self, id: u64) -> Vec<Arc<Event>> {
+        self.events.iter().filter(|event| event.id > id).cloned().collect()
+    }
+
+    /// Returns the number of events in the cache.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns wether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    fn event(id: u64) -> Event {
+        Event::new("data", vec!["tag1"]).with_id(id)
+    }
+
+    fn ids(tail_cache: &TailCache, id: u64) -> Vec<u64> {
+        tail_cache.events_after(id).iter().map(|e| e.id).collect()
+    }
+
+    #[test]
+    fn test_tail_cache() {
+        let mut tail_cache = TailCache::new(3, 5);
+
+        assert!(tail_cache.is_empty());
+        assert_eq!(tail_cache.first_id(), 6);
+        assert!(tail_cache.covers(5));
+        assert!(!tail_cache.covers(4));
+
+        for id in 6..11 {
+            tail_cache.push(event(id));
+        }
+        tail_cache.push(event(7));
+
+        assert_eq!(tail_cache.len(), 3);
+        assert_eq!(tail_cache.first_id(), 8);
+        assert_eq!(tail_cache.last_id(), 10);
+        assert!(tail_cache.covers(7));
+        assert!(!tail_cache.covers(6));
+        assert_eq!(ids(&tail_cache, 7), vec![8, 9, 10]);
+        assert_eq!(ids(&tail_cache, 9), vec![10]);
+        assert_eq!(ids(&tail_cache, 10), Vec::<u64>::new());
+
+        tail_cache.discard_before(10);
+
+        assert_eq!(tail_cache.first_id(), 10);
+        assert_eq!(ids(&tail_cache, 0), vec![10]);
+
+        tail_cache.discard_before(11);
+
+        assert!(tail_cache.is_empty());
+        assert_eq!(tail_cache.first_id(), 11);
+        assert!(tail_cache.covers(10));
+    }
+
+    #[test]
+    fn test_disabled_tail_cache() {
+        let mut tail_cache = TailCache::new(0, 0);
+
+        tail_cache.push(event(1));
+
+        assert!(tail_cache.is_empty());
+        assert_eq!(tail_cache.last_id(), 1);
+        assert!(tail_cache.covers(1));
+        assert!(!tail_cache.covers(0));
+    }
+}
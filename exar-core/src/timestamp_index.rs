@@ -0,0 +1,229 @@
+use super::*;
+
+use std::cmp::{max, min};
+
+/// Exar DB's sparse timestamp index.
+///
+/// It splits the events of a log into consecutive blocks of `granularity` events and stores the range
+/// of timestamps of each block, allowing to find the events within a time range without scanning the whole log.
+/// Timestamps are not required to be in `id` order, although the index is most effective when they are.
+///
+/// The event `id`s it returns are mapped to byte offsets by the `LogIndex` of each segment.
+///
+/// # Examples
+/// ```
+/// extern crate exar;
+///
+/// # fn main() {
+/// use exar::*;
+///
+/// let mut timestamp_index = TimestampIndex::new(2);
+/// for id in 1..7 {
+///     timestamp_index.insert(id, id * 1000);
+/// }
+///
+/// assert_eq!(timestamp_index.interval(Some(3500), Some(4500)), Interval::new(2, 4));
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimestampIndex {
+    blocks: Vec<TimestampBlock>,
+    granularity: u64
+}
+
+impl TimestampIndex {
+    /// Creates a new empty timestamp index with the given granularity.
+    pub fn new(granularity: u64) -> TimestampIndex {
+        TimestampIndex {
+            blocks: vec![],
+            granularity: max(granularity, 1)
+        }
+    }
+
+    /// Creates a new timestamp index with the given granularity and completed blocks.
+    pub fn with_blocks(granularity: u64, blocks: Vec<TimestampBlock>) -> TimestampIndex {
+        TimestampIndex {
+            blocks: blocks,
+            granularity: max(granularity, 1)
+        }
+    }
+
+    /// Adds the given event `id` and timestamp to the index, and returns wether the last block has been completed.
+    ///
+    /// Events must be inserted in `id` order, an event preceding the last one inserted is ignored.
+    pub fn insert(&mut self, id: u64, timestamp: u64) -> bool {
+        if id <= self.last_id() {
+            return false
+        }
+        let granularity = self.granularity;
+        match self.blocks.last_mut() {
+            Some(ref mut block) if block.nr_of_events < granularity => {
+                block.last_id = id;
+                block.min_timestamp = min(block.min_timestamp, timestamp);
+                block.max_timestamp = max(block.max_timestamp, timestamp);
+                block.nr_of_events += 1;
+                return block.nr_of_events == granularity
+            },
+            _ => ()
+        }
+        self.blocks.push(TimestampBlock::new(id, timestamp, timestamp, 1));
+        granularity == 1
+    }
+
+    /// Returns the granularity of the index.
+    pub fn granularity(&self) -> u64 {
+        self.granularity
+    }
+
+    /// Returns the `id` of the last event known to the index.
+    pub fn last_id(&self) -> u64 {
+        self.blocks.last().map_or(0, |block| block.last_id)
+    }
+
+    /// Returns the completed blocks of the index.
+    pub fn completed_blocks(&self) -> &[TimestampBlock] {
+        match self.blocks.last() {
+            Some(block) if block.nr_of_events < self.granularity => &self.blocks[..self.blocks.len() - 1],
+            _ => &self.blocks
+        }
+    }
+
+    /// Returns the interval of event `id`s containing all the events with a timestamp within the given range
+    /// (both bounds are inclusive), using the same convention as `Query::interval`.
+    ///
+    /// The interval is unbounded if the end of the range is not set, since events logged later may match it.
+    pub fn interval(&self, from_timestamp: Option<u64>, to_timestamp: Option<u64>) -> Interval<u64> {
+        let last_id = self.last_id();
+        let start = match from_timestamp {
+            Some(from_timestamp) => match self.blocks.iter().position(|block| block.max_timestamp >= from_timestamp) {
+                Some(0) => 0,
+                Some(i) => self.blocks[i - 1].last_id,
+                None => last_id
+            },
+            None => 0
+        };
+        let end = match to_timestamp {
+            Some(to_timestamp) => match self.blocks.iter().rev().find(|block| block.min_timestamp <= to_timestamp) {
+                Some(block) => max(block.last_id, start),
+                None => start
+            },
+            None => u64::max_value()
+        };
+        Interval::new(start, end)
+    }
+
+    /// Removes the blocks containing only events preceding the given event `id`.
+    pub fn remove_before(&mut self, id: u64) {
+        self.blocks.retain(|block| block.last_id >= id);
+    }
+}
+
+/// Exar DB's timestamp index block.
+///
+/// It describes the range of timestamps of consecutive events ending at a given event `id`,
+/// and it is persisted as a line in the timestamp index file of a log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimestampBlock {
+    /// The `id` of the last event of the block.
+    pub last_id: u64,
+    /// The minimum timestamp of the events in the block.
+    pub min_timestamp: u64,
+    /// The maximum timestamp of the events in the block.
+    pub max_timestamp: u64,
+    /// The number of events in the block.
+    pub nr_of_events: u64
+}
+
+impl TimestampBlock {
+    /// Returns a new `TimestampBlock` with the given last event `id`, range of timestamps and number of events.
+    pub fn new(last_id: u64, min_timestamp: u64, max_timestamp: u64, nr_of_events: u64) -> TimestampBlock {
+        TimestampBlock {
+            last_id: last_id,
+            min_timestamp: min_timestamp,
+            max_timestamp: max_timestamp,
+            nr_of_events: nr_of_events
+        }
+    }
+}
+
+impl ToTabSeparatedString for TimestampBlock {
+    fn to_tab_separated_string(&self) -> String {
+        tab_separated!(self.last_id, self.min_timestamp, self.max_timestamp, self.nr_of_events)
+    }
+}
+
+impl FromTabSeparatedStr for TimestampBlock {
+    fn from_tab_separated_str(s: &str) -> Result<TimestampBlock, ParseError> {
+        let mut parser = TabSeparatedParser::new(4, s);
+        let last_id = try!(parser.parse_next());
+        let min_timestamp = try!(parser.parse_next());
+        let max_timestamp = try!(parser.parse_next());
+        let nr_of_events = try!(parser.parse_next());
+        Ok(TimestampBlock::new(last_id, min_timestamp, max_timestamp, nr_of_events))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn test_insert() {
+        let mut timestamp_index = TimestampIndex::new(3);
+
+        assert_eq!(timestamp_index.last_id(), 0);
+        assert!(!timestamp_index.insert(1, 3000));
+        assert!(!timestamp_index.insert(2, 1000));
+        assert!(timestamp_index.insert(3, 2000));
+        assert!(!timestamp_index.insert(3, 9000));
+        assert!(!timestamp_index.insert(4, 4000));
+
+        assert_eq!(timestamp_index.last_id(), 4);
+        assert_eq!(timestamp_index.completed_blocks(), &[TimestampBlock::new(3, 1000, 3000, 3)]);
+        assert_eq!(timestamp_index.blocks.last(), Some(&TimestampBlock::new(4, 4000, 4000, 1)));
+    }
+
+    #[test]
+    fn test_interval() {
+        let mut timestamp_index = TimestampIndex::new(10);
+
+        assert_eq!(timestamp_index.interval(Some(1000), Some(2000)), Interval::new(0, 0));
+
+        for id in 1..36 {
+            timestamp_index.insert(id, id * 1000);
+        }
+
+        assert_eq!(timestamp_index.interval(None, None), Interval::new(0, u64::max_value()));
+        assert_eq!(timestamp_index.interval(Some(5000), Some(8000)), Interval::new(0, 10));
+        assert_eq!(timestamp_index.interval(Some(15000), Some(25000)), Interval::new(10, 30));
+        assert_eq!(timestamp_index.interval(Some(25000), None), Interval::new(20, u64::max_value()));
+        assert_eq!(timestamp_index.interval(None, Some(12000)), Interval::new(0, 20));
+        assert_eq!(timestamp_index.interval(Some(33000), Some(40000)), Interval::new(30, 35));
+        assert_eq!(timestamp_index.interval(Some(40000), Some(50000)), Interval::new(35, 35));
+        assert_eq!(timestamp_index.interval(Some(0), Some(500)), Interval::new(0, 0));
+
+        timestamp_index.insert(36, 500);
+
+        assert_eq!(timestamp_index.interval(Some(0), Some(500)), Interval::new(0, 36));
+    }
+
+    #[test]
+    fn test_remove_before() {
+        let mut timestamp_index = TimestampIndex::new(10);
+        for id in 1..36 {
+            timestamp_index.insert(id, id * 1000);
+        }
+
+        timestamp_index.remove_before(15);
+
+        assert_eq!(timestamp_index.completed_blocks().iter().map(|block| block.last_id).collect::<Vec<_>>(), vec![20, 30]);
+        assert_eq!(timestamp_index.last_id(), 35);
+    }
+
+    #[test]
+    fn test_block_tab_separated_encoding() {
+        let block = TimestampBlock::new(100, 1000, 2000, 10);
+        assert_encoded_eq!(block, "100\t1000\t2000\t10");
+        assert_decoded_eq!("100\t1000\t2000\t10", block);
+    }
+}
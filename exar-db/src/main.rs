@@ -22,10 +22,19 @@
 //! log4rs_path = "/path/to/log4rs.toml"
 //! [database]
 //! logs_path = "~/exar-db/data"
-//! scanners = { nr_of_scanners = 2, sleep_time_in_ms = 10 }
+//! scanners = { min_nr_of_scanners = 0, max_nr_of_scanners = 2, idle_timeout_in_ms = 60000, sleep_time_in_ms = 10 }
+//! durability = "IntervalMs(50)"
+//! segment_size = "MaxBytes(104857600)"
+//! event_stream_buffer_size = 1000
+//! tail_cache_size = 1000
 //! [database.collections.my-collection]
 //! routing_strategy = "Random"
-//! scanners = { nr_of_scanners = 4, sleep_time_in_ms = 5 }
+//! durability = "EveryEvent"
+//! scanners = { min_nr_of_scanners = 1, max_nr_of_scanners = 4, sleep_time_in_ms = 5 }
+//! retention = { max_age_in_ms = 604800000, check_interval_in_ms = 60000 }
+//! compaction = { key_tag_prefix = "key:", interval_in_ms = 600000 }
+//! consumer_groups = { ack_timeout_in_ms = 30000, max_deliveries = 5 }
+//! deduplication = { window_size = 100000, max_age_in_ms = 86400000 }
 //! [server]
 //! host = "127.0.0.1"
 //! port = 38580
@@ -35,6 +44,9 @@
 //!
 //! Then run Exar DB by specifying the config file location: `exar-db --config=/path/to/config.toml`.
 //!
+//! The `nr_of_scanners` setting of previous versions is no longer supported, configuration files using it
+//! must replace it with `min_nr_of_scanners` and `max_nr_of_scanners`, setting both to its value keeps the same scanners always running.
+//!
 //! For more information about the `database` and `server` configuration sections,
 //! check the documentation about
 //! [DatabaseConfig](https://bfil.github.io/exar-db/exar/struct.DatabaseConfig.html) and
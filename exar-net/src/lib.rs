@@ -4,6 +4,8 @@
 //! ## Protocol messages
 //! The protocol is text-based and uses line-separated messages,
 //! each message consists of tab-separated values.
+//! `PublishBatch`, `PublishBatchExpecting`, `PublishExpectingStreamVersion` and `PublishBatchExpectingStreamVersion` messages
+//! are the only ones spanning multiple lines.
 //!
 //! ### Connect
 //! Message used to initialize a connection to Exar DB.
@@ -40,6 +42,43 @@
 //! - The 3rd field is the event timestamp (in ms), if set to 0 the timestamp will be set by the event logger.
 //! - The 4th field is the event data/payload, it can contain tabs (`\t`) but new-lines (`\n`) must be escaped.
 //!
+//! The protocol is text-based, so the event data must be valid UTF-8: binary data can be published
+//! using the embedded database, but invalid UTF-8 sequences are replaced by `U+FFFD` when sent over TCP.
+//!
+//! ### PublishIdempotent
+//! Message used to publish an event carrying a unique client `id` into a collection, so that publishing it again
+//! (for instance after a timeout) is acknowledged with the `id` of the original event instead of publishing a duplicate.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! PublishIdempotent    client_id    tag1 tag2    timestamp    event_data
+//! ```
+//!
+//! - The 1st field is the string `PublishIdempotent`.
+//! - The 2nd field is the client `id` of the event, it must not contain tabs (`\t`).
+//! - The following fields contain the event, in the same format used by the `Publish` message.
+//!
+//! ### PublishToStream
+//! Message used to publish an event into a collection, appending it to the given stream,
+//! whose version is incremented and assigned to the event.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! PublishToStream    stream_id    tag1 tag2    timestamp    event_data
+//! ```
+//!
+//! - The 1st field is the string `PublishToStream`.
+//! - The 2nd field is the `id` of the stream, it must not contain tabs (`\t`).
+//! - The following fields contain the event, in the same format used by the `Publish` message.
+//!
+//! An event carrying a client `id` is appended to a stream using a `PublishIdempotentToStream` message instead:
+//!
+//! ```text
+//! PublishIdempotentToStream    client_id    stream_id    tag1 tag2    timestamp    event_data
+//! ```
+//!
 //! ### Published
 //! Message used to acknowledge a successfully published event.
 //!
@@ -48,7 +87,129 @@
 //! ```
 //!
 //! - The 1st field is the string `Published`.
-//! - The 2nd field is the `id` (or sequence number) of the event that has been published.
+//! - The 2nd field is the `id` (or sequence number) of the event that has been published,
+//!   or of the original event if an event with the same client `id` has already been published.
+//!
+//! ### PublishBatch
+//! Message used to publish a batch of events into a collection atomically,
+//! either all the events are published with contiguous `id`s or none of them is.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! PublishBatch    number_of_events
+//! Publish    tag1 tag2    timestamp    event_data
+//! Publish    tag1 tag2    timestamp    event_data
+//! ```
+//!
+//! - The first line contains the string `PublishBatch` and the number of events in the batch.
+//! - Each following line contains an event, in the same format used by the `Publish` message,
+//!   or by the `PublishIdempotent`, `PublishToStream` or `PublishIdempotentToStream` message
+//!   if the event carries a client `id` and/or belongs to a stream.
+//!
+//! ### PublishedBatch
+//! Message used to acknowledge a successfully published batch of events.
+//!
+//! ```text
+//! PublishedBatch    first_event_id    last_event_id
+//! ```
+//!
+//! - The 1st field is the string `PublishedBatch`.
+//! - The 2nd field is the `id` (or sequence number) of the first event of the batch.
+//! - The 3rd field is the `id` (or sequence number) of the last event of the batch.
+//!
+//! If the events of the batch carrying a client `id` have already been published, the `id`s of the original events are returned.
+//!
+//! ### PublishExpecting
+//! Message used to publish an event into a collection only if the `id` of the last event published is the expected one,
+//! it is acknowledged by a `Published` message, or by a `WrongExpectedVersion` error if any other event has been published.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! PublishExpecting    expected_last_event_id    tag1 tag2    timestamp    event_data
+//! ```
+//!
+//! - The 1st field is the string `PublishExpecting`.
+//! - The 2nd field is the expected `id` (or sequence number) of the last event published, 0 if none is expected.
+//! - The following fields contain the event, in the same format used by the `Publish` message.
+//!
+//! ### PublishBatchExpecting
+//! Message used to publish a batch of events into a collection atomically only if the `id` of the last event published
+//! is the expected one, it is acknowledged by a `PublishedBatch` message, or by a `WrongExpectedVersion` error
+//! if any other event has been published.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! PublishBatchExpecting    expected_last_event_id    number_of_events
+//! Publish    tag1 tag2    timestamp    event_data
+//! Publish    tag1 tag2    timestamp    event_data
+//! ```
+//!
+//! - The first line contains the string `PublishBatchExpecting`, the expected `id` of the last event published
+//!   and the number of events in the batch.
+//! - Each following line contains an event, in the same format used by the `PublishBatch` message.
+//!
+//! ### PublishExpectingStreamVersion
+//! Message used to publish an event into its stream only if the current version of the stream is the expected one,
+//! it is acknowledged by a `Published` message, or by a `WrongExpectedVersion` error
+//! if any other event has been published into the stream.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! PublishExpectingStreamVersion    expected_stream_version
+//! PublishToStream    stream_id    tag1 tag2    timestamp    event_data
+//! ```
+//!
+//! - The first line contains the string `PublishExpectingStreamVersion` and the expected version of the stream,
+//!   0 if the stream is not expected to exist.
+//! - The following line contains the event, in the same format used by the `PublishBatch` message,
+//!   it must belong to a stream.
+//!
+//! ### PublishBatchExpectingStreamVersion
+//! Message used to publish a batch of events into their stream atomically only if the current version of the stream
+//! is the expected one, it is acknowledged by a `PublishedBatch` message, or by a `WrongExpectedVersion` error
+//! if any other event has been published into the stream.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! PublishBatchExpectingStreamVersion    expected_stream_version    number_of_events
+//! PublishToStream    stream_id    tag1 tag2    timestamp    event_data
+//! PublishToStream    stream_id    tag1 tag2    timestamp    event_data
+//! ```
+//!
+//! - The first line contains the string `PublishBatchExpectingStreamVersion`, the expected version of the stream
+//!   and the number of events in the batch.
+//! - Each following line contains an event, in the same format used by the `PublishBatch` message,
+//!   all the events must belong to the same stream.
+//!
+//! ### GetStreamVersion
+//! Message used to retrieve the version of the last event published into a stream.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! GetStreamVersion    stream_id
+//! ```
+//!
+//! - The 1st field is the string `GetStreamVersion`.
+//! - The 2nd field is the `id` of the stream.
+//!
+//! ### StreamVersion
+//! Message containing the version of the last event published into a stream.
+//!
+//! *It is received after a `GetStreamVersion`*.
+//!
+//! ```text
+//! StreamVersion    stream_id    version
+//! ```
+//!
+//! - The 1st field is the string `StreamVersion`.
+//! - The 2nd field is the `id` of the stream.
+//! - The 3rd field is the version of the last event published into the stream, 0 if the stream does not exist.
 //!
 //! ### Subscribe
 //! Message used to subscribe to an event stream.
@@ -56,14 +217,40 @@
 //! *It can be used only after a successful connection has been established*.
 //!
 //! ```text
-//! Subscribe    live    offset    limit    [tag1]
+//! Subscribe    live    offset    limit    [tag_expression]    [from_timestamp]    [to_timestamp]    [reverse]    [payload_filter]
 //! ```
 //!
 //! - The 1st field is the string `Subscribe`.
 //! - The 2nd field is a boolean specifying wether to keep the subscription listening to real-time events.
 //! - The 3rd field is the query offset.
 //! - The 4th field is the maximum number of events to consume, if set to 0 a limit is not set.
-//! - The 5th field is the tag the events must contain (optional).
+//! - The 5th field is the tag expression the events must match (optional), it is left empty if not set but followed by a time range.
+//!   A tag expression is either a single tag or a combination of tags using the `AND`, `OR` and `NOT` operators
+//!   and parentheses, for example `(order AND eu) OR refund NOT test`, a field without operators or parentheses is a single tag.
+//! - The 6th field is the minimum timestamp (in ms) of the events (optional), if set to 0 it is not set.
+//! - The 7th field is the maximum timestamp (in ms) of the events (optional), if set to 0 it is not set.
+//! - The 8th field is a boolean specifying wether to return the events in descending order, starting from the latest one
+//!   (optional, `false` if not set), a reverse subscription never listens to real-time events.
+//! - The 9th field is the filter the event data must match (optional), using one of the following syntaxes:
+//!   `contains:<text>`, `regex:<pattern>` or `json:<path> <operator> <value>` (for example `json:$.order.amount >= 100`).
+//!
+//! Optional fields can be left out from the end of the message, but they must be set (to their default value if needed)
+//! when followed by a field that is set.
+//!
+//! ### Query
+//! Message used to subscribe to an event stream using the query language.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! Query    query
+//! ```
+//!
+//! - The 1st field is the string `Query`.
+//! - The 2nd field is the query, for example `LIVE FROM 100 LIMIT 10 WHERE tag = 'a' AND ts > 1700000000000`,
+//!   a query that cannot be parsed results in an `Error` containing the position of the syntax error.
+//!
+//! The syntax of the query language is described in the documentation of `exar::Query`.
 //!
 //! ### Subscribed
 //! Message used to acknowledge a successful subscription.
@@ -100,10 +287,216 @@
 //!
 //! - A single field containing the string `EndOfEventStream`.
 //!
+//! ### Unsubscribe
+//! Message used to cancel the event stream or live aggregate stream being received.
+//!
+//! *It can be used only after a successful connection has been established, also during an event stream*.
+//!
+//! ```text
+//! Unsubscribe
+//! ```
+//!
+//! - A single field containing the string `Unsubscribe`.
+//!
+//! No message is returned, the stream being received is ended promptly by an `EndOfEventStream`
+//! (preceded by the final `Aggregated` message for a live aggregate), and the consumer group joined is left.
+//!
+//! ### Aggregate
+//! Message used to aggregate the events targeted by a query, which are counted on the server.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! Aggregate    group_by_tag    bucket_size    update_interval    query
+//! ```
+//!
+//! - The 1st field is the string `Aggregate`.
+//! - The 2nd field is a boolean specifying wether to group the events by tag.
+//! - The 3rd field is the size (in ms) of the timestamp buckets to group the events by, if set to 0 they are not grouped by timestamp.
+//! - The 4th field is the interval (in ms) between the updates of a live aggregate, it must be greater than 0.
+//! - The 5th field is the query, written in the query language used by the `Query` message.
+//!
+//! The aggregate of a current query is returned as a single `Aggregated` message, while a live query
+//! results in an `Aggregated` message for each update, followed by an `EndOfEventStream` if the query ends.
+//!
+//! ### Aggregated
+//! Message containing an aggregate, or an update of a live aggregate.
+//!
+//! *It is received after an `Aggregate`*.
+//!
+//! ```text
+//! Aggregated    count    min_id    max_id    min_timestamp    max_timestamp    count:bucket:tag count:bucket:tag
+//! ```
+//!
+//! - The 1st field is the string `Aggregated`.
+//! - The 2nd field is the number of aggregated events.
+//! - The 3rd and 4th fields are the minimum and maximum `id` of the aggregated events, set to 0 if there are none.
+//! - The 5th and 6th fields are the minimum and maximum timestamp (in ms) of the aggregated events, set to 0 if there are none.
+//! - The 7th field is a space-separated list of groups, each one containing the number of events in the group,
+//!   the first timestamp of its bucket and its tag, separated by colons and left empty if the events are not grouped by them.
+//!
+//! ### SubscribeDurable
+//! Message used to subscribe to an event stream on behalf of a durable consumer, using the query language.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! SubscribeDurable    consumer_name    query
+//! ```
+//!
+//! - The 1st field is the string `SubscribeDurable`.
+//! - The 2nd field is the name of the durable consumer, which must not contain whitespace.
+//! - The 3rd field is the query, written in the query language used by the `Query` message.
+//!
+//! The event stream resumes right after the last event acknowledged by the consumer, unless the query starts further ahead,
+//! and it is returned as for a `Query` message. Durable subscriptions cannot be reversed.
+//!
+//! ### Acknowledge
+//! Message used to acknowledge the processing of the events up to the given `id` on behalf of a durable consumer,
+//! the checkpoint of the consumer is stored on the server and it only ever moves forward.
+//!
+//! *It can be used only after a successful connection has been established, also during an event stream*.
+//!
+//! ```text
+//! Acknowledge    consumer_name    event_id
+//! ```
+//!
+//! - The 1st field is the string `Acknowledge`.
+//! - The 2nd field is the name of the durable consumer.
+//! - The 3rd field is the `id` of the last event processed by the consumer.
+//!
+//! No message is returned for a successful acknowledgement, while a failure results in an `Error`.
+//!
+//! ### ListCheckpoints
+//! Message used to list the checkpoints of the durable consumers of the collection.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! ListCheckpoints
+//! ```
+//!
+//! - A single field containing the string `ListCheckpoints`.
+//!
+//! ### ResetCheckpoint
+//! Message used to reset the checkpoint of a durable consumer, even to an event preceding its current checkpoint.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! ResetCheckpoint    consumer_name    event_id
+//! ```
+//!
+//! - The 1st field is the string `ResetCheckpoint`.
+//! - The 2nd field is the name of the durable consumer.
+//! - The 3rd field is the `id` of the event the consumer resumes after, set to 0 to start from the earliest event retained.
+//!
+//! ### DeleteCheckpoint
+//! Message used to delete the checkpoint of a durable consumer.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! DeleteCheckpoint    consumer_name
+//! ```
+//!
+//! - The 1st field is the string `DeleteCheckpoint`.
+//! - The 2nd field is the name of the durable consumer.
+//!
+//! ### Checkpoints
+//! Message containing the checkpoints of the durable consumers of the collection.
+//!
+//! *It is received after a `ListCheckpoints`, `ResetCheckpoint` or `DeleteCheckpoint`*.
+//!
+//! ```text
+//! Checkpoints    event_id:consumer_name event_id:consumer_name
+//! ```
+//!
+//! - The 1st field is the string `Checkpoints`.
+//! - The 2nd field is a space-separated list of checkpoints, each one containing the `id` of the last event
+//!   acknowledged by a consumer and its name, separated by a colon.
+//!
+//! ### JoinGroup
+//! Message used to join a consumer group, which shares the events matching its query among its members.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! JoinGroup    group_name    query
+//! ```
+//!
+//! - The 1st field is the string `JoinGroup`.
+//! - The 2nd field is the name of the consumer group, which must not contain whitespace.
+//! - The 3rd field is the query, written in the query language used by the `Query` message,
+//!   it is used to start the consumer group if it is not running and ignored otherwise.
+//!
+//! The event stream is returned as for a `Query` message, but each event is delivered to a single member of the group,
+//! which must then `Ack` or `Nack` it: events that are not acknowledged within the configured timeout, negatively acknowledged
+//! or left in flight by a member leaving the group are delivered again, up to the configured maximum number of deliveries,
+//! after which they are published to the dead-letter collection named `collection_name.group_name.dead-letters`.
+//! Consumer groups cannot be reversed.
+//!
+//! ### Ack
+//! Message used to acknowledge the processing of an event delivered by the consumer group joined.
+//!
+//! *It can be used only after joining a consumer group, also during its event stream*.
+//!
+//! ```text
+//! Ack    event_id
+//! ```
+//!
+//! - The 1st field is the string `Ack`.
+//! - The 2nd field is the `id` of the event processed.
+//!
+//! ### Nack
+//! Message used to negatively acknowledge an event delivered by the consumer group joined, so that it is delivered again.
+//!
+//! *It can be used only after joining a consumer group, also during its event stream*.
+//!
+//! ```text
+//! Nack    event_id
+//! ```
+//!
+//! - The 1st field is the string `Nack`.
+//! - The 2nd field is the `id` of the event that could not be processed.
+//!
+//! No message is returned for a successful `Ack` or `Nack`, while a failure results in an `Error`.
+//!
+//! ### DescribeGroup
+//! Message used to describe a consumer group of the collection.
+//!
+//! *It can be used only after a successful connection has been established*.
+//!
+//! ```text
+//! DescribeGroup    group_name
+//! ```
+//!
+//! - The 1st field is the string `DescribeGroup`.
+//! - The 2nd field is the name of the consumer group.
+//!
+//! ### ConsumerGroup
+//! Message containing the description of a consumer group.
+//!
+//! *It is received after a `DescribeGroup`*.
+//!
+//! ```text
+//! ConsumerGroup    group_name    position    lag    nr_of_dead_letters    member_id:events_in_flight member_id:events_in_flight
+//! ```
+//!
+//! - The 1st field is the string `ConsumerGroup`.
+//! - The 2nd field is the name of the consumer group.
+//! - The 3rd field is the `id` of the last event up to which all events have been acknowledged or dead-lettered.
+//! - The 4th field is the number of events published after the position of the group.
+//! - The 5th field is the number of events published to the dead-letter collection of the group.
+//! - The 6th field is a space-separated list of members, each one containing its `id` and the number of events
+//!   delivered to it and not yet acknowledged, separated by a colon.
+//!
 //! ### Error
 //! Message containing an error.
 //!
-//! *It can be received after a `Connect`, `Publish`, `Subscribe`, or during an event stream*.
+//! *It can be received after a `Connect`, `Publish`, `PublishBatch`, `PublishExpecting`, `PublishBatchExpecting`,
+//! `PublishExpectingStreamVersion`, `PublishBatchExpectingStreamVersion`, `GetStreamVersion`, `Subscribe`, `Query`, `Aggregate`, `SubscribeDurable`, `Acknowledge`, `ListCheckpoints`, `ResetCheckpoint`, `DeleteCheckpoint`, `JoinGroup`, `Ack`, `Nack`, `DescribeGroup`,
+//! or during an event stream*.
 //!
 //! ```text
 //! Error    type    [subtype]    description
@@ -112,12 +505,15 @@
 //! - The 1st field is the string `Error`.
 //! - The 2nd field is the type of the error, possible values are:
 //!   `AuthenticationError`, `ConnectionError`, `EventStreamError`, `IoError`, `ParseError`,
-//!   `SubscriptionError`, `ValidationError`.
+//!   `RetentionError`, `SubscriptionError`, `ValidationError`, `WrongExpectedVersion`.
 //! - The 3rd field is the sub-type of the error (optional), possible values are:
 //!   `Empty` or `Closed` if the error type is `EventStreamError`,
 //!   `ParseError` or `MissingField` if the error type is `ParseError`,
-//!   or a stringified value of `std::io::ErrorKind` if the error type is `IoError`.
-//! - The 4th field is the error message/description.
+//!   a stringified value of `std::io::ErrorKind` if the error type is `IoError`,
+//!   the `id` of the earliest retained event if the error type is `RetentionError`,
+//!   or the expected `id` of the last event published if the error type is `WrongExpectedVersion`.
+//! - The 4th field is the error message/description,
+//!   or the actual `id` of the last event published if the error type is `WrongExpectedVersion`.
 //!
 
 #[macro_use]
@@ -9,12 +9,65 @@ pub enum TcpMessage {
     Connect(String, Option<String>, Option<String>),
     /// Message used to acknowledge a successful connection.
     Connected,
-    /// Message used to publish an event into a collection.
+    /// Message used to publish an event into a collection, it is deduplicated if it carries a client `id`
+    /// and appended to its stream if it belongs to one.
     Publish(Event),
     /// Message used to acknowledge a successfully published event.
     Published(u64),
-    /// Message used to subscribe to an event stream.
-    Subscribe(bool, u64, Option<u64>, Option<String>),
+    /// Message used to publish a batch of events into a collection atomically.
+    PublishBatch(Vec<Event>),
+    /// Message used to acknowledge a successfully published batch of events,
+    /// containing the `id`s of the first and last events.
+    PublishedBatch(u64, u64),
+    /// Message used to publish an event into a collection only if the `id` of the last event published is the given one.
+    PublishExpecting(Event, u64),
+    /// Message used to publish a batch of events into a collection atomically
+    /// only if the `id` of the last event published is the given one.
+    PublishBatchExpecting(Vec<Event>, u64),
+    /// Message used to publish an event into its stream only if the current version of the stream is the given one.
+    PublishExpectingStreamVersion(Event, u64),
+    /// Message used to publish a batch of events into their stream atomically
+    /// only if the current version of the stream is the given one.
+    PublishBatchExpectingStreamVersion(Vec<Event>, u64),
+    /// Message used to retrieve the version of the last event published into a stream.
+    GetStreamVersion(String),
+    /// Message containing the version of the last event published into a stream, or 0 if the stream does not exist.
+    StreamVersion(String, u64),
+    /// Message used to subscribe to an event stream, optionally targeting the events matching a tag expression,
+    /// the events within a time range (timestamps in ms, inclusive), the events in descending order
+    /// and the events whose data matches a payload filter.
+    Subscribe(bool, u64, Option<u64>, Option<TagExpression>, Option<u64>, Option<u64>, bool, Option<PayloadFilter>),
+    /// Message used to subscribe to an event stream using a query written in the query language.
+    Query(Query),
+    /// Message used to aggregate the events targeted by a query written in the query language.
+    Aggregate(Aggregation, Query),
+    /// Message containing an aggregate, or an update of a live aggregate.
+    Aggregated(Aggregate),
+    /// Message used to subscribe to an event stream on behalf of a durable consumer,
+    /// resuming after its last acknowledged event, using a query written in the query language.
+    SubscribeDurable(String, Query),
+    /// Message used to acknowledge the processing of the events up to the given `id` on behalf of a durable consumer.
+    Acknowledge(String, u64),
+    /// Message used to list the checkpoints of the durable consumers of a collection.
+    ListCheckpoints,
+    /// Message used to reset the checkpoint of a durable consumer to the given event `id`.
+    ResetCheckpoint(String, u64),
+    /// Message used to delete the checkpoint of a durable consumer.
+    DeleteCheckpoint(String),
+    /// Message containing the checkpoints of the durable consumers of a collection.
+    Checkpoints(Checkpoints),
+    /// Message used to join a consumer group, which is created using a query written in the query language if not running.
+    JoinGroup(String, Query),
+    /// Message used to acknowledge the processing of an event handed by the consumer group joined.
+    Ack(u64),
+    /// Message used to negatively acknowledge an event handed by the consumer group joined, so that it is delivered again.
+    Nack(u64),
+    /// Message used to describe a consumer group.
+    DescribeGroup(String),
+    /// Message containing the description of a consumer group, including its members and lag.
+    ConsumerGroup(ConsumerGroupInfo),
+    /// Message used to cancel the event stream being received, which then ends promptly.
+    Unsubscribe,
     /// Message used to acknowledge a successful subscription.
     Subscribed,
     /// Message containing an event.
@@ -25,6 +78,26 @@ pub enum TcpMessage {
     Error(DatabaseError)
 }
 
+impl TcpMessage {
+    /// Returns the number of lines following the given first line of a TCP message.
+    ///
+    /// Most messages consist of a single line, while a `PublishBatch`, `PublishBatchExpecting`
+    /// or `PublishBatchExpectingStreamVersion` message is followed by a `Publish` line for each event in the batch,
+    /// and a `PublishExpectingStreamVersion` message is followed by the `Publish` line of its event.
+    pub fn nr_of_following_lines(first_line: &str) -> usize {
+        let mut parser = TabSeparatedParser::new(3, first_line);
+        let message_type: Result<String, ParseError> = parser.parse_next();
+        match message_type {
+            Ok(ref message_type) if message_type == "PublishBatch" => parser.parse_next().unwrap_or(0),
+            Ok(ref message_type) if message_type == "PublishBatchExpecting" || message_type == "PublishBatchExpectingStreamVersion" => {
+                parser.parse_next::<u64>().and_then(|_| parser.parse_next()).unwrap_or(0)
+            },
+            Ok(ref message_type) if message_type == "PublishExpectingStreamVersion" => 1,
+            _ => 0
+        }
+    }
+}
+
 impl ToTabSeparatedString for TcpMessage {
     fn to_tab_separated_string(&self) -> String {
         match *self {
@@ -37,18 +110,56 @@ impl ToTabSeparatedString for TcpMessage {
                 }
             },
             TcpMessage::Connected => tab_separated!("Connected"),
-            TcpMessage::Publish(Event { ref data, ref tags, ref timestamp, .. }) => {
-                tab_separated!("Publish", tags.join(" "), timestamp, data)
-            },
+            TcpMessage::Publish(ref event) => publish_line(event),
             TcpMessage::Published(ref event_id) => tab_separated!("Published", event_id),
-            TcpMessage::Subscribe(ref live, ref offset, ref limit, ref tag) => {
-                match (limit, tag) {
-                    (&Some(ref limit), &Some(ref tag)) => tab_separated!("Subscribe", live, offset, limit, tag),
-                    (&Some(ref limit), &None) => tab_separated!("Subscribe", live, offset, limit),
-                    (&None, &Some(ref tag)) => tab_separated!("Subscribe", live, offset, 0, tag),
-                    _ => tab_separated!("Subscribe", live, offset)
-                }
+            TcpMessage::PublishBatch(ref events) => {
+                let mut lines = vec![tab_separated!("PublishBatch", events.len())];
+                lines.extend(events.iter().map(publish_line));
+                lines.join("\n")
+            },
+            TcpMessage::PublishedBatch(ref first_event_id, ref last_event_id) => {
+                tab_separated!("PublishedBatch", first_event_id, last_event_id)
             },
+            TcpMessage::PublishExpecting(Event { ref data, ref tags, ref timestamp, .. }, ref expected_last_id) => {
+                tab_separated!("PublishExpecting", expected_last_id, tags.join(" "), timestamp, String::from_utf8_lossy(data))
+            },
+            TcpMessage::PublishBatchExpecting(ref events, ref expected_last_id) => {
+                let mut lines = vec![tab_separated!("PublishBatchExpecting", expected_last_id, events.len())];
+                lines.extend(events.iter().map(publish_line));
+                lines.join("\n")
+            },
+            TcpMessage::PublishExpectingStreamVersion(ref event, ref expected_version) => {
+                vec![tab_separated!("PublishExpectingStreamVersion", expected_version), publish_line(event)].join("\n")
+            },
+            TcpMessage::PublishBatchExpectingStreamVersion(ref events, ref expected_version) => {
+                let mut lines = vec![tab_separated!("PublishBatchExpectingStreamVersion", expected_version, events.len())];
+                lines.extend(events.iter().map(publish_line));
+                lines.join("\n")
+            },
+            TcpMessage::GetStreamVersion(ref stream_id) => tab_separated!("GetStreamVersion", stream_id),
+            TcpMessage::StreamVersion(ref stream_id, ref version) => tab_separated!("StreamVersion", stream_id, version),
+            TcpMessage::Subscribe(live, offset, limit, ref tag, from_timestamp, to_timestamp, reverse, ref payload_filter) => {
+                let fields = subscribe_fields(live, offset, limit, tag, from_timestamp, to_timestamp, reverse, payload_filter);
+                tab_separated!("Subscribe", fields.join("\t"))
+            },
+            TcpMessage::Query(ref query) => tab_separated!("Query", query),
+            TcpMessage::Aggregate(ref aggregation, ref query) => {
+                tab_separated!("Aggregate", aggregation.group_by_tag, aggregation.bucket_size_in_ms.unwrap_or(0),
+                               aggregation.update_interval_in_ms, query)
+            },
+            TcpMessage::Aggregated(ref aggregate) => tab_separated!("Aggregated", aggregate.to_tab_separated_string()),
+            TcpMessage::SubscribeDurable(ref consumer_name, ref query) => tab_separated!("SubscribeDurable", consumer_name, query),
+            TcpMessage::Acknowledge(ref consumer_name, ref event_id) => tab_separated!("Acknowledge", consumer_name, event_id),
+            TcpMessage::ListCheckpoints => tab_separated!("ListCheckpoints"),
+            TcpMessage::ResetCheckpoint(ref consumer_name, ref event_id) => tab_separated!("ResetCheckpoint", consumer_name, event_id),
+            TcpMessage::DeleteCheckpoint(ref consumer_name) => tab_separated!("DeleteCheckpoint", consumer_name),
+            TcpMessage::Checkpoints(ref checkpoints) => tab_separated!("Checkpoints", checkpoints.to_tab_separated_string()),
+            TcpMessage::JoinGroup(ref group_name, ref query) => tab_separated!("JoinGroup", group_name, query),
+            TcpMessage::Ack(ref event_id) => tab_separated!("Ack", event_id),
+            TcpMessage::Nack(ref event_id) => tab_separated!("Nack", event_id),
+            TcpMessage::DescribeGroup(ref group_name) => tab_separated!("DescribeGroup", group_name),
+            TcpMessage::ConsumerGroup(ref info) => tab_separated!("ConsumerGroup", info.to_tab_separated_string()),
+            TcpMessage::Unsubscribe => tab_separated!("Unsubscribe"),
             TcpMessage::Subscribed => tab_separated!("Subscribed"),
             TcpMessage::Event(ref event) => tab_separated!("Event", event.to_tab_separated_string()),
             TcpMessage::EndOfEventStream => tab_separated!("EndOfEventStream"),
@@ -72,28 +183,186 @@ impl FromTabSeparatedStr for TcpMessage {
             },
             "Connected" => Ok(TcpMessage::Connected),
             "Publish" => {
+                let message_data: String = try!(parser.parse_next());
+                parse_event(&message_data).and_then(|event| Ok(TcpMessage::Publish(event)))
+            },
+            "PublishIdempotent" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let client_id: String = try!(parser.parse_next());
+                let event_data: String = try!(parser.parse_next());
+                parse_event(&event_data).and_then(|event| Ok(TcpMessage::Publish(event.with_client_id(&client_id))))
+            },
+            "PublishToStream" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let stream_id: String = try!(parser.parse_next());
+                let event_data: String = try!(parser.parse_next());
+                parse_event(&event_data).and_then(|event| Ok(TcpMessage::Publish(event.with_stream(&stream_id))))
+            },
+            "PublishIdempotentToStream" => {
                 let message_data: String = try!(parser.parse_next());
                 let mut parser = TabSeparatedParser::new(3, &message_data);
-                let tags: String = try!(parser.parse_next());
-                let timestamp = try!(parser.parse_next());
-                let data: String = try!(parser.parse_next());
-                let tags: Vec<_> = tags.split(' ').collect();
-                Ok(TcpMessage::Publish(Event::new(&data, tags).with_timestamp(timestamp)))
+                let client_id: String = try!(parser.parse_next());
+                let stream_id: String = try!(parser.parse_next());
+                let event_data: String = try!(parser.parse_next());
+                parse_event(&event_data).and_then(|event| {
+                    Ok(TcpMessage::Publish(event.with_client_id(&client_id).with_stream(&stream_id)))
+                })
             },
             "Published" => {
                 let event_id = try!(parser.parse_next());
                 Ok(TcpMessage::Published(event_id))
             },
+            "PublishBatch" => {
+                let message_data: String = try!(parser.parse_next());
+                parse_batch(&message_data).and_then(|events| Ok(TcpMessage::PublishBatch(events)))
+            },
+            "PublishedBatch" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let first_event_id = try!(parser.parse_next());
+                let last_event_id = try!(parser.parse_next());
+                Ok(TcpMessage::PublishedBatch(first_event_id, last_event_id))
+            },
+            "PublishExpecting" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let expected_last_id = try!(parser.parse_next());
+                let event_data: String = try!(parser.parse_next());
+                parse_event(&event_data).and_then(|event| Ok(TcpMessage::PublishExpecting(event, expected_last_id)))
+            },
+            "PublishBatchExpecting" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let expected_last_id = try!(parser.parse_next());
+                let batch_data: String = try!(parser.parse_next());
+                parse_batch(&batch_data).and_then(|events| Ok(TcpMessage::PublishBatchExpecting(events, expected_last_id)))
+            },
+            "PublishExpectingStreamVersion" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut lines = message_data.splitn(2, '\n');
+                let expected_version = match lines.next().map(|expected_version| expected_version.parse()) {
+                    Some(Ok(expected_version)) => expected_version,
+                    _ => return Err(ParseError::ParseError("invalid expected stream version".to_owned()))
+                };
+                let line = try!(lines.next().ok_or(ParseError::MissingField(1)));
+                parse_publish_line(line).and_then(|event| Ok(TcpMessage::PublishExpectingStreamVersion(event, expected_version)))
+            },
+            "PublishBatchExpectingStreamVersion" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let expected_version = try!(parser.parse_next());
+                let batch_data: String = try!(parser.parse_next());
+                parse_batch(&batch_data).and_then(|events| Ok(TcpMessage::PublishBatchExpectingStreamVersion(events, expected_version)))
+            },
+            "GetStreamVersion" => {
+                let stream_id = try!(parser.parse_next());
+                Ok(TcpMessage::GetStreamVersion(stream_id))
+            },
+            "StreamVersion" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let stream_id = try!(parser.parse_next());
+                let version = try!(parser.parse_next());
+                Ok(TcpMessage::StreamVersion(stream_id, version))
+            },
             "Subscribe" => {
                 let message_data: String = try!(parser.parse_next());
-                let mut parser = TabSeparatedParser::new(4, &message_data);
+                let mut parser = TabSeparatedParser::new(8, &message_data);
                 let live = try!(parser.parse_next());
                 let offset = try!(parser.parse_next());
                 let mut limit = parser.parse_next().ok();
                 if limit.unwrap_or(0) == 0 { limit = None }
-                let tag = parser.parse_next().ok();
-                Ok(TcpMessage::Subscribe(live, offset, limit, tag))
+                let tag = match parser.parse_next::<String>().ok() {
+                    Some(ref tag) if !tag.is_empty() => Some(try!(parse_tag_field(tag))),
+                    _ => None
+                };
+                let from_timestamp = parser.parse_next().ok().and_then(|timestamp| if timestamp == 0 { None } else { Some(timestamp) });
+                let to_timestamp = parser.parse_next().ok().and_then(|timestamp| if timestamp == 0 { None } else { Some(timestamp) });
+                let reverse = parser.parse_next().unwrap_or(false);
+                let payload_filter = match parser.parse_next::<String>().ok() {
+                    Some(ref payload_filter) if !payload_filter.is_empty() => Some(try!(payload_filter.parse())),
+                    _ => None
+                };
+                Ok(TcpMessage::Subscribe(live, offset, limit, tag, from_timestamp, to_timestamp, reverse, payload_filter))
+            },
+            "Query" => {
+                let message_data: String = try!(parser.parse_next());
+                message_data.parse().and_then(|query| Ok(TcpMessage::Query(query)))
+            },
+            "Aggregate" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(4, &message_data);
+                let group_by_tag = try!(parser.parse_next());
+                let bucket_size_in_ms = try!(parser.parse_next());
+                let update_interval_in_ms = try!(parser.parse_next());
+                if update_interval_in_ms == 0 {
+                    return Err(ParseError::ParseError("aggregate update interval must be greater than 0".to_owned()))
+                }
+                let query: String = parser.parse_next().unwrap_or_default();
+                let mut aggregation = Aggregation::new().bucket_size(bucket_size_in_ms).update_interval(update_interval_in_ms);
+                aggregation.group_by_tag = group_by_tag;
+                query.parse().and_then(|query| Ok(TcpMessage::Aggregate(aggregation, query)))
+            },
+            "Aggregated" => {
+                let message_data: String = try!(parser.parse_next());
+                Aggregate::from_tab_separated_str(&message_data).and_then(|aggregate| Ok(TcpMessage::Aggregated(aggregate)))
+            },
+            "SubscribeDurable" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let consumer_name = try!(parser.parse_next());
+                let query: String = parser.parse_next().unwrap_or_default();
+                query.parse().and_then(|query| Ok(TcpMessage::SubscribeDurable(consumer_name, query)))
+            },
+            "Acknowledge" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let consumer_name = try!(parser.parse_next());
+                let event_id = try!(parser.parse_next());
+                Ok(TcpMessage::Acknowledge(consumer_name, event_id))
+            },
+            "ListCheckpoints" => Ok(TcpMessage::ListCheckpoints),
+            "ResetCheckpoint" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let consumer_name = try!(parser.parse_next());
+                let event_id = try!(parser.parse_next());
+                Ok(TcpMessage::ResetCheckpoint(consumer_name, event_id))
+            },
+            "DeleteCheckpoint" => {
+                let consumer_name = try!(parser.parse_next());
+                Ok(TcpMessage::DeleteCheckpoint(consumer_name))
+            },
+            "Checkpoints" => {
+                let message_data: String = parser.parse_next().unwrap_or_default();
+                Checkpoints::from_tab_separated_str(&message_data).and_then(|checkpoints| Ok(TcpMessage::Checkpoints(checkpoints)))
+            },
+            "JoinGroup" => {
+                let message_data: String = try!(parser.parse_next());
+                let mut parser = TabSeparatedParser::new(2, &message_data);
+                let group_name = try!(parser.parse_next());
+                let query: String = parser.parse_next().unwrap_or_default();
+                query.parse().and_then(|query| Ok(TcpMessage::JoinGroup(group_name, query)))
+            },
+            "Ack" => {
+                let event_id = try!(parser.parse_next());
+                Ok(TcpMessage::Ack(event_id))
+            },
+            "Nack" => {
+                let event_id = try!(parser.parse_next());
+                Ok(TcpMessage::Nack(event_id))
+            },
+            "DescribeGroup" => {
+                let group_name = try!(parser.parse_next());
+                Ok(TcpMessage::DescribeGroup(group_name))
+            },
+            "ConsumerGroup" => {
+                let message_data: String = try!(parser.parse_next());
+                ConsumerGroupInfo::from_tab_separated_str(&message_data).and_then(|info| Ok(TcpMessage::ConsumerGroup(info)))
             },
+            "Unsubscribe" => Ok(TcpMessage::Unsubscribe),
             "Subscribed" => Ok(TcpMessage::Subscribed),
             "Event" => {
                 let message_data: String = try!(parser.parse_next());
@@ -123,14 +392,51 @@ impl Display for TcpMessage {
             TcpMessage::Connected => write!(f, "Connected"),
             TcpMessage::Publish(ref event) => write!(f, "Publish({})", event),
             TcpMessage::Published(ref event_id) => write!(f, "Published({})", event_id),
-            TcpMessage::Subscribe(ref live, ref offset, ref limit, ref tag) => {
-                match (limit, tag) {
-                    (&Some(ref limit), &Some(ref tag)) => write!(f, "Subscribe({}, {}, {}, {})", live, offset, limit, tag),
-                    (&Some(ref limit), &None) => write!(f, "Subscribe({}, {}, {})", live, offset, limit),
-                    (&None, &Some(ref tag)) => write!(f, "Subscribe({}, {}, {}, {})", live, offset, 0, tag),
-                    _ => write!(f, "Subscribe({}, {})", live, offset)
-                }
+            TcpMessage::PublishBatch(ref events) => {
+                let events: Vec<_> = events.iter().map(|event| event.to_string()).collect();
+                write!(f, "PublishBatch([{}])", events.join(", "))
+            },
+            TcpMessage::PublishedBatch(ref first_event_id, ref last_event_id) => {
+                write!(f, "PublishedBatch({}, {})", first_event_id, last_event_id)
+            },
+            TcpMessage::PublishExpecting(ref event, ref expected_last_id) => {
+                write!(f, "PublishExpecting({}, {})", event, expected_last_id)
+            },
+            TcpMessage::PublishBatchExpecting(ref events, ref expected_last_id) => {
+                let events: Vec<_> = events.iter().map(|event| event.to_string()).collect();
+                write!(f, "PublishBatchExpecting([{}], {})", events.join(", "), expected_last_id)
+            },
+            TcpMessage::PublishExpectingStreamVersion(ref event, ref expected_version) => {
+                write!(f, "PublishExpectingStreamVersion({}, {})", event, expected_version)
+            },
+            TcpMessage::PublishBatchExpectingStreamVersion(ref events, ref expected_version) => {
+                let events: Vec<_> = events.iter().map(|event| event.to_string()).collect();
+                write!(f, "PublishBatchExpectingStreamVersion([{}], {})", events.join(", "), expected_version)
+            },
+            TcpMessage::GetStreamVersion(ref stream_id) => write!(f, "GetStreamVersion({})", stream_id),
+            TcpMessage::StreamVersion(ref stream_id, ref version) => write!(f, "StreamVersion({}, {})", stream_id, version),
+            TcpMessage::Subscribe(live, offset, limit, ref tag, from_timestamp, to_timestamp, reverse, ref payload_filter) => {
+                let fields = subscribe_fields(live, offset, limit, tag, from_timestamp, to_timestamp, reverse, payload_filter);
+                write!(f, "Subscribe({})", fields.join(", "))
+            },
+            TcpMessage::Query(ref query) => write!(f, "Query({})", query),
+            TcpMessage::Aggregate(ref aggregation, ref query) => {
+                write!(f, "Aggregate({}, {}, {}, {})", aggregation.group_by_tag, aggregation.bucket_size_in_ms.unwrap_or(0),
+                       aggregation.update_interval_in_ms, query)
             },
+            TcpMessage::Aggregated(ref aggregate) => write!(f, "Aggregated({})", aggregate),
+            TcpMessage::SubscribeDurable(ref consumer_name, ref query) => write!(f, "SubscribeDurable({}, {})", consumer_name, query),
+            TcpMessage::Acknowledge(ref consumer_name, ref event_id) => write!(f, "Acknowledge({}, {})", consumer_name, event_id),
+            TcpMessage::ListCheckpoints => write!(f, "ListCheckpoints"),
+            TcpMessage::ResetCheckpoint(ref consumer_name, ref event_id) => write!(f, "ResetCheckpoint({}, {})", consumer_name, event_id),
+            TcpMessage::DeleteCheckpoint(ref consumer_name) => write!(f, "DeleteCheckpoint({})", consumer_name),
+            TcpMessage::Checkpoints(ref checkpoints) => write!(f, "Checkpoints({})", checkpoints.to_tab_separated_string()),
+            TcpMessage::JoinGroup(ref group_name, ref query) => write!(f, "JoinGroup({}, {})", group_name, query),
+            TcpMessage::Ack(ref event_id) => write!(f, "Ack({})", event_id),
+            TcpMessage::Nack(ref event_id) => write!(f, "Nack({})", event_id),
+            TcpMessage::DescribeGroup(ref group_name) => write!(f, "DescribeGroup({})", group_name),
+            TcpMessage::ConsumerGroup(ref info) => write!(f, "ConsumerGroup({})", info),
+            TcpMessage::Unsubscribe => write!(f, "Unsubscribe"),
             TcpMessage::Subscribed => write!(f, "Subscribed"),
             TcpMessage::Event(ref event) => write!(f, "Event({})", event),
             TcpMessage::EndOfEventStream => write!(f, "EndOfEventStream"),
@@ -139,11 +445,92 @@ impl Display for TcpMessage {
     }
 }
 
+// optional fields set to their default value are left out from the end of the message
+const SUBSCRIBE_OPTIONAL_FIELD_DEFAULTS: [&'static str; 6] = ["0", "", "0", "0", "false", ""];
+
+fn subscribe_fields(live: bool, offset: u64, limit: Option<u64>, tag: &Option<TagExpression>, from_timestamp: Option<u64>,
+                    to_timestamp: Option<u64>, reverse: bool, payload_filter: &Option<PayloadFilter>) -> Vec<String> {
+    let mut fields = vec![
+        live.to_string(),
+        offset.to_string(),
+        limit.unwrap_or(0).to_string(),
+        tag.as_ref().map_or(String::new(), |tag| tag.to_string()),
+        from_timestamp.unwrap_or(0).to_string(),
+        to_timestamp.unwrap_or(0).to_string(),
+        reverse.to_string(),
+        payload_filter.as_ref().map_or(String::new(), |payload_filter| payload_filter.to_string())
+    ];
+    while fields.len() > 2 && fields[fields.len() - 1] == SUBSCRIBE_OPTIONAL_FIELD_DEFAULTS[fields.len() - 3] {
+        fields.pop();
+    }
+    fields
+}
+
+// an event carrying a client id and/or belonging to a stream is published using
+// a `PublishIdempotent`, `PublishToStream` or `PublishIdempotentToStream` line instead
+fn publish_line(event: &Event) -> String {
+    let (tags, data) = (event.tags.join(" "), String::from_utf8_lossy(&event.data));
+    match (&event.client_id, &event.stream_id) {
+        (&Some(ref client_id), &Some(ref stream_id)) => {
+            tab_separated!("PublishIdempotentToStream", client_id, stream_id, tags, event.timestamp, data)
+        },
+        (&Some(ref client_id), &None) => tab_separated!("PublishIdempotent", client_id, tags, event.timestamp, data),
+        (&None, &Some(ref stream_id)) => tab_separated!("PublishToStream", stream_id, tags, event.timestamp, data),
+        (&None, &None) => tab_separated!("Publish", tags, event.timestamp, data)
+    }
+}
+
+fn parse_event(s: &str) -> Result<Event, ParseError> {
+    let mut parser = TabSeparatedParser::new(3, s);
+    let tags: String = try!(parser.parse_next());
+    let timestamp = try!(parser.parse_next());
+    let data: String = try!(parser.parse_next());
+    let tags: Vec<_> = tags.split(' ').collect();
+    Ok(Event::new(&data, tags).with_timestamp(timestamp))
+}
+
+// a tag field without operators or parentheses is a literal tag, as sent by clients predating tag expressions
+fn parse_tag_field(s: &str) -> Result<TagExpression, ParseError> {
+    let tokens: Vec<_> = s.split_whitespace().collect();
+    let has_operators = tokens.len() > 1 && tokens.iter().any(|&token| token == "AND" || token == "OR" || token == "NOT");
+    if has_operators || s.contains('(') || s.contains(')') {
+        s.parse()
+    } else {
+        Ok(TagExpression::tag(s))
+    }
+}
+
+fn parse_publish_line(s: &str) -> Result<Event, ParseError> {
+    match try!(TcpMessage::from_tab_separated_str(s)) {
+        TcpMessage::Publish(event) => Ok(event),
+        message => Err(ParseError::ParseError(format!("unexpected TCP message in place of an event: {}", message)))
+    }
+}
+
+// a batch consists of the number of events followed by a `Publish` line for each event
+fn parse_batch(s: &str) -> Result<Vec<Event>, ParseError> {
+    let mut lines = s.split('\n');
+    let nr_of_events: usize = match lines.next().map(|count| count.parse()) {
+        Some(Ok(nr_of_events)) => nr_of_events,
+        _ => return Err(ParseError::ParseError("invalid number of events in batch".to_owned()))
+    };
+    let mut events = Vec::with_capacity(nr_of_events);
+    for line in lines {
+        events.push(try!(parse_publish_line(line)));
+    }
+    if events.len() != nr_of_events {
+        return Err(ParseError::ParseError(format!("expected {} events in batch, found {}", nr_of_events, events.len())))
+    }
+    Ok(events)
+}
+
 #[cfg(test)]
 mod tests {
     use exar::*;
     use super::super::*;
 
+    use std::collections::BTreeMap;
+
     #[test]
     fn test_connect() {
         let message = TcpMessage::Connect("collection".to_owned(), None, None);
@@ -176,6 +563,34 @@ mod tests {
         assert_encoded_eq!(message, string);
         assert_decoded_eq!(string, message.clone());
         assert_eq!(format!("{}", message), format!("Publish({})", event));
+
+        let event = event.with_client_id("client1");
+        let message = TcpMessage::Publish(event.clone());
+        let string = "PublishIdempotent\tclient1\ttag1 tag2\t1234567890\tdata";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(TcpMessage::nr_of_following_lines(string), 0);
+
+        assert!(TcpMessage::from_tab_separated_str("PublishIdempotent\tclient1").is_err());
+    }
+
+    #[test]
+    fn test_publish_to_stream() {
+        let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890).with_stream("stream1");
+        let message = TcpMessage::Publish(event.clone());
+        let string = "PublishToStream\tstream1\ttag1 tag2\t1234567890\tdata";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(TcpMessage::nr_of_following_lines(string), 0);
+
+        let message = TcpMessage::Publish(event.with_client_id("client1"));
+        let string = "PublishIdempotentToStream\tclient1\tstream1\ttag1 tag2\t1234567890\tdata";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(TcpMessage::nr_of_following_lines(string), 0);
+
+        assert!(TcpMessage::from_tab_separated_str("PublishToStream\tstream1").is_err());
+        assert!(TcpMessage::from_tab_separated_str("PublishIdempotentToStream\tclient1\tstream1").is_err());
     }
 
     #[test]
@@ -187,31 +602,341 @@ mod tests {
         assert_eq!(format!("{}", message), "Published(1)");
     }
 
+    #[test]
+    fn test_publish_batch() {
+        let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+        let message = TcpMessage::PublishBatch(vec![event.clone(), event.clone().with_timestamp(0)]);
+        let string = "PublishBatch\t2\nPublish\ttag1 tag2\t1234567890\tdata\nPublish\ttag1 tag2\t0\tdata";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), format!("PublishBatch([{}, {}])", event, event.clone().with_timestamp(0)));
+        assert_eq!(TcpMessage::nr_of_following_lines("PublishBatch\t2"), 2);
+        assert_eq!(TcpMessage::nr_of_following_lines("Publish\ttag1 tag2\t1234567890\tdata"), 0);
+
+        let message = TcpMessage::PublishBatch(vec![]);
+        let string = "PublishBatch\t0";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "PublishBatch([])");
+
+        let message = TcpMessage::PublishBatch(vec![event.clone().with_client_id("client1"), event.clone()]);
+        let string = "PublishBatch\t2\nPublishIdempotent\tclient1\ttag1 tag2\t1234567890\tdata\nPublish\ttag1 tag2\t1234567890\tdata";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+
+        assert!(TcpMessage::from_tab_separated_str("PublishBatch\t2\nPublish\ttag1\t0\tdata").is_err());
+        assert!(TcpMessage::from_tab_separated_str("PublishBatch\t1\nPublished\t1").is_err());
+    }
+
+    #[test]
+    fn test_published_batch() {
+        let message = TcpMessage::PublishedBatch(1, 3);
+        let string = "PublishedBatch\t1\t3";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "PublishedBatch(1, 3)");
+    }
+
+    #[test]
+    fn test_publish_expecting() {
+        let event = Event::new("data\tdata", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+        let message = TcpMessage::PublishExpecting(event.clone(), 5);
+        let string = "PublishExpecting\t5\ttag1 tag2\t1234567890\tdata\tdata";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), format!("PublishExpecting({}, 5)", event));
+        assert_eq!(TcpMessage::nr_of_following_lines(string), 0);
+    }
+
+    #[test]
+    fn test_publish_batch_expecting() {
+        let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+        let message = TcpMessage::PublishBatchExpecting(vec![event.clone(), event.clone().with_timestamp(0)], 5);
+        let string = "PublishBatchExpecting\t5\t2\nPublish\ttag1 tag2\t1234567890\tdata\nPublish\ttag1 tag2\t0\tdata";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), format!("PublishBatchExpecting([{}, {}], 5)", event, event.clone().with_timestamp(0)));
+        assert_eq!(TcpMessage::nr_of_following_lines("PublishBatchExpecting\t5\t2"), 2);
+
+        assert!(TcpMessage::from_tab_separated_str("PublishBatchExpecting\t5\t2\nPublish\ttag1\t0\tdata").is_err());
+        assert!(TcpMessage::from_tab_separated_str("PublishBatchExpecting\t2\nPublish\ttag1\t0\tdata").is_err());
+    }
+
+    #[test]
+    fn test_publish_expecting_stream_version() {
+        let event = Event::new("data\tdata", vec!["tag1", "tag2"]).with_timestamp(1234567890).with_stream("stream1");
+        let message = TcpMessage::PublishExpectingStreamVersion(event.clone(), 5);
+        let string = "PublishExpectingStreamVersion\t5\nPublishToStream\tstream1\ttag1 tag2\t1234567890\tdata\tdata";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), format!("PublishExpectingStreamVersion({}, 5)", event));
+        assert_eq!(TcpMessage::nr_of_following_lines("PublishExpectingStreamVersion\t5"), 1);
+
+        assert!(TcpMessage::from_tab_separated_str("PublishExpectingStreamVersion\t5").is_err());
+        assert!(TcpMessage::from_tab_separated_str("PublishExpectingStreamVersion\tfive\nPublish\ttag1\t0\tdata").is_err());
+        assert!(TcpMessage::from_tab_separated_str("PublishExpectingStreamVersion\t5\nPublished\t1").is_err());
+    }
+
+    #[test]
+    fn test_publish_batch_expecting_stream_version() {
+        let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890).with_stream("stream1");
+        let message = TcpMessage::PublishBatchExpectingStreamVersion(vec![event.clone(), event.clone().with_client_id("client1")], 5);
+        let string = "PublishBatchExpectingStreamVersion\t5\t2\nPublishToStream\tstream1\ttag1 tag2\t1234567890\tdata\n\
+                      PublishIdempotentToStream\tclient1\tstream1\ttag1 tag2\t1234567890\tdata";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), format!("PublishBatchExpectingStreamVersion([{}, {}], 5)", event, event));
+        assert_eq!(TcpMessage::nr_of_following_lines("PublishBatchExpectingStreamVersion\t5\t2"), 2);
+
+        assert!(TcpMessage::from_tab_separated_str("PublishBatchExpectingStreamVersion\t5\t2\nPublish\ttag1\t0\tdata").is_err());
+    }
+
+    #[test]
+    fn test_get_stream_version() {
+        let message = TcpMessage::GetStreamVersion("stream1".to_owned());
+        let string = "GetStreamVersion\tstream1";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "GetStreamVersion(stream1)");
+    }
+
+    #[test]
+    fn test_stream_version() {
+        let message = TcpMessage::StreamVersion("stream1".to_owned(), 3);
+        let string = "StreamVersion\tstream1\t3";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "StreamVersion(stream1, 3)");
+
+        assert!(TcpMessage::from_tab_separated_str("StreamVersion\tstream1").is_err());
+    }
+
     #[test]
     fn test_subscribe() {
-        let message = TcpMessage::Subscribe(true, 0, Some(100), Some("tag1".to_owned()));
+        let message = TcpMessage::Subscribe(true, 0, Some(100), Some(TagExpression::tag("tag1")), None, None, false, None);
         let string = "Subscribe\ttrue\t0\t100\ttag1";
         assert_encoded_eq!(message, string);
         assert_decoded_eq!(string, message.clone());
         assert_eq!(format!("{}", message), "Subscribe(true, 0, 100, tag1)");
 
-        let message = TcpMessage::Subscribe(true, 0, Some(100), None);
+        let message = TcpMessage::Subscribe(true, 0, Some(100), None, None, None, false, None);
         let string = "Subscribe\ttrue\t0\t100";
         assert_encoded_eq!(message, string);
         assert_decoded_eq!(string, message.clone());
         assert_eq!(format!("{}", message), "Subscribe(true, 0, 100)");
 
-        let message = TcpMessage::Subscribe(true, 0, None, Some("tag1".to_owned()));
+        let message = TcpMessage::Subscribe(true, 0, None, Some(TagExpression::tag("tag1")), None, None, false, None);
         let string = "Subscribe\ttrue\t0\t0\ttag1";
         assert_encoded_eq!(message, string);
         assert_decoded_eq!(string, message.clone());
         assert_eq!(format!("{}", message), "Subscribe(true, 0, 0, tag1)");
 
-        let message = TcpMessage::Subscribe(true, 0, None, None);
+        let message = TcpMessage::Subscribe(true, 0, None, None, None, None, false, None);
         let string = "Subscribe\ttrue\t0";
         assert_encoded_eq!(message, string);
         assert_decoded_eq!(string, message.clone());
         assert_eq!(format!("{}", message), "Subscribe(true, 0)");
+
+        let message = TcpMessage::Subscribe(true, 0, Some(100), Some(TagExpression::tag("tag1")), Some(1000), Some(2000), false, None);
+        let string = "Subscribe\ttrue\t0\t100\ttag1\t1000\t2000";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "Subscribe(true, 0, 100, tag1, 1000, 2000)");
+
+        let message = TcpMessage::Subscribe(false, 0, None, None, Some(1000), None, false, None);
+        let string = "Subscribe\tfalse\t0\t0\t\t1000";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_decoded_eq!("Subscribe\tfalse\t0\t0\t\t1000\t0", message.clone());
+        assert_eq!(format!("{}", message), "Subscribe(false, 0, 0, , 1000)");
+
+        let message = TcpMessage::Subscribe(false, 0, Some(20), None, None, None, true, None);
+        let string = "Subscribe\tfalse\t0\t20\t\t0\t0\ttrue";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "Subscribe(false, 0, 20, , 0, 0, true)");
+
+        let tag_expression = TagExpression::tag("order").and(TagExpression::tag("eu")).or(TagExpression::tag("refund"));
+        let message = TcpMessage::Subscribe(true, 0, None, Some(tag_expression), None, None, false, None);
+        let string = "Subscribe\ttrue\t0\t0\torder AND eu OR refund";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "Subscribe(true, 0, 0, order AND eu OR refund)");
+
+        assert!(TcpMessage::from_tab_separated_str("Subscribe\ttrue\t0\t0\torder AND").is_err());
+
+        for legacy_tag in &["NOT", "OR", "order eu"] {
+            let message = TcpMessage::Subscribe(true, 0, None, Some(TagExpression::tag(legacy_tag)), None, None, false, None);
+            assert_decoded_eq!(&format!("Subscribe\ttrue\t0\t0\t{}", legacy_tag), message);
+        }
+
+        let payload_filter = PayloadFilter::contains("order\trefunded");
+        let message = TcpMessage::Subscribe(false, 0, None, None, None, None, false, Some(payload_filter));
+        let string = "Subscribe\tfalse\t0\t0\t\t0\t0\tfalse\tcontains:order\trefunded";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "Subscribe(false, 0, 0, , 0, 0, false, contains:order\trefunded)");
+
+        assert!(TcpMessage::from_tab_separated_str("Subscribe\tfalse\t0\t0\t\t0\t0\tfalse\tregex:(").is_err());
+    }
+
+    #[test]
+    fn test_query() {
+        let message = TcpMessage::Query(Query::live().offset(100).limit(10).by_tag("a").from_timestamp(1700000000001));
+        let string = "Query\tLIVE FROM 100 LIMIT 10 WHERE tag = 'a' AND ts >= 1700000000001";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_decoded_eq!("Query\tlive from 100 limit 10 where tag = 'a' and ts > 1700000000000", message.clone());
+        assert_eq!(format!("{}", message), "Query(LIVE FROM 100 LIMIT 10 WHERE tag = 'a' AND ts >= 1700000000001)");
+
+        let message = TcpMessage::Query(Query::current());
+        assert_decoded_eq!("Query\t", message);
+
+        assert_eq!(TcpMessage::from_tab_separated_str("Query\tLIVE LIMIT ten"),
+                   Err(ParseError::SyntaxError(11, "expected a non-negative integer, found ten".to_owned())));
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let aggregation = Aggregation::new().group_by_tag().bucket_size(3600000).update_interval(500);
+        let message = TcpMessage::Aggregate(aggregation, Query::live().by_tag("order-placed"));
+        let string = "Aggregate\ttrue\t3600000\t500\tLIVE WHERE tag = 'order-placed'";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "Aggregate(true, 3600000, 500, LIVE WHERE tag = 'order-placed')");
+
+        let message = TcpMessage::Aggregate(Aggregation::new(), Query::current());
+        assert_decoded_eq!("Aggregate\tfalse\t0\t1000", message);
+
+        assert!(TcpMessage::from_tab_separated_str("Aggregate\tfalse\t0").is_err());
+        assert!(TcpMessage::from_tab_separated_str("Aggregate\tfalse\t0\t0").is_err());
+        assert!(TcpMessage::from_tab_separated_str("Aggregate\tfalse\t0\t1000\tWHERE tag").is_err());
+    }
+
+    #[test]
+    fn test_aggregated() {
+        let mut aggregate = Aggregate::new();
+        aggregate.add(&Event::new("data", vec!["tag1"]).with_id(1).with_timestamp(1500), &Aggregation::new().group_by_tag());
+        let message = TcpMessage::Aggregated(aggregate);
+        let string = "Aggregated\t1\t1\t1\t1500\t1500\t1::tag1";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "Aggregated(Aggregate(1, 1, 1, 1500, 1500, [1::tag1]))");
+    }
+
+    #[test]
+    fn test_subscribe_durable() {
+        let message = TcpMessage::SubscribeDurable("consumer".to_owned(), Query::live().by_tag("tag1"));
+        let string = "SubscribeDurable\tconsumer\tLIVE WHERE tag = 'tag1'";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "SubscribeDurable(consumer, LIVE WHERE tag = 'tag1')");
+    }
+
+    #[test]
+    fn test_acknowledge() {
+        let message = TcpMessage::Acknowledge("consumer".to_owned(), 10);
+        let string = "Acknowledge\tconsumer\t10";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "Acknowledge(consumer, 10)");
+    }
+
+    #[test]
+    fn test_checkpoint_management() {
+        let message = TcpMessage::ListCheckpoints;
+        let string = "ListCheckpoints";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "ListCheckpoints");
+
+        let message = TcpMessage::ResetCheckpoint("consumer".to_owned(), 5);
+        let string = "ResetCheckpoint\tconsumer\t5";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "ResetCheckpoint(consumer, 5)");
+
+        let message = TcpMessage::DeleteCheckpoint("consumer".to_owned());
+        let string = "DeleteCheckpoint\tconsumer";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "DeleteCheckpoint(consumer)");
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let mut checkpoints = Checkpoints::new();
+        assert!(checkpoints.acknowledge("consumer1", 10).is_ok());
+        assert!(checkpoints.acknowledge("consumer2", 3).is_ok());
+        let message = TcpMessage::Checkpoints(checkpoints);
+        let string = "Checkpoints\t10:consumer1 3:consumer2";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "Checkpoints(10:consumer1 3:consumer2)");
+
+        let message = TcpMessage::Checkpoints(Checkpoints::new());
+        assert_decoded_eq!("Checkpoints", message.clone());
+        assert_decoded_eq!("Checkpoints\t", message);
+    }
+
+    #[test]
+    fn test_join_group() {
+        let message = TcpMessage::JoinGroup("group".to_owned(), Query::live().by_tag("tag1"));
+        let string = "JoinGroup\tgroup\tLIVE WHERE tag = 'tag1'";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "JoinGroup(group, LIVE WHERE tag = 'tag1')");
+    }
+
+    #[test]
+    fn test_ack_and_nack() {
+        let message = TcpMessage::Ack(10);
+        let string = "Ack\t10";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "Ack(10)");
+
+        let message = TcpMessage::Nack(10);
+        let string = "Nack\t10";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "Nack(10)");
+    }
+
+    #[test]
+    fn test_describe_group() {
+        let message = TcpMessage::DescribeGroup("group".to_owned());
+        let string = "DescribeGroup\tgroup";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "DescribeGroup(group)");
+    }
+
+    #[test]
+    fn test_consumer_group() {
+        let mut members = BTreeMap::new();
+        members.insert(1, 2);
+        members.insert(3, 0);
+        let message = TcpMessage::ConsumerGroup(ConsumerGroupInfo {
+            name: "group".to_owned(),
+            members: members,
+            position: 10,
+            lag: 4,
+            nr_of_dead_letters: 1
+        });
+        let string = "ConsumerGroup\tgroup\t10\t4\t1\t1:2 3:0";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "ConsumerGroup(ConsumerGroupInfo(group, 10, 4, 1, [1:2, 3:0]))");
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let message = TcpMessage::Unsubscribe;
+        let string = "Unsubscribe";
+        assert_encoded_eq!(message, string);
+        assert_decoded_eq!(string, message.clone());
+        assert_eq!(format!("{}", message), "Unsubscribe");
     }
 
     #[test]
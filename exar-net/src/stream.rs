@@ -2,7 +2,7 @@ use exar::*;
 use super::*;
 
 use std::io::prelude::*;
-use std::io::{BufReader, BufWriter, Lines};
+use std::io::{BufReader, BufWriter, ErrorKind};
 use std::net::TcpStream;
 
 /// A bidiectional TCP message stream.
@@ -32,8 +32,9 @@ impl<T: Read + Write + TryClone> TcpMessageStream<T> {
         let mut line = String::new();
         match self.reader.read_line(&mut line) {
             Ok(_) => {
-                let trimmed_line = line.trim();
-                match TcpMessage::from_tab_separated_str(trimmed_line) {
+                let mut message = line.trim().to_owned();
+                try!(read_following_lines(&mut self.reader, &mut message));
+                match TcpMessage::from_tab_separated_str(&message) {
                     Ok(message) => Ok(message),
                     Err(err) => Err(DatabaseError::ParseError(err))
                 }
@@ -80,13 +81,13 @@ impl<T: Read + Write + TryClone> TryClone for TcpMessageStream<T> {
 
 /// An iterator over the messages received on a stream.
 pub struct TcpMessages<T: Read + Write> {
-    lines: Lines<BufReader<T>>
+    reader: BufReader<T>
 }
 
 impl<T: Read + Write> TcpMessages<T> {
     pub fn new(stream: TcpMessageStream<T>) -> TcpMessages<T> {
         TcpMessages {
-            lines: stream.reader.lines()
+            reader: stream.reader
         }
     }
 }
@@ -94,13 +95,45 @@ impl<T: Read + Write> TcpMessages<T> {
 impl<T: Read + Write> Iterator for TcpMessages<T> {
     type Item = Result<TcpMessage, DatabaseError>;
     fn next(&mut self) -> Option<Self::Item> {
-        match self.lines.next() {
-            Some(Ok(ref message)) => match TcpMessage::from_tab_separated_str(message) {
-                Ok(message) => Some(Ok(message)),
-                Err(err) => Some(Err(DatabaseError::ParseError(err)))
+        let mut message = String::new();
+        match self.reader.read_line(&mut message) {
+            Ok(0) => None,
+            Ok(_) => {
+                strip_line_ending(&mut message);
+                match read_following_lines(&mut self.reader, &mut message) {
+                    Ok(()) => match TcpMessage::from_tab_separated_str(&message) {
+                        Ok(message) => Some(Ok(message)),
+                        Err(err) => Some(Err(DatabaseError::ParseError(err)))
+                    },
+                    Err(err) => Some(Err(err))
+                }
             },
-            Some(Err(err)) => Some(Err(DatabaseError::from_io_error(err))),
-            None => None
+            Err(err) => Some(Err(DatabaseError::from_io_error(err)))
+        }
+    }
+}
+
+fn read_following_lines<R: BufRead>(reader: &mut R, message: &mut String) -> Result<(), DatabaseError> {
+    for _ in 0..TcpMessage::nr_of_following_lines(message) {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => return Err(DatabaseError::IoError(ErrorKind::UnexpectedEof, "incomplete TCP message".to_owned())),
+            Ok(_) => {
+                strip_line_ending(&mut line);
+                message.push('\n');
+                message.push_str(&line);
+            },
+            Err(err) => return Err(DatabaseError::from_io_error(err))
+        }
+    }
+    Ok(())
+}
+
+fn strip_line_ending(line: &mut String) {
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
         }
     }
 }
@@ -179,4 +212,29 @@ mod tests {
 
         assert!(remove_file("message-stream.log").is_ok());
     }
+
+    #[test]
+    fn test_multi_line_tcp_messages() {
+
+        let log_stream = LogStream::new("multi-line-message-stream.log").expect("Unable to create log stream");
+        let mut stream = TcpMessageStream::new(log_stream).expect("Unable to create message stream");
+
+        let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+        let message = TcpMessage::PublishBatch(vec![event.clone(), event.clone()]);
+        let next_message = TcpMessage::PublishedBatch(1, 2);
+
+        assert!(stream.send_message(message.clone()).is_ok());
+        assert!(stream.send_message(next_message.clone()).is_ok());
+        assert_eq!(stream.recv_message(), Ok(message.clone()));
+        assert_eq!(stream.recv_message(), Ok(next_message.clone()));
+
+        let mut messages = stream.try_clone().expect("Unable to clone message stream").messages();
+
+        assert_eq!(messages.next(), Some(Ok(message)));
+        assert_eq!(messages.next(), Some(Ok(next_message)));
+
+        assert_eq!(messages.next(), None);
+
+        assert!(remove_file("multi-line-message-stream.log").is_ok());
+    }
 }
@@ -6,14 +6,20 @@ use exar_net::*;
 use std::io::ErrorKind;
 use std::net::TcpStream;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
 
 /// Exar DB's server connection handler.
 ///
 /// It manages the TCP stream associated to a single remote connection.
 pub struct Handler {
     credentials: Credentials,
-    stream: TcpMessageStream<TcpStream>,
-    state: State
+    db: Arc<Mutex<Database>>,
+    stream: Arc<Mutex<TcpMessageStream<TcpStream>>>,
+    state: State,
+    stream_thread: Option<JoinHandle<()>>,
+    cancellation_handle: Option<CancellationHandle>,
+    consumer_group_member: Option<ConsumerGroupMember>
 }
 
 impl Handler {
@@ -23,28 +29,75 @@ impl Handler {
         TcpMessageStream::new(stream).and_then(|stream| {
             Ok(Handler {
                 credentials: credentials,
-                stream: stream,
-                state: State::Idle(db)
+                db: db,
+                stream: Arc::new(Mutex::new(stream)),
+                state: State::Idle,
+                stream_thread: None,
+                cancellation_handle: None,
+                consumer_group_member: None
             })
         })
     }
 
     /// Runs the connection handler which processes one incoming TCP message at a time.
+    ///
+    /// Event and aggregate streams are sent from a separate thread, while they are being sent only `Acknowledge`,
+    /// `Ack`, `Nack` and `Unsubscribe` messages are processed right away,
+    /// any other message is processed once the stream has ended.
+    ///
+    /// Event streams are sent only as fast as the client reads them: while the client is not reading,
+    /// the bounded event stream fills up and the scanners pause its subscription.
+    ///
+    /// Once the client has disconnected, the stream being sent (if any) is cancelled and the consumer group joined
+    /// (if any) is left, the handler returns once the thread sending the stream has ended.
     pub fn run(&mut self) {
-        match self.stream.try_clone() {
+        let stream = self.stream.lock().unwrap().try_clone();
+        match stream {
             Ok(stream) => {
                 for message in stream.messages() {
                     let _ = match message {
-                        Ok(message) => match self.recv(message) {
-                            Ok(result) => self.send(result),
-                            Err(err) => self.fail(err)
+                        Ok(message) => {
+                            if !Handler::is_processed_while_streaming(&message) {
+                                self.wait_for_stream_thread();
+                            }
+                            match self.recv(message) {
+                                Ok(result) => self.send(result),
+                                Err(err) => self.fail(err)
+                            }
                         },
-                        Err(err) => self.fail(err)
+                        // invalid data is reported to the client, while any other I/O error means the connection is lost
+                        Err(DatabaseError::IoError(kind, _)) if kind != ErrorKind::InvalidData => break,
+                        Err(err) => {
+                            self.wait_for_stream_thread();
+                            self.fail(err)
+                        }
                     };
                 }
             },
             Err(err) => warn!("Unable to accept client connection: {}", err)
         }
+        self.unsubscribe();
+        self.wait_for_stream_thread();
+    }
+
+    fn is_processed_while_streaming(message: &TcpMessage) -> bool {
+        match *message {
+            TcpMessage::Acknowledge(_, _) | TcpMessage::Ack(_) | TcpMessage::Nack(_) | TcpMessage::Unsubscribe => true,
+            _ => false
+        }
+    }
+
+    fn wait_for_stream_thread(&mut self) {
+        if let Some(stream_thread) = self.stream_thread.take() {
+            let _ = stream_thread.join();
+        }
+    }
+
+    fn unsubscribe(&mut self) {
+        if let Some(cancellation_handle) = self.cancellation_handle.take() {
+            cancellation_handle.cancel();
+        }
+        self.consumer_group_member = None;
     }
 
     fn update_state(&mut self, state: State) {
@@ -65,11 +118,12 @@ impl Handler {
 
     fn recv(&mut self, message: TcpMessage) -> Result<ActionResult, DatabaseError> {
         match (message, self.state.clone()) {
-            (TcpMessage::Connect(collection_name, given_username, given_password), State::Idle(db)) => {
+            (TcpMessage::Connect(collection_name, given_username, given_password), State::Idle) => {
                 if self.verify_authentication(given_username, given_password) {
-                    match db.lock().unwrap().connect(&collection_name) {
+                    let connection = self.db.lock().unwrap().connect(&collection_name);
+                    match connection {
                         Ok(connection) => {
-                            self.update_state(State::Connected(connection));
+                            self.update_state(State::Connected(collection_name, connection));
                             Ok(ActionResult::Connected)
                         },
                         Err(err) => Err(err)
@@ -78,56 +132,197 @@ impl Handler {
                     Err(DatabaseError::AuthenticationError)
                 }
             },
-            (TcpMessage::Publish(event), State::Connected(connection)) => {
+            (TcpMessage::Publish(event), State::Connected(_, connection)) => {
                 connection.publish(event).and_then(|event_id| {
                     Ok(ActionResult::Published(event_id))
                 })
             },
-            (TcpMessage::Subscribe(live, offset, limit, tag), State::Connected(connection)) => {
-                connection.subscribe(Query::new(live, offset, limit, tag)).and_then(|event_stream| {
+            (TcpMessage::PublishBatch(events), State::Connected(_, connection)) => {
+                connection.publish_batch(events).and_then(|(first_event_id, last_event_id)| {
+                    Ok(ActionResult::PublishedBatch(first_event_id, last_event_id))
+                })
+            },
+            (TcpMessage::PublishExpecting(event, expected_last_id), State::Connected(_, connection)) => {
+                connection.publish_expecting(event, expected_last_id).and_then(|event_id| {
+                    Ok(ActionResult::Published(event_id))
+                })
+            },
+            (TcpMessage::PublishBatchExpecting(events, expected_last_id), State::Connected(_, connection)) => {
+                connection.publish_batch_expecting(events, expected_last_id).and_then(|(first_event_id, last_event_id)| {
+                    Ok(ActionResult::PublishedBatch(first_event_id, last_event_id))
+                })
+            },
+            (TcpMessage::PublishExpectingStreamVersion(event, expected_version), State::Connected(_, connection)) => {
+                connection.publish_expecting_stream_version(event, expected_version).and_then(|event_id| {
+                    Ok(ActionResult::Published(event_id))
+                })
+            },
+            (TcpMessage::PublishBatchExpectingStreamVersion(events, expected_version), State::Connected(_, connection)) => {
+                connection.publish_batch_expecting_stream_version(events, expected_version).and_then(|(first_event_id, last_event_id)| {
+                    Ok(ActionResult::PublishedBatch(first_event_id, last_event_id))
+                })
+            },
+            (TcpMessage::GetStreamVersion(stream_id), State::Connected(_, connection)) => {
+                let version = connection.stream_version(&stream_id);
+                Ok(ActionResult::StreamVersion(stream_id, version))
+            },
+            (TcpMessage::Subscribe(live, offset, limit, tag_expression, from_timestamp, to_timestamp, reverse, payload_filter),
+             State::Connected(_, connection)) => {
+                let mut query = match tag_expression {
+                    Some(TagExpression::Tag(tag)) => Query::new(live, offset, limit, Some(tag)),
+                    Some(tag_expression) => Query::new(live, offset, limit, None).by_tag_expression(tag_expression),
+                    None => Query::new(live, offset, limit, None)
+                };
+                query.from_timestamp = from_timestamp;
+                query.to_timestamp = to_timestamp;
+                query.payload_filter = payload_filter;
+                if reverse {
+                    query = query.reverse();
+                }
+                connection.subscribe(query).and_then(|event_stream| {
+                    Ok(ActionResult::EventStream(event_stream))
+                })
+            },
+            (TcpMessage::Query(query), State::Connected(_, connection)) => {
+                connection.subscribe(query).and_then(|event_stream| {
                     Ok(ActionResult::EventStream(event_stream))
                 })
             },
+            (TcpMessage::Aggregate(aggregation, query), State::Connected(_, connection)) => {
+                let live_stream = query.live_stream;
+                connection.aggregate(query, aggregation).and_then(|aggregate_stream| {
+                    Ok(ActionResult::AggregateStream(aggregate_stream, live_stream))
+                })
+            },
+            (TcpMessage::SubscribeDurable(consumer_name, query), State::Connected(_, connection)) => {
+                connection.subscribe_durable(&consumer_name, query).and_then(|event_stream| {
+                    Ok(ActionResult::EventStream(event_stream))
+                })
+            },
+            (TcpMessage::Acknowledge(consumer_name, event_id), State::Connected(_, connection)) => {
+                connection.acknowledge(&consumer_name, event_id).and_then(|_| Ok(ActionResult::Acknowledged))
+            },
+            (TcpMessage::ListCheckpoints, State::Connected(_, connection)) => {
+                Ok(ActionResult::Checkpoints(connection.checkpoints()))
+            },
+            (TcpMessage::ResetCheckpoint(consumer_name, event_id), State::Connected(_, connection)) => {
+                connection.reset_checkpoint(&consumer_name, event_id).and_then(|_| {
+                    Ok(ActionResult::Checkpoints(connection.checkpoints()))
+                })
+            },
+            (TcpMessage::DeleteCheckpoint(consumer_name), State::Connected(_, connection)) => {
+                connection.delete_checkpoint(&consumer_name).and_then(|_| {
+                    Ok(ActionResult::Checkpoints(connection.checkpoints()))
+                })
+            },
+            (TcpMessage::JoinGroup(group_name, query), State::Connected(collection_name, _)) => {
+                let joined = self.db.lock().unwrap().join_consumer_group(&collection_name, &group_name, query);
+                joined.and_then(|(member, event_stream)| {
+                    self.consumer_group_member = Some(member);
+                    Ok(ActionResult::EventStream(event_stream))
+                })
+            },
+            (TcpMessage::Ack(event_id), State::Connected(_, _)) => {
+                self.with_consumer_group_member(|member| member.ack(event_id))
+            },
+            (TcpMessage::Nack(event_id), State::Connected(_, _)) => {
+                self.with_consumer_group_member(|member| member.nack(event_id))
+            },
+            (TcpMessage::DescribeGroup(group_name), State::Connected(_, connection)) => {
+                connection.consumer_group_info(&group_name).and_then(|info| Ok(ActionResult::ConsumerGroup(info)))
+            },
+            (TcpMessage::Unsubscribe, State::Connected(_, _)) => {
+                self.unsubscribe();
+                Ok(ActionResult::Unsubscribed)
+            },
             _ => Err(DatabaseError::IoError(ErrorKind::InvalidData, "unexpected TCP message".to_owned()))
         }
     }
 
+    fn with_consumer_group_member<F>(&self, f: F) -> Result<ActionResult, DatabaseError>
+        where F: FnOnce(&ConsumerGroupMember) -> bool {
+        match self.consumer_group_member {
+            Some(ref member) => if f(member) {
+                Ok(ActionResult::Acknowledged)
+            } else {
+                Err(DatabaseError::ValidationError(ValidationError::new("event is not in flight to this member")))
+            },
+            None => Err(DatabaseError::ValidationError(ValidationError::new("not a member of a consumer group")))
+        }
+    }
+
     fn send(&mut self, result: ActionResult) -> Result<(), DatabaseError> {
         match result {
-            ActionResult::Connected => self.stream.send_message(TcpMessage::Connected),
-            ActionResult::Published(event_id) => self.stream.send_message(TcpMessage::Published(event_id)),
+            ActionResult::Connected => send_message(&self.stream, TcpMessage::Connected),
+            ActionResult::Published(event_id) => send_message(&self.stream, TcpMessage::Published(event_id)),
+            ActionResult::PublishedBatch(first_event_id, last_event_id) => {
+                send_message(&self.stream, TcpMessage::PublishedBatch(first_event_id, last_event_id))
+            },
             ActionResult::EventStream(event_stream) => {
-                self.stream.send_message(TcpMessage::Subscribed).and_then(|_| {
-                    for event in event_stream {
-                        let send_result = self.stream.send_message(TcpMessage::Event(event));
-                        if send_result.is_err() { return send_result }
+                self.cancellation_handle = Some(event_stream.cancellation_handle());
+                self.send_in_background(move |stream| {
+                    send_message(stream, TcpMessage::Subscribed).and_then(|_| {
+                        for event in event_stream {
+                            try!(send_message(stream, TcpMessage::Event(event)));
+                        }
+                        send_message(stream, TcpMessage::EndOfEventStream)
+                    })
+                })
+            },
+            ActionResult::AggregateStream(aggregate_stream, live_stream) => {
+                self.cancellation_handle = Some(aggregate_stream.cancellation_handle());
+                self.send_in_background(move |stream| {
+                    for aggregate in aggregate_stream {
+                        try!(send_message(stream, TcpMessage::Aggregated(aggregate)));
+                    }
+                    if live_stream {
+                        send_message(stream, TcpMessage::EndOfEventStream)
+                    } else {
+                        Ok(())
                     }
-                    self.stream.send_message(TcpMessage::EndOfEventStream)
                 })
-            }
+            },
+            ActionResult::Acknowledged => Ok(()),
+            ActionResult::Checkpoints(checkpoints) => send_message(&self.stream, TcpMessage::Checkpoints(checkpoints)),
+            ActionResult::ConsumerGroup(info) => send_message(&self.stream, TcpMessage::ConsumerGroup(info)),
+            ActionResult::StreamVersion(stream_id, version) => send_message(&self.stream, TcpMessage::StreamVersion(stream_id, version)),
+            ActionResult::Unsubscribed => Ok(())
         }
     }
 
+    fn send_in_background<F>(&mut self, send: F) -> Result<(), DatabaseError>
+        where F: FnOnce(&Mutex<TcpMessageStream<TcpStream>>) -> Result<(), DatabaseError> + Send + 'static {
+        let stream = self.stream.clone();
+        self.stream_thread = Some(thread::spawn(move || {
+            let _ = send(&stream);
+        }));
+        Ok(())
+    }
+
     fn fail(&mut self, error: DatabaseError) -> Result<(), DatabaseError> {
         let error = TcpMessage::Error(error);
-        self.stream.send_message(error)
+        send_message(&self.stream, error)
     }
 }
 
+fn send_message(stream: &Mutex<TcpMessageStream<TcpStream>>, message: TcpMessage) -> Result<(), DatabaseError> {
+    stream.lock().unwrap().send_message(message)
+}
+
 /// A list specifying categories of connection state.
 #[derive(Clone)]
 pub enum State {
     /// The connection is idle and awaiting a `Connect` message.
-    Idle(Arc<Mutex<Database>>),
-    /// The connection has been established.
-    Connected(Connection)
+    Idle,
+    /// The connection to the collection with the given name has been established.
+    Connected(String, Connection)
 }
 
 impl ToString for State {
     fn to_string(&self) -> String {
         match *self {
-            State::Idle(_) => "Idle".to_owned(),
-            State::Connected(_) => "Connected".to_owned()
+            State::Idle => "Idle".to_owned(),
+            State::Connected(_, _) => "Connected".to_owned()
         }
     }
 }
@@ -138,8 +333,22 @@ pub enum ActionResult {
     Connected,
     /// The event has been published with the given `id`.
     Published(u64),
+    /// The batch of events has been published with the given first and last `id`s.
+    PublishedBatch(u64, u64),
     /// The subscription has been accepted and the event stream is available.
-    EventStream(EventStream)
+    EventStream(EventStream),
+    /// The aggregation has been accepted and the aggregate stream is available, for a live query if set to true.
+    AggregateStream(AggregateStream, bool),
+    /// The events have been acknowledged on behalf of a durable consumer or a consumer group member.
+    Acknowledged,
+    /// The checkpoints of the durable consumers have been retrieved or updated.
+    Checkpoints(Checkpoints),
+    /// The consumer group has been described.
+    ConsumerGroup(ConsumerGroupInfo),
+    /// The version of the given stream has been retrieved.
+    StreamVersion(String, u64),
+    /// The event stream being sent has been cancelled.
+    Unsubscribed
 }
 
 #[cfg(test)]
@@ -153,6 +362,7 @@ mod tests {
     use std::io::ErrorKind;
     use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
     use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::channel;
     use std::thread;
     use std::thread::JoinHandle;
     use std::time::Duration;
@@ -192,8 +402,11 @@ mod tests {
 
             drop(client);
 
-            assert!(remove_file(format!("{}.log", collection_name)).is_ok());
-            assert!(remove_file(format!("{}.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.tags.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.streams.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.timestamps.log", collection_name)).is_ok());
 
              handle.join().expect("Unable to join server thread");
         });
@@ -217,8 +430,11 @@ mod tests {
 
             drop(client);
 
-            assert!(remove_file(format!("{}.log", collection_name)).is_ok());
-            assert!(remove_file(format!("{}.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.tags.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.streams.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.timestamps.log", collection_name)).is_ok());
 
             handle.join().expect("Unable to join server thread");
         });
@@ -241,7 +457,17 @@ mod tests {
             assert!(client.send_message(TcpMessage::Publish(event.clone())).is_ok());
             assert_eq!(client.recv_message(), Ok(TcpMessage::Published(1)));
 
-            assert!(client.send_message(TcpMessage::Subscribe(false, 0, None, None)).is_ok());
+            assert!(client.send_message(TcpMessage::Subscribe(false, 0, None, None, None, None, false, None)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Subscribed));
+            if let Ok(TcpMessage::Event(received_event)) = client.recv_message() {
+                assert_eq!(received_event, event.clone().with_id(1));
+                assert_eq!(client.recv_message(), Ok(TcpMessage::EndOfEventStream));
+            } else {
+                panic!("Unable to receive event");
+            }
+
+            let query: Query = "CURRENT WHERE tag = 'tag2' AND ts <= 1234567890".parse().expect("Unable to parse query");
+            assert!(client.send_message(TcpMessage::Query(query.clone())).is_ok());
             assert_eq!(client.recv_message(), Ok(TcpMessage::Subscribed));
             if let Ok(TcpMessage::Event(received_event)) = client.recv_message() {
                 assert_eq!(received_event, event.with_id(1));
@@ -250,22 +476,404 @@ mod tests {
                 panic!("Unable to receive event");
             }
 
+            assert!(client.send_message(TcpMessage::Aggregate(Aggregation::new().group_by_tag(), query)).is_ok());
+            if let Ok(TcpMessage::Aggregated(aggregate)) = client.recv_message() {
+                assert_eq!(aggregate.count, 1);
+                assert_eq!(aggregate.groups.get(&AggregateGroup::new(Some("tag1"), None)), Some(&1));
+            } else {
+                panic!("Unable to receive aggregate");
+            }
+
             drop(client);
 
-            assert!(remove_file(format!("{}.log", collection_name)).is_ok());
-            assert!(remove_file(format!("{}.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.tags.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.streams.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.timestamps.log", collection_name)).is_ok());
 
              handle.join().expect("Unable to join server thread");
         });
     }
 
+    #[test]
+    fn test_publish_batch() {
+        with_addr(&mut |addr| {
+            let collection_name = random_collection_name();
+
+            let handle = create_handler(addr, Credentials::empty());
+            let mut client = create_client(addr);
+
+            assert!(client.send_message(TcpMessage::Connect(collection_name.to_owned(),
+                                        None, None)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Connected));
+
+            let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+
+            assert!(client.send_message(TcpMessage::PublishBatch(vec![event.clone(), event.clone()])).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::PublishedBatch(1, 2)));
+
+            assert!(client.send_message(TcpMessage::PublishBatch(vec![event.clone(), Event::new("data", vec![])])).is_ok());
+            let validation_error = ValidationError::new("event must not contain empty tags");
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Error(DatabaseError::ValidationError(validation_error))));
+
+            assert!(client.send_message(TcpMessage::Publish(event.clone())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Published(3)));
+
+            drop(client);
+
+            assert!(remove_file(format!("{}.1.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.tags.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.streams.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.timestamps.log", collection_name)).is_ok());
+
+            handle.join().expect("Unable to join server thread");
+        });
+    }
+
+    #[test]
+    fn test_publish_expecting() {
+        with_addr(&mut |addr| {
+            let collection_name = random_collection_name();
+
+            let handle = create_handler(addr, Credentials::empty());
+            let mut client = create_client(addr);
+
+            assert!(client.send_message(TcpMessage::Connect(collection_name.to_owned(),
+                                        None, None)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Connected));
+
+            let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+
+            assert!(client.send_message(TcpMessage::PublishExpecting(event.clone(), 0)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Published(1)));
+
+            assert!(client.send_message(TcpMessage::PublishBatchExpecting(vec![event.clone(), event.clone()], 1)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::PublishedBatch(2, 3)));
+
+            assert!(client.send_message(TcpMessage::PublishExpecting(event.clone(), 1)).is_ok());
+            let wrong_expected_version = DatabaseError::WrongExpectedVersion { expected: 1, actual: 3 };
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Error(wrong_expected_version)));
+
+            drop(client);
+
+            assert!(remove_file(format!("{}.1.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.tags.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.streams.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.timestamps.log", collection_name)).is_ok());
+
+            handle.join().expect("Unable to join server thread");
+        });
+    }
+
+    #[test]
+    fn test_streams() {
+        with_addr(&mut |addr| {
+            let collection_name = random_collection_name();
+
+            let handle = create_handler(addr, Credentials::empty());
+            let mut client = create_client(addr);
+
+            assert!(client.send_message(TcpMessage::Connect(collection_name.to_owned(),
+                                        None, None)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Connected));
+
+            let event = Event::new("data", vec!["order"]).with_timestamp(1234567890).with_stream("order-1");
+
+            assert!(client.send_message(TcpMessage::Publish(event.clone())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Published(1)));
+
+            assert!(client.send_message(TcpMessage::PublishExpectingStreamVersion(event.clone(), 1)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Published(2)));
+
+            assert!(client.send_message(TcpMessage::PublishBatchExpectingStreamVersion(vec![event.clone(), event.clone()], 2)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::PublishedBatch(3, 4)));
+
+            assert!(client.send_message(TcpMessage::PublishExpectingStreamVersion(event.clone(), 2)).is_ok());
+            let wrong_expected_version = DatabaseError::WrongExpectedVersion { expected: 2, actual: 4 };
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Error(wrong_expected_version)));
+
+            assert!(client.send_message(TcpMessage::GetStreamVersion("order-1".to_owned())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::StreamVersion("order-1".to_owned(), 4)));
+
+            assert!(client.send_message(TcpMessage::GetStreamVersion("order-2".to_owned())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::StreamVersion("order-2".to_owned(), 0)));
+
+            let query = Query::current().by_stream("order-1").from_stream_version(3);
+            assert!(client.send_message(TcpMessage::Query(query)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Subscribed));
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Event(Event::new("data", vec!["order"]).with_id(3).with_timestamp(1234567890))));
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Event(Event::new("data", vec!["order"]).with_id(4).with_timestamp(1234567890))));
+            assert_eq!(client.recv_message(), Ok(TcpMessage::EndOfEventStream));
+
+            drop(client);
+
+            assert!(remove_file(format!("{}.1.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.tags.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.streams.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.timestamps.log", collection_name)).is_ok());
+
+            handle.join().expect("Unable to join server thread");
+        });
+    }
+
+    #[test]
+    fn test_publish_idempotent() {
+        with_addr(&mut |addr| {
+            let collection_name = random_collection_name();
+
+            let handle = create_handler(addr, Credentials::empty());
+            let mut client = create_client(addr);
+
+            assert!(client.send_message(TcpMessage::Connect(collection_name.to_owned(),
+                                        None, None)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Connected));
+
+            let event = Event::new("data", vec!["tag1", "tag2"]).with_timestamp(1234567890);
+
+            assert!(client.send_message(TcpMessage::Publish(event.clone().with_client_id("client1"))).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Published(1)));
+
+            assert!(client.send_message(TcpMessage::Publish(event.clone())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Published(2)));
+
+            assert!(client.send_message(TcpMessage::Publish(event.clone().with_client_id("client1"))).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Published(1)));
+
+            let events = vec![event.clone().with_client_id("client2"), event.clone().with_client_id("client3")];
+            assert!(client.send_message(TcpMessage::PublishBatch(events.clone())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::PublishedBatch(3, 4)));
+
+            assert!(client.send_message(TcpMessage::PublishBatch(events)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::PublishedBatch(3, 4)));
+
+            drop(client);
+
+            assert!(remove_file(format!("{}.1.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.tags.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.streams.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.timestamps.log", collection_name)).is_ok());
+
+            handle.join().expect("Unable to join server thread");
+        });
+    }
+
+    #[test]
+    fn test_durable_subscriptions() {
+        with_addr(&mut |addr| {
+            let collection_name = random_collection_name();
+
+            let handle = create_handler(addr, Credentials::empty());
+            let mut client = create_client(addr);
+
+            assert!(client.send_message(TcpMessage::Connect(collection_name.to_owned(),
+                                        None, None)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Connected));
+
+            let event = Event::new("data", vec!["tag1"]).with_timestamp(1234567890);
+
+            assert!(client.send_message(TcpMessage::PublishBatch(vec![event.clone(), event.clone(), event.clone()])).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::PublishedBatch(1, 3)));
+
+            let mut checkpoints = Checkpoints::new();
+            assert!(checkpoints.reset("consumer", 1).is_ok());
+
+            assert!(client.send_message(TcpMessage::ResetCheckpoint("consumer".to_owned(), 1)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Checkpoints(checkpoints.clone())));
+            assert!(client.send_message(TcpMessage::Acknowledge("other".to_owned(), 2)).is_ok());
+            assert!(client.send_message(TcpMessage::DeleteCheckpoint("other".to_owned())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Checkpoints(checkpoints.clone())));
+            assert!(client.send_message(TcpMessage::ListCheckpoints).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Checkpoints(checkpoints)));
+
+            assert!(client.send_message(TcpMessage::Acknowledge("consumer".to_owned(), 4)).is_ok());
+            let validation_error = ValidationError::new("checkpoint must not follow the last event published");
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Error(DatabaseError::ValidationError(validation_error))));
+
+            assert!(client.send_message(TcpMessage::SubscribeDurable("consumer".to_owned(), Query::live())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Subscribed));
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Event(event.clone().with_id(2))));
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Event(event.with_id(3))));
+
+            assert!(client.send_message(TcpMessage::Acknowledge("consumer".to_owned(), 3)).is_ok());
+
+            drop(client);
+
+            handle.join().expect("Unable to join server thread");
+
+            let log = Log::new("", &collection_name, 100000);
+            assert_eq!(log.restore_checkpoints().map(|checkpoints| checkpoints.get("consumer")), Ok(Some(3)));
+
+            assert!(remove_file(format!("{}.1.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.tags.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.streams.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.timestamps.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.checkpoints.log", collection_name)).is_ok());
+        });
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        with_addr(&mut |addr| {
+            let collection_name = random_collection_name();
+            let dead_letter_collection_name = dead_letter_collection_name(&collection_name, "group");
+
+            let handle = create_handler(addr, Credentials::empty());
+            let mut client = create_client(addr);
+
+            assert!(client.send_message(TcpMessage::Connect(collection_name.to_owned(),
+                                        None, None)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Connected));
+
+            let event = Event::new("data", vec!["tag1"]).with_timestamp(1234567890);
+
+            assert!(client.send_message(TcpMessage::PublishBatch(vec![event.clone(), event.clone()])).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::PublishedBatch(1, 2)));
+
+            assert!(client.send_message(TcpMessage::Ack(1)).is_ok());
+            let validation_error = ValidationError::new("not a member of a consumer group");
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Error(DatabaseError::ValidationError(validation_error))));
+
+            assert!(client.send_message(TcpMessage::JoinGroup("group".to_owned(), Query::current())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Subscribed));
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Event(event.clone().with_id(1))));
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Event(event.clone().with_id(2))));
+
+            assert!(client.send_message(TcpMessage::Ack(1)).is_ok());
+            assert!(client.send_message(TcpMessage::Nack(2)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Event(event.with_id(2))));
+            assert!(client.send_message(TcpMessage::Ack(2)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::EndOfEventStream));
+
+            assert!(client.send_message(TcpMessage::Ack(2)).is_ok());
+            let validation_error = ValidationError::new("event is not in flight to this member");
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Error(DatabaseError::ValidationError(validation_error))));
+
+            assert!(client.send_message(TcpMessage::DescribeGroup("group".to_owned())).is_ok());
+            if let Ok(TcpMessage::ConsumerGroup(info)) = client.recv_message() {
+                assert_eq!(info.name, "group");
+                assert_eq!(info.position, 2);
+                assert_eq!(info.lag, 0);
+                assert_eq!(info.nr_of_dead_letters, 0);
+            } else {
+                panic!("Unable to receive consumer group");
+            }
+
+            drop(client);
+
+            handle.join().expect("Unable to join server thread");
+
+            for name in vec![collection_name, dead_letter_collection_name] {
+                assert!(remove_file(format!("{}.1.log", name)).is_ok());
+                assert!(remove_file(format!("{}.1.index.log", name)).is_ok());
+                assert!(remove_file(format!("{}.tags.log", name)).is_ok());
+                assert!(remove_file(format!("{}.streams.log", name)).is_ok());
+                assert!(remove_file(format!("{}.timestamps.log", name)).is_ok());
+            }
+        });
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        with_addr(&mut |addr| {
+            let collection_name = random_collection_name();
+
+            let handle = create_handler(addr, Credentials::empty());
+            let mut client = create_client(addr);
+
+            assert!(client.send_message(TcpMessage::Connect(collection_name.to_owned(),
+                                        None, None)).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Connected));
+
+            let event = Event::new("data", vec!["tag1"]).with_timestamp(1234567890);
+
+            assert!(client.send_message(TcpMessage::Publish(event.clone())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Published(1)));
+
+            assert!(client.send_message(TcpMessage::Query(Query::live())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Subscribed));
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Event(event.clone().with_id(1))));
+
+            assert!(client.send_message(TcpMessage::Unsubscribe).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::EndOfEventStream));
+
+            assert!(client.send_message(TcpMessage::Publish(event.clone())).is_ok());
+            assert_eq!(client.recv_message(), Ok(TcpMessage::Published(2)));
+
+            drop(client);
+
+            assert!(remove_file(format!("{}.1.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.tags.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.streams.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.timestamps.log", collection_name)).is_ok());
+
+            handle.join().expect("Unable to join server thread");
+        });
+    }
+
+    #[test]
+    fn test_disconnection_during_live_streams() {
+        for join_group in vec![false, true] {
+            with_addr(&mut |addr| {
+                let collection_name = random_collection_name();
+                let db = Arc::new(Mutex::new(Database::new(DatabaseConfig::default())));
+                let (handler_sender, handler_receiver) = channel();
+                let handler_db = db.clone();
+                thread::spawn(move || {
+                    let listener = TcpListener::bind(addr).expect("Unable to bind to address");
+                    let (stream, _) = listener.accept().expect("Unable to accept connection");
+                    let mut handler = Handler::new(stream, handler_db, Credentials::empty()).expect("Unable to create TCP connection handler");
+                    handler.run();
+                    let _ = handler_sender.send(handler);
+                });
+                thread::sleep(Duration::from_millis(100));
+                let mut client = create_client(addr);
+
+                assert!(client.send_message(TcpMessage::Connect(collection_name.to_owned(),
+                                            None, None)).is_ok());
+                assert_eq!(client.recv_message(), Ok(TcpMessage::Connected));
+
+                let event = Event::new("data", vec!["tag1"]).with_timestamp(1234567890);
+
+                assert!(client.send_message(TcpMessage::Publish(event.clone())).is_ok());
+                assert_eq!(client.recv_message(), Ok(TcpMessage::Published(1)));
+
+                let subscription = if join_group { TcpMessage::JoinGroup("group".to_owned(), Query::live()) } else { TcpMessage::Query(Query::live()) };
+                assert!(client.send_message(subscription).is_ok());
+                assert_eq!(client.recv_message(), Ok(TcpMessage::Subscribed));
+                assert_eq!(client.recv_message(), Ok(TcpMessage::Event(event.clone().with_id(1))));
+
+                drop(client);
+
+                let handler = handler_receiver.recv_timeout(Duration::from_millis(1000)).expect("Unable to stop the handler");
+                assert!(handler.cancellation_handle.is_none());
+                assert!(handler.consumer_group_member.is_none());
+                assert!(handler.stream_thread.is_none());
+
+                let mut db = db.lock().unwrap();
+                if join_group {
+                    let info = db.connect(&collection_name).and_then(|connection| connection.consumer_group_info("group"));
+                    assert_eq!(info.map(|info| info.members.is_empty()), Ok(true));
+                    assert!(db.drop_collection(&dead_letter_collection_name(&collection_name, "group")).is_ok());
+                }
+                assert!(db.drop_collection(&collection_name).is_ok());
+            });
+        }
+    }
+
     #[test]
     fn test_unexpected_tcp_message() {
         with_addr(&mut |addr| {
             let handle = create_handler(addr, Credentials::empty());
             let mut client = create_client(addr);
 
-            assert!(client.send_message(TcpMessage::Subscribe(false, 0, None, None)).is_ok());
+            assert!(client.send_message(TcpMessage::Subscribe(false, 0, None, None, None, None, false, None)).is_ok());
             assert_eq!(client.recv_message(), Ok(TcpMessage::Error(DatabaseError::IoError(ErrorKind::InvalidData, "unexpected TCP message".to_owned()))));
 
             drop(client);
@@ -181,8 +181,11 @@ mod tests {
                                         None, None)).is_ok());
             assert_eq!(client.recv_message(), Ok(TcpMessage::Connected));
 
-            assert!(remove_file(format!("{}.log", collection_name)).is_ok());
-            assert!(remove_file(format!("{}.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.tags.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.streams.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.timestamps.log", collection_name)).is_ok());
         });
     }
 
@@ -206,8 +209,11 @@ mod tests {
                                         Some("username".to_owned()), Some("password".to_owned()))).is_ok());
             assert_eq!(client.recv_message(), Ok(TcpMessage::Connected));
 
-            assert!(remove_file(format!("{}.log", collection_name)).is_ok());
-            assert!(remove_file(format!("{}.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.1.index.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.tags.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.streams.log", collection_name)).is_ok());
+            assert!(remove_file(format!("{}.timestamps.log", collection_name)).is_ok());
         });
     }
 }
@@ -60,11 +60,17 @@ export class Query {
     offset: number;
     limit: number;
     tag: string;
+    reverse: boolean;
 
-    constructor(liveStream: boolean, offset: number = 0, limit: number = 0, tag?: string) {
-        this.liveStream = liveStream;
+    constructor(liveStream: boolean, offset: number = 0, limit: number = 0, tag?: string, reverse: boolean = false) {
+        this.liveStream = liveStream && !reverse;
         this.offset = offset;
         this.limit = limit;
         this.tag = tag;
+        this.reverse = reverse;
+    }
+
+    static last(limit: number) {
+        return new Query(false, 0, limit, undefined, true);
     }
 }
@@ -109,20 +109,23 @@ export class Subscribe implements TcpMessage {
     }
 
     toTabSeparatedString() {
+       let reverseFields = this.query.reverse ? [0, 0, true] : [];
        return TcpMessageEncoder.toTabSeparatedString('Subscribe',
            this.query.liveStream,
            this.query.offset || 0,
            this.query.limit || 0,
-           this.query.tag);
+           this.query.reverse ? this.query.tag || '' : this.query.tag,
+           ...reverseFields);
     }
 
     static fromTabSeparatedString(data: string) {
-        let messageParts = TcpMessageDecoder.parseTabSeparatedString(data, 5);
+        let messageParts = TcpMessageDecoder.parseTabSeparatedString(data, 8);
         let liveStream = messageParts[1] === 'true';
         let offset = parseInt(messageParts[2]);
         let limit = parseInt(messageParts[3]);
-        let tag = messageParts[4];
-        let query = new Query(liveStream, offset, limit, tag);
+        let tag = messageParts[4] || undefined;
+        let reverse = messageParts[7] === 'true';
+        let query = new Query(liveStream, offset, limit, tag, reverse);
         return new Subscribe(query);
     }
 }
@@ -158,6 +161,9 @@ export class DatabaseError implements TcpMessage {
     toString() {
         if(this.type === 'ParseError' && this.subType === 'MissingField') {
             return `${this.type}: missing field at position ${this.data}`;
+        } else if(this.type === 'ParseError' && this.subType === 'SyntaxError') {
+            let [position, description] = TcpMessageDecoder.parseTabSeparatedString(this.data, 2);
+            return `${this.type}: ${description} at position ${position}`;
         } else if(this.type === 'AuthenticationError') {
             return `${this.type}: missing or invalid credentials`;
         } return `${this.type}: ${this.data}`;
@@ -20,6 +20,7 @@ export class ConnectionHandler {
     tags: string;
 
     liveStream: boolean = false;
+    reverse: boolean = false;
     offset: string;
     limit: string;
     tag: string;
@@ -72,7 +73,7 @@ export class ConnectionHandler {
     }
 
     subscribe() {
-        let query = new Query(this.liveStream, parseInt(this.offset), parseInt(this.limit), this.tag);
+        let query = new Query(this.liveStream, parseInt(this.offset), parseInt(this.limit), this.tag, this.reverse);
         this.exarClient.subscribe(query).then(
             eventStream => {
                 this.logMessage('Subscribed', false);